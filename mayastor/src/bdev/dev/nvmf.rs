@@ -46,6 +46,8 @@ pub(super) struct Nvmf {
     prchk_flags: u32,
     /// uuid of the spdk bdev
     uuid: Option<uuid::Uuid>,
+    /// the nqn of the host we connect as, or the default one when not set
+    hostnqn: Option<String>,
 }
 
 /// Convert a URI to an Nvmf "object"
@@ -108,6 +110,8 @@ impl TryFrom<&Url> for Nvmf {
             },
         )?;
 
+        let hostnqn = parameters.remove("hostnqn");
+
         if let Some(keys) = uri::keys(parameters) {
             warn!("ignored parameters: {}", keys);
         }
@@ -121,6 +125,7 @@ impl TryFrom<&Url> for Nvmf {
             subnqn: segments[0].to_string(),
             prchk_flags,
             uuid,
+            hostnqn,
         })
     }
 }
@@ -161,6 +166,10 @@ impl CreateDestroy for Nvmf {
         }
 
         let cname = CString::new(self.name.clone()).unwrap();
+        let chostnqn = self
+            .hostnqn
+            .as_ref()
+            .map(|nqn| CString::new(nqn.as_str()).unwrap());
         let mut context = NvmeCreateContext::new(self);
 
         let (sender, receiver) = oneshot::channel::<ErrnoResult<()>>();
@@ -172,7 +181,9 @@ impl CreateDestroy for Nvmf {
                 cname.as_ptr(),
                 &mut context.names[0],
                 context.count,
-                std::ptr::null_mut(),
+                chostnqn
+                    .as_ref()
+                    .map_or(std::ptr::null(), |nqn| nqn.as_ptr()),
                 context.prchk_flags,
                 Some(done_nvme_create_cb),
                 cb_arg(sender),
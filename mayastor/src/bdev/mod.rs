@@ -3,6 +3,8 @@ use async_trait::async_trait;
 pub use nexus::{
     nexus_bdev::{
         nexus_create,
+        nexus_create_ext,
+        nexus_create_with_block_size,
         nexus_lookup,
         Nexus,
         NexusState,
@@ -13,6 +15,7 @@ pub use nexus::{
     nexus_child_error_store::{ActionType, NexusErrStore, QueryType},
     nexus_child_status_config,
     nexus_io::Bio,
+    nexus_journal::{EventOrigin, NexusEvent, NexusEventType, NexusRequest},
     nexus_label::{GPTHeader, GptEntry},
     nexus_metadata_content::{
         NexusConfig,
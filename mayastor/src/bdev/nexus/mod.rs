@@ -14,6 +14,7 @@ macro_rules! c_str {
 
 pub mod nexus_bdev;
 pub mod nexus_bdev_children;
+pub mod nexus_bdev_emulation;
 pub mod nexus_bdev_rebuild;
 pub mod nexus_bdev_snapshot;
 mod nexus_channel;
@@ -22,7 +23,9 @@ pub(crate) mod nexus_child_error_store;
 pub mod nexus_child_status_config;
 mod nexus_config;
 pub mod nexus_fn_table;
+mod nexus_generation;
 pub mod nexus_io;
+pub mod nexus_journal;
 pub mod nexus_label;
 pub mod nexus_metadata;
 pub mod nexus_metadata_content;
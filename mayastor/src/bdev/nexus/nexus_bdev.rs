@@ -8,6 +8,7 @@ use std::{
     convert::TryFrom,
     fmt::{Display, Formatter},
     os::raw::c_void,
+    sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
 };
 
 use futures::channel::oneshot;
@@ -39,6 +40,7 @@ use crate::{
         nexus,
         nexus::{
             instances,
+            nexus_bdev_emulation::EmulatedRanges,
             nexus_channel::{
                 DREvent,
                 NexusChannel,
@@ -47,13 +49,20 @@ use crate::{
             },
             nexus_child::{ChildError, ChildState, NexusChild},
             nexus_io::{nvme_admin_opc, Bio, IoStatus, IoType},
+            nexus_journal::{
+                EventOrigin,
+                NexusEvent,
+                NexusEventType,
+                NexusJournal,
+                NexusRequest,
+            },
             nexus_label::LabelError,
             nexus_nbd::{NbdDisk, NbdError},
         },
     },
     core::{Bdev, CoreError, DmaError, Protocol, Reactor, Share},
     ffihelper::errno_result_from_i32,
-    lvs::Lvol,
+    lvs::{Error as LvsError, Lvol},
     nexus_uri::{bdev_destroy, NexusBdevError},
     rebuild::RebuildError,
     subsys,
@@ -134,6 +143,27 @@ pub enum Error {
     NexusIncomplete { name: String },
     #[snafu(display("Children of nexus {} have mixed block sizes", name))]
     MixedBlockSizes { name: String },
+    #[snafu(display(
+        "Invalid block size {} for nexus {}, must be a power of two between 512 and 4096",
+        blk_size,
+        name
+    ))]
+    InvalidBlockSize { blk_size: u32, name: String },
+    #[snafu(display(
+        "Block size {} of nexus {} is incompatible with block size {} of its children",
+        blk_size,
+        name,
+        child_blk_size
+    ))]
+    IncompatibleBlockSize {
+        blk_size: u32,
+        child_blk_size: u32,
+        name: String,
+    },
+    #[snafu(display("Failed to allocate emulation buffer of nexus {}", name))]
+    EmulationAlloc { source: DmaError, name: String },
+    #[snafu(display("Emulated IO failed on nexus {}", name))]
+    EmulatedIo { source: CoreError, name: String },
     #[snafu(display(
         "Child {} of nexus {} has incompatible size or block size",
         child,
@@ -184,6 +214,8 @@ pub enum Error {
     ChildNotFound { child: String, name: String },
     #[snafu(display("Suitable rebuild source for nexus {} not found", name))]
     NoRebuildSource { name: String },
+    #[snafu(display("No child of nexus {} given to rebuild", name))]
+    NoRebuildDestination { name: String },
     #[snafu(display(
         "Failed to create rebuild job for child {} of nexus {}",
         child,
@@ -245,6 +277,35 @@ pub enum Error {
     FailedGetHandle,
     #[snafu(display("Failed to create snapshot on nexus {}", name))]
     FailedCreateSnapshot { name: String, source: CoreError },
+    #[snafu(display("{} is not a snapshot of nexus {}", snapshot, name))]
+    InvalidSnapshotName { snapshot: String, name: String },
+    #[snafu(display("Snapshot {} of nexus {} not found", snapshot, name))]
+    SnapshotNotFound { snapshot: String, name: String },
+    #[snafu(display(
+        "Failed snapshot operation on child {} of nexus {}",
+        child,
+        name
+    ))]
+    FailedChildSnapshot {
+        child: String,
+        name: String,
+        source: CoreError,
+    },
+    #[snafu(display(
+        "Failed snapshot operation on replica {} of nexus {}",
+        child,
+        name
+    ))]
+    FailedReplicaSnapshot {
+        child: String,
+        name: String,
+        source: LvsError,
+    },
+    #[snafu(display(
+        "Can not revert nexus {} to a snapshot while rebuilding",
+        name
+    ))]
+    RevertWhileRebuilding { name: String },
 }
 
 impl From<Error> for tonic::Status {
@@ -274,6 +335,12 @@ impl From<Error> for tonic::Status {
             Error::ChildGeometry {
                 ..
             } => Status::invalid_argument(e.to_string()),
+            Error::InvalidBlockSize {
+                ..
+            } => Status::invalid_argument(e.to_string()),
+            Error::IncompatibleBlockSize {
+                ..
+            } => Status::invalid_argument(e.to_string()),
             Error::OpenChild {
                 ..
             } => Status::invalid_argument(e.to_string()),
@@ -283,6 +350,15 @@ impl From<Error> for tonic::Status {
             Error::ChildNotFound {
                 ..
             } => Status::not_found(e.to_string()),
+            Error::InvalidSnapshotName {
+                ..
+            } => Status::invalid_argument(e.to_string()),
+            Error::SnapshotNotFound {
+                ..
+            } => Status::not_found(e.to_string()),
+            Error::RevertWhileRebuilding {
+                ..
+            } => Status::failed_precondition(e.to_string()),
             e => Status::new(Code::Internal, e.to_string()),
         }
     }
@@ -316,6 +392,9 @@ pub struct Nexus {
     pub(super) state: std::sync::Mutex<NexusState>,
     /// the offset in num blocks where the data partition starts
     pub data_ent_offset: u64,
+    /// the offset in num blocks where the metadata partition starts, its first
+    /// block holds the checkpoint of a rebuild of the child
+    pub meta_ent_offset: u64,
     /// the handle to be used when sharing the nexus, this allows for the bdev
     /// to be shared with vbdevs on top
     pub(crate) share_handle: Option<String>,
@@ -323,6 +402,34 @@ pub struct Nexus {
     pub nexus_target: Option<NexusTarget>,
     /// the maximum number of times to attempt to send an IO
     pub(crate) max_io_attempts: i32,
+    /// journal of the most recent events of this nexus
+    pub(crate) journal: NexusJournal,
+    /// the nexus has been published read-only
+    pub(crate) share_read_only: AtomicBool,
+    /// write redundancy has been lost and writes are rejected
+    pub(crate) degraded_read_only: AtomicBool,
+    /// largest number of healthy children seen since the last removal of a
+    /// child, write redundancy is lost when it drops below this and below 2
+    pub(crate) healthy_peak: AtomicUsize,
+    /// block size requested at creation, inherited from the children if None
+    pub(crate) requested_blk_size: Option<u32>,
+    /// generation of the ownership of the replicas by the nexus, part of the
+    /// host NQN it connects to its nvmf children with
+    pub(crate) owner_generation: u64,
+    /// largest block size of the children, data_ent_offset is expressed in
+    /// it
+    pub(crate) child_blk_size: u32,
+    /// ranges (in bytes) of the children currently being written by emulated
+    /// IO, used to serialise read-modify-write cycles
+    pub(crate) emulated_ranges: std::sync::Mutex<EmulatedRanges>,
+    /// bitmask of the optional IO types which are natively supported by all
+    /// children receiving IO, see update_io_capabilities()
+    pub(crate) io_capabilities: AtomicU32,
+    /// the nexus has received a write since it was opened, after which the
+    /// rebuilds of its children can no longer resume from a checkpoint
+    pub(crate) written: AtomicBool,
+    /// write generation of the nexus, bumped each time it is opened
+    pub(crate) generation: u64,
 }
 
 unsafe impl core::marker::Sync for Nexus {}
@@ -332,6 +439,8 @@ unsafe impl core::marker::Send for Nexus {}
 pub enum NexusStatus {
     /// The nexus cannot perform any IO operation
     Faulted,
+    /// Write redundancy is lost, the nexus only serves reads
+    ReadOnly,
     /// Degraded, one or more child is missing but IO can still flow
     Degraded,
     /// Online
@@ -366,6 +475,7 @@ impl ToString for NexusStatus {
             NexusStatus::Degraded => "degraded",
             NexusStatus::Online => "online",
             NexusStatus::Faulted => "faulted",
+            NexusStatus::ReadOnly => "read_only",
         }
         .parse()
         .unwrap()
@@ -411,10 +521,22 @@ impl Nexus {
             state: std::sync::Mutex::new(NexusState::Init),
             bdev_raw: Box::into_raw(b),
             data_ent_offset: 0,
+            meta_ent_offset: 0,
             share_handle: None,
             size,
             nexus_target: None,
             max_io_attempts: cfg.err_store_opts.max_io_attempts,
+            journal: NexusJournal::new(cfg.nexus_opts.journal_size),
+            share_read_only: AtomicBool::new(false),
+            degraded_read_only: AtomicBool::new(false),
+            healthy_peak: AtomicUsize::new(0),
+            requested_blk_size: None,
+            owner_generation: 0,
+            child_blk_size: 0,
+            emulated_ranges: std::sync::Mutex::new(EmulatedRanges::default()),
+            io_capabilities: AtomicU32::new(0),
+            written: AtomicBool::new(false),
+            generation: 0,
         });
 
         n.bdev.set_uuid(match uuid {
@@ -442,6 +564,49 @@ impl Nexus {
         *self.state.lock().unwrap() = state;
         state
     }
+    /// record an event in the journal of the nexus
+    pub(crate) fn record_event(
+        &self,
+        event: NexusEventType,
+        child: Option<&str>,
+        reason: impl Into<String>,
+        origin: EventOrigin,
+    ) {
+        self.record(NexusEvent::new(event, child, reason.into(), origin));
+    }
+
+    fn record(&self, event: NexusEvent) {
+        debug!("{}: {}", self.name, event);
+        self.journal.record(event);
+    }
+
+    /// record the gRPC request which is about to act on the nexus. The
+    /// events of the nexus are attributed to the request until the returned
+    /// request is dropped, which must be once the request has completed
+    #[must_use]
+    pub fn record_request(
+        &self,
+        request: &str,
+        child: Option<&str>,
+    ) -> NexusRequest {
+        let current = self.journal.begin_request(request);
+        let mut event = NexusEvent::new(
+            NexusEventType::Request,
+            child,
+            format!("{} request received", request),
+            EventOrigin::Rpc(request.to_string()),
+        );
+        event.request = Some(current.id());
+        self.record(event);
+        current
+    }
+
+    /// returns the journal of the nexus from the oldest to the most recent
+    /// event
+    pub fn events(&self) -> Vec<NexusEvent> {
+        self.journal.events()
+    }
+
     /// returns the size in bytes of the nexus instance
     pub fn size(&self) -> u64 {
         u64::from(self.bdev.block_len()) * self.bdev.num_blocks()
@@ -469,6 +634,75 @@ impl Nexus {
             "{}: Dynamic reconfiguration event: {:?} completed {:?}",
             self.name, event, result
         );
+
+        self.record_event(
+            NexusEventType::Reconfigured,
+            None,
+            format!("{:?} completed with status {}", event, result),
+            EventOrigin::Nexus,
+        );
+
+        self.update_io_capabilities();
+        self.update_read_only();
+        self.update_rebuild_priorities();
+    }
+
+    /// returns true if writes to the nexus are rejected, either because it
+    /// has been published read-only or because write redundancy was lost
+    pub fn is_read_only(&self) -> bool {
+        self.share_read_only.load(Ordering::Relaxed)
+            || self.degraded_read_only.load(Ordering::Relaxed)
+    }
+
+    /// number of children that are open and take part in the IO
+    fn healthy_children(&self) -> usize {
+        self.children
+            .iter()
+            .filter(|c| c.state() == ChildState::Open)
+            .count()
+    }
+
+    /// forget about the children that were healthy before, so that removing
+    /// a child on purpose does not count as losing write redundancy
+    pub(crate) fn reset_healthy_peak(&self) {
+        self.healthy_peak
+            .store(self.healthy_children(), Ordering::Relaxed);
+        self.update_read_only();
+    }
+
+    /// enter or leave the read-only state, if configured to do so. The nexus
+    /// becomes read-only when it loses a healthy child and is left with less
+    /// than 2 of them. Adding a child to a nexus that never had write
+    /// redundancy, and rebuilding it, leaves the nexus writable.
+    fn update_read_only(&self) {
+        if !Config::get().nexus_opts.degraded_read_only {
+            return;
+        }
+
+        let healthy = self.healthy_children();
+        let peak = self
+            .healthy_peak
+            .fetch_max(healthy, Ordering::Relaxed)
+            .max(healthy);
+        let read_only = peak >= 2 && healthy < 2;
+
+        if self.degraded_read_only.swap(read_only, Ordering::Relaxed)
+            != read_only
+        {
+            let (event, reason) = if read_only {
+                (
+                    NexusEventType::ReadOnly,
+                    format!("only {} healthy child(ren) left", healthy),
+                )
+            } else {
+                (
+                    NexusEventType::ReadWrite,
+                    "write redundancy restored".to_string(),
+                )
+            };
+            warn!("{}: {}, {}", self.name, event, reason);
+            self.record_event(event, None, reason, EventOrigin::Nexus);
+        }
     }
 
     /// Opens the Nexus instance for IO
@@ -476,7 +710,9 @@ impl Nexus {
         debug!("Opening nexus {}", self.name);
 
         self.try_open_children().await?;
+        self.update_io_capabilities();
         self.sync_labels().await?;
+        self.bump_generation().await;
         self.register().await
     }
 
@@ -488,13 +724,17 @@ impl Nexus {
         // Now register the bdev but update its size first
         // to ensure we adhere to the partitions.
         self.data_ent_offset = label.offset();
-        let size_blocks = self.size / self.bdev.block_len() as u64;
+        self.meta_ent_offset = label.metadata_offset();
+        let blk_size = self.bdev.block_len() as u64;
+        let size_blocks = self.size / blk_size;
 
         self.bdev.set_block_count(std::cmp::min(
             // nexus is allowed to be smaller than the children
             size_blocks,
-            // label might be smaller than expected due to the on disk metadata
-            label.get_block_count(),
+            // label might be smaller than expected due to the on disk
+            // metadata, the label is in units of the child block
+            // size
+            label.get_block_count() * self.child_blk_size as u64 / blk_size,
         ));
 
         Ok(())
@@ -678,14 +918,69 @@ impl Nexus {
         &mut *(n as *mut Nexus)
     }
 
-    /// determine if any of the children do not support the requested
-    /// io type. Break the loop on first occurrence.
-    /// TODO: optionally add this check during nexus creation
-    pub fn io_is_supported(&self, io_type: IoType) -> bool {
-        self.children
+    /// IO types which are only advertised when all children receiving IO
+    /// support them
+    const OPTIONAL_IO_TYPES: [IoType; 4] = [
+        IoType::Flush,
+        IoType::Reset,
+        IoType::Unmap,
+        IoType::WriteZeros,
+    ];
+
+    /// Recompute the IO types supported by the nexus as the intersection of
+    /// the IO types supported by the children which receive IO, so that the
+    /// outcome of an IO does not depend on the child it is sent to.
+    pub(crate) fn update_io_capabilities(&self) {
+        let capabilities = self
+            .children
             .iter()
-            .filter_map(|e| e.bdev.as_ref())
-            .any(|b| b.io_type_supported(io_type))
+            .filter(|c| c.state() == ChildState::Open || c.rebuilding())
+            .filter_map(|c| c.bdev.as_ref())
+            .map(|b| {
+                Self::OPTIONAL_IO_TYPES
+                    .iter()
+                    .filter(|t| b.io_type_supported(**t))
+                    .fold(0, |mask, t| mask | 1 << u32::from(*t))
+            })
+            .fold(None, |all: Option<u32>, mask| {
+                Some(all.map_or(mask, |all| all & mask))
+            })
+            .unwrap_or(0);
+
+        let previous =
+            self.io_capabilities.swap(capabilities, Ordering::Relaxed);
+        if previous != capabilities {
+            info!(
+                "{}: IO capabilities changed from {:#x} to {:#x}",
+                self.name, previous, capabilities
+            );
+        }
+    }
+
+    /// returns true if all children receiving IO natively support the io type
+    fn io_natively_supported(&self, io_type: IoType) -> bool {
+        self.io_capabilities.load(Ordering::Relaxed) & 1 << u32::from(io_type)
+            != 0
+    }
+
+    /// determine if the nexus supports the requested io type, see
+    /// update_io_capabilities()
+    pub fn io_is_supported(&self, io_type: IoType) -> bool {
+        match io_type {
+            // partial child blocks can not be unmapped or zeroed, leave it to
+            // the bdev layer to emulate write zeroes using regular writes
+            IoType::Unmap | IoType::WriteZeros if self.is_emulated() => false,
+            // write zeroes submitted to a child lacking native support is
+            // emulated with buffered writes of zeroes by the bdev layer
+            IoType::WriteZeros => true,
+            _ => self.io_natively_supported(io_type),
+        }
+    }
+
+    /// returns true if write zeroes is emulated for at least one child
+    pub fn write_zeroes_emulated(&self) -> bool {
+        self.io_is_supported(IoType::WriteZeros)
+            && !self.io_natively_supported(IoType::WriteZeros)
     }
 
     /// main IO completion routine
@@ -752,6 +1047,13 @@ impl Nexus {
         }
 
         let ch = NexusChannel::inner_from_channel(ch);
+        let bio = Bio::from(io);
+        let nexus = bio.nexus_as_ref();
+        if nexus.child_range(bio.offset(), bio.num_blocks()).is_none() {
+            nexus.emulated_readv(&bio, &ch.readers[ch.previous]);
+            return;
+        }
+
         let (desc, ch) = ch.readers[ch.previous].io_tuple();
         let ret = Self::readv_impl(io, desc, ch);
         if ret != 0 {
@@ -788,7 +1090,13 @@ impl Nexus {
             return;
         }
 
-        let (desc, ch) = channels.readers[child.unwrap()].io_tuple();
+        let child = child.unwrap();
+        if self.child_range(io.offset(), io.num_blocks()).is_none() {
+            self.emulated_readv(io, &channels.readers[child]);
+            return;
+        }
+
+        let (desc, ch) = channels.readers[child].io_tuple();
 
         let ret = Self::readv_impl(io.as_ptr(), desc, ch);
 
@@ -812,14 +1120,22 @@ impl Nexus {
     ) -> i32 {
         let io = Bio::from(pio);
         let nexus = io.nexus_as_ref();
+        // unaligned reads never make it here, see emulated_readv()
+        let (offset, num_blocks) =
+            match nexus.child_range(io.offset(), io.num_blocks()) {
+                Some(range) => range,
+                None => return -libc::EINVAL,
+            };
+        let (offset, num_blocks) =
+            nexus.child_io_range(desc, (offset, num_blocks));
         unsafe {
             spdk_bdev_readv_blocks(
                 desc,
                 ch,
                 io.iovs(),
                 io.iov_count(),
-                io.offset() + nexus.data_ent_offset,
-                io.num_blocks(),
+                offset,
+                num_blocks,
                 Some(Self::io_completion),
                 io.as_ptr() as *mut _,
             )
@@ -866,19 +1182,37 @@ impl Nexus {
 
     /// write vectored IO to the underlying children.
     pub(crate) fn writev(&self, io: &Bio, channels: &NexusChannelInner) {
+        // when emulating smaller blocks all writes are serialised with the
+        // read-modify-write cycles of partial child blocks
+        if self.is_emulated() {
+            self.emulated_writev(io, channels);
+            return;
+        }
+
+        let (offset, num_blocks) =
+            match self.child_range(io.offset(), io.num_blocks()) {
+                Some(range) => range,
+                None => {
+                    io.fail();
+                    return;
+                }
+            };
+
         // in case of writes, we want to write to all underlying children
         let results = channels
             .writers
             .iter()
             .map(|c| unsafe {
                 let (desc, chan) = c.io_tuple();
+                let (offset, num_blocks) =
+                    self.child_io_range(desc, (offset, num_blocks));
                 spdk_bdev_writev_blocks(
                     desc,
                     chan,
                     io.iovs(),
                     io.iov_count(),
-                    io.offset() + io.nexus_as_ref().data_ent_offset,
-                    io.num_blocks(),
+                    offset,
+                    num_blocks,
                     Some(Self::io_completion),
                     io.as_ptr() as *mut _,
                 )
@@ -889,16 +1223,27 @@ impl Nexus {
     }
 
     pub(crate) fn unmap(&self, io: &Bio, channels: &NexusChannelInner) {
+        let (offset, num_blocks) =
+            match self.child_range(io.offset(), io.num_blocks()) {
+                Some(range) => range,
+                None => {
+                    io.fail();
+                    return;
+                }
+            };
+
         let results = channels
             .writers
             .iter()
             .map(|c| unsafe {
                 let (desc, chan) = c.io_tuple();
+                let (offset, num_blocks) =
+                    self.child_io_range(desc, (offset, num_blocks));
                 spdk_bdev_unmap_blocks(
                     desc,
                     chan,
-                    io.offset() + io.nexus_as_ref().data_ent_offset,
-                    io.num_blocks(),
+                    offset,
+                    num_blocks,
                     Some(Self::io_completion),
                     io.as_ptr() as *mut _,
                 )
@@ -908,17 +1253,30 @@ impl Nexus {
         self.check_io_submission(&results, &io);
     }
 
+    /// write zeroes to all children, children lacking native support have it
+    /// emulated by the bdev layer with buffered writes of zeroes
     pub(crate) fn write_zeroes(&self, io: &Bio, channels: &NexusChannelInner) {
+        let (offset, num_blocks) =
+            match self.child_range(io.offset(), io.num_blocks()) {
+                Some(range) => range,
+                None => {
+                    io.fail();
+                    return;
+                }
+            };
+
         let results = channels
             .writers
             .iter()
             .map(|c| unsafe {
                 let (b, c) = c.io_tuple();
+                let (offset, num_blocks) =
+                    self.child_io_range(b, (offset, num_blocks));
                 spdk_bdev_write_zeroes_blocks(
                     b,
                     c,
-                    io.offset() + io.nexus_as_ref().data_ent_offset,
-                    io.num_blocks(),
+                    offset,
+                    num_blocks,
                     Some(Self::io_completion),
                     io.as_ptr() as *mut _,
                 )
@@ -993,6 +1351,10 @@ impl Nexus {
     /// Faulted
     /// No child is online so the nexus is faulted
     /// This may be made more configurable in the future
+    ///
+    /// ReadOnly
+    /// Write redundancy was lost and the nexus was configured to reject
+    /// writes when that happens
     pub fn status(&self) -> NexusStatus {
         match *self.state.lock().unwrap() {
             NexusState::Init => NexusStatus::Degraded,
@@ -1011,7 +1373,11 @@ impl Nexus {
                     // at least one child online, so the Nexus is also online
                     .any(|c| c.state() == ChildState::Open)
                 {
-                    NexusStatus::Degraded
+                    if self.degraded_read_only.load(Ordering::Relaxed) {
+                        NexusStatus::ReadOnly
+                    } else {
+                        NexusStatus::Degraded
+                    }
                 } else {
                     // nexus has no children or at least no child is online
                     NexusStatus::Faulted
@@ -1033,6 +1399,42 @@ pub async fn nexus_create(
     uuid: Option<&str>,
     children: &[String],
 ) -> Result<(), Error> {
+    nexus_create_with_block_size(name, size, uuid, None, children).await
+}
+
+/// Create a nexus with the given logical block size, the block size of the
+/// children is used when None. A block size smaller than that of the children
+/// (i.e. 512 on top of 4Kn children) is emulated using read-modify-write.
+pub async fn nexus_create_with_block_size(
+    name: &str,
+    size: u64,
+    uuid: Option<&str>,
+    block_size: Option<u32>,
+    children: &[String],
+) -> Result<(), Error> {
+    nexus_create_ext(name, size, uuid, block_size, 0, children).await
+}
+
+/// Create a nexus with the given logical block size which connects to its
+/// nvmf children as their owner in the generation `owner_generation`, so that
+/// replicas owned by the nexus in a newer generation refuse the connection.
+pub async fn nexus_create_ext(
+    name: &str,
+    size: u64,
+    uuid: Option<&str>,
+    block_size: Option<u32>,
+    owner_generation: u64,
+    children: &[String],
+) -> Result<(), Error> {
+    if let Some(blk_size) = block_size {
+        if !blk_size.is_power_of_two() || blk_size < 512 || blk_size > 4096 {
+            return Err(Error::InvalidBlockSize {
+                blk_size,
+                name: name.to_string(),
+            });
+        }
+    }
+
     // global variable defined in the nexus module
     let nexus_list = instances();
     if nexus_list.iter().any(|n| n.name == name) {
@@ -1042,6 +1444,8 @@ pub async fn nexus_create(
     }
 
     let mut ni = Nexus::new(name, size, uuid, None);
+    ni.requested_blk_size = block_size;
+    ni.owner_generation = owner_generation;
 
     for child in children {
         if let Err(err) = ni.create_and_register(child).await {
@@ -1076,7 +1480,15 @@ pub async fn nexus_create(
             return Err(e);
         }
 
-        Ok(_) => nexus_list.push(ni),
+        Ok(_) => {
+            ni.record_event(
+                NexusEventType::Created,
+                None,
+                format!("created with {} children", children.len()),
+                EventOrigin::Nexus,
+            );
+            nexus_list.push(ni)
+        }
     }
     Ok(())
 }
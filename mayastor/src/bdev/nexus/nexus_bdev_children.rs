@@ -42,6 +42,7 @@ use crate::{
             nexus_channel::DREvent,
             nexus_child::{ChildState, NexusChild},
             nexus_child_status_config::ChildStatusConfig,
+            nexus_journal::{EventOrigin, NexusEventType},
             nexus_label::{
                 LabelError,
                 NexusChildLabel,
@@ -54,6 +55,7 @@ use crate::{
     },
     core::Bdev,
     nexus_uri::{bdev_create, bdev_destroy, NexusBdevError},
+    subsys::nexus_host_nqn,
 };
 
 impl Nexus {
@@ -82,7 +84,7 @@ impl Nexus {
         uri: &str,
     ) -> Result<(), NexusBdevError> {
         assert_eq!(*self.state.lock().unwrap(), NexusState::Init);
-        let name = bdev_create(&uri).await?;
+        let name = bdev_create(&self.child_bdev_uri(uri)).await?;
         self.children.push(NexusChild::new(
             uri.to_string(),
             self.name.clone(),
@@ -93,6 +95,29 @@ impl Nexus {
         Ok(())
     }
 
+    /// returns the URI to create the bdev of the child `uri` with. The nexus
+    /// connects to nvmf children with its own host NQN, as replicas which are
+    /// owned by a nexus only accept connections from their owner in the
+    /// current generation of the ownership
+    pub(crate) fn child_bdev_uri(&self, uri: &str) -> String {
+        match url::Url::parse(uri) {
+            Ok(mut url)
+                if url.scheme() == "nvmf"
+                    && !url.query_pairs().any(|(k, _)| k == "hostnqn") =>
+            {
+                url.query_pairs_mut().append_pair(
+                    "hostnqn",
+                    &nexus_host_nqn(
+                        &self.bdev.uuid_as_string(),
+                        self.owner_generation,
+                    ),
+                );
+                url.to_string()
+            }
+            _ => uri.to_string(),
+        }
+    }
+
     /// add a new child to an existing nexus. note that the child is added and
     /// opened but not taking part of any new IO's that are submitted to the
     /// nexus.
@@ -117,7 +142,15 @@ impl Nexus {
                     e.verbose()
                 );
                 match self.get_child_by_name(uri) {
-                    Ok(child) => child.fault(Reason::RebuildFailed).await,
+                    Ok(child) => {
+                        child.fault(Reason::RebuildFailed).await;
+                        self.record_event(
+                            NexusEventType::ChildFaulted,
+                            Some(uri),
+                            format!("rebuild failed to start: {}", e),
+                            EventOrigin::Rebuild,
+                        );
+                    }
                     Err(e) => error!(
                         "Failed to find newly added child {}, error: {}",
                         uri,
@@ -135,14 +168,21 @@ impl Nexus {
         &mut self,
         uri: &str,
     ) -> Result<NexusStatus, Error> {
-        let name = bdev_create(&uri).await.context(CreateChild {
-            name: self.name.clone(),
-        })?;
+        let name = bdev_create(&self.child_bdev_uri(uri)).await.context(
+            CreateChild {
+                name: self.name.clone(),
+            },
+        )?;
 
         let child_bdev = match Bdev::lookup_by_name(&name) {
             Some(child) => {
-                if child.block_len() != self.bdev.block_len()
-                    || self.min_num_blocks() > child.num_blocks()
+                // a child with smaller blocks than the others can be added
+                // as long as the layout of the nexus lines up with them
+                if !Self::child_blk_size_fits(
+                    child.block_len(),
+                    self.child_blk_size,
+                ) || self.min_num_blocks() * self.child_blk_size as u64
+                    > child.size_in_bytes()
                 {
                     if let Err(err) = bdev_destroy(uri).await {
                         error!(
@@ -190,6 +230,12 @@ impl Nexus {
 
                 self.children.push(child);
                 self.child_count += 1;
+                self.record_event(
+                    NexusEventType::ChildAdded,
+                    Some(uri),
+                    "child added and requires a rebuild",
+                    EventOrigin::Nexus,
+                );
 
                 if let Err(e) = self.sync_labels().await {
                     error!("Failed to sync labels {:?}", e);
@@ -241,6 +287,14 @@ impl Nexus {
 
         self.children.remove(idx);
         self.child_count -= 1;
+        self.update_io_capabilities();
+        self.reset_healthy_peak();
+        self.record_event(
+            NexusEventType::ChildRemoved,
+            Some(uri),
+            "child removed",
+            EventOrigin::Nexus,
+        );
 
         // Update child status to remove this child
         NexusChild::save_state_change();
@@ -261,6 +315,12 @@ impl Nexus {
 
         if let Some(child) = self.children.iter_mut().find(|c| c.name == name) {
             child.offline().await;
+            self.record_event(
+                NexusEventType::ChildOffline,
+                Some(name),
+                "child taken offline",
+                EventOrigin::Nexus,
+            );
         } else {
             return Err(Error::ChildNotFound {
                 name: self.name.clone(),
@@ -313,6 +373,16 @@ impl Nexus {
                     _ => {
                         child.fault(reason).await;
                         NexusChild::save_state_change();
+                        self.record_event(
+                            NexusEventType::ChildFaulted,
+                            Some(name),
+                            reason.to_string(),
+                            match reason {
+                                Reason::IoError => EventOrigin::Io,
+                                Reason::RebuildFailed => EventOrigin::Rebuild,
+                                _ => EventOrigin::Nexus,
+                            },
+                        );
                         self.reconfigure(DREvent::ChildFault).await;
                     }
                 }
@@ -339,11 +409,18 @@ impl Nexus {
     ) -> Result<NexusStatus, Error> {
         trace!("{} Online child request", self.name);
 
+        let uri = self.child_bdev_uri(name);
         if let Some(child) = self.children.iter_mut().find(|c| c.name == name) {
-            child.online(self.size).await.context(OpenChild {
+            child.online(self.size, &uri).await.context(OpenChild {
                 child: name.to_owned(),
                 name: self.name.clone(),
             })?;
+            self.record_event(
+                NexusEventType::ChildOnline,
+                Some(name),
+                "child brought online and requires a rebuild",
+                EventOrigin::Nexus,
+            );
             self.start_rebuild(name).await.map(|_| {})?;
             Ok(self.status())
         } else {
@@ -388,18 +465,51 @@ impl Nexus {
             });
         }
 
-        let blk_size = self.children[0].bdev.as_ref().unwrap().block_len();
-
-        if self
+        // the children share a layout in units of the largest block size
+        // among them, which only lines up with the blocks of the others when
+        // all of them are powers of two, i.e. 512 and 4096
+        let blk_sizes = self
             .children
             .iter()
-            .any(|b| b.bdev.as_ref().unwrap().block_len() != blk_size)
+            .map(|c| c.bdev.as_ref().unwrap().block_len())
+            .collect::<Vec<_>>();
+        let child_blk_size = *blk_sizes.iter().max().unwrap();
+
+        if blk_sizes
+            .iter()
+            .any(|b| !Self::child_blk_size_fits(*b, child_blk_size))
         {
             return Err(Error::MixedBlockSizes {
                 name: self.name.clone(),
             });
         }
 
+        // a block size which differs from that of the children can only be
+        // mapped onto them when both are powers of two, i.e. 512 on top of
+        // 4096 (emulated) or 4096 on top of 512
+        let blk_size = match self.requested_blk_size {
+            Some(blk_size)
+                if blk_size != child_blk_size
+                    && !child_blk_size.is_power_of_two() =>
+            {
+                return Err(Error::IncompatibleBlockSize {
+                    blk_size,
+                    child_blk_size,
+                    name: self.name.clone(),
+                });
+            }
+            Some(blk_size) => blk_size,
+            None => child_blk_size,
+        };
+
+        if blk_size < child_blk_size {
+            info!(
+                "{}: emulating {} byte blocks on top of {} byte child blocks",
+                self.name, blk_size, child_blk_size
+            );
+        }
+
+        self.child_blk_size = child_blk_size;
         self.bdev.set_block_len(blk_size);
 
         let size = self.size;
@@ -464,7 +574,18 @@ impl Nexus {
             .iter()
             .map(|child| futures.push(child.get_label()))
             .for_each(drop);
-        join_all(futures).await
+
+        // the labels are compared in units of child_blk_size, as children
+        // with smaller blocks have the same label laid out in their own
+        let mut list = join_all(futures).await;
+        for label in list.iter_mut() {
+            if let Some(bdev) = label.child.bdev.as_ref() {
+                label.label = label.label.take().map(|l| {
+                    l.with_block_size(bdev.block_len(), self.child_blk_size)
+                });
+            }
+        }
+        list
     }
 
     /// Update labels of child devices as required:
@@ -547,13 +668,17 @@ impl Nexus {
 
     /// The nexus is allowed to be smaller then the underlying child devices
     /// this function returns the smallest blockcnt of all online children as
-    /// they MAY vary in size.
+    /// they MAY vary in size. As the children may also vary in block size, it
+    /// is expressed in units of child_blk_size.
     pub(crate) fn min_num_blocks(&self) -> u64 {
         let mut blockcnt = std::u64::MAX;
         self.children
             .iter()
             .filter(|c| c.state() == ChildState::Open)
-            .map(|c| c.bdev.as_ref().unwrap().num_blocks())
+            .map(|c| {
+                c.bdev.as_ref().unwrap().size_in_bytes()
+                    / self.child_blk_size as u64
+            })
             .collect::<Vec<_>>()
             .iter()
             .map(|s| {
@@ -565,6 +690,18 @@ impl Nexus {
         blockcnt
     }
 
+    /// returns true if a child with blocks of `blk_size` can be laid out in
+    /// units of `child_blk_size`, the largest block size of the children
+    pub(crate) fn child_blk_size_fits(
+        blk_size: u32,
+        child_blk_size: u32,
+    ) -> bool {
+        blk_size == child_blk_size
+            || (blk_size.is_power_of_two()
+                && child_blk_size.is_power_of_two()
+                && blk_size < child_blk_size)
+    }
+
     /// lookup a child by its name
     pub fn child_lookup(&self, name: &str) -> Option<&NexusChild> {
         self.children
@@ -0,0 +1,453 @@
+//!
+//! Emulation of a nexus block size that is smaller than the block size of its
+//! children, i.e. 512 byte blocks (512e) on top of 4Kn children.
+//!
+//! Children may also differ in block size, eg a 512 byte aio child next to a
+//! 4Kn NVMe child. They then share a layout in units of the largest block
+//! size, which is scaled to the blocks of each child when submitting IO.
+//!
+//! IO which starts and ends on a child block boundary is passed on to the
+//! children as is. Reads of partial child blocks are served from a bounce
+//! buffer covering the whole child blocks. Writes are turned into a
+//! read-modify-write cycle of the partial child blocks at the head and the tail
+//! of the IO. To prevent concurrent writes to the same child block from
+//! overwriting each other, all writes of an emulating nexus take this path and
+//! are serialised per range of child blocks. This is considerably slower than
+//! native IO so children with a matching block size should be preferred.
+//! Children failing emulated IO are retired just like they are for native IO.
+
+use std::{convert::TryFrom, ops::Range, ptr, sync::Arc};
+
+use futures::{channel::oneshot, future::join_all};
+use snafu::ResultExt;
+
+use spdk_sys::{
+    iovec,
+    spdk_bdev_desc,
+    spdk_bdev_desc_get_bdev,
+    spdk_bdev_get_block_size,
+};
+
+use crate::{
+    bdev::{
+        nexus::{
+            nexus_bdev::{EmulatedIo, EmulationAlloc, Error, Nexus},
+            nexus_channel::NexusChannelInner,
+            nexus_io::{Bio, IoStatus},
+        },
+        VerboseError,
+    },
+    core::{Bdev, BdevHandle, Descriptor, Reactors},
+};
+
+/// Byte range of the data partition of the children which is covered by an
+/// IO, extended to whole child blocks.
+#[derive(Debug, Clone)]
+struct EmulatedSpan {
+    /// offset of the first child block
+    start: u64,
+    /// length of all child blocks
+    len: u64,
+    /// offset of the IO within the span
+    head: u64,
+    /// length of the IO
+    io_len: u64,
+}
+
+impl EmulatedSpan {
+    fn range(&self) -> Range<u64> {
+        self.start .. self.start + self.len
+    }
+
+    /// true if the IO does not start on a child block boundary
+    fn partial_head(&self) -> bool {
+        self.head != 0
+    }
+
+    /// true if the IO does not end on a child block boundary
+    fn partial_tail(&self) -> bool {
+        self.head + self.io_len != self.len
+    }
+}
+
+/// Byte ranges of the children which are being written by emulated IO, and
+/// the writes waiting for one of them to be unlocked.
+#[derive(Debug, Default)]
+pub(crate) struct EmulatedRanges {
+    locked: Vec<Range<u64>>,
+    waiters: Vec<oneshot::Sender<()>>,
+}
+
+/// An emulated IO which failed, along with the child it failed on if any.
+struct EmulationFailure {
+    child: Option<Bdev>,
+    error: Error,
+}
+
+impl From<Error> for EmulationFailure {
+    fn from(error: Error) -> Self {
+        Self {
+            child: None,
+            error,
+        }
+    }
+}
+
+impl Nexus {
+    /// returns true if the nexus exposes a smaller block size than that of
+    /// its children
+    pub fn is_emulated(&self) -> bool {
+        self.bdev.block_len() < self.child_blk_size
+    }
+
+    /// translate a range of nexus blocks into a range of child blocks
+    /// including the offset of the data partition. Returns None if the range
+    /// does not start and end on a child block boundary, which can only
+    /// happen when emulating.
+    #[inline]
+    pub(crate) fn child_range(
+        &self,
+        offset: u64,
+        num_blocks: u64,
+    ) -> Option<(u64, u64)> {
+        let blk_size = self.bdev.block_len() as u64;
+        let child_blk_size = self.child_blk_size as u64;
+
+        if blk_size == child_blk_size {
+            return Some((offset + self.data_ent_offset, num_blocks));
+        }
+
+        let start = offset * blk_size;
+        let len = num_blocks * blk_size;
+        if start % child_blk_size != 0 || len % child_blk_size != 0 {
+            return None;
+        }
+
+        Some((
+            start / child_blk_size + self.data_ent_offset,
+            len / child_blk_size,
+        ))
+    }
+
+    /// converts a block in units of child_blk_size into a block of a child
+    /// with blocks of `blk_size` bytes
+    pub(crate) fn child_lba(&self, blk_size: u32, lba: u64) -> u64 {
+        lba * u64::from(self.child_blk_size) / u64::from(blk_size)
+    }
+
+    /// converts a range of blocks in units of child_blk_size into blocks of
+    /// a child with blocks of `blk_size` bytes
+    pub(crate) fn child_blocks(
+        &self,
+        blk_size: u32,
+        range: Range<u64>,
+    ) -> Range<u64> {
+        self.child_lba(blk_size, range.start)
+            .. self.child_lba(blk_size, range.end)
+    }
+
+    /// scales a range of child blocks as returned by child_range() to the
+    /// blocks of the child the descriptor belongs to
+    #[inline]
+    pub(crate) fn child_io_range(
+        &self,
+        desc: *mut spdk_bdev_desc,
+        (offset, num_blocks): (u64, u64),
+    ) -> (u64, u64) {
+        let blk_size =
+            unsafe { spdk_bdev_get_block_size(spdk_bdev_desc_get_bdev(desc)) };
+        if blk_size == self.child_blk_size {
+            return (offset, num_blocks);
+        }
+        (
+            self.child_lba(blk_size, offset),
+            self.child_lba(blk_size, num_blocks),
+        )
+    }
+
+    /// returns the span of child blocks covered by the IO
+    fn emulated_span(&self, io: &Bio) -> EmulatedSpan {
+        let blk_size = self.bdev.block_len() as u64;
+        let child_blk_size = self.child_blk_size as u64;
+
+        let io_start = io.offset() * blk_size;
+        let io_len = io.num_blocks() * blk_size;
+        let start = io_start / child_blk_size * child_blk_size;
+        let end = (io_start + io_len + child_blk_size - 1) / child_blk_size
+            * child_blk_size;
+
+        EmulatedSpan {
+            start,
+            len: end - start,
+            head: io_start - start,
+            io_len,
+        }
+    }
+
+    /// byte offset of the data partition on the children
+    fn data_offset(&self) -> u64 {
+        self.data_ent_offset * self.child_blk_size as u64
+    }
+
+    /// read partial child blocks into a bounce buffer and copy the requested
+    /// part into the buffers of the IO
+    pub(crate) fn emulated_readv(&self, io: &Bio, reader: &BdevHandle) {
+        let desc = Arc::clone(&*reader.desc);
+        let mut io = io.clone();
+
+        Reactors::current().send_future(async move {
+            let nexus = io.nexus_as_ref();
+            let result = nexus.emulated_read(&io, desc).await;
+            Self::emulated_complete(&mut io, result);
+        });
+    }
+
+    /// write to all children, using a read-modify-write cycle for partial
+    /// child blocks
+    pub(crate) fn emulated_writev(
+        &self,
+        io: &Bio,
+        channels: &NexusChannelInner,
+    ) {
+        // partial blocks must be read from a child which is in sync
+        let reader = match channels.readers.first() {
+            Some(h) => Arc::clone(&*h.desc),
+            None => {
+                error!("{}: No child available to read from", self.name);
+                io.fail();
+                return;
+            }
+        };
+        let writers = channels
+            .writers
+            .iter()
+            .map(|h| Arc::clone(&*h.desc))
+            .collect::<Vec<_>>();
+
+        let mut io = io.clone();
+        Reactors::current().send_future(async move {
+            let nexus = io.nexus_as_ref();
+            let span = nexus.emulated_span(&io);
+
+            nexus.lock_emulated_range(span.range()).await;
+            let result =
+                nexus.emulated_write(&io, &span, writers, reader).await;
+            nexus.unlock_emulated_range(&span.range());
+
+            Self::emulated_complete(&mut io, result);
+        });
+    }
+
+    async fn emulated_read(
+        &self,
+        io: &Bio,
+        desc: Arc<Descriptor>,
+    ) -> Result<(), EmulationFailure> {
+        let span = self.emulated_span(io);
+        let child = desc.get_bdev();
+        let hdl = BdevHandle::try_from(desc).context(EmulatedIo {
+            name: self.name.clone(),
+        })?;
+
+        let mut buf = hdl.dma_malloc(span.len).context(EmulationAlloc {
+            name: self.name.clone(),
+        })?;
+        hdl.read_at(self.data_offset() + span.start, &mut buf)
+            .await
+            .context(EmulatedIo {
+                name: self.name.clone(),
+            })
+            .map_err(|error| EmulationFailure {
+                child: Some(child),
+                error,
+            })?;
+
+        let head = span.head as usize;
+        copy_to_iovs(io, &buf.as_slice()[head .. head + span.io_len as usize]);
+        Ok(())
+    }
+
+    async fn emulated_write(
+        &self,
+        io: &Bio,
+        span: &EmulatedSpan,
+        writers: Vec<Arc<Descriptor>>,
+        reader: Arc<Descriptor>,
+    ) -> Result<(), EmulationFailure> {
+        let child_blk_size = self.child_blk_size as u64;
+        let data_offset = self.data_offset();
+
+        let reader_bdev = reader.get_bdev();
+        let reader = BdevHandle::try_from(reader).context(EmulatedIo {
+            name: self.name.clone(),
+        })?;
+        let mut buf = reader.dma_malloc(span.len).context(EmulationAlloc {
+            name: self.name.clone(),
+        })?;
+
+        // read the partial child blocks at the head and the tail of the IO
+        let mut partial = Vec::new();
+        if span.partial_head() {
+            partial.push(0);
+        }
+        if span.partial_tail()
+            && (span.len > child_blk_size || partial.is_empty())
+        {
+            partial.push(span.len - child_blk_size);
+        }
+        if !partial.is_empty() {
+            let mut block =
+                reader.dma_malloc(child_blk_size).context(EmulationAlloc {
+                    name: self.name.clone(),
+                })?;
+            for offset in partial {
+                reader
+                    .read_at(data_offset + span.start + offset, &mut block)
+                    .await
+                    .context(EmulatedIo {
+                        name: self.name.clone(),
+                    })
+                    .map_err(|error| EmulationFailure {
+                        child: Some(reader_bdev.clone()),
+                        error,
+                    })?;
+                let offset = offset as usize;
+                buf.as_mut_slice()[offset .. offset + child_blk_size as usize]
+                    .copy_from_slice(block.as_slice());
+            }
+        }
+
+        // modify
+        let head = span.head as usize;
+        copy_from_iovs(
+            io,
+            &mut buf.as_mut_slice()[head .. head + span.io_len as usize],
+        );
+
+        // and write to all children
+        let handles = writers
+            .into_iter()
+            .map(BdevHandle::try_from)
+            .collect::<Result<Vec<_>, _>>()
+            .context(EmulatedIo {
+                name: self.name.clone(),
+            })?;
+        let results = join_all(
+            handles
+                .iter()
+                .map(|h| h.write_at(data_offset + span.start, &buf)),
+        )
+        .await;
+
+        for (handle, result) in handles.iter().zip(results) {
+            result
+                .context(EmulatedIo {
+                    name: self.name.clone(),
+                })
+                .map_err(|error| EmulationFailure {
+                    child: Some(handle.get_bdev()),
+                    error,
+                })?;
+        }
+        Ok(())
+    }
+
+    /// wait until no other emulated write overlaps the range and claim it
+    async fn lock_emulated_range(&self, range: Range<u64>) {
+        loop {
+            let unlocked = {
+                let mut ranges = self.emulated_ranges.lock().unwrap();
+                if !ranges
+                    .locked
+                    .iter()
+                    .any(|r| r.start < range.end && range.start < r.end)
+                {
+                    ranges.locked.push(range);
+                    return;
+                }
+                let (sender, receiver) = oneshot::channel();
+                ranges.waiters.push(sender);
+                receiver
+            };
+            // woken up whenever a range is unlocked, which may not be the
+            // one overlapping ours, hence check again
+            let _ = unlocked.await;
+        }
+    }
+
+    fn unlock_emulated_range(&self, range: &Range<u64>) {
+        let waiters = {
+            let mut ranges = self.emulated_ranges.lock().unwrap();
+            if let Some(index) = ranges.locked.iter().position(|r| r == range) {
+                ranges.locked.swap_remove(index);
+            }
+            std::mem::take(&mut ranges.waiters)
+        };
+        for waiter in waiters {
+            let _ = waiter.send(());
+        }
+    }
+
+    fn emulated_complete(io: &mut Bio, result: Result<(), EmulationFailure>) {
+        // there are no child IOs which are accounted for
+        io.reset(0);
+
+        if let Err(failure) = result {
+            let nexus = io.nexus_as_ref().name.clone();
+            error!(
+                "{}: emulated {:?} failed: {}",
+                nexus,
+                io.io_type(),
+                failure.error.verbose()
+            );
+
+            // the child which failed the IO is retired as it would be for
+            // native IO, and the IO is retried for as long as attempts are
+            // left, see Bio::complete()
+            if let Some(child) = failure.child {
+                Reactors::master().send_future(Bio::child_retire(nexus, child));
+            }
+            io.ctx_as_mut_ref().status = IoStatus::Failed;
+        }
+
+        io.complete();
+    }
+}
+
+/// copy the data from the buffers of the IO into buf
+fn copy_from_iovs(io: &Bio, buf: &mut [u8]) {
+    let mut copied = 0;
+    for iov in iovs(io) {
+        let len = std::cmp::min(iov.iov_len as usize, buf.len() - copied);
+        unsafe {
+            ptr::copy_nonoverlapping(
+                iov.iov_base as *const u8,
+                buf[copied ..].as_mut_ptr(),
+                len,
+            );
+        }
+        copied += len;
+    }
+}
+
+/// copy buf into the buffers of the IO
+fn copy_to_iovs(io: &Bio, buf: &[u8]) {
+    let mut copied = 0;
+    for iov in iovs(io) {
+        let len = std::cmp::min(iov.iov_len as usize, buf.len() - copied);
+        unsafe {
+            ptr::copy_nonoverlapping(
+                buf[copied ..].as_ptr(),
+                iov.iov_base as *mut u8,
+                len,
+            );
+        }
+        copied += len;
+    }
+}
+
+/// the iovecs describing the buffers of the IO
+fn iovs(io: &Bio) -> impl Iterator<Item = iovec> {
+    let iovs = io.iovs();
+    (0 .. io.iov_count() as usize).map(move |i| unsafe { *iovs.add(i) })
+}
@@ -1,10 +1,16 @@
-use futures::channel::oneshot::Receiver;
+use std::{sync::atomic::Ordering, time::Duration};
+
+use futures::channel::{mpsc, oneshot::Receiver};
 use snafu::ResultExt;
 
 use rpc::mayastor::{
+    RebuildLimits as RpcRebuildLimits,
+    RebuildMode as RpcRebuildMode,
+    RebuildPriority as RpcRebuildPriority,
     RebuildProgressReply,
     RebuildStateReply,
     RebuildStatsReply,
+    RebuildUpdate as RpcRebuildUpdate,
 };
 
 use crate::{
@@ -21,17 +27,23 @@ use crate::{
             },
             nexus_channel::DREvent,
             nexus_child::{ChildState, NexusChild, Reason},
+            nexus_journal::{EventOrigin, NexusEventType},
         },
         VerboseError,
     },
     core::Reactors,
     rebuild::{
         ClientOperations,
-        RebuildError,
+        RebuildCheckpoint,
         RebuildJob,
+        RebuildLimits,
+        RebuildMode,
+        RebuildPriority,
         RebuildState,
         RebuildStats,
+        RebuildUpdate,
     },
+    subsys::Config,
 };
 
 impl Nexus {
@@ -41,45 +53,123 @@ impl Nexus {
         &mut self,
         name: &str,
     ) -> Result<Receiver<RebuildState>, Error> {
-        trace!("{}: start rebuild request for {}", self.name, name);
+        self.start_shared_rebuild(&[name.to_string()]).await
+    }
+
+    /// Starts a single rebuild job for all of the given children, which
+    /// reads each segment from the source once and writes it to all of them.
+    /// Returns a receiver channel which can be used to await the completion
+    /// of the job.
+    pub async fn start_shared_rebuild(
+        &mut self,
+        names: &[String],
+    ) -> Result<Receiver<RebuildState>, Error> {
+        self.start_rebuild_with_options(
+            names,
+            Self::default_rebuild_mode(),
+            Config::get().nexus_opts.rebuild_verify,
+        )
+        .await
+    }
+
+    /// How rebuilds copy the segments unless requested otherwise
+    pub fn default_rebuild_mode() -> RebuildMode {
+        if Config::get().nexus_opts.rebuild_checksum {
+            RebuildMode::Checksum
+        } else {
+            RebuildMode::Full
+        }
+    }
+
+    /// Starts a single rebuild job for all of the given children, which
+    /// copies the segments according to the given mode and, if requested,
+    /// verifies the children once all segments have been copied
+    pub async fn start_rebuild_with_options(
+        &mut self,
+        names: &[String],
+        mode: RebuildMode,
+        verify: bool,
+    ) -> Result<Receiver<RebuildState>, Error> {
+        trace!(
+            "{}: start {} rebuild request for {:?}, verify: {}",
+            self.name,
+            mode,
+            names,
+            verify
+        );
+
+        let name = match names.first() {
+            Some(name) => name.as_str(),
+            None => {
+                return Err(Error::NoRebuildDestination {
+                    name: self.name.clone(),
+                })
+            }
+        };
 
-        let src_child_name = match self
+        // all healthy children can serve as a source, the rebuild switches
+        // over to the next one should reads from a source fail
+        let src_child_names = self
             .children
             .iter()
-            .find(|c| c.state() == ChildState::Open && c.name != name)
-        {
-            Some(child) => Ok(child.name.clone()),
-            None => Err(Error::NoRebuildSource {
+            .filter(|c| {
+                c.state() == ChildState::Open && !names.contains(&c.name)
+            })
+            .map(|c| c.name.clone())
+            .collect::<Vec<_>>();
+        if src_child_names.is_empty() {
+            return Err(Error::NoRebuildSource {
                 name: self.name.clone(),
-            }),
-        }?;
+            });
+        }
 
-        let dst_child_name =
-            match self.children.iter_mut().find(|c| c.name == name) {
+        let mut dst_blk_size = None;
+        for dst in names {
+            match self.children.iter().find(|c| &c.name == dst) {
                 Some(c)
                     if c.state() == ChildState::Faulted(Reason::OutOfSync) =>
                 {
-                    Ok(c.name.clone())
+                    // a job copies in units of the block size of its
+                    // destinations, which must therefore all be the same
+                    let blk_size = c.bdev.as_ref().map(|b| b.block_len());
+                    if dst_blk_size.is_some() && dst_blk_size != blk_size {
+                        return Err(Error::MixedBlockSizes {
+                            name: self.name.clone(),
+                        });
+                    }
+                    dst_blk_size = blk_size;
                 }
-                Some(c) => Err(Error::ChildNotDegraded {
-                    child: name.to_owned(),
-                    name: self.name.clone(),
-                    state: c.state().to_string(),
-                }),
-                None => Err(Error::ChildNotFound {
-                    child: name.to_owned(),
-                    name: self.name.clone(),
-                }),
-            }?;
+                Some(c) => {
+                    return Err(Error::ChildNotDegraded {
+                        child: dst.to_owned(),
+                        name: self.name.clone(),
+                        state: c.state().to_string(),
+                    })
+                }
+                None => {
+                    return Err(Error::ChildNotFound {
+                        child: dst.to_owned(),
+                        name: self.name.clone(),
+                    })
+                }
+            }
+        }
 
         let job = RebuildJob::create(
             &self.name,
-            &src_child_name,
-            &dst_child_name,
-            std::ops::Range::<u64> {
-                start: self.data_ent_offset,
-                end: self.bdev.num_blocks() + self.data_ent_offset,
-            },
+            &src_child_names,
+            name,
+            // the rebuild range is in units of the block size of the
+            // destinations, which may be smaller than child_blk_size
+            self.child_blocks(
+                dst_blk_size.unwrap_or(self.child_blk_size),
+                std::ops::Range::<u64> {
+                    start: self.data_ent_offset,
+                    end: self.data_ent_offset
+                        + self.bdev.num_blocks() * self.bdev.block_len() as u64
+                            / self.child_blk_size as u64,
+                },
+            ),
             |nexus, job| {
                 Reactors::current().send_future(async move {
                     Nexus::notify_rebuild(nexus, job).await;
@@ -91,6 +181,45 @@ impl Nexus {
             name: self.name.clone(),
         })?;
 
+        for dst in &names[1 ..] {
+            if let Err(e) = job.add_destination(dst) {
+                let _ = RebuildJob::remove(name);
+                return Err(Error::CreateRebuildError {
+                    child: dst.to_owned(),
+                    name: self.name.clone(),
+                    source: e,
+                });
+            }
+        }
+        job.as_client().set_priority(self.rebuild_priority());
+        if let Err(e) = job.set_mode(mode).and_then(|_| job.set_verify(verify))
+        {
+            let _ = RebuildJob::remove(name);
+            return Err(Error::RebuildOperationError {
+                job: name.to_owned(),
+                name: self.name.clone(),
+                source: e,
+            });
+        }
+        job.set_spread_reads(Config::get().nexus_opts.rebuild_spread_reads);
+
+        // The destinations have received all writes up to the point at which
+        // a previous rebuild was interrupted, eg by a restart, as long as no
+        // writes have been issued since the nexus was opened and no other
+        // nexus has opened the sources in between.
+        let resume = !self.written.load(Ordering::Relaxed)
+            && self.sources_hold_generation().await;
+        let resumed = job
+            .use_checkpoints(
+                self.child_lba(
+                    dst_blk_size.unwrap_or(self.child_blk_size),
+                    self.meta_ent_offset,
+                ),
+                self.generation,
+                resume,
+            )
+            .await;
+
         // We're now rebuilding the `dst_child` which means it HAS to become an
         // active participant in the frontend nexus bdev for Writes.
         // This is because the rebuild job copies from src to target child
@@ -101,10 +230,33 @@ impl Nexus {
         // rebuilt ranges in sync with the other children.
         self.reconfigure(DREvent::ChildRebuild).await;
 
-        job.as_client().start().context(RebuildOperationError {
-            job: name.to_owned(),
-            name: self.name.clone(),
-        })
+        let receiver = match job.as_client().start() {
+            Ok(receiver) => receiver,
+            Err(e) => {
+                let _ = RebuildJob::remove(name);
+                return Err(Error::RebuildOperationError {
+                    job: name.to_owned(),
+                    name: self.name.clone(),
+                    source: e,
+                });
+            }
+        };
+
+        for dst in names {
+            self.record_event(
+                NexusEventType::RebuildStarted,
+                Some(dst),
+                match resumed {
+                    Some(blk) => format!(
+                        "{} rebuild from {}, resuming at block {}",
+                        mode, job.source, blk
+                    ),
+                    None => format!("{} rebuild from {}", mode, job.source),
+                },
+                EventOrigin::Nexus,
+            );
+        }
+        Ok(receiver)
     }
 
     /// Terminates a rebuild in the background
@@ -115,6 +267,11 @@ impl Nexus {
         // If a rebuild job is not found that's ok
         // as we were just going to remove it anyway.
         if let Ok(rj) = self.get_rebuild_job(name) {
+            // a shared job carries on with its other destinations
+            if rj.remove_destination(name) {
+                return;
+            }
+
             let ch = rj.as_client().terminate();
             if let Err(e) = ch.await {
                 error!(
@@ -125,13 +282,30 @@ impl Nexus {
         }
     }
 
-    /// Stop a rebuild job in the background
-    pub async fn stop_rebuild(&self, name: &str) -> Result<(), Error> {
+    /// Stop a rebuild job in the background. Stopping the rebuild of an
+    /// additional destination of a shared job leaves the job running for its
+    /// other destinations.
+    pub async fn stop_rebuild(&mut self, name: &str) -> Result<(), Error> {
         match self.get_rebuild_job(name) {
-            Ok(rj) => rj.as_client().stop().context(RebuildOperationError {
-                job: name.to_owned(),
-                name: self.name.clone(),
-            }),
+            Ok(rj) => {
+                if rj.remove_destination(name) {
+                    let result = self
+                        .on_rebuild_complete_child(
+                            rj,
+                            name,
+                            RebuildState::Stopped,
+                            String::new(),
+                        )
+                        .await;
+                    self.reconfigure(DREvent::ChildRebuild).await;
+                    return result;
+                }
+
+                rj.as_client().stop().context(RebuildOperationError {
+                    job: name.to_owned(),
+                    name: self.name.clone(),
+                })
+            }
             // If a rebuild task is not found return ok
             // as we were just going to remove it anyway.
             Err(_) => Ok(()),
@@ -156,6 +330,60 @@ impl Nexus {
         })
     }
 
+    /// Set the rate limits of a rebuild job
+    pub async fn set_rebuild_limits(
+        &self,
+        name: &str,
+        limits: RebuildLimits,
+    ) -> Result<(), Error> {
+        self.get_rebuild_job(name)?.as_client().set_limits(limits);
+        Ok(())
+    }
+
+    /// Set the priority class of a rebuild job
+    pub async fn set_rebuild_priority(
+        &self,
+        name: &str,
+        priority: RebuildPriority,
+    ) -> Result<(), Error> {
+        self.get_rebuild_job(name)?
+            .as_client()
+            .set_priority(priority);
+        Ok(())
+    }
+
+    /// Rebuilds are urgent when the nexus has only a single healthy child
+    /// left, as losing it would mean losing the data
+    fn rebuild_priority(&self) -> RebuildPriority {
+        let healthy = self
+            .children
+            .iter()
+            .filter(|c| c.state() == ChildState::Open)
+            .count();
+
+        if healthy <= 1 {
+            RebuildPriority::Urgent
+        } else {
+            RebuildPriority::Normal
+        }
+    }
+
+    /// Raise the priority of the rebuild jobs of the nexus to urgent when
+    /// the number of healthy children has dropped to one
+    pub(crate) fn update_rebuild_priorities(&self) {
+        if self.rebuild_priority() != RebuildPriority::Urgent {
+            return;
+        }
+
+        self.children
+            .iter()
+            .filter_map(|c| RebuildJob::lookup(&c.name).ok())
+            .filter(|job| job.priority() < RebuildPriority::Urgent)
+            .for_each(|job| {
+                job.as_client().set_priority(RebuildPriority::Urgent)
+            });
+    }
+
     /// Return the state of a rebuild job
     pub async fn get_rebuild_state(
         &mut self,
@@ -173,7 +401,7 @@ impl Nexus {
         name: &str,
     ) -> Result<RebuildStatsReply, Error> {
         let rj = self.get_rebuild_job(name)?;
-        Ok(rj.stats().into())
+        Ok(rj.destination_stats(name).into())
     }
 
     /// Returns the rebuild progress of child target `name`
@@ -184,21 +412,46 @@ impl Nexus {
         let rj = self.get_rebuild_job(name)?;
 
         Ok(RebuildProgressReply {
-            progress: rj.as_client().stats().progress as u32,
+            progress: rj.destination_stats(name).progress as u32,
+        })
+    }
+
+    /// Watches the rebuild of child target `name`, the returned channel
+    /// receives an update on every state change and every `interval`
+    pub fn watch_rebuild(
+        &self,
+        name: &str,
+        interval: Duration,
+    ) -> Result<mpsc::UnboundedReceiver<RebuildUpdate>, Error> {
+        self.get_rebuild_job(name)?;
+        RebuildJob::watch(name, interval).context(RebuildOperationError {
+            job: name.to_owned(),
+            name: self.name.clone(),
         })
     }
 
     /// Cancels all rebuilds jobs associated with the child.
     /// Returns a list of rebuilding children whose rebuild job was cancelled.
     pub async fn cancel_child_rebuild_jobs(&self, name: &str) -> Vec<String> {
+        // jobs reading from the child, be it their current source or one
+        // they spread their reads across, switch over to their other sources
+        self.children
+            .iter()
+            .filter_map(|c| RebuildJob::lookup(&c.name).ok())
+            .for_each(|job| {
+                job.remove_source(name);
+            });
+
+        // which leaves the child as the source of those jobs only which have
+        // no healthy source left
         let mut src_jobs = self.get_rebuild_job_src(name);
         let mut terminated_jobs = Vec::new();
         let mut rebuilding_children = Vec::new();
 
-        // terminate all jobs with the child as a source
+        // terminate all jobs left with the child as their source
         src_jobs.iter_mut().for_each(|j| {
             terminated_jobs.push(j.as_client().terminate());
-            rebuilding_children.push(j.destination.clone());
+            rebuilding_children.extend(Self::healthy_destinations(j));
         });
 
         // wait for the jobs to complete terminating
@@ -208,14 +461,43 @@ impl Nexus {
             }
         }
 
-        // terminate the only possible job with the child as a destination
+        // terminate the only possible job with the child as a destination,
+        // unless it is an additional destination of a shared job in which
+        // case the job carries on with its other destinations
+        if let Ok(j) = self.get_rebuild_job(name) {
+            if j.destination == name {
+                rebuilding_children.extend(
+                    Self::healthy_destinations(j)
+                        .into_iter()
+                        .filter(|d| d != name),
+                );
+            }
+        }
         self.terminate_rebuild(name).await;
         rebuilding_children
     }
 
-    /// Start a rebuild for each of the children
+    /// Destinations of a job which have not failed
+    fn healthy_destinations(job: &RebuildJob) -> Vec<String> {
+        job.destinations()
+            .into_iter()
+            .filter(|d| job.destination_error(d).is_none())
+            .collect()
+    }
+
+    /// Start rebuilding the children, using a single job to rebuild all of
+    /// them. Should that fail, a job is started for each of the children.
     /// todo: how to proceed if no healthy child is found?
     pub async fn start_rebuild_jobs(&mut self, child_names: Vec<String>) {
+        if child_names.len() > 1 {
+            match self.start_shared_rebuild(&child_names).await {
+                Ok(_) => return,
+                Err(e) => {
+                    error!("Failed to start shared rebuild: {}", e.verbose())
+                }
+            }
+        }
+
         for name in child_names {
             if let Err(e) = self.start_rebuild(&name).await {
                 error!("Failed to start rebuild: {}", e.verbose());
@@ -250,15 +532,68 @@ impl Nexus {
         Ok(job)
     }
 
-    /// On rebuild job completion it updates the child and the nexus
+    /// On rebuild job completion it updates the children and the nexus
     /// based on the rebuild job's final state
     async fn on_rebuild_complete_job(
         &mut self,
         job: &RebuildJob,
     ) -> Result<(), Error> {
-        let recovering_child = self.get_child_by_name(&job.destination)?;
+        let mut result = Ok(());
+        for destination in job.destinations() {
+            let (state, error) = match job.destination_error(&destination) {
+                Some(e) => (RebuildState::Failed, e.verbose()),
+                None => (job.state(), job.error_desc()),
+            };
+            let r = self
+                .on_rebuild_complete_child(job, &destination, state, error)
+                .await;
+            if result.is_ok() {
+                result = r;
+            }
+        }
+
+        self.reconfigure(DREvent::ChildRebuild).await;
+        result
+    }
+
+    /// Updates a child rebuilt by a job based on the final state of its
+    /// rebuild
+    async fn on_rebuild_complete_child(
+        &mut self,
+        job: &RebuildJob,
+        name: &str,
+        state: RebuildState,
+        error: String,
+    ) -> Result<(), Error> {
+        // the checkpoint block is in units of child_blk_size
+        let checkpoint_bytes =
+            self.meta_ent_offset * self.child_blk_size as u64;
+        let recovering_child = self.get_child_by_name(name)?;
+
+        // the failure of a destination of a shared job has been dealt with
+        // while the job carried on
+        if state == RebuildState::Failed
+            && recovering_child.state()
+                == ChildState::Faulted(Reason::RebuildFailed)
+        {
+            return Ok(());
+        }
 
-        match job.state() {
+        // the checkpoint must not outlive the job, as the child stops
+        // receiving writes unless it has been rebuilt
+        if let Ok((bdev, hdl)) = recovering_child.get_dev() {
+            let checkpoint_lba = checkpoint_bytes / bdev.block_len() as u64;
+            if let Err(e) = RebuildCheckpoint::clear(&hdl, checkpoint_lba).await
+            {
+                warn!(
+                    "Failed to clear the rebuild checkpoint of child {}: {}",
+                    recovering_child.name,
+                    e.verbose()
+                );
+            }
+        }
+
+        let (event, reason) = match state {
             RebuildState::Completed => {
                 recovering_child.set_state(ChildState::Open);
                 NexusChild::save_state_change();
@@ -266,42 +601,49 @@ impl Nexus {
                     "Child {} has been rebuilt successfully",
                     recovering_child.name
                 );
+                (
+                    NexusEventType::RebuildCompleted,
+                    format!("rebuilt from {}", job.source),
+                )
             }
             RebuildState::Stopped => {
                 info!(
                     "Rebuild job for child {} of nexus {} stopped",
-                    &job.destination, &self.name,
+                    name, &self.name,
                 );
+                (NexusEventType::RebuildStopped, "rebuild stopped".into())
             }
             RebuildState::Failed => {
                 // rebuild has failed so we need to set the child as faulted
                 // allowing the control plane to replace it with another
-                if let Some(RebuildError::ReadIoError {
-                    ..
-                }) = job.error
-                {
-                    // todo: retry rebuild using another child as source?
-                }
                 recovering_child.fault(Reason::RebuildFailed).await;
                 error!(
                     "Rebuild job for child {} of nexus {} failed, error: {}",
-                    &job.destination,
-                    &self.name,
-                    job.error_desc(),
+                    name, &self.name, error,
                 );
+                (NexusEventType::RebuildFailed, error)
             }
             _ => {
                 recovering_child.fault(Reason::RebuildFailed).await;
                 error!(
                     "Rebuild job for child {} of nexus {} failed with state {:?}",
-                    &job.destination,
+                    name,
                     &self.name,
-                    job.state(),
+                    state,
                 );
+                (
+                    NexusEventType::RebuildFailed,
+                    format!("rebuild ended in state {}", state),
+                )
             }
+        };
+
+        // the child can now serve as a source of the rebuilds to come
+        if state == RebuildState::Completed {
+            self.persist_generation(name).await;
         }
 
-        self.reconfigure(DREvent::ChildRebuild).await;
+        self.record_event(event, Some(name), reason, EventOrigin::Rebuild);
         Ok(())
     }
 
@@ -312,7 +654,27 @@ impl Nexus {
         })?;
 
         if !j.state().done() {
-            // Leave all states as they are
+            // a destination of a shared job failed while the job carries on
+            // with its other destinations, leave all other states as they are
+            if let Some(e) = j.destination_error(&job) {
+                let error = e.verbose();
+                let result = self
+                    .on_rebuild_complete_child(
+                        j,
+                        &job,
+                        RebuildState::Failed,
+                        error,
+                    )
+                    .await;
+                self.reconfigure(DREvent::ChildRebuild).await;
+                return result;
+            }
+            return Ok(());
+        }
+
+        // failed destinations of a shared job notify the job under their
+        // own name, the completion of the job is handled once
+        if j.destination != job {
             return Ok(());
         }
 
@@ -354,6 +716,80 @@ impl From<RebuildStats> for RebuildStatsReply {
             block_size: stats.block_size,
             tasks_total: stats.tasks_total,
             tasks_active: stats.tasks_active,
+            priority: RpcRebuildPriority::from(stats.priority) as i32,
+            limits: Some(stats.limits.into()),
+            source: stats.source,
+            source_switches: stats.source_switches,
+            destinations: stats.destinations,
+            error: stats.error.unwrap_or_default(),
+            mode: RpcRebuildMode::from(stats.mode) as i32,
+            segments_skipped: stats.segments_skipped,
+            verify: stats.verify,
+            segments_verified: stats.segments_verified,
+            verify_mismatches: stats.verify_mismatches,
+            start_time: stats
+                .start_time
+                .map(|t| t.to_rfc3339())
+                .unwrap_or_default(),
+            throughput: stats.throughput,
+        }
+    }
+}
+
+impl From<RebuildPriority> for RpcRebuildPriority {
+    fn from(priority: RebuildPriority) -> Self {
+        match priority {
+            RebuildPriority::Low => RpcRebuildPriority::RebuildLow,
+            RebuildPriority::Normal => RpcRebuildPriority::RebuildNormal,
+            RebuildPriority::Urgent => RpcRebuildPriority::RebuildUrgent,
+        }
+    }
+}
+
+impl From<RpcRebuildPriority> for RebuildPriority {
+    fn from(priority: RpcRebuildPriority) -> Self {
+        match priority {
+            RpcRebuildPriority::RebuildLow => RebuildPriority::Low,
+            RpcRebuildPriority::RebuildNormal => RebuildPriority::Normal,
+            RpcRebuildPriority::RebuildUrgent => RebuildPriority::Urgent,
+        }
+    }
+}
+
+impl From<RebuildUpdate> for RpcRebuildUpdate {
+    fn from(update: RebuildUpdate) -> Self {
+        RpcRebuildUpdate {
+            state: update.state.to_string(),
+            stats: Some(update.stats.into()),
+            throughput: update.throughput,
+            eta_secs: update.eta.map_or(-1, |eta| eta.as_secs() as i64),
+        }
+    }
+}
+
+impl From<RebuildMode> for RpcRebuildMode {
+    fn from(mode: RebuildMode) -> Self {
+        match mode {
+            RebuildMode::Full => RpcRebuildMode::RebuildFull,
+            RebuildMode::Checksum => RpcRebuildMode::RebuildChecksum,
+        }
+    }
+}
+
+impl From<RebuildLimits> for RpcRebuildLimits {
+    fn from(limits: RebuildLimits) -> Self {
+        RpcRebuildLimits {
+            bandwidth: limits.bandwidth,
+            segments: limits.segments,
+        }
+    }
+}
+
+impl From<RpcRebuildLimits> for RebuildLimits {
+    fn from(limits: RpcRebuildLimits) -> Self {
+        RebuildLimits {
+            bandwidth: limits.bandwidth,
+            segments: limits.segments,
         }
     }
 }
@@ -1,11 +1,25 @@
 //! Implements snapshot operations on a nexus.
+//!
+//! A snapshot of a nexus is created on all its children at the same time, the
+//! snapshot of each replica is named after the replica and the creation time
+//! of the snapshot. Listing, destroying and reverting to a snapshot is done on
+//! all children of the nexus which are open, directly for local replicas and
+//! through custom NVMe admin commands for remote replicas.
 
-use rpc::mayastor::CreateSnapshotReply;
+use std::{collections::BTreeMap, convert::TryFrom};
+
+use rpc::mayastor::{CreateSnapshotReply, ListSnapshotsReply, Snapshot};
 
 use crate::{
-    bdev::nexus::nexus_bdev::{Error, Nexus},
-    core::BdevHandle,
-    lvs::Lvol,
+    bdev::{
+        nexus::{
+            nexus_bdev::{Error, Nexus},
+            nexus_child::{ChildState, NexusChild, Reason},
+        },
+        VerboseError,
+    },
+    core::{BdevHandle, CoreError},
+    lvs::{Error as LvsError, Lvol},
 };
 
 impl Nexus {
@@ -25,4 +39,219 @@ impl Nexus {
             Err(Error::FailedGetHandle)
         }
     }
+
+    /// List the snapshots of the nexus, oldest first. A snapshot is complete
+    /// if all open children have it
+    pub async fn list_snapshots(&self) -> Result<ListSnapshotsReply, Error> {
+        let children = self.snapshot_children().collect::<Vec<_>>();
+        let mut snapshots = BTreeMap::<u64, usize>::new();
+        for child in &children {
+            for time in self.child_snapshots(child).await? {
+                *snapshots.entry(time).or_default() += 1;
+            }
+        }
+
+        Ok(ListSnapshotsReply {
+            snapshots: snapshots
+                .into_iter()
+                .map(|(time, count)| Snapshot {
+                    name: Lvol::format_snapshot_name(&self.bdev.name(), time),
+                    created: time,
+                    complete: count == children.len(),
+                })
+                .collect(),
+        })
+    }
+
+    /// Destroy the snapshot `name` on all children which have it
+    pub async fn destroy_snapshot(&self, name: &str) -> Result<(), Error> {
+        let time = self.snapshot_time(name)?;
+
+        let mut found = false;
+        for child in self.snapshot_children() {
+            if !self.child_snapshots(child).await?.contains(&time) {
+                continue;
+            }
+            found = true;
+
+            let hdl = self.child_handle(child)?;
+            match Lvol::try_from(hdl.get_bdev()) {
+                Ok(lvol) => lvol
+                    .destroy_snapshot(time)
+                    .await
+                    .map_err(|e| self.replica_snapshot_err(child, e))?,
+                Err(_) => hdl
+                    .destroy_snapshot(time)
+                    .await
+                    .map_err(|e| self.child_snapshot_err(child, e))?,
+            }
+        }
+
+        if !found {
+            return Err(self.snapshot_not_found(name));
+        }
+
+        info!("{}: destroyed snapshot {}", self.name, name);
+        Ok(())
+    }
+
+    /// Revert all children to the snapshot `name`, which all open children
+    /// must have. IO to the nexus is paused while the children are reverted.
+    /// Should a child fail to revert, the children which are not in sync with
+    /// the others are faulted before IO resumes, which are those which have
+    /// not been reverted or, if none has, the child which failed to.
+    pub async fn revert_to_snapshot(
+        &mut self,
+        name: &str,
+    ) -> Result<(), Error> {
+        let time = self.snapshot_time(name)?;
+
+        // a child being rebuilt would be rebuilt from reverted and not yet
+        // reverted children
+        if self.children.iter().any(|c| c.rebuilding()) {
+            return Err(Error::RevertWhileRebuilding {
+                name: self.name.clone(),
+            });
+        }
+
+        let children = self
+            .snapshot_children()
+            .map(|c| c.name.clone())
+            .collect::<Vec<_>>();
+        for child_name in &children {
+            let child = self.snapshot_child(child_name);
+            if !self.child_snapshots(child).await?.contains(&time) {
+                return Err(self.snapshot_not_found(name));
+            }
+        }
+
+        self.pause().await?;
+        let mut reverted = Vec::new();
+        let mut result = Ok(());
+        for child_name in &children {
+            let child = self.snapshot_child(child_name);
+            result = match self.child_handle(child) {
+                Ok(hdl) => match Lvol::try_from(hdl.get_bdev()) {
+                    Ok(lvol) => lvol
+                        .revert_to_snapshot(time)
+                        .await
+                        .map_err(|e| self.replica_snapshot_err(child, e)),
+                    Err(_) => hdl
+                        .revert_snapshot(time)
+                        .await
+                        .map_err(|e| self.child_snapshot_err(child, e)),
+                },
+                Err(e) => Err(e),
+            };
+            if result.is_err() {
+                break;
+            }
+            reverted.push(child_name.clone());
+        }
+
+        if result.is_err() {
+            let out_of_sync = if reverted.is_empty() {
+                children[.. 1].to_vec()
+            } else {
+                children[reverted.len() ..].to_vec()
+            };
+            for child in out_of_sync {
+                if let Err(e) =
+                    self.fault_child(&child, Reason::OutOfSync).await
+                {
+                    error!(
+                        "{}: failed to fault child {} which is out of sync after a failed revert: {}",
+                        self.name,
+                        child,
+                        e.verbose()
+                    );
+                }
+            }
+        }
+        self.resume().await?;
+
+        if result.is_ok() {
+            info!("{}: reverted to snapshot {}", self.name, name);
+        }
+        result
+    }
+
+    /// returns the creation time of the snapshot `name` of the nexus
+    fn snapshot_time(&self, name: &str) -> Result<u64, Error> {
+        Lvol::snapshot_time(&self.bdev.name(), name).ok_or_else(|| {
+            Error::InvalidSnapshotName {
+                snapshot: name.to_string(),
+                name: self.name.clone(),
+            }
+        })
+    }
+
+    /// returns the children of the nexus which hold its snapshots
+    fn snapshot_children(&self) -> impl Iterator<Item = &NexusChild> {
+        self.children
+            .iter()
+            .filter(|c| c.state() == ChildState::Open)
+    }
+
+    /// returns the child `name` which holds the snapshots of the nexus
+    fn snapshot_child(&self, name: &str) -> &NexusChild {
+        self.children.iter().find(|c| c.name == name).unwrap()
+    }
+
+    /// returns a handle to `child`
+    fn child_handle(&self, child: &NexusChild) -> Result<BdevHandle, Error> {
+        child
+            .handle()
+            .map_err(|e| self.child_snapshot_err(child, e))
+    }
+
+    /// returns the creation times of the snapshots of `child`
+    async fn child_snapshots(
+        &self,
+        child: &NexusChild,
+    ) -> Result<Vec<u64>, Error> {
+        let hdl = self.child_handle(child)?;
+        let bdev = hdl.get_bdev();
+        if let Ok(lvol) = Lvol::try_from(bdev.clone()) {
+            Ok(lvol.snapshots().into_iter().map(|(t, _)| t).collect())
+        } else if bdev.driver() == "nvme" {
+            hdl.list_snapshots()
+                .await
+                .map_err(|e| self.child_snapshot_err(child, e))
+        } else {
+            // only replicas have snapshots
+            Ok(Vec::new())
+        }
+    }
+
+    fn snapshot_not_found(&self, name: &str) -> Error {
+        Error::SnapshotNotFound {
+            snapshot: name.to_string(),
+            name: self.name.clone(),
+        }
+    }
+
+    fn child_snapshot_err(
+        &self,
+        child: &NexusChild,
+        source: CoreError,
+    ) -> Error {
+        Error::FailedChildSnapshot {
+            child: child.name.clone(),
+            name: self.name.clone(),
+            source,
+        }
+    }
+
+    fn replica_snapshot_err(
+        &self,
+        child: &NexusChild,
+        source: LvsError,
+    ) -> Error {
+        Error::FailedReplicaSnapshot {
+            child: child.name.clone(),
+            name: self.name.clone(),
+            source,
+        }
+    }
 }
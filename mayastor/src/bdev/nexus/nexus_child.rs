@@ -279,22 +279,22 @@ impl NexusChild {
         NexusChild::save_state_change();
     }
 
-    /// Online a previously offlined child.
+    /// Online a previously offlined child, re-creating its bdev from `uri`.
     /// The child is set out-of-sync so that it will be rebuilt.
     /// TODO: channels need to be updated when bdevs are opened
     pub(crate) async fn online(
         &mut self,
         parent_size: u64,
+        uri: &str,
     ) -> Result<String, ChildError> {
         // Only online a child if it was previously set offline. Check for a
         // "Closed" state as that is what offlining a child will set it to.
         match self.state.load() {
             ChildState::Closed => {
                 // Re-create the bdev as it will have been previously destroyed.
-                let name =
-                    bdev_create(&self.name).await.context(ChildBdevCreate {
-                        child: self.name.clone(),
-                    })?;
+                let name = bdev_create(uri).await.context(ChildBdevCreate {
+                    child: self.name.clone(),
+                })?;
                 self.bdev = Bdev::lookup_by_name(&name);
             }
             _ => return Err(ChildError::ChildNotClosed {}),
@@ -481,7 +481,7 @@ impl NexusChild {
     /// Return the rebuild progress on this child, if rebuilding
     pub fn get_rebuild_progress(&self) -> i32 {
         self.get_rebuild_job()
-            .map(|j| j.stats().progress as i32)
+            .map(|j| j.destination_stats(&self.name).progress as i32)
             .unwrap_or_else(|| -1)
     }
 
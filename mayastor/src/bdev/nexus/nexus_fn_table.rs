@@ -1,4 +1,7 @@
-use std::ffi::{c_void, CString};
+use std::{
+    ffi::{c_void, CString},
+    sync::atomic::Ordering,
+};
 
 use once_cell::sync::Lazy;
 
@@ -125,6 +128,20 @@ impl NexusFnTable {
 
         let nexus = nio.nexus_as_ref();
         let io_type = nio.io_type();
+
+        if matches!(io_type, IoType::Write | IoType::Unmap | IoType::WriteZeros)
+        {
+            if nexus.is_read_only() {
+                trace!("{}: rejecting {:?}, read-only", nexus.name, io_type);
+                nio.fail_write_protected();
+                return;
+            }
+            // avoid dirtying the cache line on every write
+            if !nexus.written.load(Ordering::Relaxed) {
+                nexus.written.store(true, Ordering::Relaxed);
+            }
+        }
+
         match io_type {
             IoType::Read => nexus.readv(&nio, &mut ch),
             IoType::Write => nexus.writev(&nio, &ch),
@@ -0,0 +1,248 @@
+//!
+//! The write generation of a nexus tells whether its children may have been
+//! written to by someone else since a rebuild was interrupted, in which case
+//! the destination can not be resumed from its checkpoint.
+//!
+//! Each time the nexus is opened, the generation is bumped and persisted on
+//! its healthy children, in the last block of the "MayaMeta" partition. A
+//! rebuild checkpoint records the generation it was written at, and a rebuild
+//! only resumes from it when the sources still hold the generation of the
+//! nexus, which is either that of the checkpoint or the one following it.
+//! Another nexus which opened the sources in between would have bumped their
+//! generation once more.
+
+use std::io::Cursor;
+
+use bincode::{deserialize_from, serialize_into};
+use crc::crc32;
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bdev::{
+        nexus::{
+            nexus_bdev::Nexus,
+            nexus_child::{ChildState, NexusChild},
+            nexus_metadata::MetaDataError,
+        },
+        VerboseError,
+    },
+    core::BdevHandle,
+};
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+struct WriteGeneration {
+    /// signature identifying this as a write generation
+    signature: [u8; 8],
+    /// CRC-32 checksum of the generation with the checksum set to 0
+    checksum: u32,
+    /// name of the nexus the generation belongs to
+    nexus: String,
+    /// number of times the nexus has been opened
+    generation: u64,
+}
+
+impl WriteGeneration {
+    const SIGNATURE: [u8; 8] = *b"MayaWgen";
+
+    fn checksum(&self) -> u32 {
+        let mut copy = self.clone();
+        copy.checksum = 0;
+        crc32::checksum_ieee(&bincode::serialize(&copy).unwrap_or_default())
+    }
+}
+
+impl NexusChild {
+    /// Read the write generation of the nexus persisted at byte `offset` of
+    /// the child. Returns None if it holds none, or one of another nexus.
+    async fn read_generation(
+        &self,
+        hdl: &BdevHandle,
+        offset: u64,
+    ) -> Result<Option<u64>, MetaDataError> {
+        let block_size = hdl.get_bdev().block_len() as u64;
+        let mut buf = hdl.dma_malloc(block_size).map_err(|source| {
+            MetaDataError::ReadAlloc {
+                name: String::from("write generation"),
+                source,
+            }
+        })?;
+        hdl.read_at(offset, &mut buf).await.map_err(|source| {
+            MetaDataError::ReadError {
+                name: String::from("write generation"),
+                source,
+            }
+        })?;
+
+        let generation: WriteGeneration =
+            match deserialize_from(&mut Cursor::new(buf.as_slice())) {
+                Ok(generation) => generation,
+                Err(_) => return Ok(None),
+            };
+
+        if generation.signature != WriteGeneration::SIGNATURE
+            || generation.checksum != generation.checksum()
+            || generation.nexus != self.parent
+        {
+            return Ok(None);
+        }
+
+        Ok(Some(generation.generation))
+    }
+
+    /// Persist the write generation of the nexus at byte `offset` of the
+    /// child
+    async fn write_generation(
+        &self,
+        hdl: &BdevHandle,
+        offset: u64,
+        generation: u64,
+    ) -> Result<(), MetaDataError> {
+        let block_size = hdl.get_bdev().block_len() as u64;
+
+        let mut generation = WriteGeneration {
+            signature: WriteGeneration::SIGNATURE,
+            checksum: 0,
+            nexus: self.parent.clone(),
+            generation,
+        };
+        generation.checksum = generation.checksum();
+
+        let mut buf = hdl.dma_malloc(block_size).map_err(|source| {
+            MetaDataError::WriteAlloc {
+                name: String::from("write generation"),
+                source,
+            }
+        })?;
+        buf.fill(0);
+        serialize_into(&mut Cursor::new(buf.as_mut_slice()), &generation)
+            .map_err(|source| MetaDataError::SerializeError {
+                source,
+            })?;
+
+        hdl.write_at(offset, &buf).await.map_err(|source| {
+            MetaDataError::WriteError {
+                name: String::from("write generation"),
+                source,
+            }
+        })?;
+        Ok(())
+    }
+}
+
+impl Nexus {
+    /// byte offset of the write generation on a child with blocks of
+    /// `blk_size` bytes, the last block of the "MayaMeta" partition
+    fn generation_offset(&self, blk_size: u32) -> u64 {
+        self.meta_ent_offset * u64::from(self.child_blk_size)
+            + Nexus::METADATA_PARTITION_SIZE
+            - u64::from(blk_size)
+    }
+
+    /// The write generation held by a child, None if it holds none or fails
+    /// to read it
+    async fn child_generation(&self, child: &NexusChild) -> Option<u64> {
+        let result = async {
+            let (bdev, hdl) = child.get_dev().map_err(|source| {
+                MetaDataError::NexusChildError {
+                    source,
+                }
+            })?;
+            child
+                .read_generation(&hdl, self.generation_offset(bdev.block_len()))
+                .await
+        }
+        .await;
+
+        result.unwrap_or_else(|e| {
+            warn!(
+                "{}: failed to read the write generation of child {}: {}",
+                self.name,
+                child.name,
+                e.verbose()
+            );
+            None
+        })
+    }
+
+    /// The write generations held by the healthy children, None for those
+    /// holding none or failing to read it
+    async fn child_generations(&self) -> Vec<Option<u64>> {
+        join_all(
+            self.children
+                .iter()
+                .filter(|c| c.state() == ChildState::Open)
+                .map(|child| self.child_generation(child)),
+        )
+        .await
+    }
+
+    /// Bump the write generation of the nexus and persist it on its healthy
+    /// children, which must be done before the nexus receives any IO. Should
+    /// that fail, the sources no longer hold the generation of the nexus and
+    /// the rebuilds of its children start from scratch.
+    pub(crate) async fn bump_generation(&mut self) {
+        let generation = self
+            .child_generations()
+            .await
+            .into_iter()
+            .flatten()
+            .max()
+            .unwrap_or(0)
+            + 1;
+        self.generation = generation;
+
+        for child in &self.children {
+            if child.state() == ChildState::Open {
+                self.write_child_generation(child).await;
+            }
+        }
+
+        debug!("{}: opened at write generation {}", self.name, generation);
+    }
+
+    /// true if all healthy children, which are the sources of a rebuild,
+    /// still hold the write generation of the nexus, ie no other nexus
+    /// opened them since this one was
+    pub(crate) async fn sources_hold_generation(&self) -> bool {
+        let generations = self.child_generations().await;
+        !generations.is_empty()
+            && generations.iter().all(|g| *g == Some(self.generation))
+    }
+
+    /// Persist the write generation of the nexus on the child `name`, eg once
+    /// it has been rebuilt
+    pub(crate) async fn persist_generation(&self, name: &str) {
+        if let Some(child) = self.children.iter().find(|c| c.name == name) {
+            self.write_child_generation(child).await;
+        }
+    }
+
+    async fn write_child_generation(&self, child: &NexusChild) {
+        let result = async {
+            let (bdev, hdl) = child.get_dev().map_err(|source| {
+                MetaDataError::NexusChildError {
+                    source,
+                }
+            })?;
+            child
+                .write_generation(
+                    &hdl,
+                    self.generation_offset(bdev.block_len()),
+                    self.generation,
+                )
+                .await
+        }
+        .await;
+
+        if let Err(e) = result {
+            warn!(
+                "{}: failed to persist write generation {} on child {}: {}",
+                self.name,
+                self.generation,
+                child.name,
+                e.verbose()
+            );
+        }
+    }
+}
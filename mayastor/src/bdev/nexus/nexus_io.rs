@@ -10,6 +10,8 @@ use spdk_sys::{
     spdk_bdev_free_io,
     spdk_bdev_io,
     spdk_bdev_io_complete,
+    spdk_bdev_io_complete_nvme_status,
+    spdk_bdev_io_complete_scsi_status,
     spdk_bdev_io_get_io_channel,
     spdk_io_channel,
 };
@@ -17,9 +19,10 @@ use spdk_sys::{
 use crate::{
     bdev::{
         nexus::{
-            nexus_bdev::{Nexus, NEXUS_PRODUCT_ID},
+            nexus_bdev::{Nexus, NexusTarget, NEXUS_PRODUCT_ID},
             nexus_channel::DREvent,
             nexus_fn_table::NexusFnTable,
+            nexus_journal::{EventOrigin, NexusEventType},
         },
         nexus_lookup,
         ChildState,
@@ -219,6 +222,11 @@ pub mod nvme_admin_opc {
     // pub const GET_FEATURES: u8 = 0x0a;
     // Vendor-specific
     pub const CREATE_SNAPSHOT: u8 = 0xc0;
+    pub const SEGMENT_CHECKSUM: u8 = 0xc2;
+    pub const DESTROY_SNAPSHOT: u8 = 0xc4;
+    pub const LIST_SNAPSHOTS: u8 = 0xc6;
+    pub const REVERT_SNAPSHOT: u8 = 0xc8;
+    pub const REVERT_SNAPSHOT_STATE: u8 = 0xca;
 }
 
 impl Bio {
@@ -270,6 +278,36 @@ impl Bio {
         }
     }
 
+    /// fail the IO as the nexus is read-only, with a status the initiator
+    /// can tell apart from a media error: "namespace is write protected" for
+    /// NVMe, or the "write protected" sense data for a SCSI LUN
+    #[inline]
+    pub(crate) fn fail_write_protected(&self) {
+        if matches!(
+            self.nexus_as_ref().nexus_target,
+            Some(NexusTarget::NexusIscsiTarget)
+        ) {
+            unsafe {
+                spdk_bdev_io_complete_scsi_status(
+                    self.0.as_ptr(),
+                    spdk_sys::SPDK_SCSI_STATUS_CHECK_CONDITION,
+                    spdk_sys::SPDK_SCSI_SENSE_DATA_PROTECT,
+                    0x27, // WRITE PROTECTED
+                    0x00,
+                )
+            }
+        } else {
+            unsafe {
+                spdk_bdev_io_complete_nvme_status(
+                    self.0.as_ptr(),
+                    0,
+                    0,    // SPDK_NVME_SCT_GENERIC
+                    0x20, // SPDK_NVME_SC_NAMESPACE_IS_WRITE_PROTECTED
+                )
+            }
+        }
+    }
+
     #[inline]
     pub(crate) fn complete(&mut self) {
         let pio_ctx = self.ctx_as_mut_ref();
@@ -315,7 +353,7 @@ impl Bio {
         self.complete();
     }
 
-    async fn child_retire(nexus: String, child: Bdev) {
+    pub(crate) async fn child_retire(nexus: String, child: Bdev) {
         error!("{:#?}", child);
 
         if let Some(nexus) = nexus_lookup(&nexus) {
@@ -334,6 +372,12 @@ impl Bio {
                     );
 
                     let uri = child.name.clone();
+                    nexus.record_event(
+                        NexusEventType::ChildFaulted,
+                        Some(&uri),
+                        Reason::IoError.to_string(),
+                        EventOrigin::Io,
+                    );
                     nexus.pause().await.unwrap();
                     nexus.reconfigure(DREvent::ChildFault).await;
                     //nexus.remove_child(&uri).await.unwrap();
@@ -0,0 +1,249 @@
+//!
+//! Bounded journal of the events that happened to a nexus.
+//!
+//! Without a journal the only record of what happened to a nexus (children
+//! being faulted, rebuilds starting or failing, the nexus being published,
+//! reconfiguration events etc.) is scattered over the log. Each nexus keeps a
+//! ring buffer of such events, each carrying the time, the reason and the
+//! origin of the event. Once the journal is full the oldest event is dropped.
+//!
+//! While a gRPC request acts on the nexus, the events the nexus records on its
+//! own behalf are attributed to that request, and carry its ID along with the
+//! event recording the request itself.
+
+use std::{
+    collections::VecDeque,
+    fmt::{Display, Formatter},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+        Mutex,
+    },
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Type of an event recorded in the journal
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum NexusEventType {
+    /// a request acting on the nexus was received
+    Request,
+    /// the nexus has been created
+    Created,
+    /// a child has been added to the nexus
+    ChildAdded,
+    /// a child has been removed from the nexus
+    ChildRemoved,
+    /// a child has been brought online
+    ChildOnline,
+    /// a child has been taken offline
+    ChildOffline,
+    /// a child has been faulted
+    ChildFaulted,
+    /// a rebuild of a child has been started
+    RebuildStarted,
+    /// a rebuild of a child has completed successfully
+    RebuildCompleted,
+    /// a rebuild of a child has been stopped
+    RebuildStopped,
+    /// a rebuild of a child has failed
+    RebuildFailed,
+    /// the nexus has been published
+    Published,
+    /// the nexus has been unpublished
+    Unpublished,
+    /// the IO channels of the nexus have been reconfigured
+    Reconfigured,
+    /// the nexus started rejecting writes as write redundancy was lost
+    ReadOnly,
+    /// the nexus accepts writes again as write redundancy was restored
+    ReadWrite,
+}
+
+impl Display for NexusEventType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Request => "request",
+            Self::Created => "created",
+            Self::ChildAdded => "child_added",
+            Self::ChildRemoved => "child_removed",
+            Self::ChildOnline => "child_online",
+            Self::ChildOffline => "child_offline",
+            Self::ChildFaulted => "child_faulted",
+            Self::RebuildStarted => "rebuild_started",
+            Self::RebuildCompleted => "rebuild_completed",
+            Self::RebuildStopped => "rebuild_stopped",
+            Self::RebuildFailed => "rebuild_failed",
+            Self::Published => "published",
+            Self::Unpublished => "unpublished",
+            Self::Reconfigured => "reconfigured",
+            Self::ReadOnly => "read_only",
+            Self::ReadWrite => "read_write",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Origin of an event recorded in the journal
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum EventOrigin {
+    /// the gRPC request with the given name
+    Rpc(String),
+    /// the nexus itself
+    Nexus,
+    /// a rebuild job of the nexus
+    Rebuild,
+    /// the IO path, i.e. failed IOs
+    Io,
+}
+
+impl Display for EventOrigin {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Rpc(request) => write!(f, "rpc:{}", request),
+            Self::Nexus => write!(f, "nexus"),
+            Self::Rebuild => write!(f, "rebuild"),
+            Self::Io => write!(f, "io"),
+        }
+    }
+}
+
+/// A single event recorded in the journal
+#[derive(Debug, Clone)]
+pub struct NexusEvent {
+    /// time at which the event was recorded
+    pub timestamp: DateTime<Utc>,
+    /// type of the event
+    pub event: NexusEventType,
+    /// uri of the child the event relates to, if any
+    pub child: Option<String>,
+    /// human readable reason of the event
+    pub reason: String,
+    /// what caused the event
+    pub origin: EventOrigin,
+    /// ID of the gRPC request which caused the event, if any
+    pub request: Option<u64>,
+}
+
+impl NexusEvent {
+    /// create a new event timestamped with the current time
+    pub fn new(
+        event: NexusEventType,
+        child: Option<&str>,
+        reason: String,
+        origin: EventOrigin,
+    ) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            event,
+            child: child.map(String::from),
+            reason,
+            origin,
+            request: None,
+        }
+    }
+}
+
+impl Display for NexusEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} child: {}, origin: {}, request: {}, reason: {}",
+            self.timestamp.to_rfc3339(),
+            self.event,
+            self.child.as_deref().unwrap_or("-"),
+            self.origin,
+            self.request.map_or("-".to_string(), |id| id.to_string()),
+            self.reason,
+        )
+    }
+}
+
+/// the gRPC request acting on a nexus, by ID and name
+type CurrentRequest = Arc<Mutex<Option<(u64, String)>>>;
+
+/// A gRPC request acting on a nexus, to which the events the nexus records on
+/// its own behalf are attributed until it is dropped
+#[derive(Debug)]
+pub struct NexusRequest {
+    id: u64,
+    current: CurrentRequest,
+}
+
+impl NexusRequest {
+    /// ID of the request in the journal
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+impl Drop for NexusRequest {
+    fn drop(&mut self) {
+        let mut current = self.current.lock().unwrap();
+        if matches!(*current, Some((id, _)) if id == self.id) {
+            *current = None;
+        }
+    }
+}
+
+/// Ring buffer of the most recent events of a nexus
+#[derive(Debug)]
+pub struct NexusJournal {
+    /// maximum number of events kept, zero disables the journal
+    capacity: usize,
+    events: Mutex<VecDeque<NexusEvent>>,
+    /// ID of the next gRPC request
+    next_request: AtomicU64,
+    /// the gRPC request currently acting on the nexus, if any
+    request: CurrentRequest,
+}
+
+impl NexusJournal {
+    /// create a new journal holding at most capacity events
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            events: Mutex::new(VecDeque::with_capacity(capacity)),
+            next_request: AtomicU64::new(1),
+            request: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// start attributing the events of the nexus to the gRPC request with
+    /// the given name, until the returned request is dropped
+    pub fn begin_request(&self, name: &str) -> NexusRequest {
+        let id = self.next_request.fetch_add(1, Ordering::Relaxed);
+        *self.request.lock().unwrap() = Some((id, name.to_string()));
+        NexusRequest {
+            id,
+            current: Arc::clone(&self.request),
+        }
+    }
+
+    /// record an event dropping the oldest one if the journal is full. An
+    /// event of the nexus itself is attributed to the current request
+    pub fn record(&self, mut event: NexusEvent) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if event.origin == EventOrigin::Nexus && event.request.is_none() {
+            if let Some((id, name)) = self.request.lock().unwrap().clone() {
+                event.origin = EventOrigin::Rpc(name);
+                event.request = Some(id);
+            }
+        }
+
+        let mut events = self.events.lock().unwrap();
+        if events.len() == self.capacity {
+            events.pop_front();
+        }
+        events.push_back(event);
+    }
+
+    /// returns a copy of all events, from the oldest to the most recent one
+    pub fn events(&self) -> Vec<NexusEvent> {
+        self.events.lock().unwrap().iter().cloned().collect()
+    }
+}
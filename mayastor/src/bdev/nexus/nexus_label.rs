@@ -146,10 +146,15 @@ impl Nexus {
     pub const METADATA_PARTITION_TYPE_ID: &'static str =
         "27663382-e5e6-11e9-81b4-ca5ca5ca5ca5";
 
+    /// Size in bytes of our "MayaMeta" partition.
+    pub const METADATA_PARTITION_SIZE: u64 = 4 << 20;
+
     /// Generate a new nexus label based on the nexus configuration.
     /// The meta partition is fixed in size and aligned to a 1MB boundary.
     pub(crate) fn generate_label(&mut self) -> NexusLabel {
-        let block_size: u32 = self.bdev.block_len();
+        // the label is written to the children, so it is laid out in units
+        // of their block size rather than the one of the nexus
+        let block_size: u32 = self.child_blk_size;
         let num_blocks: u64 = self.min_num_blocks();
 
         //
@@ -195,7 +200,9 @@ impl Nexus {
             // 1MB aligned
             ent_start: header.lba_start,
             // 4MB
-            ent_end: header.lba_start + u64::from((4 << 20) / block_size) - 1,
+            ent_end: header.lba_start
+                + Nexus::METADATA_PARTITION_SIZE / u64::from(block_size)
+                - 1,
             ent_attr: 0,
             ent_name: GptName {
                 name: "MayaMeta".into(),
@@ -233,8 +240,9 @@ impl Nexus {
     fn get_primary_data(
         &self,
         label: &NexusLabel,
+        block_size: u32,
     ) -> Result<LabelData, LabelError> {
-        let block_size = self.bdev.block_len() as u64;
+        let block_size = block_size as u64;
         let blocks = Aligned::get_blocks(
             GPTHeader::PARTITION_TABLE_SIZE as u64,
             block_size,
@@ -275,8 +283,9 @@ impl Nexus {
     fn get_secondary_data(
         &self,
         label: &NexusLabel,
+        block_size: u32,
     ) -> Result<LabelData, LabelError> {
-        let block_size = self.bdev.block_len() as u64;
+        let block_size = block_size as u64;
         let blocks = Aligned::get_blocks(
             GPTHeader::PARTITION_TABLE_SIZE as u64,
             block_size,
@@ -307,17 +316,40 @@ impl Nexus {
         })
     }
 
+    /// The primary and secondary label data to write to a child. The label
+    /// is laid out in units of child_blk_size, which is converted to the
+    /// block size of the child so that it covers the same bytes on all
+    /// children.
+    fn get_label_data(
+        &self,
+        label: &NexusLabel,
+        child: &NexusChild,
+    ) -> Result<(LabelData, LabelData), LabelError> {
+        let block_size = child
+            .bdev
+            .as_ref()
+            .map_or(self.child_blk_size, |b| b.block_len());
+        let label = label.with_block_size(self.child_blk_size, block_size);
+
+        Ok((
+            self.get_primary_data(&label, block_size)?,
+            self.get_secondary_data(&label, block_size)?,
+        ))
+    }
+
     pub async fn write_labels(
         &self,
         target: &NexusLabel,
         list: &[NexusChildLabel<'_>],
     ) -> Result<(), LabelError> {
-        let primary = self.get_primary_data(target)?;
-        let secondary = self.get_secondary_data(target)?;
+        let data = list
+            .iter()
+            .map(|label| self.get_label_data(target, label.child))
+            .collect::<Result<Vec<_>, _>>()?;
 
         let mut futures = Vec::new();
 
-        for label in list {
+        for (label, (primary, secondary)) in list.iter().zip(data.iter()) {
             match label.get_label_status() {
                 NexusLabelStatus::Both => {
                     // Nothing to do as both labels are already valid.
@@ -374,12 +406,17 @@ impl Nexus {
         &self,
         label: &NexusLabel,
     ) -> Result<(), LabelError> {
-        let primary = self.get_primary_data(label)?;
-        let secondary = self.get_secondary_data(label)?;
+        let data = self
+            .children
+            .iter()
+            .map(|child| self.get_label_data(label, child))
+            .collect::<Result<Vec<_>, _>>()?;
 
         let mut futures = Vec::new();
 
-        for child in &self.children {
+        for (child, (primary, secondary)) in
+            self.children.iter().zip(data.iter())
+        {
             futures.push(child.write_at(primary.offset, &primary.buf));
             futures.push(child.write_at(secondary.offset, &secondary.buf));
         }
@@ -653,6 +690,11 @@ pub struct NexusLabel {
 }
 
 impl NexusLabel {
+    /// returns the offset to the metadata partition
+    pub(crate) fn metadata_offset(&self) -> u64 {
+        self.partitions[0].ent_start
+    }
+
     /// returns the offset to the first data segment
     pub(crate) fn offset(&self) -> u64 {
         self.partitions[1].ent_start
@@ -662,6 +704,53 @@ impl NexusLabel {
     pub(crate) fn get_block_count(&self) -> u64 {
         self.partitions[1].ent_end - self.partitions[1].ent_start + 1
     }
+
+    /// Returns the label as laid out on a disk with blocks of `to` bytes,
+    /// covering the same bytes as this label does on a disk with blocks of
+    /// `from` bytes. This allows children with different block sizes to
+    /// share the same layout, which is expressed in units of the largest one.
+    pub(crate) fn with_block_size(&self, from: u32, to: u32) -> NexusLabel {
+        if from == to {
+            return self.clone();
+        }
+
+        let lba = |lba: u64| lba * u64::from(from) / u64::from(to);
+        let num_blocks = lba(self.primary.lba_alt + 1);
+
+        let partitions = self
+            .partitions
+            .iter()
+            .map(|entry| GptEntry {
+                ent_start: lba(entry.ent_start),
+                ent_end: lba(entry.ent_end + 1) - 1,
+                ..entry.clone()
+            })
+            .collect::<Vec<_>>();
+
+        let mut primary = self.primary;
+        primary.lba_alt = num_blocks - 1;
+        primary.lba_start = lba(self.primary.lba_start);
+        primary.lba_end = (num_blocks - 1)
+            - (GPTHeader::PARTITION_TABLE_SIZE / u64::from(to))
+            - 1;
+        primary.table_crc = GptEntry::checksum(&partitions);
+        primary.checksum();
+
+        let mut mbr = self.mbr;
+        mbr.entries[0].num_sectors = if num_blocks > u32::max_value().into() {
+            u32::max_value()
+        } else {
+            (num_blocks as u32) - 1
+        };
+
+        NexusLabel {
+            status: self.status,
+            mbr,
+            primary,
+            partitions,
+            secondary: primary.to_backup(),
+        }
+    }
 }
 
 impl Display for NexusLabel {
@@ -4,7 +4,8 @@
 //! present.
 //!
 //! The data layout is as follows:
-//!  - The first block of the partition is not used.
+//!  - The first block of the partition is reserved for the checkpoint of a
+//!    rebuild of the child, see RebuildCheckpoint.
 //!  - The second block contains a MetaDataHeader (currently 72 bytes) while the
 //!    remainder of the block is padded with zeros.
 //!  - The "index" starts at the third block and contains a fixed number of
@@ -12,6 +13,8 @@
 //!    object that has been written to the partition.
 //!  - The first usable "data" block is the first block following the index
 //!    (whose size is aligned to the blocksize of the disk).
+//!  - The last block of the partition is reserved for the write generation of
+//!    the nexus, see nexus_generation.
 //!
 //! ## Example
 //! Sample code to create a new index and add a config object:
@@ -211,7 +214,8 @@ impl MetaDataHeader {
             entry_size: MetaDataHeader::INDEX_ENTRY_SIZE,
             index_checksum: 0,
             data_start: data_start as u64,
-            data_end: partition.ent_end - partition.ent_start - 1,
+            // skip the last block of the partition
+            data_end: partition.ent_end - partition.ent_start - 2,
         }
     }
 }
@@ -33,6 +33,7 @@ use crate::{
 const IOCTL_BLKGETSIZE: u32 = ior!(0x12, 114, std::mem::size_of::<u64>());
 const SET_TIMEOUT: u32 = io!(0xab, 9);
 const SET_SIZE: u32 = io!(0xab, 2);
+const IOCTL_BLKROSET: u32 = io!(0x12, 93);
 
 #[derive(Debug, Snafu)]
 pub enum NbdError {
@@ -40,6 +41,8 @@ pub enum NbdError {
     Unavailable {},
     #[snafu(display("Failed to start NBD on {}", dev))]
     StartNbd { source: Errno, dev: String },
+    #[snafu(display("Failed to set NBD device {} read-only", dev))]
+    SetReadOnly { source: Errno, dev: String },
 }
 
 extern "C" {
@@ -229,6 +232,55 @@ impl NbdDisk {
         })
     }
 
+    /// Mark the nbd device read-only so that the kernel rejects writes
+    /// before they ever reach the nexus.
+    pub fn set_read_only(&self) -> Result<(), NbdError> {
+        let done = Arc::new(AtomicBool::new(false));
+        let d = done.clone();
+        let (sender, receiver) =
+            std::sync::mpsc::channel::<Result<(), Errno>>();
+
+        // like in destroy() the ioctl is issued from a different thread as
+        // opening the device may require IO to be served by this core
+        let path = self.get_path();
+        let dev = path.clone();
+        Mthread::spawn_unaffinitized(move || {
+            let read_only: libc::c_int = 1;
+            let result = match OpenOptions::new()
+                .read(true)
+                .open(Path::new(&dev))
+            {
+                Ok(f) => {
+                    let rc = unsafe {
+                        libc::ioctl(
+                            f.as_raw_fd(),
+                            IOCTL_BLKROSET as u64,
+                            &read_only,
+                        )
+                    };
+                    if rc < 0 {
+                        Err(Errno::last())
+                    } else {
+                        Ok(())
+                    }
+                }
+                Err(e) => Err(Errno::from_i32(e.raw_os_error().unwrap_or(0))),
+            };
+            sender.send(result).unwrap();
+            d.store(true, SeqCst);
+        });
+
+        while !done.load(SeqCst) {
+            Reactors::current().poll_once();
+        }
+
+        receiver.recv().unwrap().context(SetReadOnly {
+            dev: path.clone(),
+        })?;
+        info!("NBD device {} set read-only", path);
+        Ok(())
+    }
+
     /// Stop and release nbd device.
     pub fn destroy(self) {
         let started = Arc::new(AtomicBool::new(false));
@@ -1,3 +1,5 @@
+use std::sync::atomic::Ordering;
+
 use async_trait::async_trait;
 use snafu::ResultExt;
 
@@ -14,6 +16,7 @@ use crate::{
             ShareNvmfNexus,
             UnshareNexus,
         },
+        nexus_journal::{EventOrigin, NexusEventType},
         nexus_nbd::NbdDisk,
     },
     core::{Protocol, Share},
@@ -98,49 +101,99 @@ impl From<&NexusTarget> for ShareProtocolNexus {
 }
 
 impl Nexus {
+    /// Publish the nexus over the given protocol. When read_only is set,
+    /// writes are rejected by the nexus itself and the device is also marked
+    /// read-only for the hosts: within the kernel for NBD, and as a write
+    /// protected namespace for NVMf. Writes to an iSCSI LUN fail with the
+    /// "write protected" sense data.
     pub async fn share(
         &mut self,
         protocol: ShareProtocolNexus,
         _key: Option<String>,
+        read_only: bool,
     ) -> Result<String, Error> {
         // This function should be idempotent as it's possible that
         // we get called more than once for some odd reason.
         if let Some(target) = &self.nexus_target {
             // We're already shared ...
-            if ShareProtocolNexus::from(target) == protocol {
-                // Same protocol as that requested, simply return Ok()
+            if ShareProtocolNexus::from(target) == protocol
+                && self.share_read_only.load(Ordering::Relaxed) == read_only
+            {
+                // Same protocol and access mode as requested, return Ok()
                 warn!("{} is already shared", self.name);
                 return Ok(self.get_share_uri().unwrap());
             }
 
-            // Error as protocol differs from that requested.
+            // Error as protocol or access mode differs from that requested.
             return Err(Error::AlreadyShared {
                 name: self.name.clone(),
             });
         }
 
-        match protocol {
+        // reject writes before the target is created, so that no initiator
+        // ever gets to write to a nexus which is meant to be read-only
+        self.share_read_only.store(read_only, Ordering::Relaxed);
+
+        let uri = match self.share_target(protocol, read_only).await {
+            Ok(uri) => uri,
+            Err(error) => {
+                self.share_read_only.store(false, Ordering::Relaxed);
+                return Err(error);
+            }
+        };
+
+        self.record_event(
+            NexusEventType::Published,
+            None,
+            format!(
+                "published {} over {:?} as {}",
+                if read_only { "read-only" } else { "read-write" },
+                protocol,
+                uri
+            ),
+            EventOrigin::Nexus,
+        );
+        Ok(uri)
+    }
+
+    /// create the target for the given protocol
+    async fn share_target(
+        &mut self,
+        protocol: ShareProtocolNexus,
+        read_only: bool,
+    ) -> Result<String, Error> {
+        let uri = match protocol {
             ShareProtocolNexus::NexusNbd => {
                 let disk = NbdDisk::create(&self.name).await.context(
                     ShareNbdNexus {
                         name: self.name.clone(),
                     },
                 )?;
+                if read_only {
+                    if let Err(error) = disk.set_read_only() {
+                        disk.destroy();
+                        return Err(error).context(ShareNbdNexus {
+                            name: self.name.clone(),
+                        });
+                    }
+                }
                 let uri = disk.as_uri();
                 self.nexus_target = Some(NexusTarget::NbdDisk(disk));
-                Ok(uri)
+                uri
             }
             ShareProtocolNexus::NexusIscsi => {
                 let uri = self.share_iscsi().await?;
                 self.nexus_target = Some(NexusTarget::NexusIscsiTarget);
-                Ok(uri)
+                uri
             }
             ShareProtocolNexus::NexusNvmf => {
                 let uri = self.share_nvmf().await?;
                 self.nexus_target = Some(NexusTarget::NexusNvmfTarget);
-                Ok(uri)
+                uri
             }
-        }
+        };
+
+        Ok(uri)
     }
 
     pub async fn unshare_nexus(&mut self) -> Result<(), Error> {
@@ -156,9 +209,17 @@ impl Nexus {
             }
             None => {
                 warn!("{} was not shared", self.name);
+                return Ok(());
             }
         }
-
+        self.share_read_only.store(false, Ordering::Relaxed);
+
+        self.record_event(
+            NexusEventType::Unpublished,
+            None,
+            "unpublished",
+            EventOrigin::Nexus,
+        );
         Ok(())
     }
 
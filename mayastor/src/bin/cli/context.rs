@@ -1,7 +1,7 @@
 use crate::{BdevClient, JsonClient, MayaClient};
 use byte_unit::Byte;
 use clap::ArgMatches;
-use std::cmp::max;
+use std::{cmp::max, io::Write};
 
 pub struct Context {
     pub(crate) client: MayaClient,
@@ -50,6 +50,14 @@ impl Context {
         }
     }
 
+    /// overwrites the current line, eg to show progress
+    pub(crate) fn v1_inline(&self, s: &str) {
+        if self.verbosity > 0 {
+            print!("\r{}\x1b[K", s);
+            let _ = std::io::stdout().flush();
+        }
+    }
+
     pub(crate) fn v2(&self, s: &str) {
         if self.verbosity > 1 {
             println!("{}", s)
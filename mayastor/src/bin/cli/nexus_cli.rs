@@ -25,6 +25,20 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
                 .multiple(true)
                 .index(3)
                 .help("list of children to add"),
+        )
+        .arg(
+            Arg::with_name("block-size")
+                .short("b")
+                .long("block-size")
+                .value_name("BLOCK_SIZE")
+                .help("block size of the nexus in bytes (default: inherited from the children)"),
+        )
+        .arg(
+            Arg::with_name("owner-generation")
+                .short("g")
+                .long("owner-generation")
+                .value_name("GENERATION")
+                .help("generation of the ownership of the replicas by the nexus (default: 0)"),
         );
 
     let destroy = SubCommand::with_name("destroy")
@@ -43,7 +57,9 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
         .arg(Arg::with_name("uuid").required(true).index(1)
             .help("uuid for the nexus"))
         .arg(Arg::with_name("key").required(false).index(2)
-            .help("crypto key to use"));
+            .help("crypto key to use"))
+        .arg(Arg::with_name("read-only").short("r").long("read-only").takes_value(false)
+            .help("Publish the nexus read-only, rejecting all writes"));
 
     let unpublish = SubCommand::with_name("unpublish")
         .about("unpublish the nexus")
@@ -109,6 +125,15 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
                 .help("uuid of nexus"),
         );
 
+    let events = SubCommand::with_name("events")
+        .about("list the events recorded in the journal of the nexus")
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(1)
+                .help("uuid of nexus"),
+        );
+
     SubCommand::with_name("nexus")
         .settings(&[
             AppSettings::SubcommandRequiredElseHelp,
@@ -124,6 +149,7 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
         .subcommand(unpublish)
         .subcommand(list)
         .subcommand(children)
+        .subcommand(events)
         .subcommand(nexus_child_cli::subcommands())
 }
 
@@ -136,6 +162,7 @@ pub async fn handler(
         ("destroy", Some(args)) => nexus_destroy(ctx, &args).await,
         ("list", Some(args)) => nexus_list(ctx, &args).await,
         ("children", Some(args)) => nexus_children(ctx, &args).await,
+        ("events", Some(args)) => nexus_events(ctx, &args).await,
         ("publish", Some(args)) => nexus_publish(ctx, &args).await,
         ("unpublish", Some(args)) => nexus_unpublish(ctx, &args).await,
         ("add", Some(args)) => nexus_add(ctx, &args).await,
@@ -160,6 +187,18 @@ async fn nexus_create(
         .split_whitespace()
         .map(|c| c.to_string())
         .collect::<Vec<String>>();
+    let block_size = match matches.value_of("block-size") {
+        Some(b) => b.parse::<u32>().map_err(|_| {
+            Status::invalid_argument(format!("Bad block size '{}'", b))
+        })?,
+        None => 0,
+    };
+    let owner_generation = match matches.value_of("owner-generation") {
+        Some(g) => g.parse::<u64>().map_err(|_| {
+            Status::invalid_argument(format!("Bad owner generation '{}'", g))
+        })?,
+        None => 0,
+    };
 
     ctx.v2(&format!(
         "Creating nexus {} of size {} ",
@@ -173,6 +212,8 @@ async fn nexus_create(
             uuid: uuid.clone(),
             size,
             children,
+            block_size,
+            owner_generation,
         })
         .await?;
     ctx.v1(&format!("Nexus {} created", uuid));
@@ -275,12 +316,61 @@ async fn nexus_children(
     Ok(())
 }
 
+async fn nexus_events(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let uuid = matches.value_of("uuid").unwrap().to_string();
+
+    let resp = ctx
+        .client
+        .get_nexus_events(rpc::GetNexusEventsRequest {
+            uuid: uuid.clone(),
+        })
+        .await?;
+
+    let events = &resp.get_ref().events;
+    if events.is_empty() {
+        ctx.v1(&format!("No events recorded for nexus {}", uuid));
+        return Ok(());
+    }
+
+    let table = events
+        .iter()
+        .map(|e| {
+            let child = if e.child.is_empty() {
+                "-".to_string()
+            } else {
+                e.child.clone()
+            };
+            vec![
+                e.timestamp.clone(),
+                e.event.clone(),
+                child,
+                e.origin.clone(),
+                if e.request == 0 {
+                    "-".to_string()
+                } else {
+                    e.request.to_string()
+                },
+                e.reason.clone(),
+            ]
+        })
+        .collect();
+    ctx.print_list(
+        vec!["TIME", "EVENT", "CHILD", "ORIGIN", "REQUEST", "REASON"],
+        table,
+    );
+    Ok(())
+}
+
 async fn nexus_publish(
     mut ctx: Context,
     matches: &ArgMatches<'_>,
 ) -> Result<(), Status> {
     let uuid = matches.value_of("uuid").unwrap().to_string();
     let key = matches.value_of("key").unwrap_or("").to_string();
+    let read_only = matches.is_present("read-only");
     let prot = match matches.value_of("protocol") {
         None => rpc::ShareProtocolNexus::NexusNbd,
         Some("nvmf") => rpc::ShareProtocolNexus::NexusNvmf,
@@ -300,6 +390,7 @@ async fn nexus_publish(
             uuid,
             key,
             share: prot.into(),
+            read_only,
         })
         .await?;
     ctx.v1(&format!("Nexus published at {}", resp.get_ref().device_uri));
@@ -370,6 +461,7 @@ fn nexus_state_to_str(idx: i32) -> &'static str {
         rpc::NexusState::NexusOnline => "online",
         rpc::NexusState::NexusDegraded => "degraded",
         rpc::NexusState::NexusFaulted => "faulted",
+        rpc::NexusState::NexusReadOnly => "read-only",
     }
 }
 
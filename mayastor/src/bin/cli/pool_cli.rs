@@ -1,9 +1,46 @@
-use super::context::Context;
+use crate::{context::Context, parse_size, replica_cli::parse_wipe_method};
 use ::rpc::mayastor as rpc;
 use byte_unit::Byte;
 use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use std::convert::TryFrom;
 use tonic::Status;
 
+/// options of the pools which are given when they are created, and checked
+/// when they are imported
+fn option_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("overcommit")
+            .short("o")
+            .long("overcommit")
+            .takes_value(true)
+            .value_name("PERCENT")
+            .help("Max size of the replicas in percent of the capacity, 0 for no limit (default unchanged)"),
+        Arg::with_name("cluster-size")
+            .short("c")
+            .long("cluster-size")
+            .takes_value(true)
+            .value_name("BYTES")
+            .help("Size of the allocation unit (default 4MiB)"),
+        Arg::with_name("md-ratio")
+            .short("m")
+            .long("md-ratio")
+            .takes_value(true)
+            .value_name("PERCENT")
+            .help("Metadata pages per cluster in percent (default 100)"),
+        Arg::with_name("clear")
+            .long("clear")
+            .takes_value(true)
+            .possible_values(&["none", "unmap", "write-zeroes"])
+            .help("How to clear the disks when creating the pool (default none)"),
+        Arg::with_name("wipe")
+            .short("w")
+            .long("wipe")
+            .takes_value(true)
+            .possible_values(&["none", "unmap", "write-zeroes", "random"])
+            .help("How to wipe the data of the replicas when destroying them (default none)"),
+    ]
+}
+
 pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
     let create = SubCommand::with_name("create")
         .about("Create storage pool")
@@ -18,8 +55,9 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
                 .required(true)
                 .multiple(true)
                 .index(2)
-                .help("Disk device files"),
-        );
+                .help("Disk device files, striped in the given order"),
+        )
+        .args(&option_args());
     let destroy = SubCommand::with_name("destroy")
         .about("Destroy storage pool")
         .arg(
@@ -28,6 +66,48 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
                 .index(1)
                 .help("Storage pool name"),
         );
+    let export = SubCommand::with_name("export")
+        .about("Export storage pool keeping its data")
+        .arg(
+            Arg::with_name("pool")
+                .required(true)
+                .index(1)
+                .help("Storage pool name"),
+        );
+    let import = SubCommand::with_name("import")
+        .about("Import exported storage pool")
+        .arg(
+            Arg::with_name("pool")
+                .required(true)
+                .index(1)
+                .help("Storage pool name"),
+        )
+        .arg(
+            Arg::with_name("disk")
+                .required(true)
+                .multiple(true)
+                .index(2)
+                .help("Disk device files, in the order they were striped"),
+        )
+        .args(&option_args());
+    let check = SubCommand::with_name("check")
+        .about("Check the consistency of the metadata of storage pool")
+        .arg(
+            Arg::with_name("pool")
+                .required(true)
+                .index(1)
+                .help("Storage pool name"),
+        )
+        .arg(
+            Arg::with_name("repair")
+                .short("r")
+                .long("repair")
+                .takes_value(false)
+                .help(
+                    "Repair the issues which can be safely fixed, \
+                     the pool must be freshly imported and unused",
+                ),
+        );
     SubCommand::with_name("pool")
         .settings(&[
             AppSettings::SubcommandRequiredElseHelp,
@@ -37,6 +117,9 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
         .about("Storage pool management")
         .subcommand(create)
         .subcommand(destroy)
+        .subcommand(export)
+        .subcommand(import)
+        .subcommand(check)
         .subcommand(SubCommand::with_name("list").about("List storage pools"))
 }
 
@@ -47,6 +130,9 @@ pub async fn handler(
     match matches.subcommand() {
         ("create", Some(args)) => create(ctx, args).await,
         ("destroy", Some(args)) => destroy(ctx, args).await,
+        ("export", Some(args)) => export(ctx, args).await,
+        ("import", Some(args)) => import(ctx, args).await,
+        ("check", Some(args)) => check(ctx, args).await,
         ("list", Some(args)) => list(ctx, args).await,
         (cmd, _) => {
             Err(Status::not_found(format!("command {} does not exist", cmd)))
@@ -64,12 +150,14 @@ async fn create(
         .unwrap()
         .map(|dev| dev.to_owned())
         .collect();
+    let options = options(matches)?;
 
     ctx.v2(&format!("Creating pool {}", name));
     ctx.client
         .create_pool(rpc::CreatePoolRequest {
             name: name.clone(),
             disks,
+            ..options
         })
         .await?;
     ctx.v1(&format!("Created pool {}", name));
@@ -92,6 +180,129 @@ async fn destroy(
     Ok(())
 }
 
+async fn export(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let name = matches.value_of("pool").unwrap().to_owned();
+
+    ctx.v2(&format!("Exporting pool {}", name));
+    ctx.client
+        .export_pool(rpc::ExportPoolRequest {
+            name: name.clone(),
+        })
+        .await?;
+    ctx.v1(&format!("Exported pool {}", name));
+    Ok(())
+}
+
+async fn import(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let name = matches.value_of("pool").unwrap().to_owned();
+    let disks = matches
+        .values_of("disk")
+        .unwrap()
+        .map(|dev| dev.to_owned())
+        .collect();
+    let options = options(matches)?;
+
+    ctx.v2(&format!("Importing pool {}", name));
+    ctx.client
+        .import_pool(rpc::ImportPoolRequest {
+            name: name.clone(),
+            disks,
+            overcommit_limit: options.overcommit_limit,
+            cluster_size: options.cluster_size,
+            metadata_ratio: options.metadata_ratio,
+            clear_method: options.clear_method,
+            wipe_method: options.wipe_method,
+        })
+        .await?;
+    ctx.v1(&format!("Imported pool {}", name));
+    Ok(())
+}
+
+async fn check(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let name = matches.value_of("pool").unwrap().to_owned();
+    let repair = matches.is_present("repair");
+
+    ctx.v2(&format!("Checking pool {}", name));
+    let reply = ctx
+        .client
+        .check_pool(rpc::CheckPoolRequest {
+            name: name.clone(),
+            repair,
+        })
+        .await?;
+    let reply = reply.get_ref();
+    ctx.v2(&format!(
+        "Checked {} blobs and {} clusters",
+        reply.blobs, reply.clusters
+    ));
+    if reply.issues.is_empty() {
+        ctx.v1(&format!("No issues found in pool {}", name));
+        return Ok(());
+    }
+
+    let table = reply
+        .issues
+        .iter()
+        .map(|i| {
+            vec![
+                issue_kind_to_str(i.kind).to_string(),
+                if i.repaired { "yes" } else { "no" }.to_string(),
+                i.description.clone(),
+            ]
+        })
+        .collect();
+    ctx.print_list(vec!["ISSUE", "REPAIRED", "DESCRIPTION"], table);
+
+    Ok(())
+}
+
+/// returns the options of the pool given on the command line, in a create
+/// request without name and disks
+fn options(matches: &ArgMatches<'_>) -> Result<rpc::CreatePoolRequest, Status> {
+    let percent = |arg: &str, what: &str| match matches.value_of(arg) {
+        Some(v) => v.parse::<u32>().map_err(|_| {
+            Status::invalid_argument(format!("Bad {} '{}'", what, v))
+        }),
+        None => Ok(0),
+    };
+    let cluster_size = match matches.value_of("cluster-size") {
+        Some(v) => parse_size(v)
+            .ok()
+            .and_then(|size| u32::try_from(size.get_bytes()).ok())
+            .ok_or_else(|| {
+                Status::invalid_argument(format!("Bad cluster size '{}'", v))
+            })?,
+        None => 0,
+    };
+    let clear_method = match matches.value_of("clear") {
+        Some("none") => rpc::PoolClearMethod::PoolClearNone,
+        Some("unmap") => rpc::PoolClearMethod::PoolClearUnmap,
+        Some("write-zeroes") => rpc::PoolClearMethod::PoolClearWriteZeroes,
+        _ => rpc::PoolClearMethod::PoolClearDefault,
+    };
+
+    Ok(rpc::CreatePoolRequest {
+        overcommit_limit: match matches.value_of("overcommit") {
+            Some(_) => Some(percent("overcommit", "overcommit limit")?),
+            None => None,
+        },
+        cluster_size,
+        metadata_ratio: percent("md-ratio", "metadata ratio")?,
+        clear_method: clear_method as i32,
+        wipe_method: parse_wipe_method(matches.value_of("wipe")) as i32,
+        ..Default::default()
+    })
+}
+
 async fn list(
     mut ctx: Context,
     _matches: &ArgMatches<'_>,
@@ -112,21 +323,55 @@ async fn list(
         .map(|p| {
             let cap = Byte::from_bytes(p.capacity.into());
             let used = Byte::from_bytes(p.used.into());
+            let committed = Byte::from_bytes(p.committed.into());
             let state = pool_state_to_str(p.state);
+            let disks = p
+                .disk_states
+                .iter()
+                .map(|d| match rpc::DiskState::from_i32(d.state) {
+                    Some(rpc::DiskState::DiskOnline) => d.uri.clone(),
+                    _ => format!("{} ({})", d.uri, disk_state_to_str(d.state)),
+                })
+                .collect::<Vec<_>>();
             vec![
                 p.name.clone(),
                 state.to_string(),
                 ctx.units(cap),
                 ctx.units(used),
-                p.disks.join(" "),
+                ctx.units(committed),
+                disks.join(" "),
             ]
         })
         .collect();
-    ctx.print_list(vec!["NAME", "STATE", ">CAPACITY", ">USED", "DISKS"], table);
+    ctx.print_list(
+        vec!["NAME", "STATE", ">CAPACITY", ">USED", ">COMMITTED", "DISKS"],
+        table,
+    );
 
     Ok(())
 }
 
+fn disk_state_to_str(idx: i32) -> &'static str {
+    match rpc::DiskState::from_i32(idx).unwrap() {
+        rpc::DiskState::DiskUnknown => "unknown",
+        rpc::DiskState::DiskOnline => "online",
+        rpc::DiskState::DiskRemoving => "removing",
+        rpc::DiskState::DiskMissing => "missing",
+    }
+}
+
+fn issue_kind_to_str(idx: i32) -> &'static str {
+    match rpc::PoolIssueKind::from_i32(idx).unwrap() {
+        rpc::PoolIssueKind::IssueUnknown => "unknown",
+        rpc::PoolIssueKind::IssueClusterNotAllocated => "cluster-not-allocated",
+        rpc::PoolIssueKind::IssueClusterShared => "cluster-shared",
+        rpc::PoolIssueKind::IssueClusterLeaked => "cluster-leaked",
+        rpc::PoolIssueKind::IssueOrphanedBlob => "orphaned-blob",
+        rpc::PoolIssueKind::IssueBrokenSnapshotChain => "broken-snapshot-chain",
+        rpc::PoolIssueKind::IssueUnreadableBlob => "unreadable-blob",
+    }
+}
+
 fn pool_state_to_str(idx: i32) -> &'static str {
     match rpc::PoolState::from_i32(idx).unwrap() {
         rpc::PoolState::PoolUnknown => "unknown",
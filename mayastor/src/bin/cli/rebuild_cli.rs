@@ -1,8 +1,9 @@
 //!
 //! methods to interact with the rebuild process
 
-use crate::context::Context;
+use crate::{context::Context, parse_size};
 use ::rpc::mayastor as rpc;
+use byte_unit::Byte;
 use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use tonic::Status;
 
@@ -18,6 +19,11 @@ pub async fn handler(
         ("state", Some(args)) => state(ctx, &args).await,
         ("stats", Some(args)) => stats(ctx, &args).await,
         ("progress", Some(args)) => progress(ctx, &args).await,
+        ("watch", Some(args)) => watch(ctx, &args).await,
+        ("list", Some(args)) => list(ctx, &args).await,
+        ("limit", Some(args)) => limit(ctx, &args).await,
+        ("priority", Some(args)) => priority(ctx, &args).await,
+        ("node-limit", Some(args)) => node_limit(ctx, &args).await,
         (cmd, _) => {
             Err(Status::not_found(format!("command {} does not exist", cmd)))
         }
@@ -38,6 +44,25 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
                 .required(true)
                 .index(2)
                 .help("uri of child to start rebuilding"),
+        )
+        .arg(
+            Arg::with_name("shared")
+                .index(3)
+                .multiple(true)
+                .help("uris of further children rebuilt by the same job"),
+        )
+        .arg(
+            Arg::with_name("mode")
+                .short("m")
+                .long("mode")
+                .value_name("MODE")
+                .possible_values(&["full", "checksum"])
+                .help("copy all segments, or only those whose checksums differ; defaults to the node configuration"),
+        )
+        .arg(
+            Arg::with_name("verify")
+                .long("verify")
+                .help("verify the children once all segments are copied"),
         );
 
     let stop = SubCommand::with_name("stop")
@@ -130,6 +155,100 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
                 .help("uri of child to get the rebuild progress from"),
         );
 
+    let limit = SubCommand::with_name("limit")
+        .about("limits the rate of a rebuild, 0 is unlimited")
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(1)
+                .help("uuid of the nexus"),
+        )
+        .arg(
+            Arg::with_name("uri")
+                .required(true)
+                .index(2)
+                .help("uri of child to limit the rebuild of"),
+        )
+        .arg(
+            Arg::with_name("bandwidth")
+                .short("b")
+                .long("bandwidth")
+                .value_name("BANDWIDTH")
+                .help("bandwidth per second with optional unit suffix"),
+        )
+        .arg(
+            Arg::with_name("segments")
+                .short("s")
+                .long("segments")
+                .value_name("SEGMENTS")
+                .help("segments copied per second"),
+        );
+
+    let priority = SubCommand::with_name("priority")
+        .about("sets the priority class of a rebuild")
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(1)
+                .help("uuid of the nexus"),
+        )
+        .arg(
+            Arg::with_name("uri")
+                .required(true)
+                .index(2)
+                .help("uri of child to set the rebuild priority of"),
+        )
+        .arg(
+            Arg::with_name("priority")
+                .required(true)
+                .index(3)
+                .possible_values(&["low", "normal", "urgent"])
+                .help("priority class of the rebuild"),
+        );
+
+    let watch = SubCommand::with_name("watch")
+        .about("shows the progress of a rebuild until it has ended")
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(1)
+                .help("uuid of the nexus"),
+        )
+        .arg(
+            Arg::with_name("uri")
+                .required(true)
+                .index(2)
+                .help("uri of child to watch the rebuild of"),
+        )
+        .arg(
+            Arg::with_name("interval")
+                .short("i")
+                .long("interval")
+                .value_name("MS")
+                .default_value("1000")
+                .help("interval between progress updates in milliseconds"),
+        );
+
+    let list =
+        SubCommand::with_name("list").about("lists all rebuilds of the node");
+
+    let node_limit = SubCommand::with_name("node-limit")
+        .about("gets or limits the rate of all rebuilds of the node, 0 is unlimited")
+        .arg(
+            Arg::with_name("bandwidth")
+                .short("b")
+                .long("bandwidth")
+                .value_name("BANDWIDTH")
+                .help("bandwidth per second with optional unit suffix"),
+        )
+        .arg(
+            Arg::with_name("segments")
+                .short("s")
+                .long("segments")
+                .value_name("SEGMENTS")
+                .help("segments copied per second"),
+        );
+
     SubCommand::with_name("rebuild")
         .settings(&[
             AppSettings::SubcommandRequiredElseHelp,
@@ -144,6 +263,11 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
         .subcommand(state)
         .subcommand(stats)
         .subcommand(progress)
+        .subcommand(watch)
+        .subcommand(list)
+        .subcommand(limit)
+        .subcommand(priority)
+        .subcommand(node_limit)
 }
 
 async fn start(
@@ -152,11 +276,23 @@ async fn start(
 ) -> Result<(), Status> {
     let uuid = matches.value_of("uuid").unwrap().to_string();
     let uri = matches.value_of("uri").unwrap().to_string();
+    let shared_uris = matches
+        .values_of("shared")
+        .map(|uris| uris.map(|u| u.to_string()).collect())
+        .unwrap_or_else(Vec::new);
+    let mode = match matches.value_of("mode") {
+        Some("full") => rpc::RebuildMode::RebuildFull,
+        Some("checksum") => rpc::RebuildMode::RebuildChecksum,
+        _ => rpc::RebuildMode::RebuildDefault,
+    };
 
     ctx.client
         .start_rebuild(rpc::StartRebuildRequest {
             uuid: uuid.clone(),
             uri: uri.clone(),
+            shared_uris,
+            mode: mode as i32,
+            verify: matches.is_present("verify"),
         })
         .await?;
     ctx.v1(&format!(
@@ -278,19 +414,51 @@ async fn stats(
             "block_size",
             "tasks_total",
             "tasks_active",
+            "priority",
+            "bandwidth_limit",
+            "segment_limit",
+            "source",
+            "source_switches",
+            "destinations",
+            "error",
+            "mode",
+            "segments_skipped",
+            "verify",
+            "segments_verified",
+            "verify_mismatches",
+            "start_time",
+            "throughput",
         ],
-        vec![vec![
-            response.blocks_total,
-            response.blocks_recovered,
-            response.progress,
-            response.segment_size_blks,
-            response.block_size,
-            response.tasks_total,
-            response.tasks_active,
-        ]
-        .iter()
-        .map(|s| s.to_string())
-        .collect()],
+        vec![{
+            let limits = response.limits.clone().unwrap_or_default();
+            let mut row: Vec<String> = vec![
+                response.blocks_total,
+                response.blocks_recovered,
+                response.progress,
+                response.segment_size_blks,
+                response.block_size,
+                response.tasks_total,
+                response.tasks_active,
+            ]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+            row.push(priority_to_str(response.priority).to_string());
+            row.push(limits.bandwidth.to_string());
+            row.push(limits.segments.to_string());
+            row.push(response.source.clone());
+            row.push(response.source_switches.to_string());
+            row.push(response.destinations.join(","));
+            row.push(response.error.clone());
+            row.push(mode_to_str(response.mode).to_string());
+            row.push(response.segments_skipped.to_string());
+            row.push(response.verify.to_string());
+            row.push(response.segments_verified.to_string());
+            row.push(response.verify_mismatches.to_string());
+            row.push(response.start_time.clone());
+            row.push(ctx.units(Byte::from_bytes(response.throughput.into())));
+            row
+        }],
     );
     Ok(())
 }
@@ -320,3 +488,230 @@ async fn progress(
     );
     Ok(())
 }
+
+/// parse the bandwidth and segments arguments, which default to unlimited
+fn parse_limits(
+    matches: &ArgMatches<'_>,
+) -> Result<rpc::RebuildLimits, Status> {
+    let bandwidth = match matches.value_of("bandwidth") {
+        Some(b) => parse_size(b)
+            .map_err(|s| {
+                Status::invalid_argument(format!("Bad bandwidth '{}'", s))
+            })?
+            .get_bytes() as u64,
+        None => 0,
+    };
+    let segments = match matches.value_of("segments") {
+        Some(s) => s.parse::<u64>().map_err(|_| {
+            Status::invalid_argument(format!("Bad segments '{}'", s))
+        })?,
+        None => 0,
+    };
+
+    Ok(rpc::RebuildLimits {
+        bandwidth,
+        segments,
+    })
+}
+
+async fn watch(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let uuid = matches.value_of("uuid").unwrap().to_string();
+    let uri = matches.value_of("uri").unwrap().to_string();
+    let interval = matches.value_of("interval").unwrap();
+    let interval_ms = interval.parse::<u64>().map_err(|_| {
+        Status::invalid_argument(format!("Bad interval '{}'", interval))
+    })?;
+
+    ctx.v2(&format!(
+        "Watching the rebuild of child {} on nexus {}",
+        uri, uuid
+    ));
+    let mut updates = ctx
+        .client
+        .watch_rebuild(rpc::WatchRebuildRequest {
+            uuid,
+            uri,
+            interval_ms,
+        })
+        .await?
+        .into_inner();
+
+    while let Some(update) = updates.message().await? {
+        let stats = update.stats.unwrap_or_default();
+        ctx.v1_inline(&format!(
+            "{} {:>3}% {}/s eta {} {}",
+            progress_bar(stats.progress),
+            stats.progress,
+            ctx.units(Byte::from_bytes(update.throughput.into())),
+            eta_to_str(update.eta_secs),
+            update.state,
+        ));
+    }
+    ctx.v1("");
+    Ok(())
+}
+
+async fn list(
+    mut ctx: Context,
+    _matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    ctx.v2("Listing the rebuilds of the node");
+    let response = ctx.client.list_rebuilds(rpc::Null {}).await?.into_inner();
+
+    if response.rebuilds.is_empty() {
+        ctx.v1("No rebuilds found");
+        return Ok(());
+    }
+
+    let table = response
+        .rebuilds
+        .iter()
+        .map(|r| {
+            let stats = r.stats.clone().unwrap_or_default();
+            vec![
+                r.uuid.clone(),
+                r.uri.clone(),
+                r.state.clone(),
+                stats.progress.to_string(),
+                stats.source,
+                stats.start_time,
+                ctx.units(Byte::from_bytes(stats.throughput.into())),
+                stats.error,
+            ]
+        })
+        .collect();
+    ctx.print_list(
+        vec![
+            "NEXUS",
+            "CHILD",
+            "STATE",
+            ">PROGRESS (%)",
+            "SOURCE",
+            "START_TIME",
+            ">THROUGHPUT/S",
+            "ERROR",
+        ],
+        table,
+    );
+    Ok(())
+}
+
+/// progress bar of a fixed width for the given percentage
+fn progress_bar(progress: u64) -> String {
+    const WIDTH: usize = 40;
+    let done = std::cmp::min(progress as usize, 100) * WIDTH / 100;
+    format!("[{}{}]", "#".repeat(done), ".".repeat(WIDTH - done))
+}
+
+fn eta_to_str(eta_secs: i64) -> String {
+    if eta_secs < 0 {
+        "--:--:--".to_string()
+    } else {
+        format!(
+            "{:02}:{:02}:{:02}",
+            eta_secs / 3600,
+            eta_secs / 60 % 60,
+            eta_secs % 60
+        )
+    }
+}
+
+fn priority_to_str(priority: i32) -> &'static str {
+    match rpc::RebuildPriority::from_i32(priority) {
+        Some(rpc::RebuildPriority::RebuildLow) => "low",
+        Some(rpc::RebuildPriority::RebuildNormal) => "normal",
+        Some(rpc::RebuildPriority::RebuildUrgent) => "urgent",
+        None => "unknown",
+    }
+}
+
+fn mode_to_str(mode: i32) -> &'static str {
+    match rpc::RebuildMode::from_i32(mode) {
+        Some(rpc::RebuildMode::RebuildFull) => "full",
+        Some(rpc::RebuildMode::RebuildChecksum) => "checksum",
+        _ => "unknown",
+    }
+}
+
+async fn limit(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let uuid = matches.value_of("uuid").unwrap().to_string();
+    let uri = matches.value_of("uri").unwrap().to_string();
+    let limits = parse_limits(matches)?;
+
+    ctx.v2(&format!(
+        "Limiting the rebuild of child {} on nexus {} to {:?}",
+        uri, uuid, limits
+    ));
+    ctx.client
+        .set_rebuild_limits(rpc::SetRebuildLimitsRequest {
+            uuid: uuid.clone(),
+            uri: uri.clone(),
+            limits: Some(limits),
+        })
+        .await?;
+    ctx.v1(&format!(
+        "Limits of the rebuild of child {} on nexus {} set",
+        uri, uuid
+    ));
+    Ok(())
+}
+
+async fn priority(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let uuid = matches.value_of("uuid").unwrap().to_string();
+    let uri = matches.value_of("uri").unwrap().to_string();
+    let priority = match matches.value_of("priority").unwrap() {
+        "low" => rpc::RebuildPriority::RebuildLow,
+        "urgent" => rpc::RebuildPriority::RebuildUrgent,
+        _ => rpc::RebuildPriority::RebuildNormal,
+    };
+
+    ctx.client
+        .set_rebuild_priority(rpc::SetRebuildPriorityRequest {
+            uuid: uuid.clone(),
+            uri: uri.clone(),
+            priority: priority as i32,
+        })
+        .await?;
+    ctx.v1(&format!(
+        "Priority of the rebuild of child {} on nexus {} set to {}",
+        uri,
+        uuid,
+        priority_to_str(priority as i32)
+    ));
+    Ok(())
+}
+
+async fn node_limit(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    if matches.is_present("bandwidth") || matches.is_present("segments") {
+        let limits = parse_limits(matches)?;
+        ctx.client.set_node_rebuild_limits(limits.clone()).await?;
+        ctx.v1(&format!("Node rebuild limits set to {:?}", limits));
+        return Ok(());
+    }
+
+    let response = ctx
+        .client
+        .get_node_rebuild_limits(rpc::Null {})
+        .await?
+        .into_inner();
+    ctx.print_list(
+        vec!["bandwidth_limit", "segment_limit"],
+        vec![vec![
+            response.bandwidth.to_string(),
+            response.segments.to_string(),
+        ]],
+    );
+    Ok(())
+}
@@ -40,7 +40,14 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
                 .short("t")
                 .long("thin")
                 .takes_value(false)
-                .help("Whether replica is thin provisioned (default false)"));
+                .help("Whether replica is thin provisioned (default false)"))
+        .arg(
+            Arg::with_name("reservation")
+                .short("r")
+                .long("reservation")
+                .takes_value(true)
+                .value_name("NUMBER")
+                .help("Space of the pool reserved for a thin replica (default none)"));
 
     let destroy = SubCommand::with_name("destroy")
         .about("Destroy replica")
@@ -49,6 +56,14 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
                 .required(true)
                 .index(1)
                 .help("Replica uuid"),
+        )
+        .arg(
+            Arg::with_name("wipe")
+                .short("w")
+                .long("wipe")
+                .takes_value(true)
+                .possible_values(&["none", "unmap", "write-zeroes", "random"])
+                .help("How to wipe the data of the replica (default the default of its pool)"),
         );
 
     let share = SubCommand::with_name("share").about("Share or unshare replica")
@@ -63,6 +78,86 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
                 .index(2)
                 .help("Name of a protocol (nvmf, iscsi) used for sharing or \"none\" to unshare the replica"));
 
+    let resize = SubCommand::with_name("resize")
+        .about("Grow replica, which may be shared")
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(1)
+                .help("Replica uuid"),
+        )
+        .arg(
+            Arg::with_name("size")
+                .required(true)
+                .index(2)
+                .help("New size of the replica"),
+        );
+
+    let clone = SubCommand::with_name("clone")
+        .about("Create a thin writable replica from a snapshot")
+        .arg(
+            Arg::with_name("snapshot")
+                .required(true)
+                .index(1)
+                .help("Name of the snapshot"))
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(2)
+                .help("Unique uuid of the clone"))
+        .arg(
+            Arg::with_name("protocol")
+                .short("p")
+                .long("protocol")
+                .takes_value(true)
+                .value_name("PROTOCOL")
+                .help("Name of a protocol (nvmf) used for sharing the clone (default none)"));
+
+    let inflate = SubCommand::with_name("inflate")
+        .about("Make a clone independent of its snapshot")
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(1)
+                .help("Replica uuid"))
+        .arg(
+            Arg::with_name("decouple")
+                .short("d")
+                .long("decouple")
+                .takes_value(false)
+                .help("Only copy the data of the snapshot, keeping the clone thin (default false)"));
+
+    let transfer =
+        SubCommand::with_name("transfer")
+            .about("Transfer the ownership of a replica to a nexus")
+            .arg(
+                Arg::with_name("uuid")
+                    .required(true)
+                    .index(1)
+                    .help("Replica uuid"),
+            )
+            .arg(Arg::with_name("nexus").required(true).index(2).help(
+                "Uuid of the new owning nexus or \"\" to release the ownership",
+            ))
+            .arg(Arg::with_name("generation").required(true).index(3).help(
+                "Generation of the ownership, newer than the current one",
+            ));
+
+    let migrate = SubCommand::with_name("migrate")
+        .about("Move replica to another pool of the node, keeping it shared")
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(1)
+                .help("Replica uuid"),
+        )
+        .arg(
+            Arg::with_name("pool")
+                .required(true)
+                .index(2)
+                .help("Name of the pool to move the replica to"),
+        );
+
     SubCommand::with_name("replica")
         .settings(&[
             AppSettings::SubcommandRequiredElseHelp,
@@ -73,7 +168,16 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
         .subcommand(create)
         .subcommand(destroy)
         .subcommand(share)
+        .subcommand(resize)
+        .subcommand(clone)
+        .subcommand(inflate)
+        .subcommand(transfer)
+        .subcommand(migrate)
         .subcommand(SubCommand::with_name("list").about("List replicas"))
+        .subcommand(
+            SubCommand::with_name("wipes")
+                .about("List replicas being wiped and destroyed"),
+        )
         .subcommand(
             SubCommand::with_name("stats").about("IO stats of replicas"),
         )
@@ -87,7 +191,13 @@ pub async fn handler(
         ("create", Some(args)) => replica_create(ctx, &args).await,
         ("destroy", Some(args)) => replica_destroy(ctx, &args).await,
         ("list", Some(args)) => replica_list(ctx, &args).await,
+        ("wipes", Some(args)) => replica_wipes(ctx, &args).await,
         ("share", Some(args)) => replica_share(ctx, &args).await,
+        ("resize", Some(args)) => replica_resize(ctx, &args).await,
+        ("clone", Some(args)) => replica_clone(ctx, &args).await,
+        ("inflate", Some(args)) => replica_inflate(ctx, &args).await,
+        ("transfer", Some(args)) => replica_transfer(ctx, &args).await,
+        ("migrate", Some(args)) => replica_migrate(ctx, &args).await,
         ("stats", Some(args)) => replica_stat(ctx, &args).await,
         (cmd, _) => {
             Err(Status::not_found(format!("command {} does not exist", cmd)))
@@ -105,6 +215,14 @@ async fn replica_create(
         .map_err(|s| Status::invalid_argument(format!("Bad size '{}'", s)))?;
     let thin = matches.is_present("thin");
     let share = parse_replica_protocol(matches.value_of("protocol"))?;
+    let reservation = match matches.value_of("reservation") {
+        Some(r) => parse_size(r)
+            .map_err(|s| {
+                Status::invalid_argument(format!("Bad reservation '{}'", s))
+            })?
+            .get_bytes() as u64,
+        None => 0,
+    };
 
     ctx.v2(&format!("Creating replica {} on pool {}", uuid, pool));
     let rq = rpc::CreateReplicaRequest {
@@ -113,6 +231,8 @@ async fn replica_create(
         thin,
         share,
         size: size.get_bytes() as u64,
+        properties: None,
+        reservation,
     };
     let resp = ctx.client.create_replica(rq).await?;
     ctx.v1(&format!("Created {}", resp.get_ref().uri));
@@ -124,13 +244,122 @@ async fn replica_destroy(
     matches: &ArgMatches<'_>,
 ) -> Result<(), Status> {
     let uuid = matches.value_of("uuid").unwrap().to_owned();
+    let wipe = parse_wipe_method(matches.value_of("wipe"));
 
     ctx.v2(&format!("Destroying replica {}", uuid));
     ctx.client
         .destroy_replica(rpc::DestroyReplicaRequest {
             uuid,
+            wipe: wipe as i32,
+        })
+        .await?;
+    Ok(())
+}
+
+async fn replica_resize(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let uuid = matches.value_of("uuid").unwrap().to_owned();
+    let size = parse_size(matches.value_of("size").unwrap())
+        .map_err(|s| Status::invalid_argument(format!("Bad size '{}'", s)))?;
+
+    ctx.v2(&format!("Resizing replica {}", uuid));
+    let resp = ctx
+        .client
+        .resize_replica(rpc::ResizeReplicaRequest {
+            uuid: uuid.clone(),
+            size: size.get_bytes() as u64,
+        })
+        .await?;
+    let size = Byte::from_bytes(resp.get_ref().size.into());
+    ctx.v1(&format!("Resized {} to {}", uuid, ctx.units(size)));
+    Ok(())
+}
+
+async fn replica_clone(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let snapshot = matches.value_of("snapshot").unwrap().to_owned();
+    let uuid = matches.value_of("uuid").unwrap().to_owned();
+    let share = parse_replica_protocol(matches.value_of("protocol"))?;
+
+    ctx.v2(&format!("Cloning snapshot {} into {}", snapshot, uuid));
+    let resp = ctx
+        .client
+        .create_clone(rpc::CreateCloneRequest {
+            snapshot,
+            uuid,
+            share,
+        })
+        .await?;
+    ctx.v1(&format!("Created {}", resp.get_ref().uri));
+    Ok(())
+}
+
+async fn replica_inflate(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let uuid = matches.value_of("uuid").unwrap().to_owned();
+    let decouple = matches.is_present("decouple");
+
+    ctx.v2(&format!("Inflating replica {}", uuid));
+    ctx.client
+        .inflate_replica(rpc::InflateReplicaRequest {
+            uuid: uuid.clone(),
+            decouple,
         })
         .await?;
+    ctx.v1(&format!("Inflated {}", uuid));
+    Ok(())
+}
+
+async fn replica_transfer(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let uuid = matches.value_of("uuid").unwrap().to_owned();
+    let nexus_uuid = matches.value_of("nexus").unwrap().to_owned();
+    let generation = value_t!(matches.value_of("generation"), u64)
+        .map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+    ctx.v2(&format!(
+        "Transferring replica {} to nexus {} generation {}",
+        uuid, nexus_uuid, generation
+    ));
+    ctx.client
+        .transfer_replica_ownership(rpc::TransferReplicaOwnershipRequest {
+            uuid: uuid.clone(),
+            nexus_uuid: nexus_uuid.clone(),
+            generation,
+        })
+        .await?;
+    ctx.v1(&format!("Transferred {} to nexus {}", uuid, nexus_uuid));
+    Ok(())
+}
+
+async fn replica_migrate(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let uuid = matches.value_of("uuid").unwrap().to_owned();
+    let pool = matches.value_of("pool").unwrap().to_owned();
+
+    ctx.v2(&format!("Migrating replica {} to pool {}", uuid, pool));
+    let resp = ctx
+        .client
+        .migrate_replica(rpc::MigrateReplicaRequest {
+            uuid: uuid.clone(),
+            pool,
+        })
+        .await?;
+    ctx.v1(&format!(
+        "Migrated {} to pool {}",
+        uuid,
+        resp.get_ref().pool
+    ));
     Ok(())
 }
 
@@ -154,18 +383,69 @@ async fn replica_list(
         .map(|r| {
             let proto = replica_protocol_to_str(r.share);
             let size = ctx.units(Byte::from_bytes(r.size.into()));
+            let allocated = ctx.units(Byte::from_bytes(r.allocated.into()));
             vec![
                 r.pool.clone(),
                 r.uuid.clone(),
                 r.thin.to_string(),
                 proto.to_string(),
                 size,
+                allocated,
                 r.uri.clone(),
             ]
         })
         .collect();
     ctx.print_list(
-        vec!["POOL", "NAME", ">THIN", ">SHARE", ">SIZE", "URI"],
+        vec![
+            "POOL",
+            "NAME",
+            ">THIN",
+            ">SHARE",
+            ">SIZE",
+            ">ALLOCATED",
+            "URI",
+        ],
+        table,
+    );
+
+    Ok(())
+}
+
+async fn replica_wipes(
+    mut ctx: Context,
+    _matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    ctx.v2("Requesting a list of wipes");
+
+    let resp = ctx.client.list_wipes(rpc::Null {}).await?;
+    let wipes = &resp.get_ref().wipes;
+    if wipes.is_empty() {
+        ctx.v1("No replicas being wiped");
+        return Ok(());
+    }
+
+    let table = wipes
+        .iter()
+        .map(|w| {
+            let wiped = ctx.units(Byte::from_bytes(w.wiped.into()));
+            let total = ctx.units(Byte::from_bytes(w.total.into()));
+            let percent = if w.total == 0 {
+                100
+            } else {
+                w.wiped * 100 / w.total
+            };
+            vec![
+                w.pool.clone(),
+                w.uuid.clone(),
+                wipe_method_to_str(w.method).to_string(),
+                wiped,
+                total,
+                format!("{}%", percent),
+            ]
+        })
+        .collect();
+    ctx.print_list(
+        vec!["POOL", "NAME", "METHOD", ">WIPED", ">TOTAL", ">PROGRESS"],
         table,
     );
 
@@ -239,6 +519,29 @@ fn parse_replica_protocol(pcol: Option<&str>) -> Result<i32, Status> {
     }
 }
 
+/// returns the wipe method given on the command line, the default if none
+pub(crate) fn parse_wipe_method(
+    method: Option<&str>,
+) -> rpc::ReplicaWipeMethod {
+    match method {
+        Some("none") => rpc::ReplicaWipeMethod::WipeNone,
+        Some("unmap") => rpc::ReplicaWipeMethod::WipeUnmap,
+        Some("write-zeroes") => rpc::ReplicaWipeMethod::WipeWriteZeroes,
+        Some("random") => rpc::ReplicaWipeMethod::WipeRandom,
+        _ => rpc::ReplicaWipeMethod::WipeDefault,
+    }
+}
+
+fn wipe_method_to_str(idx: i32) -> &'static str {
+    match rpc::ReplicaWipeMethod::from_i32(idx) {
+        Some(rpc::ReplicaWipeMethod::WipeNone) => "none",
+        Some(rpc::ReplicaWipeMethod::WipeUnmap) => "unmap",
+        Some(rpc::ReplicaWipeMethod::WipeWriteZeroes) => "write-zeroes",
+        Some(rpc::ReplicaWipeMethod::WipeRandom) => "random",
+        _ => "default",
+    }
+}
+
 fn replica_protocol_to_str(idx: i32) -> &'static str {
     match rpc::ShareProtocolReplica::from_i32(idx) {
         Some(rpc::ShareProtocolReplica::ReplicaNone) => "none",
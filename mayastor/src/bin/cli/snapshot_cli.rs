@@ -3,6 +3,7 @@
 
 use crate::context::Context;
 use ::rpc::mayastor as rpc;
+use chrono::{TimeZone, Utc};
 use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use tonic::Status;
 
@@ -12,6 +13,9 @@ pub async fn handler(
 ) -> Result<(), Status> {
     match matches.subcommand() {
         ("create", Some(args)) => create(ctx, &args).await,
+        ("list", Some(args)) => list(ctx, &args).await,
+        ("destroy", Some(args)) => destroy(ctx, &args).await,
+        ("revert", Some(args)) => revert(ctx, &args).await,
         (cmd, _) => {
             Err(Status::not_found(format!("command {} does not exist", cmd)))
         }
@@ -28,6 +32,45 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
                 .help("uuid of the nexus"),
         );
 
+    let list = SubCommand::with_name("list")
+        .about("list the snapshots of a nexus")
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(1)
+                .help("uuid of the nexus"),
+        );
+
+    let destroy = SubCommand::with_name("destroy")
+        .about("destroy a snapshot on all replicas of a nexus")
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(1)
+                .help("uuid of the nexus"),
+        )
+        .arg(
+            Arg::with_name("name")
+                .required(true)
+                .index(2)
+                .help("name of the snapshot"),
+        );
+
+    let revert = SubCommand::with_name("revert")
+        .about("revert all replicas of a nexus to a snapshot")
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(1)
+                .help("uuid of the nexus"),
+        )
+        .arg(
+            Arg::with_name("name")
+                .required(true)
+                .index(2)
+                .help("name of the snapshot"),
+        );
+
     SubCommand::with_name("snapshot")
         .settings(&[
             AppSettings::SubcommandRequiredElseHelp,
@@ -36,6 +79,9 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
         ])
         .about("Snapshot management")
         .subcommand(create)
+        .subcommand(list)
+        .subcommand(destroy)
+        .subcommand(revert)
 }
 
 async fn create(
@@ -52,3 +98,74 @@ async fn create(
     ctx.v1(&format!("Creating snapshot on nexus {}", uuid));
     Ok(())
 }
+
+async fn list(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let uuid = matches.value_of("uuid").unwrap().to_string();
+
+    ctx.v2(&format!("Listing the snapshots of nexus {}", uuid));
+    let response = ctx
+        .client
+        .list_snapshots(rpc::ListSnapshotsRequest {
+            uuid,
+        })
+        .await?
+        .into_inner();
+
+    if response.snapshots.is_empty() {
+        ctx.v1("No snapshots found");
+        return Ok(());
+    }
+
+    let table = response
+        .snapshots
+        .iter()
+        .map(|s| {
+            vec![
+                s.name.clone(),
+                Utc.timestamp(s.created as i64, 0).to_rfc3339(),
+                s.complete.to_string(),
+            ]
+        })
+        .collect();
+    ctx.print_list(vec!["NAME", "CREATED", "COMPLETE"], table);
+    Ok(())
+}
+
+async fn destroy(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let uuid = matches.value_of("uuid").unwrap().to_string();
+    let name = matches.value_of("name").unwrap().to_string();
+
+    ctx.v2(&format!("Destroying snapshot {} of nexus {}", name, uuid));
+    ctx.client
+        .destroy_snapshot(rpc::DestroySnapshotRequest {
+            uuid,
+            name: name.clone(),
+        })
+        .await?;
+    ctx.v1(&format!("Destroyed snapshot {}", name));
+    Ok(())
+}
+
+async fn revert(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> Result<(), Status> {
+    let uuid = matches.value_of("uuid").unwrap().to_string();
+    let name = matches.value_of("name").unwrap().to_string();
+
+    ctx.v2(&format!("Reverting nexus {} to snapshot {}", uuid, name));
+    ctx.client
+        .revert_replica_to_snapshot(rpc::RevertReplicaToSnapshotRequest {
+            uuid: uuid.clone(),
+            name: name.clone(),
+        })
+        .await?;
+    ctx.v1(&format!("Reverted nexus {} to snapshot {}", uuid, name));
+    Ok(())
+}
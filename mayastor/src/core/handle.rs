@@ -4,30 +4,52 @@ use std::{
     mem::ManuallyDrop,
     os::raw::c_void,
     sync::Arc,
+    time::Duration,
 };
 
+use crc::crc64;
 use futures::channel::oneshot;
 use nix::errno::Errno;
 use serde::export::{fmt::Error, Formatter};
+use snafu::ResultExt;
 
 use spdk_sys::{
     spdk_bdev_desc,
+    spdk_bdev_flush,
     spdk_bdev_free_io,
     spdk_bdev_io,
     spdk_bdev_nvme_admin_passthru_ro,
     spdk_bdev_read,
     spdk_bdev_reset,
+    spdk_bdev_unmap,
     spdk_bdev_write,
+    spdk_bdev_write_zeroes,
     spdk_io_channel,
 };
 
 use crate::{
     bdev::nexus::nexus_io::nvme_admin_opc,
-    core::{Bdev, CoreError, Descriptor, DmaBuf, DmaError, IoChannel},
+    core::{
+        poller::sleep,
+        Bdev,
+        CoreError,
+        Descriptor,
+        DmaAllocFailed,
+        DmaBuf,
+        DmaError,
+        IoChannel,
+    },
     ffihelper::cb_arg,
+    lvs::RevertState,
     subsys,
 };
 
+/// interval at which the state of the revert of a replica is polled
+const REVERT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// largest range of blocks, in bytes, of which a checksum can be computed
+pub const MAX_CHECKSUM_SIZE: u64 = 1 << 20;
+
 /// A handle to a bdev, is an interface to submit IO. The ['Descriptor'] may be
 /// shared between cores freely. The ['IoChannel'] however, must be allocated on
 /// the core where the IO is submitted from.
@@ -182,6 +204,75 @@ impl BdevHandle {
         }
     }
 
+    /// unmap `len` bytes at the given offset, the data of which is no longer
+    /// needed
+    pub async fn unmap(&self, offset: u64, len: u64) -> Result<(), CoreError> {
+        let (s, r) = oneshot::channel::<bool>();
+        let errno = unsafe {
+            spdk_bdev_unmap(
+                self.desc.as_ptr(),
+                self.channel.as_ptr(),
+                offset,
+                len,
+                Some(Self::io_completion_cb),
+                cb_arg(s),
+            )
+        };
+
+        if errno != 0 {
+            return Err(CoreError::UnmapDispatch {
+                source: Errno::from_i32(errno.abs()),
+                offset,
+                len,
+            });
+        }
+
+        if r.await.expect("Failed awaiting unmap IO") {
+            Ok(())
+        } else {
+            Err(CoreError::UnmapFailed {
+                offset,
+                len,
+            })
+        }
+    }
+
+    /// write zeroes to `len` bytes at the given offset
+    pub async fn write_zeroes(
+        &self,
+        offset: u64,
+        len: u64,
+    ) -> Result<(), CoreError> {
+        let (s, r) = oneshot::channel::<bool>();
+        let errno = unsafe {
+            spdk_bdev_write_zeroes(
+                self.desc.as_ptr(),
+                self.channel.as_ptr(),
+                offset,
+                len,
+                Some(Self::io_completion_cb),
+                cb_arg(s),
+            )
+        };
+
+        if errno != 0 {
+            return Err(CoreError::WriteZeroesDispatch {
+                source: Errno::from_i32(errno.abs()),
+                offset,
+                len,
+            });
+        }
+
+        if r.await.expect("Failed awaiting write zeroes IO") {
+            Ok(())
+        } else {
+            Err(CoreError::WriteZeroesFailed {
+                offset,
+                len,
+            })
+        }
+    }
+
     pub async fn reset(&self) -> Result<usize, CoreError> {
         let (s, r) = oneshot::channel::<bool>();
         let errno = unsafe {
@@ -206,6 +297,33 @@ impl BdevHandle {
         }
     }
 
+    /// flush the bdev, persisting the data of all completed writes
+    pub async fn flush(&self) -> Result<(), CoreError> {
+        let (s, r) = oneshot::channel::<bool>();
+        let errno = unsafe {
+            spdk_bdev_flush(
+                self.desc.as_ptr(),
+                self.channel.as_ptr(),
+                0,
+                self.get_bdev().size_in_bytes(),
+                Some(Self::io_completion_cb),
+                cb_arg(s),
+            )
+        };
+
+        if errno != 0 {
+            return Err(CoreError::FlushDispatch {
+                source: Errno::from_i32(errno.abs()),
+            });
+        }
+
+        if r.await.expect("Failed awaiting flush IO") {
+            Ok(())
+        } else {
+            Err(CoreError::FlushFailed {})
+        }
+    }
+
     /// create a snapshot, only works for nvme bdev
     /// returns snapshot time as u64 seconds since Unix epoch
     pub async fn create_snapshot(&self) -> Result<u64, CoreError> {
@@ -217,6 +335,173 @@ impl BdevHandle {
         Ok(now as u64)
     }
 
+    /// list the snapshots of a replica, only works for nvme bdev
+    /// returns the creation times of the snapshots as u64 seconds since Unix
+    /// epoch, oldest first
+    pub async fn list_snapshots(&self) -> Result<Vec<u64>, CoreError> {
+        let mut cmd = spdk_sys::spdk_nvme_cmd::default();
+        cmd.set_opc(nvme_admin_opc::LIST_SNAPSHOTS.into());
+        cmd.nsid = 1;
+
+        let size = 4096;
+        let mut buf = self.dma_malloc(size).context(DmaAllocFailed {
+            size,
+        })?;
+        self.nvme_admin(&cmd, Some(&mut buf)).await?;
+
+        // the number of snapshots followed by their creation times
+        let mut times = buf
+            .as_slice()
+            .chunks_exact(8)
+            .map(|c| {
+                let mut time = [0u8; 8];
+                time.copy_from_slice(c);
+                u64::from_le_bytes(time)
+            })
+            .collect::<Vec<_>>();
+        let count = times.remove(0) as usize;
+        times.truncate(count);
+        Ok(times)
+    }
+
+    /// destroy the snapshot of a replica created at `time`, only works for
+    /// nvme bdev
+    pub async fn destroy_snapshot(&self, time: u64) -> Result<(), CoreError> {
+        let mut cmd = spdk_sys::spdk_nvme_cmd::default();
+        cmd.set_opc(nvme_admin_opc::DESTROY_SNAPSHOT.into());
+        cmd.nsid = 1;
+        subsys::encode_snapshot_time(&mut cmd, time);
+        debug!("Destroying snapshot created at {}", time);
+        self.nvme_admin(&cmd, None).await
+    }
+
+    /// revert a replica to its snapshot created at `time`, only works for
+    /// nvme bdev. The target reverts the replica in the background, its state
+    /// is polled until the revert is over.
+    pub async fn revert_snapshot(&self, time: u64) -> Result<(), CoreError> {
+        let mut cmd = spdk_sys::spdk_nvme_cmd::default();
+        cmd.set_opc(nvme_admin_opc::REVERT_SNAPSHOT.into());
+        cmd.nsid = 1;
+        subsys::encode_snapshot_time(&mut cmd, time);
+        debug!("Reverting to snapshot created at {}", time);
+        self.nvme_admin(&cmd, None).await?;
+
+        loop {
+            sleep(REVERT_POLL_INTERVAL).await;
+            match self.revert_snapshot_state().await? {
+                RevertState::Running => continue,
+                RevertState::Done => return Ok(()),
+                _ => {
+                    return Err(CoreError::NvmeAdminFailed {
+                        opcode: nvme_admin_opc::REVERT_SNAPSHOT.into(),
+                    })
+                }
+            }
+        }
+    }
+
+    /// returns the state of the last revert of a replica to one of its
+    /// snapshots, only works for nvme bdev
+    pub async fn revert_snapshot_state(
+        &self,
+    ) -> Result<RevertState, CoreError> {
+        let mut cmd = spdk_sys::spdk_nvme_cmd::default();
+        cmd.set_opc(nvme_admin_opc::REVERT_SNAPSHOT_STATE.into());
+        cmd.nsid = 1;
+
+        let size = self.get_bdev().block_len() as u64;
+        let mut buf = self.dma_malloc(size).context(DmaAllocFailed {
+            size,
+        })?;
+        self.nvme_admin(&cmd, Some(&mut buf)).await?;
+        Ok(RevertState::from(buf.as_slice()[0]))
+    }
+
+    /// CRC-64 checksum of `num_blocks` blocks starting at block `offset`,
+    /// which may span at most MAX_CHECKSUM_SIZE bytes.
+    /// The checksum of a replica shared by a Mayastor NVMf target is computed
+    /// by the target, so that its data does not have to be transferred.
+    /// Other bdevs, including local NVMe devices, are read locally instead.
+    pub async fn checksum(
+        &self,
+        offset: u64,
+        num_blocks: u64,
+    ) -> Result<u64, CoreError> {
+        let bdev = self.get_bdev();
+        let size = num_blocks * bdev.block_len() as u64;
+        if size > MAX_CHECKSUM_SIZE || offset + num_blocks > bdev.num_blocks() {
+            return Err(CoreError::InvalidOffset {
+                offset,
+            });
+        }
+
+        if bdev.driver() == "nvme" && self.is_mayastor_target().await {
+            match self.nvme_segment_checksum(offset, num_blocks).await {
+                Ok(checksum) => return Ok(checksum),
+                Err(e) => debug!(
+                    "{}: remote checksum failed, reading locally: {}",
+                    bdev.name(),
+                    e
+                ),
+            }
+        }
+
+        let mut buf = self.dma_malloc(size).context(DmaAllocFailed {
+            size,
+        })?;
+        self.read_at(offset * bdev.block_len() as u64, &mut buf)
+            .await?;
+        Ok(crc64::checksum_ecma(buf.as_slice()))
+    }
+
+    /// true if the nvme bdev is a namespace of a Mayastor NVMf target, whose
+    /// controller implements the custom admin commands, as opposed to a
+    /// local NVMe device which may give its own meaning to the opcodes
+    pub async fn is_mayastor_target(&self) -> bool {
+        let mut buf = match self.dma_malloc(4096) {
+            Ok(buf) => buf,
+            Err(_) => return false,
+        };
+        if self.nvme_identify_ctrlr(&mut buf).await.is_err() {
+            return false;
+        }
+
+        // the model number and the NVM subsystem NQN of the controller
+        let data = buf.as_slice();
+        let model = String::from_utf8_lossy(&data[24 .. 64]);
+        let subnqn = String::from_utf8_lossy(&data[768 .. 1024]);
+        model.trim_end() == "Mayastor NVMe controller"
+            || subnqn.starts_with("nqn.2019-05.io.openebs:")
+    }
+
+    /// have the target compute the CRC-64 checksum of a range of blocks,
+    /// only works for nvme bdev of a Mayastor NVMf target
+    pub async fn nvme_segment_checksum(
+        &self,
+        offset: u64,
+        num_blocks: u64,
+    ) -> Result<u64, CoreError> {
+        let mut cmd = spdk_sys::spdk_nvme_cmd::default();
+        cmd.set_opc(nvme_admin_opc::SEGMENT_CHECKSUM.into());
+        cmd.nsid = 1;
+        // start of the range in cdw10/11, number of blocks in cdw12
+        unsafe {
+            *spdk_sys::nvme_cmd_cdw10_get(&mut cmd) = offset as u32;
+            *spdk_sys::nvme_cmd_cdw11_get(&mut cmd) = (offset >> 32) as u32;
+            *spdk_sys::nvme_cmd_cdw12_get(&mut cmd) = num_blocks as u32;
+        }
+
+        let size = self.get_bdev().block_len() as u64;
+        let mut buf = self.dma_malloc(size).context(DmaAllocFailed {
+            size,
+        })?;
+        self.nvme_admin(&cmd, Some(&mut buf)).await?;
+
+        let mut checksum = [0u8; 8];
+        checksum.copy_from_slice(&buf.as_slice()[.. 8]);
+        Ok(u64::from_le_bytes(checksum))
+    }
+
     /// identify controller
     /// buffer must be at least 4096B
     pub async fn nvme_identify_ctrlr(
@@ -18,7 +18,7 @@ pub use env::{
     SIG_RECEIVED,
 };
 
-pub use handle::BdevHandle;
+pub use handle::{BdevHandle, MAX_CHECKSUM_SIZE};
 pub use nvme::{GenericStatusCode, NvmeStatus};
 pub use reactor::{Reactor, ReactorState, Reactors, REACTOR_LIST};
 pub use share::{Protocol, Share};
@@ -81,10 +81,34 @@ pub enum CoreError {
         offset: u64,
         len: u64,
     },
+    #[snafu(display(
+        "Failed to dispatch unmap at offset {} length {}",
+        offset,
+        len
+    ))]
+    UnmapDispatch {
+        source: Errno,
+        offset: u64,
+        len: u64,
+    },
+    #[snafu(display(
+        "Failed to dispatch write zeroes at offset {} length {}",
+        offset,
+        len
+    ))]
+    WriteZeroesDispatch {
+        source: Errno,
+        offset: u64,
+        len: u64,
+    },
     #[snafu(display("Failed to dispatch reset",))]
     ResetDispatch {
         source: Errno,
     },
+    #[snafu(display("Failed to dispatch flush",))]
+    FlushDispatch {
+        source: Errno,
+    },
     #[snafu(display("Failed to dispatch NVMe Admin command {:x}h", opcode))]
     NvmeAdminDispatch {
         source: Errno,
@@ -100,12 +124,33 @@ pub enum CoreError {
         offset: u64,
         len: u64,
     },
+    #[snafu(display("Unmap failed at offset {} length {}", offset, len))]
+    UnmapFailed {
+        offset: u64,
+        len: u64,
+    },
+    #[snafu(display(
+        "Write zeroes failed at offset {} length {}",
+        offset,
+        len
+    ))]
+    WriteZeroesFailed {
+        offset: u64,
+        len: u64,
+    },
     #[snafu(display("Reset failed"))]
     ResetFailed {},
+    #[snafu(display("Flush failed"))]
+    FlushFailed {},
     #[snafu(display("NVMe Admin command {:x}h failed", opcode))]
     NvmeAdminFailed {
         opcode: u16,
     },
+    #[snafu(display("Failed to allocate a DMA buffer of {} bytes", size))]
+    DmaAllocFailed {
+        source: DmaError,
+        size: u64,
+    },
     #[snafu(display("failed to share {}", source))]
     ShareNvmf {
         source: NvmfError,
@@ -4,6 +4,8 @@ use std::{
     time::Duration,
 };
 
+use futures::channel::oneshot;
+
 use spdk_sys::{
     spdk_poller,
     spdk_poller_pause,
@@ -146,3 +148,23 @@ impl<'a> Builder<'a> {
         }
     }
 }
+
+/// Waits for the given duration without blocking the reactor
+pub async fn sleep(duration: Duration) {
+    let (s, r) = oneshot::channel::<()>();
+    let mut sender = Some(s);
+
+    let poller = Builder::new()
+        .with_name("sleep")
+        .with_interval(duration.as_micros() as u64)
+        .with_poll_fn(move || {
+            if let Some(s) = sender.take() {
+                let _ = s.send(());
+            }
+            0
+        })
+        .build();
+
+    let _ = r.await;
+    poller.stop();
+}
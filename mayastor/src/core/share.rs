@@ -2,7 +2,7 @@ use async_trait::async_trait;
 use pin_utils::core_reexport::fmt::Formatter;
 use std::fmt::Display;
 
-#[derive(Debug, PartialOrd, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialOrd, PartialEq)]
 /// Indicates what protocol the bdev is shared as
 pub enum Protocol {
     /// not shared by any of the variants
@@ -8,6 +8,9 @@
 //! grpc perspective we provide. Also, by doing his, we can test the methods
 //! without the need for setting up a grpc client.
 
+use std::time::Duration;
+
+use futures::StreamExt;
 use tonic::{Request, Response, Status};
 use tracing::instrument;
 
@@ -16,7 +19,7 @@ use ::rpc::mayastor::*;
 use crate::{
     bdev::{
         nexus::{instances, nexus_bdev},
-        nexus_create,
+        nexus_create_ext,
         Reason,
     },
     grpc::{
@@ -31,6 +34,9 @@ use crate::{
         GrpcResult,
     },
     host::{blk_device, resource},
+    rebuild,
+    rebuild::RebuildJob,
+    subsys::Config,
 };
 
 #[derive(Debug)]
@@ -65,6 +71,33 @@ impl mayastor_server::Mayastor for MayastorSvc {
         sync_config(pool_grpc::destroy(args)).await
     }
 
+    #[instrument(level = "debug", err)]
+    async fn export_pool(
+        &self,
+        request: Request<ExportPoolRequest>,
+    ) -> GrpcResult<Null> {
+        let args = request.into_inner();
+        sync_config(pool_grpc::export(args)).await
+    }
+
+    #[instrument(level = "debug", err)]
+    async fn import_pool(
+        &self,
+        request: Request<ImportPoolRequest>,
+    ) -> GrpcResult<Pool> {
+        let args = request.into_inner();
+        sync_config(pool_grpc::import(args)).await
+    }
+
+    #[instrument(level = "debug", err)]
+    async fn check_pool(
+        &self,
+        request: Request<CheckPoolRequest>,
+    ) -> GrpcResult<CheckPoolReply> {
+        let args = request.into_inner();
+        pool_grpc::check(args).await
+    }
+
     #[instrument(level = "debug", err)]
     async fn list_pools(
         &self,
@@ -117,6 +150,68 @@ impl mayastor_server::Mayastor for MayastorSvc {
         sync_config(pool_grpc::share_replica(args)).await
     }
 
+    #[instrument(level = "debug", err)]
+    async fn resize_replica(
+        &self,
+        request: Request<ResizeReplicaRequest>,
+    ) -> GrpcResult<Replica> {
+        let args = request.into_inner();
+        pool_grpc::resize_replica(args).await
+    }
+
+    #[instrument(level = "debug", err)]
+    async fn create_clone(
+        &self,
+        request: Request<CreateCloneRequest>,
+    ) -> GrpcResult<Replica> {
+        let args = request.into_inner();
+        sync_config(pool_grpc::create_clone(args)).await
+    }
+
+    #[instrument(level = "debug", err)]
+    async fn inflate_replica(
+        &self,
+        request: Request<InflateReplicaRequest>,
+    ) -> GrpcResult<Replica> {
+        let args = request.into_inner();
+        pool_grpc::inflate_replica(args).await
+    }
+
+    #[instrument(level = "debug", err)]
+    async fn set_replica_properties(
+        &self,
+        request: Request<SetReplicaPropertiesRequest>,
+    ) -> GrpcResult<Replica> {
+        let args = request.into_inner();
+        pool_grpc::set_replica_properties(args).await
+    }
+
+    #[instrument(level = "debug", err)]
+    async fn transfer_replica_ownership(
+        &self,
+        request: Request<TransferReplicaOwnershipRequest>,
+    ) -> GrpcResult<Replica> {
+        let args = request.into_inner();
+        pool_grpc::transfer_replica_ownership(args).await
+    }
+
+    #[instrument(level = "debug", err)]
+    async fn list_wipes(
+        &self,
+        _request: Request<Null>,
+    ) -> GrpcResult<ListWipesReply> {
+        pool_grpc::list_wipes()
+    }
+
+    #[instrument(level = "debug", err)]
+    async fn migrate_replica(
+        &self,
+        request: Request<MigrateReplicaRequest>,
+    ) -> GrpcResult<Replica> {
+        let args = request.into_inner();
+        sync_config(pool_grpc::migrate_replica(args)).await
+    }
+
     #[instrument(level = "debug", err)]
     async fn create_nexus(
         &self,
@@ -127,13 +222,25 @@ impl mayastor_server::Mayastor for MayastorSvc {
             let uuid = args.uuid.clone();
             let name = uuid_to_name(&args.uuid)?;
             locally! { async move {
-                nexus_create(&name, args.size, Some(&args.uuid), &args.children).await
-            }}
-            ;
+                let block_size = match args.block_size {
+                    0 => None,
+                    block_size => Some(block_size),
+                };
+                nexus_create_ext(
+                    &name,
+                    args.size,
+                    Some(&args.uuid),
+                    block_size,
+                    args.owner_generation,
+                    &args.children,
+                )
+                .await
+            }};
             let nexus = nexus_lookup(&uuid)?;
             info!("Created nexus {}", uuid);
             Ok(Response::new(nexus.to_grpc()))
-        }).await
+        })
+        .await
     }
 
     #[instrument(level = "debug", err)]
@@ -199,7 +306,10 @@ impl mayastor_server::Mayastor for MayastorSvc {
             let uuid = args.uuid.clone();
             debug!("Removing child {} from nexus {} ...", args.uri, uuid);
             locally! { async move {
-                nexus_lookup(&args.uuid)?.remove_child(&args.uri).await
+                let nexus = nexus_lookup(&args.uuid)?;
+                let _request =
+                    nexus.record_request("RemoveChildNexus", Some(&args.uri));
+                nexus.remove_child(&args.uri).await
             }};
             info!("Removed child from nexus {}", uuid);
             Ok(Response::new(Null {}))
@@ -219,7 +329,10 @@ impl mayastor_server::Mayastor for MayastorSvc {
             let uri = args.uri.clone();
             debug!("Faulting child {} on nexus {}", uri, uuid);
             locally! { async move {
-                nexus_lookup(&args.uuid)?.fault_child(&args.uri, Reason::Rpc).await
+                let nexus = nexus_lookup(&args.uuid)?;
+                let _request =
+                    nexus.record_request("FaultNexusChild", Some(&args.uri));
+                nexus.fault_child(&args.uri, Reason::Rpc).await
             }};
             info!("Faulted child {} on nexus {}", uri, uuid);
             Ok(Response::new(Null {}))
@@ -227,6 +340,26 @@ impl mayastor_server::Mayastor for MayastorSvc {
         .await
     }
 
+    #[instrument(level = "debug", err)]
+    async fn get_nexus_events(
+        &self,
+        request: Request<GetNexusEventsRequest>,
+    ) -> GrpcResult<GetNexusEventsReply> {
+        let args = request.into_inner();
+        trace!("{:?}", args);
+        let reply = locally! { async move {
+            nexus_lookup(&args.uuid).map(|nexus| GetNexusEventsReply {
+                events: nexus
+                    .events()
+                    .into_iter()
+                    .map(NexusEvent::from)
+                    .collect(),
+            })
+        }};
+        trace!("{:?}", reply);
+        Ok(Response::new(reply))
+    }
+
     #[instrument(level = "debug", err)]
     async fn publish_nexus(
         &self,
@@ -260,7 +393,9 @@ impl mayastor_server::Mayastor for MayastorSvc {
             };
 
             let device_uri = locally! { async move {
-                nexus_lookup(&args.uuid)?.share(share_protocol, key).await
+                let nexus = nexus_lookup(&args.uuid)?;
+                let _request = nexus.record_request("PublishNexus", None);
+                nexus.share(share_protocol, key, args.read_only).await
             }};
 
             info!("Published nexus {} under {}", uuid, device_uri);
@@ -282,7 +417,9 @@ impl mayastor_server::Mayastor for MayastorSvc {
             let uuid = args.uuid.clone();
             debug!("Unpublishing nexus {} ...", uuid);
             locally! { async move {
-                nexus_lookup(&args.uuid)?.unshare_nexus().await
+                let nexus = nexus_lookup(&args.uuid)?;
+                let _request = nexus.record_request("UnpublishNexus", None);
+                nexus.unshare_nexus().await
             }};
             info!("Unpublished nexus {}", uuid);
             Ok(Response::new(Null {}))
@@ -307,6 +444,8 @@ impl mayastor_server::Mayastor for MayastorSvc {
 
             locally! { async move {
                 let nexus = nexus_lookup(&args.uuid)?;
+                let _request =
+                    nexus.record_request("ChildOperation", Some(&args.uri));
                 if onl {
                     nexus.online_child(&args.uri).await
                 } else {
@@ -327,7 +466,24 @@ impl mayastor_server::Mayastor for MayastorSvc {
         let args = request.into_inner();
         trace!("{:?}", args);
         locally! { async move {
-            nexus_lookup(&args.uuid)?.start_rebuild(&args.uri).await.map(|_|{})
+            let nexus = nexus_lookup(&args.uuid)?;
+            let _request =
+                nexus.record_request("StartRebuild", Some(&args.uri));
+            let mut uris = vec![args.uri.clone()];
+            uris.extend(args.shared_uris.iter().cloned());
+            let mode = match RebuildMode::from_i32(args.mode) {
+                Some(RebuildMode::RebuildFull) => rebuild::RebuildMode::Full,
+                Some(RebuildMode::RebuildChecksum) => {
+                    rebuild::RebuildMode::Checksum
+                }
+                _ => nexus_bdev::Nexus::default_rebuild_mode(),
+            };
+            let verify =
+                args.verify || Config::get().nexus_opts.rebuild_verify;
+            nexus
+                .start_rebuild_with_options(&uris, mode, verify)
+                .await
+                .map(|_|{})
         }};
 
         Ok(Response::new(Null {}))
@@ -341,7 +497,9 @@ impl mayastor_server::Mayastor for MayastorSvc {
         let args = request.into_inner();
         trace!("{:?}", args);
         locally! { async move {
-          nexus_lookup(&args.uuid)?.stop_rebuild(&args.uri).await
+          let nexus = nexus_lookup(&args.uuid)?;
+          let _request = nexus.record_request("StopRebuild", Some(&args.uri));
+          nexus.stop_rebuild(&args.uri).await
         }};
 
         Ok(Response::new(Null {}))
@@ -354,7 +512,9 @@ impl mayastor_server::Mayastor for MayastorSvc {
     ) -> GrpcResult<Null> {
         let msg = request.into_inner();
         locally! { async move {
-          nexus_lookup(&msg.uuid)?.pause_rebuild(&msg.uri).await
+          let nexus = nexus_lookup(&msg.uuid)?;
+          let _request = nexus.record_request("PauseRebuild", Some(&msg.uri));
+          nexus.pause_rebuild(&msg.uri).await
         }};
 
         Ok(Response::new(Null {}))
@@ -367,7 +527,9 @@ impl mayastor_server::Mayastor for MayastorSvc {
     ) -> GrpcResult<Null> {
         let msg = request.into_inner();
         locally! { async move {
-          nexus_lookup(&msg.uuid)?.resume_rebuild(&msg.uri).await
+          let nexus = nexus_lookup(&msg.uuid)?;
+          let _request = nexus.record_request("ResumeRebuild", Some(&msg.uri));
+          nexus.resume_rebuild(&msg.uri).await
         }};
 
         Ok(Response::new(Null {}))
@@ -409,6 +571,118 @@ impl mayastor_server::Mayastor for MayastorSvc {
         }}))
     }
 
+    #[instrument(level = "debug", err)]
+    async fn list_rebuilds(
+        &self,
+        request: Request<Null>,
+    ) -> GrpcResult<ListRebuildsReply> {
+        let args = request.into_inner();
+        trace!("{:?}", args);
+        let rebuilds = locally! { async move {
+            Ok::<_, Status>(RebuildJob::list())
+        }};
+
+        Ok(Response::new(ListRebuildsReply {
+            rebuilds: rebuilds.into_iter().map(Into::into).collect(),
+        }))
+    }
+
+    type WatchRebuildStream =
+        tokio::sync::mpsc::UnboundedReceiver<Result<RebuildUpdate, Status>>;
+
+    #[instrument(level = "debug", err)]
+    async fn watch_rebuild(
+        &self,
+        request: Request<WatchRebuildRequest>,
+    ) -> GrpcResult<Self::WatchRebuildStream> {
+        let args = request.into_inner();
+        trace!("{:?}", args);
+        // frequent updates cost the reactor of the job, so bound the rate
+        let interval = Duration::from_millis(match args.interval_ms {
+            0 => 1000,
+            ms => ms.max(100),
+        });
+        let mut updates = locally! { async move {
+            let nexus = nexus_lookup(&args.uuid)?;
+            let _request =
+                nexus.record_request("WatchRebuild", Some(&args.uri));
+            nexus.watch_rebuild(&args.uri, interval)
+        }};
+
+        // the updates are sent from the reactor of the rebuild job
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(update) = updates.next().await {
+                if sender.send(Ok(update.into())).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Response::new(receiver))
+    }
+
+    #[instrument(level = "debug", err)]
+    async fn set_rebuild_limits(
+        &self,
+        request: Request<SetRebuildLimitsRequest>,
+    ) -> GrpcResult<Null> {
+        let msg = request.into_inner();
+        let limits: rebuild::RebuildLimits =
+            msg.limits.unwrap_or_default().into();
+        locally! { async move {
+          let nexus = nexus_lookup(&msg.uuid)?;
+          let _request =
+              nexus.record_request("SetRebuildLimits", Some(&msg.uri));
+          nexus.set_rebuild_limits(&msg.uri, limits).await
+        }};
+
+        Ok(Response::new(Null {}))
+    }
+
+    #[instrument(level = "debug", err)]
+    async fn set_rebuild_priority(
+        &self,
+        request: Request<SetRebuildPriorityRequest>,
+    ) -> GrpcResult<Null> {
+        let msg = request.into_inner();
+        let priority: rebuild::RebuildPriority =
+            match RebuildPriority::from_i32(msg.priority) {
+                Some(priority) => priority.into(),
+                None => {
+                    return Err(Status::invalid_argument(format!(
+                        "invalid rebuild priority {}",
+                        msg.priority
+                    )))
+                }
+            };
+        locally! { async move {
+          let nexus = nexus_lookup(&msg.uuid)?;
+          let _request =
+              nexus.record_request("SetRebuildPriority", Some(&msg.uri));
+          nexus.set_rebuild_priority(&msg.uri, priority).await
+        }};
+
+        Ok(Response::new(Null {}))
+    }
+
+    #[instrument(level = "debug", err)]
+    async fn set_node_rebuild_limits(
+        &self,
+        request: Request<RebuildLimits>,
+    ) -> GrpcResult<Null> {
+        RebuildJob::set_node_limits(request.into_inner().into());
+        Ok(Response::new(Null {}))
+    }
+
+    #[instrument(level = "debug", err)]
+    async fn get_node_rebuild_limits(
+        &self,
+        _request: Request<Null>,
+    ) -> GrpcResult<RebuildLimits> {
+        Ok(Response::new(RebuildJob::node_limits().into()))
+    }
+
     #[instrument(level = "debug", err)]
     async fn create_snapshot(
         &self,
@@ -428,6 +702,54 @@ impl mayastor_server::Mayastor for MayastorSvc {
         .await
     }
 
+    #[instrument(level = "debug", err)]
+    async fn list_snapshots(
+        &self,
+        request: Request<ListSnapshotsRequest>,
+    ) -> GrpcResult<ListSnapshotsReply> {
+        let args = request.into_inner();
+        let reply = locally! { async move {
+            nexus_lookup(&args.uuid)?.list_snapshots().await
+        }};
+        trace!("{:?}", reply);
+        Ok(Response::new(reply))
+    }
+
+    #[instrument(level = "debug", err)]
+    async fn destroy_snapshot(
+        &self,
+        request: Request<DestroySnapshotRequest>,
+    ) -> GrpcResult<Null> {
+        sync_config(async {
+            let args = request.into_inner();
+            debug!(
+                "Destroying snapshot {} of nexus {} ...",
+                args.name, args.uuid
+            );
+            locally! { async move {
+                nexus_lookup(&args.uuid)?.destroy_snapshot(&args.name).await
+            }};
+            Ok(Response::new(Null {}))
+        })
+        .await
+    }
+
+    #[instrument(level = "debug", err)]
+    async fn revert_replica_to_snapshot(
+        &self,
+        request: Request<RevertReplicaToSnapshotRequest>,
+    ) -> GrpcResult<Null> {
+        let args = request.into_inner();
+        debug!(
+            "Reverting nexus {} to snapshot {} ...",
+            args.uuid, args.name
+        );
+        locally! { async move {
+            nexus_lookup(&args.uuid)?.revert_to_snapshot(&args.name).await
+        }};
+        Ok(Response::new(Null {}))
+    }
+
     #[instrument(level = "debug", err)]
     async fn list_block_devices(
         &self,
@@ -9,8 +9,10 @@ use crate::{
         instances,
         nexus_bdev::{Error, Nexus, NexusStatus},
         nexus_child::{ChildState, NexusChild, Reason},
+        nexus_io::IoType,
+        nexus_journal::NexusEvent,
     },
-    rebuild::RebuildJob,
+    rebuild::{RebuildInfo, RebuildJob},
 };
 
 /// Map the internal child states into rpc child states (i.e. the states that
@@ -33,12 +35,37 @@ impl From<NexusStatus> for rpc::NexusState {
     fn from(nexus: NexusStatus) -> Self {
         match nexus {
             NexusStatus::Faulted => rpc::NexusState::NexusFaulted,
+            NexusStatus::ReadOnly => rpc::NexusState::NexusReadOnly,
             NexusStatus::Degraded => rpc::NexusState::NexusDegraded,
             NexusStatus::Online => rpc::NexusState::NexusOnline,
         }
     }
 }
 
+impl From<NexusEvent> for rpc::NexusEvent {
+    fn from(event: NexusEvent) -> Self {
+        rpc::NexusEvent {
+            timestamp: event.timestamp.to_rfc3339(),
+            event: event.event.to_string(),
+            child: event.child.unwrap_or_default(),
+            reason: event.reason,
+            origin: event.origin.to_string(),
+            request: event.request.unwrap_or_default(),
+        }
+    }
+}
+
+impl From<RebuildInfo> for rpc::Rebuild {
+    fn from(info: RebuildInfo) -> Self {
+        rpc::Rebuild {
+            uuid: name_to_uuid(&info.nexus).to_string(),
+            uri: info.destination,
+            state: info.state.to_string(),
+            stats: Some(info.stats.into()),
+        }
+    }
+}
+
 impl NexusChild {
     /// Convert nexus child object to grpc representation.
     ///
@@ -70,6 +97,14 @@ impl Nexus {
                 .map(|ch| ch.to_grpc())
                 .collect::<Vec<_>>(),
             rebuilds: RebuildJob::count() as u32,
+            read_only: self.is_read_only(),
+            capabilities: Some(rpc::NexusCapabilities {
+                flush: self.io_is_supported(IoType::Flush),
+                reset: self.io_is_supported(IoType::Reset),
+                unmap: self.io_is_supported(IoType::Unmap),
+                write_zeroes: self.io_is_supported(IoType::WriteZeros),
+                write_zeroes_emulated: self.write_zeroes_emulated(),
+            }),
         }
     }
 }
@@ -119,6 +154,7 @@ pub async fn nexus_add_child(
     args: rpc::AddChildNexusRequest,
 ) -> Result<rpc::Child, Error> {
     let n = nexus_lookup(&args.uuid)?;
+    let _request = n.record_request("AddChildNexus", Some(&args.uri));
     // TODO: do not add child if it already exists (idempotency)
     // For that we need api to check existence of child by name (not uri that
     // contain parameters that may change).
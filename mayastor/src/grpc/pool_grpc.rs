@@ -8,33 +8,71 @@ use tonic::{Response, Status};
 use tracing::instrument;
 
 use rpc::mayastor::{
+    CheckPoolReply,
+    CheckPoolRequest,
+    CreateCloneRequest,
     CreatePoolRequest,
     CreateReplicaRequest,
     DestroyPoolRequest,
     DestroyReplicaRequest,
+    DiskState as RpcDiskState,
+    ExportPoolRequest,
+    ImportPoolRequest,
+    InflateReplicaRequest,
     ListPoolsReply,
     ListReplicasReply,
+    ListWipesReply,
+    MigrateReplicaRequest,
     Null,
     Pool,
+    PoolClearMethod,
+    PoolDisk as RpcPoolDisk,
+    PoolIssue,
+    PoolIssueKind,
     PoolState,
     Replica,
+    ReplicaLabel,
+    ReplicaProperties,
     ReplicaStats,
+    ReplicaWipeMethod,
+    ResizeReplicaRequest,
+    SetReplicaPropertiesRequest,
     ShareReplicaReply,
     ShareReplicaRequest,
     StatReplicasReply,
     Stats,
+    TransferReplicaOwnershipRequest,
+    WipeProgress as RpcWipeProgress,
 };
 
 use crate::{
-    core::{Bdev, BdevStats, CoreError, Protocol, Share},
+    core::{Bdev, BdevStats, Protocol, Share},
     grpc::{rpc_call, GrpcResult},
-    lvs::{Error as LvsError, Error, Lvol, Lvs},
+    lvs::{
+        ClearMethod,
+        DiskState,
+        Error as LvsError,
+        Error,
+        Issue,
+        IssueKind,
+        Lvol,
+        LvolProperties,
+        Lvs,
+        LvsState,
+        PoolDisk,
+        PoolHealth,
+        WipeMethod,
+        WipeProgress,
+    },
     nexus_uri::NexusBdevError,
 };
 
 impl From<LvsError> for Status {
     fn from(e: LvsError) -> Self {
         match e {
+            Error::Import {
+                source, ..
+            } if source == Errno::ENOENT => Status::not_found(e.to_string()),
             Error::Import {
                 ..
             } => Status::invalid_argument(e.to_string()),
@@ -44,6 +82,52 @@ impl From<LvsError> for Status {
             Error::Invalid {
                 ..
             } => Status::invalid_argument(e.to_string()),
+            Error::RepCreate {
+                source, ..
+            } if source == Errno::ENOSPC => {
+                Status::resource_exhausted(e.to_string())
+            }
+            Error::RepReserve {
+                source, ..
+            } if source == Errno::ENOSPC => {
+                Status::resource_exhausted(e.to_string())
+            }
+            Error::RepResize {
+                source, ..
+            } if source == Errno::EINVAL => {
+                Status::invalid_argument(e.to_string())
+            }
+            Error::RepResize {
+                source, ..
+            } if source == Errno::ENOSPC => {
+                Status::resource_exhausted(e.to_string())
+            }
+            Error::RepClone {
+                source, ..
+            } if source == Errno::EINVAL => {
+                Status::invalid_argument(e.to_string())
+            }
+            Error::RepInflate {
+                source, ..
+            } if source == Errno::ENOSPC => {
+                Status::resource_exhausted(e.to_string())
+            }
+            Error::RepDestroy {
+                source, ..
+            } if source == Errno::EBUSY => {
+                Status::failed_precondition(e.to_string())
+            }
+            Error::SetProperty {
+                source, ..
+            } if source == Errno::EINVAL => {
+                Status::invalid_argument(e.to_string())
+            }
+            Error::StaleOwner {
+                ..
+            } => Status::failed_precondition(e.to_string()),
+            Error::CheckRepair {
+                ..
+            } => Status::failed_precondition(e.to_string()),
             _ => Status::internal(e.to_string()),
         }
     }
@@ -59,14 +143,114 @@ impl From<Protocol> for i32 {
     }
 }
 
+impl From<DiskState> for RpcDiskState {
+    fn from(s: DiskState) -> Self {
+        match s {
+            DiskState::Online => Self::DiskOnline,
+            DiskState::Removing => Self::DiskRemoving,
+            DiskState::Missing => Self::DiskMissing,
+        }
+    }
+}
+
+impl From<PoolDisk> for RpcPoolDisk {
+    fn from(d: PoolDisk) -> Self {
+        Self {
+            uri: d.uri,
+            state: RpcDiskState::from(d.state).into(),
+        }
+    }
+}
+
+impl From<LvsState> for PoolState {
+    fn from(s: LvsState) -> Self {
+        match s {
+            LvsState::Online => Self::PoolOnline,
+            LvsState::Degraded => Self::PoolDegraded,
+            LvsState::Faulted => Self::PoolFaulted,
+        }
+    }
+}
+
+impl From<ClearMethod> for PoolClearMethod {
+    fn from(c: ClearMethod) -> Self {
+        match c {
+            ClearMethod::None => Self::PoolClearNone,
+            ClearMethod::Unmap => Self::PoolClearUnmap,
+            ClearMethod::WriteZeroes => Self::PoolClearWriteZeroes,
+        }
+    }
+}
+
+impl From<WipeMethod> for ReplicaWipeMethod {
+    fn from(w: WipeMethod) -> Self {
+        match w {
+            WipeMethod::None => Self::WipeNone,
+            WipeMethod::Unmap => Self::WipeUnmap,
+            WipeMethod::WriteZeroes => Self::WipeWriteZeroes,
+            WipeMethod::Random => Self::WipeRandom,
+        }
+    }
+}
+
+impl From<IssueKind> for PoolIssueKind {
+    fn from(k: IssueKind) -> Self {
+        match k {
+            IssueKind::ClusterNotAllocated => Self::IssueClusterNotAllocated,
+            IssueKind::ClusterShared => Self::IssueClusterShared,
+            IssueKind::ClusterLeaked => Self::IssueClusterLeaked,
+            IssueKind::OrphanedBlob => Self::IssueOrphanedBlob,
+            IssueKind::BrokenSnapshotChain => Self::IssueBrokenSnapshotChain,
+            IssueKind::UnreadableBlob => Self::IssueUnreadableBlob,
+        }
+    }
+}
+
+impl From<Issue> for PoolIssue {
+    fn from(i: Issue) -> Self {
+        Self {
+            kind: PoolIssueKind::from(i.kind).into(),
+            blob: i.blob.unwrap_or_default(),
+            cluster: i.cluster.unwrap_or_default(),
+            repaired: i.repaired,
+            description: i.description,
+        }
+    }
+}
+
+impl From<PoolHealth> for Pool {
+    fn from(h: PoolHealth) -> Self {
+        Self {
+            name: h.name,
+            disks: h.disks.iter().map(|d| d.uri.clone()).collect(),
+            state: PoolState::from(h.state).into(),
+            capacity: 0,
+            used: 0,
+            disk_states: h.disks.into_iter().map(RpcPoolDisk::from).collect(),
+            committed: 0,
+            reserved: 0,
+            overcommit_limit: 0,
+            cluster_size: 0,
+            metadata_ratio: 0,
+            clear_method: PoolClearMethod::PoolClearDefault.into(),
+            wipe_method: ReplicaWipeMethod::WipeDefault.into(),
+        }
+    }
+}
+
 impl From<Lvs> for Pool {
     fn from(l: Lvs) -> Self {
         Self {
-            name: l.name().into(),
-            disks: vec![l.base_bdev().bdev_uri().unwrap_or_else(|| "".into())],
-            state: PoolState::PoolOnline.into(),
             capacity: l.capacity(),
             used: l.used(),
+            committed: l.committed(),
+            reserved: l.reserved(),
+            overcommit_limit: l.overcommit_limit(),
+            cluster_size: l.cluster_size() as u32,
+            metadata_ratio: l.metadata_ratio(),
+            clear_method: PoolClearMethod::from(l.clear_method()).into(),
+            wipe_method: ReplicaWipeMethod::from(l.wipe_method()).into(),
+            ..Pool::from(l.health())
         }
     }
 }
@@ -82,6 +266,39 @@ impl From<BdevStats> for Stats {
     }
 }
 
+impl From<LvolProperties> for ReplicaProperties {
+    fn from(p: LvolProperties) -> Self {
+        Self {
+            nexus_uuid: p.nexus_uuid,
+            owner_generation: p.owner_generation,
+            volume_uuid: p.volume_uuid,
+            labels: p
+                .labels
+                .into_iter()
+                .map(|(key, value)| ReplicaLabel {
+                    key,
+                    value,
+                })
+                .collect(),
+            created: p.created,
+            allowed_hosts: p.allowed_hosts,
+        }
+    }
+}
+
+impl From<ReplicaProperties> for LvolProperties {
+    fn from(p: ReplicaProperties) -> Self {
+        Self {
+            nexus_uuid: p.nexus_uuid,
+            owner_generation: p.owner_generation,
+            volume_uuid: p.volume_uuid,
+            labels: p.labels.into_iter().map(|l| (l.key, l.value)).collect(),
+            created: p.created,
+            allowed_hosts: p.allowed_hosts,
+        }
+    }
+}
+
 impl From<Lvol> for Replica {
     fn from(l: Lvol) -> Self {
         Self {
@@ -91,9 +308,25 @@ impl From<Lvol> for Replica {
             size: l.size(),
             share: l.shared().unwrap().into(),
             uri: l.share_uri().unwrap(),
+            properties: Some(l.properties().into()),
+            allocated: l.allocated(),
+            reservation: l.reservation(),
+        }
+    }
+}
+
+impl From<WipeProgress> for RpcWipeProgress {
+    fn from(w: WipeProgress) -> Self {
+        Self {
+            uuid: w.uuid,
+            pool: w.pool,
+            method: ReplicaWipeMethod::from(w.method).into(),
+            wiped: w.wiped,
+            total: w.total,
         }
     }
 }
+
 /// create a pool to that can be used to provision replicas.
 ///
 /// This method should be idempotent if the pool exists. To validate
@@ -104,20 +337,73 @@ pub async fn create(args: CreatePoolRequest) -> GrpcResult<Pool> {
 }
 
 /// Destroy a pool; and deletes all lvols
-/// If the pool does not exist; it returns OK.
+/// If the pool does not exist; it returns OK. A pool which is gone, because
+/// its disks have been removed, is forgotten.
 #[instrument(level = "debug", err)]
 pub async fn destroy(args: DestroyPoolRequest) -> GrpcResult<Null> {
     if let Some(pool) = Lvs::lookup(&args.name) {
         rpc_call(pool.destroy())
+    } else {
+        Lvs::forget(&args.name);
+        Ok(Response::new(Null {}))
+    }
+}
+
+/// Export a pool keeping its data, so that its disks can be moved to another
+/// node and imported there. If the pool does not exist; it returns OK.
+#[instrument(level = "debug", err)]
+pub async fn export(args: ExportPoolRequest) -> GrpcResult<Null> {
+    if let Some(pool) = Lvs::lookup(&args.name) {
+        rpc_call(pool.export())
     } else {
         Ok(Response::new(Null {}))
     }
 }
 
-/// list all the pools found within this instance
+/// Import a pool from its disks, sharing its replicas again. It fails with
+/// not found if there is no such pool on the disks, but it is OK if the pool
+/// has already been imported from the same disks.
+#[instrument(level = "debug", err)]
+pub async fn import(args: ImportPoolRequest) -> GrpcResult<Pool> {
+    rpc_call(Lvs::import_from_disks(CreatePoolRequest {
+        name: args.name,
+        disks: args.disks,
+        overcommit_limit: args.overcommit_limit,
+        cluster_size: args.cluster_size,
+        metadata_ratio: args.metadata_ratio,
+        clear_method: args.clear_method,
+        wipe_method: args.wipe_method,
+    }))
+}
+
+/// Check the consistency of the metadata of a pool, repairing the issues which
+/// can be safely fixed if requested
+#[instrument(level = "debug", err)]
+pub async fn check(args: CheckPoolRequest) -> GrpcResult<CheckPoolReply> {
+    let pool = match Lvs::lookup(&args.name) {
+        Some(pool) => pool,
+        None => return Err(Status::not_found(args.name)),
+    };
+
+    rpc_call::<_, _, LvsError, _>(async move {
+        let check = pool.check(args.repair).await?;
+        Ok(CheckPoolReply {
+            name: args.name,
+            blobs: check.blobs,
+            clusters: check.clusters,
+            issues: check.issues.into_iter().map(PoolIssue::from).collect(),
+        })
+    })
+}
+
+/// list all the pools found within this instance, including the pools which
+/// are gone because their disks have been removed
 pub fn list() -> GrpcResult<ListPoolsReply> {
     Ok(Response::new(ListPoolsReply {
-        pools: Lvs::iter().map(|l| l.into()).collect::<Vec<Pool>>(),
+        pools: Lvs::iter()
+            .map(Pool::from)
+            .chain(Lvs::gone().into_iter().map(Pool::from))
+            .collect::<Vec<Pool>>(),
     }))
 }
 
@@ -142,46 +428,252 @@ pub async fn create_replica(args: CreateReplicaRequest) -> GrpcResult<Replica> {
         )));
     }
 
+    let props = args.properties.clone().map(LvolProperties::from);
+    if let Some(props) = &props {
+        props.validate(&args.uuid)?;
+    }
+
     rpc_call(async move {
         let p = Lvs::lookup(&args.pool).unwrap();
-        match p.create_lvol(&args.uuid, args.size, false).await {
-            Ok(lvol) if Protocol::from(args.share) == Protocol::Nvmf => {
-                match lvol.share_nvmf().await {
-                    Ok(s) => {
-                        debug!("created and shared {} as {}", lvol, s);
-                        Ok(lvol)
-                    }
-                    Err(e) => {
-                        debug!(
-                            "failed to share created lvol {}: {} .. destroying",
-                            lvol,
-                            e.to_string()
-                        );
-                        let _ = lvol.destroy().await;
-                        Err(e)
-                    }
-                }
+        let lvol = p.create_lvol(&args.uuid, args.size, args.thin).await?;
+        if args.reservation > 0 {
+            if let Err(e) = lvol.reserve(args.reservation).await {
+                let _ = lvol.destroy().await;
+                return Err(e);
+            }
+        }
+        if let Some(props) = props {
+            if let Err(e) = set_created_properties(&lvol, props).await {
+                let _ = lvol.destroy().await;
+                return Err(e);
+            }
+        }
+        share_created(lvol, args.share).await
+    })
+}
+
+/// sets the initial properties of the created lvol, including its owner
+async fn set_created_properties(
+    lvol: &Lvol,
+    props: LvolProperties,
+) -> Result<(), LvsError> {
+    lvol.set_properties(&props).await?;
+    if !props.nexus_uuid.is_empty() {
+        lvol.transfer_ownership(&props.nexus_uuid, props.owner_generation)
+            .await?;
+    }
+    Ok(())
+}
+
+/// shares the created lvol with the protocol `share`, destroying the lvol if
+/// it fails to share
+async fn share_created(lvol: Lvol, share: i32) -> Result<Lvol, LvsError> {
+    let result = match Protocol::from(share) {
+        Protocol::Off => {
+            debug!("created lvol {}", lvol);
+            return Ok(lvol);
+        }
+        Protocol::Nvmf => lvol.share_nvmf().await,
+        Protocol::Iscsi => lvol.share_iscsi().await,
+    };
+
+    match result {
+        Ok(s) => {
+            debug!("created and shared {} as {}", lvol, s);
+            Ok(lvol)
+        }
+        Err(e) => {
+            debug!(
+                "failed to share created lvol {}: {} .. destroying",
+                lvol,
+                e.to_string()
+            );
+            let _ = lvol.destroy().await;
+            Err(e)
+        }
+    }
+}
+
+/// create a thin writable clone of a snapshot on the pool of the snapshot,
+/// returns OK if the clone of that snapshot already exists. If the clone fails
+/// to share, it will be destroyed prior to returning an error.
+#[instrument(level = "debug", err)]
+pub async fn create_clone(args: CreateCloneRequest) -> GrpcResult<Replica> {
+    let snapshot = match Bdev::lookup_by_name(&args.snapshot) {
+        Some(b) => Lvol::try_from(b)?,
+        None => return Err(Status::not_found(args.snapshot)),
+    };
+
+    if let Some(b) = Bdev::lookup_by_name(&args.uuid) {
+        return match Lvol::try_from(b) {
+            Ok(lvol) if lvol.is_clone_of(&snapshot) => {
+                Ok(Response::new(Replica::from(lvol)))
             }
-            Ok(lvol) => {
-                debug!("created lvol {}", lvol);
-                Ok(lvol)
+            _ => Err(Status::already_exists(format!(
+                "{} exists and is not a clone of {}",
+                args.uuid, args.snapshot
+            ))),
+        };
+    }
+
+    if !matches!(Protocol::from(args.share), Protocol::Off | Protocol::Nvmf) {
+        return Err(Status::invalid_argument(format!(
+            "invalid protocol {}",
+            args.share
+        )));
+    }
+
+    rpc_call(async move {
+        let lvol = snapshot.create_clone(&args.uuid).await?;
+        share_created(lvol, args.share).await
+    })
+}
+
+/// make a clone independent of its parent snapshot, the replica stays
+/// shared while it is inflated
+#[instrument(level = "debug", err)]
+pub async fn inflate_replica(
+    args: InflateReplicaRequest,
+) -> GrpcResult<Replica> {
+    rpc_call(async move {
+        match Bdev::lookup_by_name(&args.uuid) {
+            Some(b) => {
+                let lvol = Lvol::try_from(b)?;
+                lvol.inflate(args.decouple).await.map(|_| lvol)
             }
-            Err(e) => Err(e),
+            None => Err(LvsError::InvalidBdev {
+                source: NexusBdevError::BdevNotFound {
+                    name: args.uuid.clone(),
+                },
+                name: args.uuid,
+            }),
         }
     })
 }
 
-/// destroy the replica on the given pool, returning OK if the replica was
-/// not found
+/// destroy the replica on the given pool after wiping its data with the
+/// requested method or the default of its pool, returning OK if the replica
+/// was not found
 #[instrument(level = "debug", err)]
 pub async fn destroy_replica(args: DestroyReplicaRequest) -> GrpcResult<Null> {
+    let method = WipeMethod::of_request(args.wipe)?;
+    // wiping a large replica takes a while, during which the other calls,
+    // including the listing of the wipes, are served
+    crate::locally! { async move {
+        match Bdev::lookup_by_name(&args.uuid).map(Lvol::try_from) {
+            Some(Ok(lvol)) => lvol.destroy_wiped(method).await.map(|_| ()),
+            Some(Err(e)) => Err(e),
+            None => Ok(()),
+        }
+    }};
+    Ok(Response::new(Null {}))
+}
+
+/// list the replicas being wiped before they are destroyed
+pub fn list_wipes() -> GrpcResult<ListWipesReply> {
+    Ok(Response::new(ListWipesReply {
+        wipes: WipeProgress::list()
+            .into_iter()
+            .map(RpcWipeProgress::from)
+            .collect(),
+    }))
+}
+
+/// move the replica to another pool of the same node, which works while the
+/// replica is shared as it keeps its share URI
+#[instrument(level = "debug", err)]
+pub async fn migrate_replica(
+    args: MigrateReplicaRequest,
+) -> GrpcResult<Replica> {
+    // copying a large replica takes a while, during which the other calls,
+    // including the listing of the rebuilds copying it, are served
+    let replica = crate::locally! { async move {
+        let lvol = match Bdev::lookup_by_name(&args.uuid).map(Lvol::try_from) {
+            Some(Ok(lvol)) => lvol,
+            Some(Err(e)) => return Err(e),
+            None => {
+                return Err(LvsError::InvalidBdev {
+                    source: NexusBdevError::BdevNotFound {
+                        name: args.uuid.clone(),
+                    },
+                    name: args.uuid,
+                })
+            }
+        };
+        match Lvs::lookup(&args.pool) {
+            Some(pool) => lvol.migrate(&pool).await.map(Replica::from),
+            None => Err(LvsError::Invalid {
+                source: Errno::ENOENT,
+                msg: format!("pool {} not found", args.pool),
+            }),
+        }
+    }};
+    Ok(Response::new(replica))
+}
+
+/// grow the replica to the given size, which works while the replica is
+/// shared so that the nexus using it can pick up the new size
+#[instrument(level = "debug", err)]
+pub async fn resize_replica(args: ResizeReplicaRequest) -> GrpcResult<Replica> {
+    rpc_call(async move {
+        match Bdev::lookup_by_name(&args.uuid) {
+            Some(b) => {
+                let lvol = Lvol::try_from(b)?;
+                lvol.resize(args.size).await.map(|_| lvol)
+            }
+            None => Err(LvsError::InvalidBdev {
+                source: NexusBdevError::BdevNotFound {
+                    name: args.uuid.clone(),
+                },
+                name: args.uuid,
+            }),
+        }
+    })
+}
+
+/// set the properties of the replica, replacing all of them but its creation
+/// time and its owner
+#[instrument(level = "debug", err)]
+pub async fn set_replica_properties(
+    args: SetReplicaPropertiesRequest,
+) -> GrpcResult<Replica> {
+    rpc_call(async move {
+        match Bdev::lookup_by_name(&args.uuid) {
+            Some(b) => {
+                let lvol = Lvol::try_from(b)?;
+                let props = args.properties.unwrap_or_default().into();
+                lvol.set_properties(&props).await.map(|_| lvol)
+            }
+            None => Err(LvsError::InvalidBdev {
+                source: NexusBdevError::BdevNotFound {
+                    name: args.uuid.clone(),
+                },
+                name: args.uuid,
+            }),
+        }
+    })
+}
+
+/// make the given nexus the owner of the replica, which is only allowed if the
+/// generation of the ownership is newer than the current one
+#[instrument(level = "debug", err)]
+pub async fn transfer_replica_ownership(
+    args: TransferReplicaOwnershipRequest,
+) -> GrpcResult<Replica> {
     rpc_call(async move {
         match Bdev::lookup_by_name(&args.uuid) {
             Some(b) => {
                 let lvol = Lvol::try_from(b)?;
-                lvol.destroy().await.map(|_r| Null {})
+                lvol.transfer_ownership(&args.nexus_uuid, args.generation)
+                    .await
+                    .map(|_| lvol)
             }
-            None => Ok(Null {}),
+            None => Err(LvsError::InvalidBdev {
+                source: NexusBdevError::BdevNotFound {
+                    name: args.uuid.clone(),
+                },
+                name: args.uuid,
+            }),
         }
     })
 }
@@ -203,8 +695,8 @@ pub fn list_replicas() -> GrpcResult<ListReplicasReply> {
     }))
 }
 
-/// shares the replica over nvmf -- replicas are always shared over nvmf if
-/// already shared returns OK.
+/// shares the replica over the given protocol, or unshares it, if already
+/// shared that way returns OK.
 ///
 /// There is no unshare RPC in mayastor_svc
 #[instrument(level = "debug", err)]
@@ -233,12 +725,11 @@ pub async fn share_replica(
                         uri: lvol.share_uri().unwrap(),
                     })
                 }
-                Protocol::Iscsi => Err(LvsError::LvolShare {
-                    source: CoreError::NotSupported {
-                        source: Errno::ENOSYS,
-                    },
-                    name: args.uuid,
-                }),
+                Protocol::Iscsi => {
+                    lvol.share_iscsi().await.map(|_| ShareReplicaReply {
+                        uri: lvol.share_uri().unwrap(),
+                    })
+                }
             }
         } else {
             Err(LvsError::InvalidBdev {
@@ -1,7 +1,12 @@
 use nix::errno::Errno;
 use snafu::Snafu;
 
-use crate::{core::CoreError, lvs::PropName, nexus_uri::NexusBdevError};
+use crate::{
+    core::CoreError,
+    lvs::PropName,
+    nexus_uri::NexusBdevError,
+    rebuild::RebuildError,
+};
 
 #[derive(Debug, Snafu)]
 #[snafu(visibility = "pub(crate)")]
@@ -21,6 +26,28 @@ pub enum Error {
         name: String,
     },
 
+    #[snafu(display("failed to flush the disks of pool {}", name))]
+    Flush { source: CoreError, name: String },
+
+    #[snafu(display("failed to stripe the disks of pool {}", name))]
+    StripeDisks { source: Errno, name: String },
+
+    #[snafu(display("failed to remove the striped disks of pool {}", name))]
+    UnstripeDisks { source: Errno, name: String },
+
+    #[snafu(display(
+        "pool {} can only be repaired when freshly imported and unused",
+        name
+    ))]
+    CheckRepair { source: Errno, name: String },
+
+    #[snafu(display("failed to set the {} of pool {}", setting, name))]
+    SetSetting {
+        source: Errno,
+        setting: String,
+        name: String,
+    },
+
     InvalidBdev {
         source: NexusBdevError,
         name: String,
@@ -38,6 +65,65 @@ pub enum Error {
     #[snafu(display("failed to destroy lvol {}", name))]
     RepDestroy { source: Errno, name: String },
 
+    #[snafu(display("failed to resize lvol {}", name))]
+    RepResize { source: Errno, name: String },
+
+    #[snafu(display("snapshot {} not found", name))]
+    SnapshotNotFound { source: Errno, name: String },
+
+    #[snafu(display("failed to wipe lvol {}", name))]
+    RepWipe { source: CoreError, name: String },
+
+    #[snafu(display("failed to copy lvol {} into {}", name, copy))]
+    RepCopy {
+        source: RebuildError,
+        name: String,
+        copy: String,
+    },
+
+    #[snafu(display("failed to track the writes to lvol {}", name))]
+    RepTrackWrites { source: Errno, name: String },
+
+    #[snafu(display("failed to rename lvol {} to {}", name, new_name))]
+    RepRename {
+        source: Errno,
+        name: String,
+        new_name: String,
+    },
+
+    #[snafu(display("failed to revert lvol {} to its snapshot", name))]
+    RepRevert { source: CoreError, name: String },
+
+    #[snafu(display("lvol {} is being reverted to its snapshot", name))]
+    RepRevertBusy { source: Errno, name: String },
+
+    #[snafu(display("failed to clone snapshot {} into {}", snapshot, name))]
+    RepClone {
+        source: Errno,
+        snapshot: String,
+        name: String,
+    },
+
+    #[snafu(display("failed to reserve space for lvol {}", name))]
+    RepReserve { source: Errno, name: String },
+
+    #[snafu(display("failed to allocate the reservation of lvol {}", name))]
+    RepAllocate { source: CoreError, name: String },
+
+    #[snafu(display("failed to inflate lvol {}", name))]
+    RepInflate { source: Errno, name: String },
+
+    #[snafu(display(
+        "ownership generation {} of lvol {} is outdated",
+        generation,
+        name
+    ))]
+    StaleOwner {
+        source: Errno,
+        name: String,
+        generation: u64,
+    },
+
     #[snafu(display("bdev {} is not a lvol", name))]
     NotALvol { source: Errno, name: String },
 
@@ -1,32 +1,45 @@
 use std::{
+    collections::{BTreeMap, HashMap},
     convert::TryFrom,
     ffi::{c_void, CStr},
     fmt::Display,
     os::raw::c_char,
     ptr::NonNull,
+    sync::{Mutex, MutexGuard},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use async_trait::async_trait;
 use futures::channel::oneshot;
 use nix::errno::Errno;
+use once_cell::sync::OnceCell;
 use pin_utils::core_reexport::fmt::Formatter;
 use tracing::instrument;
 
 use spdk_sys::{
+    lvol_allocated_clusters,
+    lvs_blob_cluster,
+    spdk_blob_get_id,
+    spdk_blob_get_parent_snapshot,
     spdk_blob_get_xattr_value,
+    spdk_blob_is_clone,
     spdk_blob_is_read_only,
     spdk_blob_is_snapshot,
     spdk_blob_set_xattr,
     spdk_blob_sync_md,
     spdk_lvol,
+    spdk_lvol_decouple_parent,
+    spdk_lvol_inflate,
+    vbdev_lvol_create_clone,
     vbdev_lvol_create_snapshot,
     vbdev_lvol_destroy,
     vbdev_lvol_get_from_bdev,
+    vbdev_lvol_resize,
 };
 
 use crate::{
     bdev::nexus::nexus_bdev::Nexus,
-    core::{Bdev, CoreError, Mthread, Protocol, Share},
+    core::{Bdev, BdevHandle, CoreError, Mthread, Protocol, Reactors, Share},
     ffihelper::{
         cb_arg,
         errno_result_from_i32,
@@ -36,27 +49,111 @@ use crate::{
         IntoCString,
     },
     lvs::{error::Error, lvs_pool::Lvs},
-    subsys::NvmfReq,
+    subsys::{nexus_host_nqn, NvmfReq, NvmfSubsystem},
+    target::{iscsi, Side},
 };
 
+/// size of the chunks in which a snapshot is copied back into its lvol
+const REVERT_CHUNK_SIZE: u64 = 1024 * 1024;
+
+/// State of the revert of a lvol to one of its snapshots in the background, on
+/// behalf of a nexus on another node
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RevertState {
+    /// no revert has been started since the lvol was loaded
+    None,
+    /// the lvol is being reverted
+    Running,
+    /// the lvol has been reverted
+    Done,
+    /// the revert failed, the lvol may have been reverted in part
+    Failed,
+}
+
+impl From<u8> for RevertState {
+    fn from(state: u8) -> Self {
+        match state {
+            1 => Self::Running,
+            2 => Self::Done,
+            3 => Self::Failed,
+            _ => Self::None,
+        }
+    }
+}
+
+impl From<RevertState> for u8 {
+    fn from(state: RevertState) -> Self {
+        match state {
+            RevertState::None => 0,
+            RevertState::Running => 1,
+            RevertState::Done => 2,
+            RevertState::Failed => 3,
+        }
+    }
+}
+
+/// state of the reverts in the background by the name of their lvol
+fn reverts() -> MutexGuard<'static, HashMap<String, RevertState>> {
+    static REVERTS: OnceCell<Mutex<HashMap<String, RevertState>>> =
+        OnceCell::new();
+
+    REVERTS
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .expect("lvol reverts poisoned")
+}
+
+/// largest size of the value of a property including its terminating nul, as
+/// the length of an xattr of a blob is stored in 16 bits
+const MAX_PROP_SIZE: usize = u16::MAX as usize;
+
 /// properties we allow for being set on the lvol, this information is stored on
 /// disk
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 #[non_exhaustive]
 pub enum PropValue {
-    Shared(bool),
+    /// protocol the lvol is shared with, restored when the pool is imported
+    Shared(Protocol),
+    /// uuid of the nexus owning the lvol
+    NexusUuid(String),
+    /// generation of the ownership of the nexus owning the lvol
+    OwnerGeneration(u64),
+    /// uuid of the volume the lvol belongs to
+    VolumeUuid(String),
+    /// user labels of the lvol
+    Labels(BTreeMap<String, String>),
+    /// creation time of the lvol in seconds since the Unix epoch
+    Created(u64),
+    /// NQNs of the hosts allowed to connect to the lvol, empty for any host
+    AllowedHosts(Vec<String>),
+    /// bytes of the pool reserved for a thin provisioned lvol
+    Reservation(u64),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone)]
 #[non_exhaustive]
 pub enum PropName {
     Shared,
+    NexusUuid,
+    OwnerGeneration,
+    VolumeUuid,
+    Labels,
+    Created,
+    AllowedHosts,
+    Reservation,
 }
 
-impl From<PropValue> for PropName {
-    fn from(v: PropValue) -> Self {
+impl From<&PropValue> for PropName {
+    fn from(v: &PropValue) -> Self {
         match v {
             PropValue::Shared(_) => Self::Shared,
+            PropValue::NexusUuid(_) => Self::NexusUuid,
+            PropValue::OwnerGeneration(_) => Self::OwnerGeneration,
+            PropValue::VolumeUuid(_) => Self::VolumeUuid,
+            PropValue::Labels(_) => Self::Labels,
+            PropValue::Created(_) => Self::Created,
+            PropValue::AllowedHosts(_) => Self::AllowedHosts,
+            PropValue::Reservation(_) => Self::Reservation,
         }
     }
 }
@@ -65,11 +162,114 @@ impl Display for PropName {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let name = match self {
             PropName::Shared => "shared",
+            PropName::NexusUuid => "nexus_uuid",
+            PropName::OwnerGeneration => "owner_generation",
+            PropName::VolumeUuid => "volume_uuid",
+            PropName::Labels => "labels",
+            PropName::Created => "created",
+            PropName::AllowedHosts => "allowed_hosts",
+            PropName::Reservation => "reservation",
         };
         write!(f, "{}", name)
     }
 }
 
+impl PropValue {
+    /// returns the value as it is stored on disk
+    fn encode(&self) -> String {
+        match self {
+            // lvols used to be shared over nvmf only, which was stored as
+            // "true", so that value keeps meaning nvmf
+            PropValue::Shared(protocol) => match protocol {
+                Protocol::Off => "false",
+                Protocol::Nvmf => "true",
+                Protocol::Iscsi => "iscsi",
+            }
+            .to_string(),
+            PropValue::NexusUuid(uuid) | PropValue::VolumeUuid(uuid) => {
+                uuid.clone()
+            }
+            PropValue::Labels(labels) => {
+                serde_json::to_string(labels).unwrap_or_default()
+            }
+            PropValue::OwnerGeneration(val)
+            | PropValue::Created(val)
+            | PropValue::Reservation(val) => val.to_string(),
+            PropValue::AllowedHosts(hosts) => {
+                serde_json::to_string(hosts).unwrap_or_default()
+            }
+        }
+    }
+
+    /// returns an error if the value is too large to be stored on disk as a
+    /// property of the lvol `name`
+    fn validate(&self, name: &str) -> Result<(), Error> {
+        if self.encode().len() < MAX_PROP_SIZE {
+            return Ok(());
+        }
+        Err(Error::SetProperty {
+            source: Errno::EINVAL,
+            prop: self.into(),
+            name: name.to_string(),
+        })
+    }
+
+    /// returns the value of the property `prop` as it is stored on disk
+    fn decode(prop: PropName, value: &str) -> Option<Self> {
+        match prop {
+            PropName::Shared => match value {
+                "false" => Some(PropValue::Shared(Protocol::Off)),
+                "true" => Some(PropValue::Shared(Protocol::Nvmf)),
+                "iscsi" => Some(PropValue::Shared(Protocol::Iscsi)),
+                _ => None,
+            },
+            PropName::NexusUuid => Some(PropValue::NexusUuid(value.into())),
+            PropName::OwnerGeneration => {
+                value.parse().ok().map(PropValue::OwnerGeneration)
+            }
+            PropName::VolumeUuid => Some(PropValue::VolumeUuid(value.into())),
+            PropName::Labels => {
+                serde_json::from_str(value).ok().map(PropValue::Labels)
+            }
+            PropName::Created => value.parse().ok().map(PropValue::Created),
+            PropName::AllowedHosts => serde_json::from_str(value)
+                .ok()
+                .map(PropValue::AllowedHosts),
+            PropName::Reservation => {
+                value.parse().ok().map(PropValue::Reservation)
+            }
+        }
+    }
+}
+
+/// The metadata of a lvol which is stored on disk, the properties which have
+/// never been set are empty
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LvolProperties {
+    /// uuid of the nexus owning the lvol
+    pub nexus_uuid: String,
+    /// generation of the ownership of the nexus owning the lvol
+    pub owner_generation: u64,
+    /// uuid of the volume the lvol belongs to
+    pub volume_uuid: String,
+    /// user labels of the lvol
+    pub labels: BTreeMap<String, String>,
+    /// creation time of the lvol in seconds since the Unix epoch
+    pub created: u64,
+    /// NQNs of the hosts allowed to connect to the lvol, empty for any host
+    pub allowed_hosts: Vec<String>,
+}
+
+impl LvolProperties {
+    /// returns an error if one of the user settable properties is too large
+    /// to be stored on disk as a property of the lvol `name`
+    pub fn validate(&self, name: &str) -> Result<(), Error> {
+        PropValue::VolumeUuid(self.volume_uuid.clone()).validate(name)?;
+        PropValue::Labels(self.labels.clone()).validate(name)?;
+        PropValue::AllowedHosts(self.allowed_hosts.clone()).validate(name)
+    }
+}
+
 #[derive(Debug)]
 /// struct representing an lvol
 pub struct Lvol(pub(crate) NonNull<spdk_lvol>);
@@ -110,27 +310,40 @@ impl Share for Lvol {
     type Error = Error;
     type Output = String;
 
-    /// we dont (want to) support replica's over iSCSI
+    /// share the lvol as an iscsi target on the replica portal
+    #[instrument(level = "debug", err)]
     async fn share_iscsi(&self) -> Result<Self::Output, Self::Error> {
-        Err(Error::LvolShare {
-            source: CoreError::NotSupported {
-                source: Errno::EINVAL,
-            },
-            name: self.name(),
-        })
+        let share = iscsi::share(&self.name(), &self.as_bdev(), Side::Replica)
+            .map_err(|source| Error::LvolShare {
+                source: CoreError::ShareIscsi {
+                    source,
+                },
+                name: self.name(),
+            })?;
+
+        self.set(PropValue::Shared(Protocol::Iscsi)).await?;
+        info!("shared {}", self);
+        Ok(share)
     }
 
     /// share the lvol as a nvmf target
     #[instrument(level = "debug", err)]
     async fn share_nvmf(&self) -> Result<Self::Output, Self::Error> {
-        let share = self.as_bdev().share_nvmf().await.map_err(|e| {
-            Error::LvolShare {
-                source: e,
-                name: self.name(),
-            }
-        })?;
+        let share_err = |source| Error::LvolShare {
+            source: CoreError::ShareNvmf {
+                source,
+            },
+            name: self.name(),
+        };
 
-        self.set(PropValue::Shared(true)).await?;
+        let ss = NvmfSubsystem::try_from(self.as_bdev()).map_err(share_err)?;
+        if let Err(e) = ss.allow_hosts(&self.allowed_hosts()) {
+            ss.destroy();
+            return Err(share_err(e));
+        }
+        let share = ss.start().await.map_err(share_err)?;
+
+        self.set(PropValue::Shared(Protocol::Nvmf)).await?;
         info!("shared {}", self);
         Ok(share)
     }
@@ -147,7 +360,7 @@ impl Share for Lvol {
                     name: self.name(),
                 })?;
 
-        self.set(PropValue::Shared(false)).await?;
+        self.set(PropValue::Shared(Protocol::Off)).await?;
         info!("unshared {}", self);
         Ok(share)
     }
@@ -159,7 +372,12 @@ impl Share for Lvol {
 
     /// returns the share URI this lvol is shared as
     fn share_uri(&self) -> Option<String> {
-        self.as_bdev().share_uri()
+        match self.shared() {
+            Some(Protocol::Iscsi) => {
+                iscsi::get_uri(Side::Replica, &self.name())
+            }
+            _ => self.as_bdev().share_uri(),
+        }
     }
 
     /// returns the URI that is used to construct the bdev. This is always None
@@ -208,11 +426,12 @@ impl Lvol {
 
     /// returns the pool of the lvol
     pub fn pool(&self) -> String {
-        unsafe {
-            Lvs(NonNull::new_unchecked(self.0.as_ref().lvol_store))
-                .name()
-                .to_string()
-        }
+        self.lvs().name().to_string()
+    }
+
+    /// returns the lvs the lvol belongs to
+    pub(crate) fn lvs(&self) -> Lvs {
+        unsafe { Lvs(NonNull::new_unchecked(self.0.as_ref().lvol_store)) }
     }
 
     /// returns a boolean indicating if the lvol is thin provisioned
@@ -220,6 +439,90 @@ impl Lvol {
         unsafe { self.0.as_ref().thin_provision }
     }
 
+    /// returns the number of bytes allocated to the lvol from its pool
+    pub fn allocated(&self) -> u64 {
+        unsafe { lvol_allocated_clusters(self.0.as_ptr()) }
+        *self.lvs().cluster_size()
+    }
+
+    /// returns the number of bytes of the pool reserved for the lvol
+    pub fn reservation(&self) -> u64 {
+        match self.get_xattr(PropName::Reservation) {
+            Ok(PropValue::Reservation(bytes)) => bytes,
+            _ => 0,
+        }
+    }
+
+    /// returns the number of bytes of the reservation of the lvol which are
+    /// not allocated yet, which other lvols can not allocate
+    pub fn reserved(&self) -> u64 {
+        self.reservation().saturating_sub(self.allocated())
+    }
+
+    /// reserves `bytes` of the pool for the thin provisioned lvol by
+    /// allocating the clusters holding its first `bytes`, so that they can be
+    /// written to without running out of space. The reservation is limited
+    /// to the size of the lvol and it fails if the pool does not have enough
+    /// unreserved free space
+    #[instrument(level = "debug", err)]
+    pub async fn reserve(&self, bytes: u64) -> Result<(), Error> {
+        let bytes = bytes.min(self.size());
+        if self.is_thin() {
+            self.allocate(bytes).await?;
+        }
+
+        self.set(PropValue::Reservation(bytes)).await?;
+        info!("{}: reserved {} bytes", self, bytes);
+        Ok(())
+    }
+
+    /// allocates the clusters holding the first `bytes` of the lvol which
+    /// are not allocated yet by writing zeroes to them. Clones can not be
+    /// allocated this way, as their unallocated clusters hold the data of
+    /// their snapshot
+    async fn allocate(&self, bytes: u64) -> Result<(), Error> {
+        let reserve_err = |source| Error::RepReserve {
+            source,
+            name: self.name(),
+        };
+        let allocate_err = |source| Error::RepAllocate {
+            source,
+            name: self.name(),
+        };
+        if self.is_clone() {
+            error!("{}: can not allocate the clusters of a clone", self);
+            return Err(reserve_err(Errno::EINVAL));
+        }
+
+        let lvs = self.lvs();
+        let cluster_size = lvs.cluster_size();
+        let blob = unsafe { self.0.as_ref().blob };
+        let clusters = (0 .. (bytes + cluster_size - 1) / cluster_size)
+            .filter(|i| unsafe { lvs_blob_cluster(blob, *i) } == 0)
+            .collect::<Vec<_>>();
+        let needed = clusters.len() as u64 * cluster_size;
+        if needed > lvs.unreserved() {
+            error!(
+                "{}: {} bytes needed for the reservation, {} unreserved in pool {}",
+                self,
+                needed,
+                lvs.unreserved(),
+                lvs.name()
+            );
+            return Err(reserve_err(Errno::ENOSPC));
+        }
+
+        let handle = BdevHandle::open_with_bdev(&self.as_bdev(), true)
+            .map_err(allocate_err)?;
+        for i in clusters {
+            handle
+                .write_zeroes(i * cluster_size, cluster_size)
+                .await
+                .map_err(allocate_err)?;
+        }
+        Ok(())
+    }
+
     /// returns a boolean indicating if the lvol is read-only
     pub fn is_read_only(&self) -> bool {
         unsafe { spdk_blob_is_read_only(self.0.as_ref().blob) }
@@ -230,6 +533,25 @@ impl Lvol {
         unsafe { spdk_blob_is_snapshot(self.0.as_ref().blob) }
     }
 
+    /// returns a boolean indicating if the lvol is a clone of a snapshot
+    pub fn is_clone(&self) -> bool {
+        unsafe { spdk_blob_is_clone(self.0.as_ref().blob) }
+    }
+
+    /// returns a boolean indicating if the lvol is a clone of the given
+    /// snapshot
+    pub fn is_clone_of(&self, snapshot: &Lvol) -> bool {
+        if self.lvs().name() != snapshot.lvs().name() {
+            return false;
+        }
+        unsafe {
+            let bs = self.lvs().0.as_ref().blobstore;
+            let id = spdk_blob_get_id(self.0.as_ref().blob);
+            spdk_blob_get_parent_snapshot(bs, id)
+                == spdk_blob_get_id(snapshot.0.as_ref().blob)
+        }
+    }
+
     /// destroy the lvol
     #[instrument(level = "debug", err)]
     pub async fn destroy(self) -> Result<String, Error> {
@@ -260,6 +582,168 @@ impl Lvol {
         Ok(name)
     }
 
+    /// grows the lvol to `size` bytes, which is rounded up to a multiple of
+    /// the cluster size of the pool. The clusters of a thick lvol are
+    /// allocated right away. The new size is announced to the users of the
+    /// lvol, including the hosts connected to it over nvmf.
+    #[instrument(level = "debug", err)]
+    pub async fn resize(&self, size: u64) -> Result<(), Error> {
+        extern "C" fn resize_cb(sender: *mut c_void, errno: i32) {
+            let sender =
+                unsafe { Box::from_raw(sender as *mut oneshot::Sender<i32>) };
+            sender.send(errno).unwrap();
+        }
+
+        let current = self.size();
+        if size == current {
+            return Ok(());
+        }
+
+        if size < current {
+            error!(
+                "{}: can not shrink from {} to {} bytes",
+                self.name(),
+                current,
+                size
+            );
+            return Err(Error::RepResize {
+                source: Errno::EINVAL,
+                name: self.name(),
+            });
+        }
+
+        // a thick lvol allocates the clusters it grows by right away
+        let lvs = self.lvs();
+        let cluster_size = lvs.cluster_size();
+        let clusters = |bytes: u64| (bytes + cluster_size - 1) / cluster_size;
+        let needed = if self.is_thin() {
+            0
+        } else {
+            (clusters(size) - clusters(current)) * cluster_size
+        };
+        lvs.check_space(&self.name(), size - current, needed)
+            .map_err(|source| Error::RepResize {
+                source,
+                name: self.name(),
+            })?;
+
+        let (s, r) = pair::<i32>();
+        unsafe {
+            vbdev_lvol_resize(self.0.as_ptr(), size, Some(resize_cb), cb_arg(s))
+        };
+
+        r.await
+            .expect("lvol resize callback is gone")
+            .to_result(|e| Error::RepResize {
+                source: Errno::from_i32(e),
+                name: self.name(),
+            })?;
+
+        info!("Resized {} from {} to {} bytes", self, current, self.size());
+        Ok(())
+    }
+
+    /// creates the thin provisioned lvol `name` on the pool of this snapshot,
+    /// which is a writable clone of the snapshot
+    #[instrument(level = "debug", err)]
+    pub async fn create_clone(&self, name: &str) -> Result<Lvol, Error> {
+        let clone_err = |source| Error::RepClone {
+            source,
+            snapshot: self.name(),
+            name: name.to_string(),
+        };
+
+        if !self.is_snapshot() {
+            error!("{} is not a snapshot and can not be cloned", self);
+            return Err(clone_err(Errno::EINVAL));
+        }
+
+        if Bdev::lookup_by_name(name).is_some() {
+            return Err(Error::RepExists {
+                source: Errno::EEXIST,
+                name: name.to_string(),
+            });
+        };
+
+        // the clone is thin provisioned but commits the size of the snapshot
+        let lvs = self.lvs();
+        lvs.check_space(name, self.size(), 0).map_err(|source| {
+            Error::RepCreate {
+                source,
+                name: name.to_string(),
+            }
+        })?;
+
+        let (s, r) = pair::<ErrnoResult<*mut spdk_lvol>>();
+        let cname = name.into_cstring();
+        unsafe {
+            vbdev_lvol_create_clone(
+                self.0.as_ptr(),
+                cname.as_ptr(),
+                Some(Self::lvol_cb),
+                cb_arg(s),
+            )
+        };
+
+        let clone = r
+            .await
+            .expect("lvol clone callback dropped")
+            .map_err(clone_err)
+            .map(|lvol| Lvol(NonNull::new(lvol).unwrap()))?;
+
+        // the clone is not shared, whatever the snapshot was
+        clone.set(PropValue::Shared(Protocol::Off)).await?;
+        clone.set_created().await?;
+
+        info!("cloned {} into {}", self, clone);
+        Ok(clone)
+    }
+
+    /// makes this clone independent of its parent snapshot, so that the
+    /// snapshot can be destroyed. Inflating allocates all clusters of the
+    /// lvol, decoupling only allocates the clusters holding data of the
+    /// parent snapshot.
+    #[instrument(level = "debug", err)]
+    pub async fn inflate(&self, decouple: bool) -> Result<(), Error> {
+        extern "C" fn inflate_cb(sender: *mut c_void, errno: i32) {
+            let sender =
+                unsafe { Box::from_raw(sender as *mut oneshot::Sender<i32>) };
+            sender.send(errno).unwrap();
+        }
+
+        if !self.is_clone() {
+            debug!("{} is not a clone, nothing to inflate", self);
+            return Ok(());
+        }
+
+        let (s, r) = pair::<i32>();
+        unsafe {
+            if decouple {
+                spdk_lvol_decouple_parent(
+                    self.0.as_ptr(),
+                    Some(inflate_cb),
+                    cb_arg(s),
+                )
+            } else {
+                spdk_lvol_inflate(self.0.as_ptr(), Some(inflate_cb), cb_arg(s))
+            }
+        };
+
+        r.await
+            .expect("lvol inflate callback is gone")
+            .to_result(|e| Error::RepInflate {
+                source: Errno::from_i32(e),
+                name: self.name(),
+            })?;
+
+        info!(
+            "{} {}",
+            if decouple { "Decoupled" } else { "Inflated" },
+            self
+        );
+        Ok(())
+    }
+
     /// callback executed after synchronizing the lvols metadata
     extern "C" fn blob_sync_cb(sender_ptr: *mut c_void, errno: i32) {
         let sender =
@@ -268,38 +752,143 @@ impl Lvol {
     }
 
     /// write the property prop on to the lvol which is stored on disk
-    #[allow(clippy::unit_arg)] // here to silence the Ok(()) variant
     #[instrument(level = "debug", err)]
     pub async fn set(&self, prop: PropValue) -> Result<(), Error> {
-        let blob = unsafe { self.0.as_ref().blob };
-        assert_ne!(blob.is_null(), true);
-
         if self.is_snapshot() {
             warn!("ignoring set property on snapshot {}", self.name());
             return Ok(());
         }
+
+        self.set_xattr(&prop)?;
+        self.sync_md().await
+    }
+
+    /// write the user settable properties, that is all but the creation
+    /// time and the owner, on to the lvol in one go. A shared lvol is shared
+    /// anew when the hosts allowed to connect to it change
+    #[instrument(level = "debug", err)]
+    pub async fn set_properties(
+        &self,
+        props: &LvolProperties,
+    ) -> Result<(), Error> {
+        if self.is_snapshot() {
+            warn!("ignoring set properties on snapshot {}", self.name());
+            return Ok(());
+        }
+
+        props.validate(&self.name())?;
+
+        let hosts = self.allowed_hosts();
+        self.set_xattr(&PropValue::VolumeUuid(props.volume_uuid.clone()))?;
+        self.set_xattr(&PropValue::Labels(props.labels.clone()))?;
+        self.set_xattr(&PropValue::AllowedHosts(props.allowed_hosts.clone()))?;
+        self.sync_md().await?;
+
+        if hosts != self.allowed_hosts() {
+            self.reshare().await?;
+        }
+        Ok(())
+    }
+
+    /// makes the nexus `nexus_uuid` the owner of the lvol, or releases the
+    /// ownership if it is empty. The `generation` of the ownership must be
+    /// newer than the current one, which is 0 for a lvol that never had an
+    /// owner, so that an outdated transfer can not take the lvol away from
+    /// its owner. A shared lvol is shared anew, disconnecting the previous
+    /// owner which can not connect again
+    #[instrument(level = "debug", err)]
+    pub async fn transfer_ownership(
+        &self,
+        nexus_uuid: &str,
+        generation: u64,
+    ) -> Result<(), Error> {
+        let props = self.properties();
+        if props.nexus_uuid == nexus_uuid
+            && props.owner_generation == generation
+        {
+            return Ok(());
+        }
+
+        if generation <= props.owner_generation {
+            error!(
+                "{}: ownership generation {} is not newer than {}",
+                self, generation, props.owner_generation
+            );
+            return Err(Error::StaleOwner {
+                source: Errno::ESTALE,
+                name: self.name(),
+                generation,
+            });
+        }
+
+        self.set_xattr(&PropValue::NexusUuid(nexus_uuid.to_string()))?;
+        self.set_xattr(&PropValue::OwnerGeneration(generation))?;
+        self.sync_md().await?;
+        self.reshare().await?;
+
+        info!(
+            "{}: transferred ownership from nexus '{}' to '{}' generation {}",
+            self, props.nexus_uuid, nexus_uuid, generation
+        );
+        Ok(())
+    }
+
+    /// returns the NQNs of the hosts allowed to connect to the lvol over
+    /// nvmf, that is the owning nexus and the allowed hosts, or none if any
+    /// host is allowed to connect
+    fn allowed_hosts(&self) -> Vec<String> {
+        let props = self.properties();
+        if props.nexus_uuid.is_empty() {
+            return props.allowed_hosts;
+        }
+
+        let mut hosts =
+            vec![nexus_host_nqn(&props.nexus_uuid, props.owner_generation)];
+        hosts.extend(props.allowed_hosts);
+        hosts
+    }
+
+    /// shares the lvol anew if it is shared over nvmf, which disconnects all
+    /// hosts and applies the hosts which are allowed to connect
+    async fn reshare(&self) -> Result<(), Error> {
+        if self.shared() == Some(Protocol::Nvmf) {
+            self.unshare().await?;
+            self.share_nvmf().await?;
+        }
+        Ok(())
+    }
+
+    /// sets the xattr of the property prop, which is only stored on disk
+    /// once the metadata of the lvol is synchronized
+    fn set_xattr(&self, prop: &PropValue) -> Result<(), Error> {
+        let blob = unsafe { self.0.as_ref().blob };
+        assert_ne!(blob.is_null(), true);
+
         if self.is_read_only() {
             warn!("{} is read-only", self.name());
         }
-        match prop {
-            PropValue::Shared(val) => {
-                let name = PropName::from(prop).to_string().into_cstring();
-                let value = if val { "true" } else { "false" }.into_cstring();
-                unsafe {
-                    spdk_blob_set_xattr(
-                        blob,
-                        name.as_ptr(),
-                        value.as_bytes_with_nul().as_ptr() as *const _,
-                        value.as_bytes_with_nul().len() as u16,
-                    )
-                }
-                .to_result(|e| Error::SetProperty {
-                    source: Errno::from_i32(e),
-                    prop: prop.into(),
-                    name: self.name(),
-                })?;
-            }
-        };
+        prop.validate(&self.name())?;
+
+        let name = PropName::from(prop).to_string().into_cstring();
+        let value = prop.encode().into_cstring();
+        unsafe {
+            spdk_blob_set_xattr(
+                blob,
+                name.as_ptr(),
+                value.as_bytes_with_nul().as_ptr() as *const _,
+                value.as_bytes_with_nul().len() as u16,
+            )
+        }
+        .to_result(|e| Error::SetProperty {
+            source: Errno::from_i32(e),
+            prop: prop.into(),
+            name: self.name(),
+        })
+    }
+
+    /// synchronizes the metadata of the lvol, storing its properties on disk
+    async fn sync_md(&self) -> Result<(), Error> {
+        let blob = unsafe { self.0.as_ref().blob };
 
         let (s, r) = pair::<i32>();
         unsafe {
@@ -311,46 +900,82 @@ impl Lvol {
                 source: Errno::from_i32(e),
                 name: self.name(),
             }
-        })?;
-
-        Ok(())
+        })
     }
 
     /// get/read a property from this lvol from disk
     #[instrument(level = "debug", err)]
     pub async fn get(&self, prop: PropName) -> Result<PropValue, Error> {
+        self.get_xattr(prop)
+    }
+
+    /// reads the xattr of the property prop
+    fn get_xattr(&self, prop: PropName) -> Result<PropValue, Error> {
         let blob = unsafe { self.0.as_ref().blob };
         assert_ne!(blob.is_null(), true);
 
-        match prop {
-            PropName::Shared => {
-                let name = prop.to_string().into_cstring();
-                let mut value: *const libc::c_char =
-                    std::ptr::null::<libc::c_char>();
-                let mut value_len: u64 = 0;
-                unsafe {
-                    spdk_blob_get_xattr_value(
-                        blob,
-                        name.as_ptr(),
-                        &mut value as *mut *const c_char as *mut *const c_void,
-                        &mut value_len,
-                    )
+        let name = prop.to_string().into_cstring();
+        let mut value: *const libc::c_char = std::ptr::null::<libc::c_char>();
+        let mut value_len: u64 = 0;
+        unsafe {
+            spdk_blob_get_xattr_value(
+                blob,
+                name.as_ptr(),
+                &mut value as *mut *const c_char as *mut *const c_void,
+                &mut value_len,
+            )
+        }
+        .to_result(|e| Error::GetProperty {
+            source: Errno::from_i32(e),
+            prop,
+            name: self.name(),
+        })?;
+
+        unsafe { CStr::from_ptr(value).to_str() }
+            .ok()
+            .and_then(|v| PropValue::decode(prop, v))
+            .ok_or_else(|| Error::Property {
+                source: Errno::EINVAL,
+                name: self.name(),
+            })
+    }
+
+    /// returns the properties of the lvol, the properties which can not be
+    /// read are left empty
+    pub fn properties(&self) -> LvolProperties {
+        let mut props = LvolProperties::default();
+        for prop in &[
+            PropName::NexusUuid,
+            PropName::OwnerGeneration,
+            PropName::VolumeUuid,
+            PropName::Labels,
+            PropName::Created,
+            PropName::AllowedHosts,
+        ] {
+            match self.get_xattr(*prop) {
+                Ok(PropValue::NexusUuid(uuid)) => props.nexus_uuid = uuid,
+                Ok(PropValue::OwnerGeneration(generation)) => {
+                    props.owner_generation = generation
                 }
-                .to_result(|e| Error::GetProperty {
-                    source: Errno::from_i32(e),
-                    prop,
-                    name: self.name(),
-                })?;
-                match unsafe { CStr::from_ptr(value).to_str() } {
-                    Ok("true") => Ok(PropValue::Shared(true)),
-                    Ok("false") => Ok(PropValue::Shared(false)),
-                    _ => Err(Error::Property {
-                        source: Errno::EINVAL,
-                        name: self.name(),
-                    }),
+                Ok(PropValue::VolumeUuid(uuid)) => props.volume_uuid = uuid,
+                Ok(PropValue::Labels(labels)) => props.labels = labels,
+                Ok(PropValue::Created(time)) => props.created = time,
+                Ok(PropValue::AllowedHosts(hosts)) => {
+                    props.allowed_hosts = hosts
                 }
+                _ => {}
             }
         }
+        props
+    }
+
+    /// records the current time as the creation time of the lvol
+    pub(crate) async fn set_created(&self) -> Result<(), Error> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        self.set(PropValue::Created(now)).await
     }
 
     /// Format snapshot name
@@ -359,6 +984,168 @@ impl Lvol {
         format!("{}-snap-{}", base_name, snapshot_time)
     }
 
+    /// returns the snapshot time encoded in `name` if it is the name of a
+    /// snapshot of `base_name`, as formatted by format_snapshot_name
+    pub fn snapshot_time(base_name: &str, name: &str) -> Option<u64> {
+        name.strip_prefix(base_name)?
+            .strip_prefix("-snap-")?
+            .parse()
+            .ok()
+    }
+
+    /// returns the snapshots of this lvol and their creation time, oldest
+    /// first
+    pub fn snapshots(&self) -> Vec<(u64, Lvol)> {
+        let name = self.name();
+        let mut snapshots = self
+            .lvs()
+            .lvols()
+            .into_iter()
+            .flatten()
+            .filter(|l| l.is_snapshot())
+            .filter_map(|l| {
+                Self::snapshot_time(&name, &l.name()).map(|t| (t, l))
+            })
+            .collect::<Vec<_>>();
+        snapshots.sort_by_key(|(t, _)| *t);
+        snapshots
+    }
+
+    /// returns the snapshot of this lvol created at `time`
+    fn snapshot(&self, time: u64) -> Result<Lvol, Error> {
+        self.snapshots()
+            .into_iter()
+            .find(|(t, _)| *t == time)
+            .map(|(_, l)| l)
+            .ok_or_else(|| Error::SnapshotNotFound {
+                source: Errno::ENOENT,
+                name: Self::format_snapshot_name(&self.name(), time),
+            })
+    }
+
+    /// destroys the snapshot of this lvol created at `time`
+    pub async fn destroy_snapshot(&self, time: u64) -> Result<(), Error> {
+        self.snapshot(time)?.destroy().await.map(|_| ())
+    }
+
+    /// starts reverting this lvol to its snapshot created at `time` in the
+    /// background, as revert_to_snapshot does, the state of which is polled
+    /// with revert_state. A revert of a large lvol takes longer than the
+    /// nexus on another node can wait for a single command to complete.
+    pub fn start_revert(&self, time: u64) -> Result<(), Error> {
+        self.snapshot(time)?;
+
+        let name = self.name();
+        {
+            let mut reverts = reverts();
+            if reverts.get(&name) == Some(&RevertState::Running) {
+                return Err(Error::RepRevertBusy {
+                    source: Errno::EBUSY,
+                    name,
+                });
+            }
+            reverts.insert(name.clone(), RevertState::Running);
+        }
+
+        let lvol = Lvol(self.0);
+        Reactors::master().send_future(async move {
+            let state = match lvol.revert_to_snapshot(time).await {
+                Ok(_) => RevertState::Done,
+                Err(e) => {
+                    error!("failed to revert {} to its snapshot: {}", lvol, e);
+                    RevertState::Failed
+                }
+            };
+            reverts().insert(name, state);
+        });
+        Ok(())
+    }
+
+    /// returns the state of the last revert of this lvol started with
+    /// start_revert
+    pub fn revert_state(&self) -> RevertState {
+        reverts()
+            .get(&self.name())
+            .copied()
+            .unwrap_or(RevertState::None)
+    }
+
+    /// reverts the content of this lvol to that of its snapshot created at
+    /// `time`. The snapshot is copied back into the lvol, skipping the chunks
+    /// which have not changed since, so that the lvol keeps its identity and
+    /// its share. Should the lvol have grown since the snapshot was created,
+    /// the range beyond the end of the snapshot is zeroed. The caller must
+    /// make sure the lvol is not written to.
+    #[instrument(level = "debug", err)]
+    pub async fn revert_to_snapshot(&self, time: u64) -> Result<(), Error> {
+        let snapshot = self.snapshot(time)?;
+        let revert_err = |source| Error::RepRevert {
+            source,
+            name: self.name(),
+        };
+
+        let src = BdevHandle::open_with_bdev(&snapshot.as_bdev(), false)
+            .map_err(revert_err)?;
+        let dst = BdevHandle::open_with_bdev(&self.as_bdev(), true)
+            .map_err(revert_err)?;
+
+        let alloc = |size: u64| {
+            let src_buf = src.dma_malloc(size);
+            let dst_buf = dst.dma_malloc(size);
+            src_buf
+                .and_then(|s| dst_buf.map(|d| (s, d)))
+                .map_err(|source| {
+                    revert_err(CoreError::DmaAllocFailed {
+                        source,
+                        size,
+                    })
+                })
+        };
+
+        // the lvol may have grown since the snapshot was created
+        let size = snapshot.size().min(self.size());
+        let (mut src_buf, mut dst_buf) = alloc(REVERT_CHUNK_SIZE.min(size))?;
+        let mut copied = 0;
+        let mut offset = 0;
+        while offset < size {
+            let len = REVERT_CHUNK_SIZE.min(size - offset);
+            if len != src_buf.len() {
+                let (s, d) = alloc(len)?;
+                src_buf = s;
+                dst_buf = d;
+            }
+
+            src.read_at(offset, &mut src_buf)
+                .await
+                .map_err(revert_err)?;
+            dst.read_at(offset, &mut dst_buf)
+                .await
+                .map_err(revert_err)?;
+            if src_buf.as_slice() != dst_buf.as_slice() {
+                dst.write_at(offset, &src_buf).await.map_err(revert_err)?;
+                copied += len;
+            }
+            offset += len;
+        }
+
+        // the range the snapshot does not cover held no data when it was
+        // created
+        if self.size() > size {
+            dst.write_zeroes(size, self.size() - size)
+                .await
+                .map_err(revert_err)?;
+        }
+
+        info!(
+            "Reverted {} to snapshot {}, {} of {} bytes changed",
+            self,
+            snapshot.name(),
+            copied,
+            size
+        );
+        Ok(())
+    }
+
     /// Create a snapshot
     pub async fn create_snapshot(
         &self,
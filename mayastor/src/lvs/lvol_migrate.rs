@@ -0,0 +1,461 @@
+//! Migration of a lvol to another pool of the same node, eg to rebalance the
+//! disks of the node. The data is copied into a lvol created on the other
+//! pool by a rebuild job, which locks the LBA range of each segment on the
+//! lvol while copying it so that the lvol stays in use meanwhile.
+//!
+//! The writes to the disks of the pool of a shared lvol are tracked during
+//! the copy. The hosts connected to the lvol are then held while the chunks
+//! of its clusters which have been written to are copied again, the lvol
+//! makes way for the copy, which takes over its name, and the namespace of
+//! its nvmf subsystem is switched over to the copy, which keeps the share
+//! URI. The switch-over is the last step which can fail, as the copy holds
+//! the only up-to-date data from then on, and the lvol is destroyed.
+
+use std::{ffi::c_void, time::Duration};
+
+use futures::{channel::oneshot, StreamExt};
+use nix::errno::Errno;
+use tracing::instrument;
+
+use spdk_sys::{
+    lvs_blob_cluster,
+    lvs_track_writes,
+    lvs_untrack_writes,
+    lvs_written,
+    vbdev_lvol_rename,
+};
+
+use crate::{
+    bdev::nexus::nexus_io::IoType,
+    core::{BdevHandle, CoreError, Protocol, Share},
+    ffihelper::{cb_arg, pair, FfiResult, IntoCString},
+    lvs::{error::Error, Lvol, Lvs, PropName, PropValue},
+    rebuild::{
+        ClientOperations,
+        RebuildError,
+        RebuildJob,
+        RebuildMode,
+        RebuildState,
+    },
+    subsys::NvmfSubsystem,
+};
+
+/// suffix of the name of the copy of a lvol while it is migrated
+const MIGRATE_SUFFIX: &str = "-migrate";
+
+/// suffix of the name of a migrated lvol until it is destroyed
+const MIGRATED_SUFFIX: &str = "-migrated";
+
+/// maximum size of the chunks of the disks of a pool in which the writes are
+/// tracked while a lvol is migrated
+const MIGRATE_CHUNK_SIZE: u64 = 64 * 1024;
+
+/// Chunks of the disks of a pool written to since it was created, until it
+/// is dropped
+struct WrittenChunks {
+    lvs: Lvs,
+    chunk_size: u64,
+}
+
+impl WrittenChunks {
+    /// starts tracking the writes to the disks of the pool of the lvol
+    fn track(lvol: &Lvol) -> Result<Self, Error> {
+        let lvs = lvol.lvs();
+        let chunk_size = MIGRATE_CHUNK_SIZE.min(lvs.cluster_size());
+        unsafe { lvs_track_writes(lvs.0.as_ptr(), chunk_size) }.to_result(
+            |e| Error::RepTrackWrites {
+                source: Errno::from_i32(-e),
+                name: lvol.name(),
+            },
+        )?;
+        Ok(Self {
+            lvs,
+            chunk_size,
+        })
+    }
+
+    /// returns whether the chunk holding the byte at `offset` of the disks
+    /// has been written to
+    fn written(&self, offset: u64) -> bool {
+        unsafe { lvs_written(self.lvs.0.as_ptr(), offset) }
+    }
+}
+
+impl Drop for WrittenChunks {
+    fn drop(&mut self) {
+        unsafe { lvs_untrack_writes(self.lvs.0.as_ptr()) };
+    }
+}
+
+/// interval at which the progress of the copy of a lvol is checked
+const MIGRATE_WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+impl Lvol {
+    /// renames the lvol, and its bdev along with it
+    async fn rename(&self, name: &str) -> Result<(), Error> {
+        extern "C" fn rename_cb(sender: *mut c_void, errno: i32) {
+            let sender =
+                unsafe { Box::from_raw(sender as *mut oneshot::Sender<i32>) };
+            sender.send(errno).unwrap();
+        }
+
+        let (s, r) = pair::<i32>();
+        let cname = name.into_cstring();
+        unsafe {
+            vbdev_lvol_rename(
+                self.0.as_ptr(),
+                cname.as_ptr(),
+                Some(rename_cb),
+                cb_arg(s),
+            )
+        };
+
+        r.await
+            .expect("lvol rename callback is gone")
+            .to_result(|e| Error::RepRename {
+                source: Errno::from_i32(e),
+                name: self.name(),
+                new_name: name.to_string(),
+            })
+    }
+
+    /// renames the lvol with the `-migrated` suffix so that `copy` takes
+    /// over its name. The name of the lvol is restored should that fail
+    async fn make_way(&self, copy: &Lvol) -> Result<(), Error> {
+        let name = self.name();
+        self.rename(&format!("{}{}", name, MIGRATED_SUFFIX)).await?;
+        if let Err(e) = copy.rename(&name).await {
+            if let Err(e) = self.rename(&name).await {
+                error!("{}: failed to restore the name {}: {}", self, name, e);
+            }
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// gives the lvol its name `name` back after make_way(), and `copy` its
+    /// name `copy_name`
+    async fn restore_names(&self, name: &str, copy: &Lvol, copy_name: &str) {
+        if let Err(e) = copy.rename(copy_name).await {
+            error!("{}: failed to restore the name {}: {}", copy, copy_name, e);
+        }
+        if let Err(e) = self.rename(name).await {
+            error!("{}: failed to restore the name {}: {}", self, name, e);
+        }
+    }
+
+    /// copies the data of the lvol into `copy` with a rebuild job, which
+    /// locks the range of each segment on the lvol while it is copied
+    async fn copy_into(
+        &self,
+        copy: &Lvol,
+        mode: RebuildMode,
+    ) -> Result<(), Error> {
+        let copy_err = |source| Error::RepCopy {
+            source,
+            name: self.name(),
+            copy: copy.name(),
+        };
+
+        let destination = format!("bdev:///{}", copy.name());
+        let job = RebuildJob::create(
+            &self.name(),
+            &[format!("bdev:///{}", self.name())],
+            &destination,
+            0 .. self.as_bdev().num_blocks(),
+            |_, _| {},
+        )
+        .map_err(copy_err)?;
+
+        let started = job.set_mode(mode).and_then(|_| {
+            let updates =
+                RebuildJob::watch(&destination, MIGRATE_WATCH_INTERVAL)?;
+            job.as_client().start().map(|_| updates)
+        });
+        let mut updates = match started {
+            Ok(updates) => updates,
+            Err(e) => {
+                let _ = RebuildJob::remove(&destination);
+                return Err(copy_err(e));
+            }
+        };
+
+        let mut state = RebuildState::Failed;
+        while let Some(update) = updates.next().await {
+            state = update.state;
+            if state.done() {
+                break;
+            }
+        }
+
+        let job = RebuildJob::remove(&destination).map_err(copy_err)?;
+        if state != RebuildState::Completed {
+            return Err(copy_err(
+                job.destination_error(&destination).cloned().unwrap_or(
+                    RebuildError::OpError {
+                        operation: "Migrate".to_string(),
+                        state: state.to_string(),
+                    },
+                ),
+            ));
+        }
+
+        let stats = job.destination_stats(&destination);
+        info!(
+            "{}: copied into {} in {} mode, {} segments skipped",
+            self, copy, mode, stats.segments_skipped
+        );
+        Ok(())
+    }
+
+    /// copies the chunks of the clusters of the lvol which have been written
+    /// to into `copy`, regardless of their content. The lvol must not be
+    /// written to meanwhile
+    async fn copy_written(
+        &self,
+        copy: &Lvol,
+        written: &WrittenChunks,
+    ) -> Result<(), Error> {
+        let copy_err = |source| Error::RepCopy {
+            source,
+            name: self.name(),
+            copy: copy.name(),
+        };
+        let cluster_size = self.lvs().cluster_size();
+        let chunk_size = written.chunk_size;
+
+        let source = BdevHandle::open_with_bdev(&self.as_bdev(), false)
+            .map_err(|source| {
+                copy_err(RebuildError::NoBdevHandle {
+                    source,
+                    bdev: self.name(),
+                })
+            })?;
+        let destination = BdevHandle::open_with_bdev(&copy.as_bdev(), true)
+            .map_err(|source| {
+                copy_err(RebuildError::NoBdevHandle {
+                    source,
+                    bdev: copy.name(),
+                })
+            })?;
+        let mut buf = source.dma_malloc(chunk_size).map_err(|source| {
+            copy_err(RebuildError::NoCopyBuffer {
+                source,
+            })
+        })?;
+
+        let blob = unsafe { self.0.as_ref().blob };
+        let clusters = self.size() / cluster_size;
+        let mut chunks = 0;
+        for i in 0 .. clusters {
+            let cluster = unsafe { lvs_blob_cluster(blob, i) };
+            if cluster == 0 {
+                continue;
+            }
+            for j in 0 .. cluster_size / chunk_size {
+                if !written.written(cluster * cluster_size + j * chunk_size) {
+                    continue;
+                }
+                let offset = i * cluster_size + j * chunk_size;
+                source.read_at(offset, &mut buf).await.map_err(|source| {
+                    copy_err(RebuildError::ReadIoError {
+                        source,
+                        bdev: self.name(),
+                    })
+                })?;
+                destination.write_at(offset, &buf).await.map_err(|source| {
+                    copy_err(RebuildError::WriteIoError {
+                        source,
+                        bdev: copy.name(),
+                    })
+                })?;
+                chunks += 1;
+            }
+        }
+
+        if copy.as_bdev().io_type_supported(IoType::Flush) {
+            destination.flush().await.map_err(|source| {
+                copy_err(RebuildError::WriteIoError {
+                    source,
+                    bdev: copy.name(),
+                })
+            })?;
+        }
+        info!("{}: copied {} chunks written into {}", self, chunks, copy);
+        Ok(())
+    }
+
+    /// copies the chunks of the lvol written to into `copy` and switches the
+    /// namespace of the nvmf subsystem of the lvol over to it once it has
+    /// taken over the name of the lvol. The hosts must be held. The names
+    /// are restored should the switch-over fail, which leaves the lvol with
+    /// the data
+    async fn switch_over(
+        &self,
+        copy: &Lvol,
+        written: &WrittenChunks,
+        subsystem: &NvmfSubsystem,
+    ) -> Result<(), Error> {
+        self.copy_written(copy, written).await?;
+
+        let name = self.name();
+        let copy_name = copy.name();
+        self.make_way(copy).await?;
+        if let Err(source) = subsystem.replace_namespace(&copy.as_bdev()) {
+            self.restore_names(&name, copy, &copy_name).await;
+            return Err(Error::LvolShare {
+                source: CoreError::ShareNvmf {
+                    source,
+                },
+                name,
+            });
+        }
+        Ok(())
+    }
+
+    /// copies the data and the properties of the lvol into `copy`, which
+    /// takes over its name and, if the lvol is shared, its nvmf namespace.
+    /// Nothing is left to fail once the namespace is switched over
+    async fn migrate_into(
+        &self,
+        copy: &Lvol,
+        shared: bool,
+    ) -> Result<(), Error> {
+        for prop in &[
+            PropName::NexusUuid,
+            PropName::OwnerGeneration,
+            PropName::VolumeUuid,
+            PropName::Labels,
+            PropName::Created,
+            PropName::AllowedHosts,
+        ] {
+            if let Ok(value) = self.get(*prop).await {
+                copy.set(value).await?;
+            }
+        }
+        if self.reservation() > 0 {
+            copy.reserve(self.reservation()).await?;
+        }
+
+        // the writes to the lvol are tracked from before the copy, so that
+        // none is missed if it is shared
+        let written = if shared {
+            Some(WrittenChunks::track(self)?)
+        } else {
+            None
+        };
+
+        // the unallocated clusters of a thin provisioned lvol read back as
+        // zeroes, which are not copied so that they stay unallocated
+        let mode = if self.is_thin() {
+            RebuildMode::Checksum
+        } else {
+            RebuildMode::Full
+        };
+        self.copy_into(copy, mode).await?;
+
+        let written = match written {
+            Some(written) => written,
+            None => return self.make_way(copy).await,
+        };
+
+        let share_err = |source| Error::LvolShare {
+            source: CoreError::ShareNvmf {
+                source,
+            },
+            name: self.name(),
+        };
+        let subsystem = match NvmfSubsystem::nqn_lookup(&self.name()) {
+            Some(subsystem) => subsystem,
+            None => {
+                return Err(Error::LvolShare {
+                    source: CoreError::NotSupported {
+                        source: Errno::ENOENT,
+                    },
+                    name: self.name(),
+                })
+            }
+        };
+
+        // the hosts are held while the chunks written to during the first
+        // copy are copied again, so that nothing is missed
+        subsystem.pause().await.map_err(share_err)?;
+        let result = self.switch_over(copy, &written, &subsystem).await;
+        if let Err(e) = subsystem.resume().await {
+            error!("{}: failed to resume the hosts: {}", self, e);
+        }
+        result
+    }
+
+    /// moves the lvol to the pool `lvs` of the same node while it is in use,
+    /// keeping its name and, if it is shared, its share URI. The data is
+    /// copied into a new lvol of the pool which replaces this lvol, which is
+    /// destroyed, or left behind with the `-migrated` suffix if that fails.
+    /// Snapshots and clones, as well as the lvols which have snapshots, can
+    /// not be migrated.
+    #[instrument(level = "debug", err)]
+    pub async fn migrate(self, lvs: &Lvs) -> Result<Lvol, Error> {
+        let name = self.name();
+        if lvs.name() == self.pool() {
+            return Err(Error::Invalid {
+                source: Errno::EINVAL,
+                msg: format!("{} is already on pool {}", name, lvs.name()),
+            });
+        }
+        if self.is_snapshot() || self.is_clone() || !self.snapshots().is_empty()
+        {
+            return Err(Error::Invalid {
+                source: Errno::EOPNOTSUPP,
+                msg: format!("{} has snapshots and can not be migrated", name),
+            });
+        }
+
+        // the namespace of a lvol shared over nvmf can be switched over to
+        // its copy, but not the bdevs of other users, eg a local nexus
+        let shared = self.shared() == Some(Protocol::Nvmf);
+        if self.as_bdev().is_claimed() && !shared {
+            return Err(Error::Invalid {
+                source: Errno::EBUSY,
+                msg: format!("{} is in use and can not be migrated", name),
+            });
+        }
+
+        // the copy commits the size of the lvol and allocates as much of the
+        // pool as it has allocated, whether it is thin provisioned or not
+        let copy_name = format!("{}{}", name, MIGRATE_SUFFIX);
+        lvs.check_space(&copy_name, self.size(), self.allocated())
+            .map_err(|source| Error::RepCreate {
+                source,
+                name: copy_name.clone(),
+            })?;
+
+        info!("{}: migrating to pool {}", self, lvs.name());
+        let copy = lvs
+            .create_lvol(&copy_name, self.size(), self.is_thin())
+            .await?;
+
+        if let Err(e) = self.migrate_into(&copy, shared).await {
+            let _ = copy.destroy().await;
+            return Err(e);
+        }
+
+        // the data is on the copy from now on, which has taken over the name
+        // and the namespace of the lvol, so the lvol is only left behind
+        // under its temporary name should it fail to be destroyed
+        let migrated = self.name();
+        if shared {
+            if let Err(e) = copy.set(PropValue::Shared(Protocol::Nvmf)).await {
+                error!("{}: failed to record that it is shared: {}", copy, e);
+            }
+        }
+        if let Err(e) = self.set(PropValue::Shared(Protocol::Off)).await {
+            warn!("{}: failed to record that it is unshared: {}", migrated, e);
+        }
+        if let Err(e) = self.destroy().await {
+            error!("failed to destroy migrated lvol {}: {}", migrated, e);
+        }
+
+        info!("migrated {} to pool {}", copy, lvs.name());
+        Ok(copy)
+    }
+}
@@ -0,0 +1,329 @@
+//! Wiping of the data of a lvol before it is destroyed, so that the clusters
+//! it frees can not be handed over to another lvol with the data still on
+//! them. Only the clusters allocated to the lvol are wiped: the unallocated
+//! clusters of a thin provisioned lvol or of a clone hold none of its data.
+//! Snapshots can not be written to, so their clusters are wiped on the disks
+//! of the pool instead, and a snapshot whose clones still depend on its data
+//! is not wiped.
+//!
+//! The progress of the lvols being wiped is kept in memory until they are
+//! destroyed, so that it can be reported while wiping large lvols.
+
+use std::{
+    collections::HashMap,
+    os::raw::c_void,
+    sync::{Mutex, MutexGuard},
+};
+
+use futures::channel::oneshot;
+use nix::errno::Errno;
+use once_cell::sync::OnceCell;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use rpc::mayastor::ReplicaWipeMethod;
+use spdk_sys::{lvs_blob_cluster, lvs_dev_wipe};
+
+use crate::{
+    bdev::nexus::nexus_io::IoType,
+    core::{BdevHandle, CoreError, DmaBuf, Share},
+    ffihelper::{cb_arg, pair, FfiResult},
+    lvs::{error::Error, Lvol, Lvs},
+};
+
+/// maximum size of the chunks in which a lvol is wiped
+const WIPE_CHUNK_SIZE: u64 = 1024 * 1024;
+
+/// Method used to wipe the data of a lvol when it is destroyed
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum WipeMethod {
+    /// the data is not wiped
+    None,
+    /// the data is unmapped, which is only safe on disks reading back zeroes
+    /// from unmapped blocks
+    Unmap,
+    /// zeroes are written over the data
+    WriteZeroes,
+    /// random data is written over the data
+    Random,
+}
+
+impl Default for WipeMethod {
+    fn default() -> Self {
+        WipeMethod::None
+    }
+}
+
+impl WipeMethod {
+    /// returns the wipe method of a request, None if the request does not
+    /// have any preference
+    pub fn of_request(method: i32) -> Result<Option<Self>, Error> {
+        match ReplicaWipeMethod::from_i32(method) {
+            Some(ReplicaWipeMethod::WipeDefault) => Ok(None),
+            Some(ReplicaWipeMethod::WipeNone) => Ok(Some(WipeMethod::None)),
+            Some(ReplicaWipeMethod::WipeUnmap) => Ok(Some(WipeMethod::Unmap)),
+            Some(ReplicaWipeMethod::WipeWriteZeroes) => {
+                Ok(Some(WipeMethod::WriteZeroes))
+            }
+            Some(ReplicaWipeMethod::WipeRandom) => Ok(Some(WipeMethod::Random)),
+            None => Err(Error::Invalid {
+                source: Errno::EINVAL,
+                msg: format!("invalid wipe method {}", method),
+            }),
+        }
+    }
+}
+
+/// Progress of wiping the data of a lvol
+#[derive(Debug, Clone)]
+pub struct WipeProgress {
+    /// uuid of the lvol
+    pub uuid: String,
+    /// name of the pool of the lvol
+    pub pool: String,
+    /// how the data is wiped
+    pub method: WipeMethod,
+    /// bytes wiped so far
+    pub wiped: u64,
+    /// bytes to wipe, the allocated bytes of the lvol
+    pub total: u64,
+}
+
+impl WipeProgress {
+    /// returns the progress of the lvols being wiped
+    pub fn list() -> Vec<WipeProgress> {
+        wipes().values().cloned().collect()
+    }
+}
+
+/// progress of the lvols being wiped by their uuid
+fn wipes() -> MutexGuard<'static, HashMap<String, WipeProgress>> {
+    static WIPES: OnceCell<Mutex<HashMap<String, WipeProgress>>> =
+        OnceCell::new();
+
+    WIPES
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .expect("wipes poisoned")
+}
+
+extern "C" fn wipe_cb(sender: *mut c_void, errno: i32) {
+    let sender = unsafe { Box::from_raw(sender as *mut oneshot::Sender<i32>) };
+    sender.send(errno).expect("receiver gone");
+}
+
+impl Lvs {
+    /// overwrites `len` bytes of the disks of the lvs at `offset` with the
+    /// given method, through the device of its blobstore which holds the
+    /// claim on them. `buf` holds the data written with WipeMethod::Random
+    async fn wipe_disks(
+        &self,
+        offset: u64,
+        len: u64,
+        method: WipeMethod,
+        buf: Option<&mut DmaBuf>,
+    ) -> Result<(), CoreError> {
+        let (payload, err) = match method {
+            WipeMethod::None => return Ok(()),
+            WipeMethod::Unmap => (
+                std::ptr::null_mut(),
+                CoreError::UnmapFailed {
+                    offset,
+                    len,
+                },
+            ),
+            WipeMethod::WriteZeroes => (
+                std::ptr::null_mut(),
+                CoreError::WriteZeroesFailed {
+                    offset,
+                    len,
+                },
+            ),
+            WipeMethod::Random => (
+                buf.expect("no buffer to wipe with random data")
+                    .as_mut_slice()
+                    .as_mut_ptr() as *mut c_void,
+                CoreError::WriteFailed {
+                    offset,
+                    len,
+                },
+            ),
+        };
+
+        let (s, r) = pair::<i32>();
+        unsafe {
+            lvs_dev_wipe(
+                self.0.as_ptr(),
+                offset,
+                len,
+                method == WipeMethod::Unmap,
+                payload,
+                Some(wipe_cb),
+                cb_arg(s),
+            )
+        };
+        r.await
+            .expect("callback gone while wiping the disks")
+            .to_result(|_| err)
+    }
+}
+
+impl Lvol {
+    /// overwrites the clusters allocated to the lvol with the given method,
+    /// recording the progress until the lvol is destroyed. The clusters of a
+    /// snapshot are overwritten on the disks of the pool, which are not
+    /// flushed as the blobstore does not flush them either. The caller must
+    /// make sure that the lvol is not in use
+    async fn wipe(&self, method: WipeMethod) -> Result<(), Error> {
+        let wipe_err = |source| Error::RepWipe {
+            source,
+            name: self.name(),
+        };
+        let lvs = self.lvs();
+        let cluster_size = lvs.cluster_size();
+        let chunk_size = WIPE_CHUNK_SIZE.min(cluster_size);
+
+        let handle = if self.is_snapshot() {
+            None
+        } else {
+            Some(
+                BdevHandle::open_with_bdev(&self.as_bdev(), true)
+                    .map_err(wipe_err)?,
+            )
+        };
+        let mut buf = match method {
+            WipeMethod::Random => Some(
+                DmaBuf::new(chunk_size, self.as_bdev().alignment()).map_err(
+                    |source| {
+                        wipe_err(CoreError::DmaAllocFailed {
+                            source,
+                            size: chunk_size,
+                        })
+                    },
+                )?,
+            ),
+            _ => None,
+        };
+
+        wipes().insert(
+            self.uuid(),
+            WipeProgress {
+                uuid: self.uuid(),
+                pool: self.pool(),
+                method,
+                wiped: 0,
+                total: self.allocated(),
+            },
+        );
+
+        let blob = unsafe { self.0.as_ref().blob };
+        let clusters = (self.size() + cluster_size - 1) / cluster_size;
+        for i in 0 .. clusters {
+            let cluster = unsafe { lvs_blob_cluster(blob, i) };
+            if cluster == 0 {
+                continue;
+            }
+            let mut done = 0;
+            while done < cluster_size {
+                if let Some(buf) = buf.as_mut() {
+                    rand::thread_rng().fill(buf.as_mut_slice());
+                }
+                let offset = i * cluster_size + done;
+                match &handle {
+                    Some(handle) => match method {
+                        WipeMethod::None => Ok(()),
+                        WipeMethod::Unmap => {
+                            handle.unmap(offset, chunk_size).await
+                        }
+                        WipeMethod::WriteZeroes => {
+                            handle.write_zeroes(offset, chunk_size).await
+                        }
+                        WipeMethod::Random => handle
+                            .write_at(offset, buf.as_ref().unwrap())
+                            .await
+                            .map(|_| ()),
+                    },
+                    None => {
+                        lvs.wipe_disks(
+                            cluster * cluster_size + done,
+                            chunk_size,
+                            method,
+                            buf.as_mut(),
+                        )
+                        .await
+                    }
+                }
+                .map_err(wipe_err)?;
+                done += chunk_size;
+            }
+
+            if let Some(progress) = wipes().get_mut(&self.uuid()) {
+                progress.wiped += cluster_size;
+            }
+        }
+
+        if let Some(handle) = handle {
+            if self.as_bdev().io_type_supported(IoType::Flush) {
+                handle.flush().await.map_err(wipe_err)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// destroys the lvol after wiping its data with the given method, or
+    /// with the default wipe method of its pool if None. A snapshot with
+    /// clones is not wiped nor destroyed, as they still depend on its data
+    #[instrument(level = "debug", err)]
+    pub async fn destroy_wiped(
+        self,
+        method: Option<WipeMethod>,
+    ) -> Result<String, Error> {
+        let method = method.unwrap_or_else(|| self.lvs().wipe_method());
+        if method == WipeMethod::None {
+            return self.destroy().await;
+        }
+        if self.is_snapshot()
+            && self
+                .lvs()
+                .lvols()
+                .map_or(false, |mut lvols| lvols.any(|l| l.is_clone_of(&self)))
+        {
+            return Err(Error::Invalid {
+                source: Errno::EBUSY,
+                msg: format!(
+                    "snapshot {} has clones which depend on its data",
+                    self.name()
+                ),
+            });
+        }
+        // the blobstore silently ignores unmaps the disks do not support
+        if method == WipeMethod::Unmap
+            && !self.lvs().base_bdev().io_type_supported(IoType::Unmap)
+        {
+            return Err(Error::Invalid {
+                source: Errno::EOPNOTSUPP,
+                msg: format!("the disks of pool {} can not unmap", self.pool()),
+            });
+        }
+
+        // no host can write to the lvol while it is being wiped
+        if self.shared().is_some() {
+            self.unshare().await?;
+        }
+
+        info!(
+            "{}: wiping {} bytes with {:?}",
+            self,
+            self.allocated(),
+            method
+        );
+        let uuid = self.uuid();
+        let result = match self.wipe(method).await {
+            Ok(_) => self.destroy().await,
+            Err(e) => Err(e),
+        };
+        wipes().remove(&uuid);
+        result
+    }
+}
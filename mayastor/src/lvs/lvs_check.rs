@@ -0,0 +1,427 @@
+//! Consistency check of the metadata of a pool. Every blob of the blobstore
+//! of the lvs is opened and its clusters are compared with the clusters the
+//! blobstore has allocated, and the snapshot a clone depends on is checked to
+//! exist.
+//!
+//! The check only reads the metadata, unless repairing is requested, in which
+//! case the safe fixes are applied: the blobs which do not belong to any lvol,
+//! and which no clone depends on, are deleted. The other issues are reported
+//! only.
+//!
+//! The clusters which are allocated by the blobstore but belong to no blob are
+//! reported as leaked, unless the pool is written to while it is checked, in
+//! which case they may be clusters being allocated to a blob.
+//!
+//! Repairing deletes blobs while the metadata may be changed by the lvols, so
+//! it is only allowed on a pool which has not been written to since it has
+//! been imported, whatever changed it, and none of whose lvols is open.
+
+use std::{
+    collections::HashMap,
+    ffi::CStr,
+    fmt::{Display, Formatter},
+    os::raw::{c_char, c_void},
+};
+
+use futures::channel::oneshot;
+use nix::errno::Errno;
+
+use spdk_sys::{
+    lvs_blob_cluster,
+    lvs_cluster_used,
+    lvs_next_blob_id,
+    lvs_reset_write_count,
+    lvs_write_count,
+    spdk_blob,
+    spdk_blob_close,
+    spdk_blob_get_num_clusters,
+    spdk_blob_get_parent_snapshot,
+    spdk_blob_get_xattr_value,
+    spdk_blob_is_snapshot,
+    spdk_blob_store,
+    spdk_bs_delete_blob,
+    spdk_bs_open_blob,
+    spdk_bs_total_data_cluster_count,
+};
+
+use crate::{
+    core::Share,
+    ffihelper::{cb_arg, pair, ErrnoResult, FfiResult},
+    lvs::{Error, Lvs},
+};
+
+/// id of no blob, the end of the blobs of a blobstore
+const BLOBID_INVALID: u64 = u64::MAX;
+
+/// Kind of an inconsistency found in the metadata of a lvs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IssueKind {
+    /// a cluster of a blob is not allocated by the blobstore
+    ClusterNotAllocated,
+    /// a cluster belongs to several blobs
+    ClusterShared,
+    /// a cluster is allocated by the blobstore but belongs to no blob
+    ClusterLeaked,
+    /// a blob does not belong to any lvol
+    OrphanedBlob,
+    /// the snapshot a clone depends on is missing or is not a snapshot
+    BrokenSnapshotChain,
+    /// the metadata of a blob can not be read
+    UnreadableBlob,
+}
+
+/// Inconsistency found in the metadata of a lvs
+#[derive(Debug, Clone)]
+pub struct Issue {
+    /// kind of the issue
+    pub kind: IssueKind,
+    /// id of the blob the issue is about, if any
+    pub blob: Option<u64>,
+    /// cluster the issue is about, if any
+    pub cluster: Option<u64>,
+    /// the issue has been repaired
+    pub repaired: bool,
+    /// human readable description of the issue
+    pub description: String,
+}
+
+/// Result of the consistency check of a lvs
+#[derive(Debug, Default)]
+pub struct LvsCheck {
+    /// number of blobs checked
+    pub blobs: u64,
+    /// number of clusters of the blobstore
+    pub clusters: u64,
+    /// inconsistencies found
+    pub issues: Vec<Issue>,
+}
+
+/// A blob as seen by the check
+struct BlobInfo {
+    /// name of the lvol the blob belongs to, if any
+    name: Option<String>,
+    snapshot: bool,
+    /// the snapshot the blob is a clone of, if any
+    parent: Option<u64>,
+}
+
+impl Display for BlobInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "lvol {}", name),
+            None => write!(f, "unnamed blob"),
+        }
+    }
+}
+
+extern "C" fn blob_op_with_handle_cb(
+    sender: *mut c_void,
+    blob: *mut spdk_blob,
+    errno: i32,
+) {
+    let sender = unsafe {
+        Box::from_raw(
+            sender as *mut oneshot::Sender<ErrnoResult<*mut spdk_blob>>,
+        )
+    };
+    let result = if errno == 0 {
+        Ok(blob)
+    } else {
+        Err(Errno::from_i32(errno.abs()))
+    };
+    sender.send(result).expect("receiver gone");
+}
+
+extern "C" fn blob_op_cb(sender: *mut c_void, errno: i32) {
+    let sender = unsafe { Box::from_raw(sender as *mut oneshot::Sender<i32>) };
+    sender.send(errno).expect("receiver gone");
+}
+
+/// opens the blob with the given id
+async fn open_blob(
+    bs: *mut spdk_blob_store,
+    id: u64,
+) -> Result<*mut spdk_blob, Errno> {
+    let (s, r) = pair::<ErrnoResult<*mut spdk_blob>>();
+    unsafe {
+        spdk_bs_open_blob(bs, id, Some(blob_op_with_handle_cb), cb_arg(s))
+    };
+    r.await.expect("callback gone while opening blob")
+}
+
+/// closes a blob opened by the check
+async fn close_blob(blob: *mut spdk_blob) -> Result<(), Errno> {
+    let (s, r) = pair::<i32>();
+    unsafe { spdk_blob_close(blob, Some(blob_op_cb), cb_arg(s)) };
+    r.await
+        .expect("callback gone while closing blob")
+        .to_result(Errno::from_i32)
+}
+
+/// deletes the blob with the given id, which must not be open
+async fn delete_blob(bs: *mut spdk_blob_store, id: u64) -> Result<(), Errno> {
+    let (s, r) = pair::<i32>();
+    unsafe { spdk_bs_delete_blob(bs, id, Some(blob_op_cb), cb_arg(s)) };
+    r.await
+        .expect("callback gone while deleting blob")
+        .to_result(Errno::from_i32)
+}
+
+/// returns the name of the lvol the blob belongs to, if any
+fn blob_name(blob: *mut spdk_blob) -> Option<String> {
+    let name = b"name\0";
+    let mut value: *const c_char = std::ptr::null();
+    let mut value_len: u64 = 0;
+    let rc = unsafe {
+        spdk_blob_get_xattr_value(
+            blob,
+            name.as_ptr() as *const c_char,
+            &mut value as *mut *const c_char as *mut *const c_void,
+            &mut value_len,
+        )
+    };
+    if rc != 0 || value.is_null() {
+        return None;
+    }
+    unsafe { CStr::from_ptr(value) }
+        .to_str()
+        .ok()
+        .map(String::from)
+}
+
+impl LvsCheck {
+    fn issue(
+        &mut self,
+        kind: IssueKind,
+        blob: Option<u64>,
+        cluster: Option<u64>,
+        description: String,
+    ) {
+        warn!("{}", description);
+        self.issues.push(Issue {
+            kind,
+            blob,
+            cluster,
+            repaired: false,
+            description,
+        });
+    }
+}
+
+impl Lvs {
+    /// returns the number of writes to the disks of the pool since it has
+    /// been imported, u64::MAX if they are not watched
+    fn write_count(&self) -> u64 {
+        unsafe { lvs_write_count(self.0.as_ptr()) }
+    }
+
+    /// forgets the writes to the disks of the pool once it has been
+    /// imported, so that it can be repaired until it is written to again
+    pub(super) fn forget_writes(&self) {
+        unsafe { lvs_reset_write_count(self.0.as_ptr(), self.write_count()) };
+    }
+
+    /// the pool has not been written to since it has been imported and none
+    /// of its lvols is open, so that no metadata is changed while it is
+    /// repaired
+    fn is_repairable(&self) -> bool {
+        self.write_count() == 0
+            && self.lvols().map_or(true, |mut lvols| {
+                lvols.all(|l| l.shared().is_none() && !l.as_bdev().is_claimed())
+            })
+    }
+
+    /// checks the consistency of the metadata of the lvs, and repairs the
+    /// issues which can be safely fixed if `repair` is set, which requires
+    /// the pool to be freshly imported and unused
+    pub async fn check(&self, repair: bool) -> Result<LvsCheck, Error> {
+        let mut writes = self.write_count();
+        if repair && !self.is_repairable() {
+            return Err(Error::CheckRepair {
+                source: Errno::EBUSY,
+                name: self.name().to_string(),
+            });
+        }
+
+        let lvs = self.0.as_ptr();
+        let bs = unsafe { self.0.as_ref().blobstore };
+        let super_blob = unsafe { self.0.as_ref().super_blob_id };
+
+        let mut check = LvsCheck::default();
+        let mut blobs = HashMap::new();
+        // the blob owning each allocated cluster
+        let mut owners = HashMap::new();
+
+        let mut id = BLOBID_INVALID;
+        loop {
+            id = unsafe { lvs_next_blob_id(lvs, id) };
+            if id == BLOBID_INVALID {
+                break;
+            }
+            check.blobs += 1;
+
+            let blob = match open_blob(bs, id).await {
+                Ok(blob) => blob,
+                Err(e) => {
+                    check.issue(
+                        IssueKind::UnreadableBlob,
+                        Some(id),
+                        None,
+                        format!("blob {:#x} can not be opened: {}", id, e),
+                    );
+                    continue;
+                }
+            };
+
+            let parent = unsafe { spdk_blob_get_parent_snapshot(bs, id) };
+            let info = BlobInfo {
+                name: blob_name(blob),
+                snapshot: unsafe { spdk_blob_is_snapshot(blob) },
+                parent: if parent == BLOBID_INVALID {
+                    None
+                } else {
+                    Some(parent)
+                },
+            };
+
+            let num_clusters = unsafe { spdk_blob_get_num_clusters(blob) };
+            for i in 0 .. num_clusters {
+                let cluster = unsafe { lvs_blob_cluster(blob, i) };
+                if cluster == 0 {
+                    continue;
+                }
+                if !unsafe { lvs_cluster_used(lvs, cluster) } {
+                    check.issue(
+                        IssueKind::ClusterNotAllocated,
+                        Some(id),
+                        Some(cluster),
+                        format!(
+                            "cluster {} of {} is not allocated",
+                            cluster, info
+                        ),
+                    );
+                }
+                if let Some(owner) = owners.insert(cluster, id) {
+                    check.issue(
+                        IssueKind::ClusterShared,
+                        Some(id),
+                        Some(cluster),
+                        format!(
+                            "cluster {} of {} also belongs to blob {:#x}",
+                            cluster, info, owner
+                        ),
+                    );
+                }
+            }
+
+            if let Err(e) = close_blob(blob).await {
+                error!("failed to close blob {:#x}: {}", id, e);
+            }
+            blobs.insert(id, info);
+        }
+
+        check.clusters = self.total_clusters();
+
+        if self.write_count() != writes {
+            warn!(
+                "{} has been written to while checked, its leaked clusters \
+                 are not reported",
+                self
+            );
+        } else if check
+            .issues
+            .iter()
+            .any(|i| i.kind == IssueKind::UnreadableBlob)
+        {
+            warn!(
+                "{} has unreadable blobs, its leaked clusters are not reported",
+                self
+            );
+        } else {
+            let md_clusters = check.clusters
+                - unsafe { spdk_bs_total_data_cluster_count(bs) };
+            for cluster in md_clusters .. check.clusters {
+                if unsafe { lvs_cluster_used(lvs, cluster) }
+                    && !owners.contains_key(&cluster)
+                {
+                    check.issue(
+                        IssueKind::ClusterLeaked,
+                        None,
+                        Some(cluster),
+                        format!("cluster {} belongs to no blob", cluster),
+                    );
+                }
+            }
+        }
+
+        for (id, info) in &blobs {
+            if let Some(parent) = info.parent {
+                let broken = match blobs.get(&parent) {
+                    Some(p) => !p.snapshot,
+                    None => true,
+                };
+                if broken {
+                    check.issue(
+                        IssueKind::BrokenSnapshotChain,
+                        Some(*id),
+                        None,
+                        format!(
+                            "{} is a clone of blob {:#x} which is not a snapshot",
+                            info, parent
+                        ),
+                    );
+                }
+            }
+        }
+
+        for (id, info) in &blobs {
+            if *id == super_blob || info.name.is_some() {
+                continue;
+            }
+            check.issue(
+                IssueKind::OrphanedBlob,
+                Some(*id),
+                None,
+                format!("blob {:#x} belongs to no lvol", id),
+            );
+
+            let has_clones = blobs.values().any(|b| b.parent == Some(*id));
+            if !repair || has_clones {
+                continue;
+            }
+            if self.write_count() != writes {
+                warn!(
+                    "{} has been written to while repaired, blob {:#x} is \
+                     not deleted",
+                    self, id
+                );
+                continue;
+            }
+            match delete_blob(bs, *id).await {
+                Ok(_) => {
+                    info!("deleted orphaned blob {:#x} of {}", id, self);
+                    check.issues.last_mut().unwrap().repaired = true;
+                }
+                Err(e) => error!(
+                    "failed to delete orphaned blob {:#x} of {}: {}",
+                    id, self, e
+                ),
+            }
+            writes = self.write_count();
+        }
+
+        if repair {
+            // the deletions are the only writes since the pool was imported
+            unsafe { lvs_reset_write_count(lvs, writes) };
+        }
+
+        info!(
+            "checked {}: {} blobs, {} clusters, {} issues",
+            self,
+            check.blobs,
+            check.clusters,
+            check.issues.len()
+        );
+        Ok(check)
+    }
+}
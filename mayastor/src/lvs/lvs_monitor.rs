@@ -0,0 +1,346 @@
+//! Health monitoring of the pools of the node. The monitor periodically
+//! checks the state of the disks of every pool, the I/O errors of the pool on
+//! its disks and the free space of the pool. Changes of the state of a pool
+//! are logged and published on the message bus.
+//!
+//! The I/O errors are counted as the I/O of the blobstore of a pool to its
+//! disks completes, without issuing any I/O of the monitor's own.
+//!
+//! A pool whose disks have been hot-removed is unloaded by the lvol store, it
+//! is remembered as faulted until it is destroyed or imported again.
+
+use std::{
+    collections::HashMap,
+    ffi::CStr,
+    os::raw::c_char,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use mbus_api::v0::PoolState as EventPoolState;
+use nix::errno::Errno;
+use once_cell::sync::OnceCell;
+
+use spdk_sys::lvs_watch_io_errors;
+
+use crate::{
+    core::{poller::sleep, Reactors},
+    lvs::{DiskState, Lvs, LvsState, PoolDisk},
+    subsys::Registration,
+};
+
+/// interval between two checks of the pools
+const MONITOR_INTERVAL: Duration = Duration::from_secs(5);
+
+/// a pool with less free space, in percent of its capacity, is degraded
+const LOW_SPACE_PERCENT: u64 = 10;
+
+/// number of consecutive checks with I/O errors after which a pool is faulted
+const MAX_ERROR_CHECKS: u32 = 3;
+
+/// Health of a pool as last seen by the monitor
+#[derive(Debug, Clone)]
+pub struct PoolHealth {
+    /// name of the pool
+    pub name: String,
+    /// state of the pool
+    pub state: LvsState,
+    /// human readable reason of the state, empty if the pool is online
+    pub reason: String,
+    /// disks of the pool and their state
+    pub disks: Vec<PoolDisk>,
+}
+
+/// A pool known to the monitor
+struct Monitored {
+    health: PoolHealth,
+    /// number of I/O errors on the disks since the last check
+    io_errors: u64,
+    /// number of consecutive checks which found I/O errors on the disks
+    error_checks: u32,
+    /// the pool is being exported or destroyed and is not checked
+    unloading: bool,
+}
+
+impl Monitored {
+    fn new(name: &str) -> Self {
+        Self {
+            health: PoolHealth {
+                name: name.to_string(),
+                state: LvsState::Online,
+                reason: String::new(),
+                disks: Vec::new(),
+            },
+            io_errors: 0,
+            error_checks: 0,
+            unloading: false,
+        }
+    }
+}
+
+impl From<LvsState> for EventPoolState {
+    fn from(s: LvsState) -> Self {
+        match s {
+            LvsState::Online => Self::Online,
+            LvsState::Degraded => Self::Degraded,
+            LvsState::Faulted => Self::Faulted,
+        }
+    }
+}
+
+/// pools known to the monitor by their name
+fn monitored() -> MutexGuard<'static, HashMap<String, Monitored>> {
+    static MONITORED: OnceCell<Mutex<HashMap<String, Monitored>>> =
+        OnceCell::new();
+
+    MONITORED
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .expect("pool monitor poisoned")
+}
+
+impl Lvs {
+    /// starts monitoring the health of all pools of the node, if not done
+    /// already
+    pub(super) fn start_monitor() {
+        static STARTED: OnceCell<()> = OnceCell::new();
+
+        STARTED.get_or_init(|| {
+            Reactors::master().send_future(async {
+                loop {
+                    sleep(MONITOR_INTERVAL).await;
+                    Self::check_all().await;
+                }
+            });
+        });
+    }
+
+    /// counts the I/O errors of this lvs on its disks from now on, until it is
+    /// unloaded
+    pub(super) fn watch_io_errors(&self) {
+        let rc = unsafe {
+            lvs_watch_io_errors(self.0.as_ptr(), Some(Self::io_error))
+        };
+        if rc != 0 && Errno::from_i32(-rc) != Errno::EALREADY {
+            warn!(
+                "pool {}: failed to watch the I/O errors on its disks: {}",
+                self.name(),
+                Errno::from_i32(-rc)
+            );
+        }
+    }
+
+    /// called on the completion of a failed I/O of the lvs `name` on its
+    /// disks, on the thread the I/O was submitted from
+    extern "C" fn io_error(name: *const c_char, bserrno: i32) {
+        let name = unsafe { CStr::from_ptr(name) }
+            .to_string_lossy()
+            .into_owned();
+        Reactors::master().send_future(async move {
+            debug!(
+                "pool {}: I/O error on its disks: {}",
+                name,
+                Errno::from_i32(-bserrno)
+            );
+            if Lvs::lookup(&name).is_none() {
+                return;
+            }
+            let mut monitored = monitored();
+            let m = monitored
+                .entry(name.clone())
+                .or_insert_with(|| Monitored::new(&name));
+            m.io_errors += 1;
+        });
+    }
+
+    /// returns the health of this lvs, the state of which is the worst of
+    /// the state of its disks and the state last seen by the monitor
+    pub fn health(&self) -> PoolHealth {
+        let disks = self.disks();
+        let state = Self::disks_state(&disks);
+        match monitored().get(self.name()) {
+            Some(m) if m.health.state > state => PoolHealth {
+                disks,
+                ..m.health.clone()
+            },
+            _ => PoolHealth {
+                name: self.name().to_string(),
+                state,
+                reason: Self::disks_reason(state).to_string(),
+                disks,
+            },
+        }
+    }
+
+    /// returns the health of the pools which have been unloaded because
+    /// their disks are gone
+    pub fn gone() -> Vec<PoolHealth> {
+        let mut gone = monitored()
+            .values()
+            .filter(|m| !m.unloading && Lvs::lookup(&m.health.name).is_none())
+            .map(|m| m.health.clone())
+            .collect::<Vec<_>>();
+        gone.sort_by(|a, b| a.name.cmp(&b.name));
+        gone
+    }
+
+    /// stops monitoring the pool `name` which is about to be exported or
+    /// destroyed
+    pub(super) fn unmonitor(name: &str) {
+        let mut m = Monitored::new(name);
+        m.unloading = true;
+        monitored().insert(name.to_string(), m);
+    }
+
+    /// forgets the pool `name`, which has been exported, destroyed or is
+    /// gone. A pool which is still loaded is monitored again from scratch
+    pub fn forget(name: &str) {
+        monitored().remove(name);
+    }
+
+    /// returns the state of a pool based on the state of its disks
+    fn disks_state(disks: &[PoolDisk]) -> LvsState {
+        if disks.iter().any(|d| d.state == DiskState::Missing) {
+            LvsState::Faulted
+        } else if disks.iter().any(|d| d.state == DiskState::Removing) {
+            LvsState::Degraded
+        } else {
+            LvsState::Online
+        }
+    }
+
+    /// returns the reason of the state of a pool based on its disks
+    fn disks_reason(state: LvsState) -> &'static str {
+        match state {
+            LvsState::Online => "",
+            LvsState::Degraded => "disks are being removed",
+            LvsState::Faulted => "disks are missing",
+        }
+    }
+
+    /// checks the health of all pools
+    async fn check_all() {
+        let names = Lvs::iter()
+            .map(|l| l.name().to_string())
+            .collect::<Vec<_>>();
+
+        for name in &names {
+            Self::check(name).await;
+        }
+
+        // the pools which are gone without being exported or destroyed
+        let gone = monitored()
+            .values()
+            .filter(|m| {
+                !m.unloading
+                    && m.health.state != LvsState::Faulted
+                    && !names.contains(&m.health.name)
+            })
+            .map(|m| m.health.name.clone())
+            .collect::<Vec<_>>();
+
+        for name in gone {
+            let disks = monitored()
+                .get(&name)
+                .map(|m| m.health.disks.clone())
+                .unwrap_or_default()
+                .into_iter()
+                .map(|d| PoolDisk {
+                    state: DiskState::Missing,
+                    ..d
+                })
+                .collect();
+            Self::update(
+                &name,
+                LvsState::Faulted,
+                "the pool is gone, its disks have been removed".into(),
+                disks,
+            );
+        }
+    }
+
+    /// checks the health of the pool `name`
+    async fn check(name: &str) {
+        if monitored().get(name).map_or(false, |m| m.unloading) {
+            return;
+        }
+
+        let lvs = match Lvs::lookup(name) {
+            Some(lvs) => lvs,
+            None => return,
+        };
+
+        let (io_errors, error_checks) = {
+            let mut monitored = monitored();
+            let m = monitored
+                .entry(name.to_string())
+                .or_insert_with(|| Monitored::new(name));
+            if m.unloading {
+                return;
+            }
+            let io_errors = std::mem::take(&mut m.io_errors);
+            m.error_checks = if io_errors > 0 { m.error_checks + 1 } else { 0 };
+            (io_errors, m.error_checks)
+        };
+
+        let disks = lvs.disks();
+        let state = Self::disks_state(&disks);
+        let (state, reason) = if state != LvsState::Online {
+            (state, Self::disks_reason(state).to_string())
+        } else if error_checks >= MAX_ERROR_CHECKS {
+            (
+                LvsState::Faulted,
+                format!("I/O errors on the disks in {} checks", error_checks),
+            )
+        } else if error_checks > 0 {
+            (
+                LvsState::Degraded,
+                format!("{} I/O errors on the disks", io_errors),
+            )
+        } else if lvs.available() * 100 < lvs.capacity() * LOW_SPACE_PERCENT {
+            (
+                LvsState::Degraded,
+                format!(
+                    "low free space, {} of {} bytes available",
+                    lvs.available(),
+                    lvs.capacity()
+                ),
+            )
+        } else {
+            (LvsState::Online, String::new())
+        };
+
+        Self::update(name, state, reason, disks);
+    }
+
+    /// records the health of the pool `name`, publishing the change of its
+    /// state if any
+    fn update(
+        name: &str,
+        state: LvsState,
+        reason: String,
+        disks: Vec<PoolDisk>,
+    ) {
+        let mut monitored = monitored();
+        let m = monitored
+            .entry(name.to_string())
+            .or_insert_with(|| Monitored::new(name));
+
+        let changed = m.health.state != state;
+        m.health.state = state;
+        m.health.reason = reason;
+        m.health.disks = disks;
+
+        if changed {
+            match state {
+                LvsState::Online => info!("pool {} is online", name),
+                _ => warn!("pool {} is {:?}: {}", name, state, m.health.reason),
+            }
+            Registration::publish_pool_event(
+                name,
+                state.into(),
+                &m.health.reason,
+            );
+        }
+    }
+}
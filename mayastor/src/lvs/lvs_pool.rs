@@ -1,38 +1,65 @@
-use std::{convert::TryFrom, fmt::Debug, os::raw::c_void, ptr::NonNull};
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    fmt::Debug,
+    os::raw::{c_char, c_void},
+    ptr::NonNull,
+    sync::{Mutex, MutexGuard},
+};
 
 use futures::channel::oneshot;
 use nix::errno::Errno;
+use once_cell::sync::OnceCell;
 use pin_utils::core_reexport::fmt::Formatter;
+use serde::{Deserialize, Serialize};
 use tracing::instrument;
 
-use rpc::mayastor::CreatePoolRequest;
+use rpc::mayastor::{CreatePoolRequest, PoolClearMethod, ReplicaWipeMethod};
 use spdk_sys::{
     lvol_store_bdev,
+    lvs_clear_method,
+    lvs_create_ext,
+    lvs_get_xattr,
+    lvs_md_pages,
+    lvs_set_xattr,
     spdk_bs_free_cluster_count,
     spdk_bs_get_cluster_size,
     spdk_bs_total_data_cluster_count,
     spdk_lvol,
     spdk_lvol_store,
+    spdk_lvs_opts,
+    spdk_lvs_opts_init,
     vbdev_get_lvol_store_by_name,
     vbdev_get_lvs_bdev_by_lvs,
     vbdev_lvol_create,
     vbdev_lvol_store_first,
     vbdev_lvol_store_next,
-    vbdev_lvs_create,
     vbdev_lvs_destruct,
     vbdev_lvs_examine,
     vbdev_lvs_unload,
     LVOL_CLEAR_WITH_UNMAP,
     LVOL_CLEAR_WITH_WRITE_ZEROES,
     LVS_CLEAR_WITH_NONE,
+    LVS_CLEAR_WITH_UNMAP,
+    LVS_CLEAR_WITH_WRITE_ZEROES,
+    SPDK_BS_PAGE_SIZE,
 };
 use url::Url;
 
 use crate::{
-    bdev::{nexus::nexus_io::IoType, util::uring, Uri},
-    core::{Bdev, Share, Uuid},
+    bdev::{nexus::nexus_io::IoType, util::uring, BdevCreateDestroy, Uri},
+    core::{Bdev, BdevHandle, CoreError, Protocol, Share, Uuid},
     ffihelper::{cb_arg, pair, AsStr, ErrnoResult, FfiResult, IntoCString},
-    lvs::{Error, Lvol, PropName, PropValue},
+    lvs::{
+        lvs_raid::Raid,
+        DiskState,
+        Error,
+        Lvol,
+        PoolDisk,
+        PropName,
+        PropValue,
+        WipeMethod,
+    },
     nexus_uri::{bdev_destroy, NexusBdevError},
 };
 
@@ -93,9 +120,118 @@ impl Debug for Lvs {
     }
 }
 
+/// State of a lvs, ordered from the best to the worst
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum LvsState {
+    /// the lvs is in normal working order
+    Online,
+    /// the lvs has experienced a failure but can still function
+    Degraded,
+    /// the lvs can no longer be used
+    Faulted,
+}
+
+/// Method used to clear the disks of a lvs when it is created
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ClearMethod {
+    /// the disks are not cleared
+    None,
+    /// the disks are unmapped
+    Unmap,
+    /// zeroes are written to the disks
+    WriteZeroes,
+}
+
+impl From<ClearMethod> for lvs_clear_method {
+    fn from(c: ClearMethod) -> Self {
+        match c {
+            ClearMethod::None => LVS_CLEAR_WITH_NONE,
+            ClearMethod::Unmap => LVS_CLEAR_WITH_UNMAP,
+            ClearMethod::WriteZeroes => LVS_CLEAR_WITH_WRITE_ZEROES,
+        }
+    }
+}
+
+impl From<lvs_clear_method> for ClearMethod {
+    fn from(c: lvs_clear_method) -> Self {
+        match c {
+            LVS_CLEAR_WITH_UNMAP => ClearMethod::Unmap,
+            LVS_CLEAR_WITH_WRITE_ZEROES => ClearMethod::WriteZeroes,
+            _ => ClearMethod::None,
+        }
+    }
+}
+
+impl ClearMethod {
+    /// returns the clear method of a pool request, None if the request does
+    /// not have any preference
+    fn of_request(args: &CreatePoolRequest) -> Result<Option<Self>, Error> {
+        match PoolClearMethod::from_i32(args.clear_method) {
+            Some(PoolClearMethod::PoolClearDefault) => Ok(None),
+            Some(PoolClearMethod::PoolClearNone) => Ok(Some(ClearMethod::None)),
+            Some(PoolClearMethod::PoolClearUnmap) => {
+                Ok(Some(ClearMethod::Unmap))
+            }
+            Some(PoolClearMethod::PoolClearWriteZeroes) => {
+                Ok(Some(ClearMethod::WriteZeroes))
+            }
+            None => Err(Error::Invalid {
+                source: Errno::EINVAL,
+                msg: format!("invalid clear method {}", args.clear_method),
+            }),
+        }
+    }
+}
+
 /// Logical Volume Store (LVS) stores the lvols
 pub struct Lvs(pub(crate) NonNull<spdk_lvol_store>);
 
+/// names of the xattrs of the super blob of a lvs holding its settings
+const CLEAR_METHOD_XATTR: &str = "clear_method";
+const OVERCOMMIT_LIMIT_XATTR: &str = "overcommit_limit";
+const WIPE_METHOD_XATTR: &str = "wipe_method";
+
+/// Settings of a lvs, as stored in the xattrs of its super blob
+#[derive(Debug, Clone, Copy)]
+struct LvsSettings {
+    /// method the disks were cleared with when the store was created
+    clear_method: ClearMethod,
+    /// maximum sum of the sizes of the lvols in percent of the capacity, 0
+    /// if there is no limit
+    overcommit_limit: u32,
+    /// default wipe method of the lvols
+    wipe_method: WipeMethod,
+}
+
+impl Default for LvsSettings {
+    fn default() -> Self {
+        Self {
+            clear_method: ClearMethod::None,
+            overcommit_limit: 0,
+            wipe_method: WipeMethod::default(),
+        }
+    }
+}
+
+/// settings of the loaded pools by their name, read from their disks when
+/// they are created or imported and kept in sync with them when they are set
+fn settings() -> MutexGuard<'static, HashMap<String, LvsSettings>> {
+    static SETTINGS: OnceCell<Mutex<HashMap<String, LvsSettings>>> =
+        OnceCell::new();
+
+    SETTINGS
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .expect("pool settings poisoned")
+}
+
+/// returns the number of metadata pages of a store with `clusters` clusters
+/// and `ratio` percent of a metadata page per cluster, as spdk_lvs_init()
+/// computes it
+fn md_pages(clusters: u64, ratio: u32) -> u64 {
+    clusters * u64::from(ratio) / 100
+}
+
 impl Lvs {
     /// generic lvol store callback
     extern "C" fn lvs_cb(
@@ -154,6 +290,183 @@ impl Lvs {
         }
     }
 
+    /// returns the size of a cluster, the unit in which lvols are allocated
+    pub fn cluster_size(&self) -> u64 {
+        let blobs = unsafe { self.0.as_ref().blobstore };
+        unsafe { spdk_bs_get_cluster_size(blobs) }
+    }
+
+    /// returns the number of clusters of the store, including the ones
+    /// holding the metadata
+    pub(super) fn total_clusters(&self) -> u64 {
+        self.base_bdev().size_in_bytes() / self.cluster_size()
+    }
+
+    /// returns the number of pages reserved for the metadata of the store
+    fn md_pages(&self) -> u64 {
+        unsafe { lvs_md_pages(self.0.as_ptr()) }
+    }
+
+    /// returns the number of metadata pages per cluster in percent, which
+    /// limits the number of lvols and snapshots the store can hold
+    pub fn metadata_ratio(&self) -> u32 {
+        let clusters = self.total_clusters();
+        ((self.md_pages() * 100 + clusters - 1) / clusters) as u32
+    }
+
+    /// returns the method the disks were cleared with when the store was
+    /// created
+    pub fn clear_method(&self) -> ClearMethod {
+        self.settings().clear_method
+    }
+
+    /// returns the settings of the store
+    fn settings(&self) -> LvsSettings {
+        settings().get(self.name()).copied().unwrap_or_default()
+    }
+
+    /// updates the settings of the store with `update`
+    fn update_settings(&self, update: impl FnOnce(&mut LvsSettings)) {
+        update(settings().entry(self.name().to_string()).or_default());
+    }
+
+    /// reads the setting `setting` of the store from its disks, `default` if
+    /// it has never been set
+    async fn get_setting(
+        &self,
+        setting: &str,
+        default: u32,
+    ) -> ErrnoResult<u32> {
+        extern "C" fn setting_cb(sender: *mut c_void, value: u32, errno: i32) {
+            let sender = unsafe {
+                Box::from_raw(sender as *mut oneshot::Sender<ErrnoResult<u32>>)
+            };
+            let result = if errno == 0 {
+                Ok(value)
+            } else {
+                Err(Errno::from_i32(errno.abs()))
+            };
+            sender.send(result).expect("receiver gone");
+        }
+
+        let cname = setting.into_cstring();
+        let (s, r) = pair::<ErrnoResult<u32>>();
+        unsafe {
+            lvs_get_xattr(
+                self.0.as_ptr(),
+                cname.as_ptr(),
+                default,
+                Some(setting_cb),
+                cb_arg(s),
+            )
+        };
+
+        r.await.expect("callback gone while reading a setting")
+    }
+
+    /// stores the setting `setting` of the store on its disks
+    async fn set_setting(
+        &self,
+        setting: &str,
+        value: u32,
+    ) -> Result<(), Error> {
+        let cname = setting.into_cstring();
+        let (s, r) = pair::<i32>();
+        unsafe {
+            lvs_set_xattr(
+                self.0.as_ptr(),
+                cname.as_ptr(),
+                value,
+                Some(Self::lvs_op_cb),
+                cb_arg(s),
+            )
+        };
+
+        r.await
+            .expect("callback gone while storing a setting")
+            .to_result(|e| Error::SetSetting {
+                source: Errno::from_i32(e),
+                setting: setting.to_string(),
+                name: self.name().to_string(),
+            })
+    }
+
+    /// forgets the settings of the pool `name` which is no longer loaded
+    fn forget_settings(name: &str) {
+        settings().remove(name);
+    }
+
+    /// reads the clear method, the overcommit limit and the wipe method of
+    /// the store from its disks
+    async fn load_settings(&self) {
+        let name = self.name();
+        let mut loaded = LvsSettings::default();
+        match self
+            .get_setting(CLEAR_METHOD_XATTR, LVS_CLEAR_WITH_NONE as u32)
+            .await
+        {
+            Ok(method) => loaded.clear_method = ClearMethod::from(method),
+            Err(e) => {
+                warn!("failed to read the clear method of {}: {}", name, e)
+            }
+        }
+
+        match self.get_setting(OVERCOMMIT_LIMIT_XATTR, 0).await {
+            Ok(percent) => loaded.overcommit_limit = percent,
+            Err(e) => {
+                warn!("failed to read the overcommit limit of {}: {}", name, e)
+            }
+        }
+
+        match self.get_setting(WIPE_METHOD_XATTR, 0).await {
+            Ok(value) => {
+                if let Ok(Some(method)) = WipeMethod::of_request(value as i32) {
+                    loaded.wipe_method = method;
+                }
+            }
+            Err(e) => {
+                warn!("failed to read the wipe method of {}: {}", name, e)
+            }
+        }
+
+        settings().insert(name.to_string(), loaded);
+    }
+
+    /// checks that the options requested for the store match the ones it was
+    /// created with, the options without a requested value are not checked
+    fn check_options(
+        &self,
+        args: &CreatePoolRequest,
+        clear_method: Option<ClearMethod>,
+    ) -> Result<(), Error> {
+        let mismatch = |option: &str| Error::Invalid {
+            source: Errno::EINVAL,
+            msg: format!(
+                "the {} of pool {} does not match the requested one",
+                option,
+                self.name()
+            ),
+        };
+
+        if args.cluster_size != 0
+            && u64::from(args.cluster_size) != self.cluster_size()
+        {
+            return Err(mismatch("cluster size"));
+        }
+        if args.metadata_ratio != 0
+            && md_pages(self.total_clusters(), args.metadata_ratio)
+                != self.md_pages()
+        {
+            return Err(mismatch("metadata ratio"));
+        }
+        match clear_method {
+            Some(method) if method != self.clear_method() => {
+                Err(mismatch("clear method"))
+            }
+            _ => Ok(()),
+        }
+    }
+
     /// returns the available capacity
     pub fn available(&self) -> u64 {
         let blobs = unsafe { self.0.as_ref().blobstore };
@@ -167,6 +480,85 @@ impl Lvs {
         self.capacity() - self.available()
     }
 
+    /// returns the sum of the sizes of the lvols, which exceeds the capacity
+    /// if thin provisioned lvols overcommit the store
+    pub fn committed(&self) -> u64 {
+        self.lvols()
+            .map(|lvols| {
+                lvols.filter(|l| !l.is_snapshot()).map(|l| l.size()).sum()
+            })
+            .unwrap_or_default()
+    }
+
+    /// returns the capacity reserved for thin provisioned lvols which they
+    /// have not allocated yet
+    pub fn reserved(&self) -> u64 {
+        self.lvols()
+            .map(|lvols| lvols.map(|l| l.reserved()).sum())
+            .unwrap_or_default()
+    }
+
+    /// returns the available capacity which is not reserved
+    pub fn unreserved(&self) -> u64 {
+        self.available().saturating_sub(self.reserved())
+    }
+
+    /// returns the maximum sum of the sizes of the lvols in percent of the
+    /// capacity, 0 if there is no limit
+    pub fn overcommit_limit(&self) -> u32 {
+        self.settings().overcommit_limit
+    }
+
+    /// sets the overcommit limit of the store in percent of its capacity, 0
+    /// for no limit, and stores it on its disks
+    pub async fn set_overcommit_limit(
+        &self,
+        percent: u32,
+    ) -> Result<(), Error> {
+        self.set_setting(OVERCOMMIT_LIMIT_XATTR, percent).await?;
+        self.update_settings(|s| s.overcommit_limit = percent);
+        Ok(())
+    }
+
+    /// returns the method the data of the lvols is wiped with when they are
+    /// destroyed without a method of their own
+    pub fn wipe_method(&self) -> WipeMethod {
+        self.settings().wipe_method
+    }
+
+    /// sets the default wipe method of the lvols of the store, and stores it
+    /// on its disks
+    pub async fn set_wipe_method(
+        &self,
+        method: WipeMethod,
+    ) -> Result<(), Error> {
+        let value = ReplicaWipeMethod::from(method) as i32;
+        self.set_setting(WIPE_METHOD_XATTR, value as u32).await?;
+        self.update_settings(|s| s.wipe_method = method);
+        Ok(())
+    }
+
+    /// sets the settings of the store which are given by the request `args`,
+    /// leaving the others as they are stored on its disks
+    async fn apply_settings(
+        &self,
+        args: &CreatePoolRequest,
+        wipe_method: Option<WipeMethod>,
+    ) -> Result<(), Error> {
+        match args.overcommit_limit {
+            Some(limit) if limit != self.overcommit_limit() => {
+                self.set_overcommit_limit(limit).await?
+            }
+            _ => (),
+        }
+        match wipe_method {
+            Some(method) if method != self.wipe_method() => {
+                self.set_wipe_method(method).await
+            }
+            _ => Ok(()),
+        }
+    }
+
     /// returns the base bdev of this lvs
     pub fn base_bdev(&self) -> Bdev {
         Bdev::from(unsafe {
@@ -174,6 +566,32 @@ impl Lvs {
         })
     }
 
+    /// returns the disks of this lvs and their state, in order
+    pub fn disks(&self) -> Vec<PoolDisk> {
+        let base_bdev = self.base_bdev();
+        match Raid::lookup(&base_bdev.name()) {
+            Some(raid) => raid.disks(),
+            None => {
+                vec![PoolDisk::of_bdev(&base_bdev.name(), DiskState::Online)]
+            }
+        }
+    }
+
+    /// returns the names of the bdevs of the disks of this lvs, in order
+    pub(super) fn disk_names(&self) -> Vec<String> {
+        let base_bdev = self.base_bdev();
+        match Raid::lookup(&base_bdev.name()) {
+            Some(raid) => raid.members(),
+            None => vec![base_bdev.name()],
+        }
+    }
+
+    /// returns the state of the lvs based on the state of its disks and
+    /// the checks of the monitor
+    pub fn state(&self) -> LvsState {
+        self.health().state
+    }
+
     /// returns the UUID of the lvs
     pub fn uuid(&self) -> String {
         let t = unsafe { self.0.as_ref().uuid.u.raw };
@@ -232,8 +650,13 @@ impl Lvs {
                 name: name.into(),
             })
         } else {
+            lvs.load_settings().await;
+            lvs.watch_io_errors();
             lvs.share_all().await;
+            lvs.forget_writes();
             info!("The pool '{}' has been imported", name);
+            Self::forget(name);
+            Self::start_monitor();
             Ok(lvs)
         }
     }
@@ -241,55 +664,117 @@ impl Lvs {
     #[instrument(level = "debug", err)]
     /// Create a pool on base bdev
     pub async fn create(name: &str, bdev: &str) -> Result<Lvs, Error> {
-        let pool_name = name.into_cstring();
-        let bdev_name = bdev.into_cstring();
+        // We used to clear a pool with UNMAP but that takes awfully
+        // long time on large SSDs (~ can take an hour). Clearing the
+        // pool is not necessary. Clearing the lvol must be done, but
+        // lvols tend to be small so there the overhead is acceptable.
+        Self::create_ext(name, bdev, 0, 0, ClearMethod::None).await
+    }
 
-        let (sender, receiver) = pair::<ErrnoResult<Lvs>>();
-        unsafe {
-            vbdev_lvs_create(
-                bdev_name.as_ptr(),
-                pool_name.as_ptr(),
-                0,
-                // We used to clear a pool with UNMAP but that takes awfully
-                // long time on large SSDs (~ can take an hour). Clearing the
-                // pool is not necessary. Clearing the lvol must be done, but
-                // lvols tend to be small so there the overhead is acceptable.
-                LVS_CLEAR_WITH_NONE,
-                Some(Self::lvs_cb),
-                cb_arg(sender),
-            )
-        }
-        .to_result(|e| Error::Create {
-            source: Errno::from_i32(e),
+    /// Create a pool on base bdev with clusters of `cluster_size` bytes and
+    /// `metadata_ratio` percent of a metadata page per cluster, 0 for the
+    /// defaults, after clearing the base bdev with `clear_method`
+    #[instrument(level = "debug", err)]
+    pub async fn create_ext(
+        name: &str,
+        bdev: &str,
+        cluster_size: u32,
+        metadata_ratio: u32,
+        clear_method: ClearMethod,
+    ) -> Result<Lvs, Error> {
+        let create_err = |source| Error::Create {
+            source,
             name: name.to_string(),
-        })?;
+        };
 
-        receiver
-            .await
-            .expect("Cancellation is not supported")
-            .map_err(|err| Error::Create {
-                source: err,
+        let base_bdev =
+            Bdev::lookup_by_name(bdev).ok_or(Error::InvalidBdev {
+                source: NexusBdevError::BdevNotFound {
+                    name: bdev.to_string(),
+                },
                 name: name.to_string(),
             })?;
+        if base_bdev.is_claimed() {
+            return Err(create_err(Errno::EBUSY));
+        }
 
-        match Self::lookup(&name) {
-            Some(pool) => {
-                info!("The pool '{}' has been created on {}", name, bdev);
-                Ok(pool)
-            }
-            None => Err(Error::Create {
-                source: Errno::ENOENT,
-                name: name.to_string(),
-            }),
+        let mut opts = spdk_lvs_opts::default();
+        unsafe { spdk_lvs_opts_init(&mut opts) };
+        if cluster_size != 0 {
+            opts.cluster_sz = cluster_size;
+        }
+        if metadata_ratio != 0 {
+            opts.num_md_pages_per_cluster_ratio = metadata_ratio;
+        }
+        opts.clear_method = clear_method.into();
+        for (dst, src) in opts.name.iter_mut().zip(name.as_bytes()) {
+            *dst = *src as c_char;
+        }
+
+        let bdev_name = bdev.into_cstring();
+        let (s, r) = pair::<ErrnoResult<Lvs>>();
+        unsafe {
+            lvs_create_ext(
+                bdev_name.as_ptr(),
+                &opts,
+                Some(Self::lvs_cb),
+                cb_arg(s),
+            )
         }
+        .to_result(|e| create_err(Errno::from_i32(e)))?;
+
+        let pool = match r.await.expect("callback gone while creating lvs") {
+            Ok(pool) => pool,
+            Err(e) => {
+                // the store is left behind if its clear method failed to be
+                // recorded
+                if let Some(pool) = Self::lookup(name) {
+                    let (s, r) = pair::<i32>();
+                    unsafe {
+                        vbdev_lvs_destruct(
+                            pool.0.as_ptr(),
+                            Some(Self::lvs_op_cb),
+                            cb_arg(s),
+                        )
+                    };
+                    let _ = r.await;
+                }
+                return Err(create_err(e));
+            }
+        };
+
+        pool.load_settings().await;
+        pool.watch_io_errors();
+        Self::forget(name);
+        Self::start_monitor();
+        info!("The pool '{}' has been created on {}", name, bdev);
+        Ok(pool)
     }
 
-    /// imports the pool if it exists, otherwise try to create it
+    /// imports the pool if it exists, otherwise try to create it. A pool
+    /// with several disks is created on the disks striped in the given order
     #[instrument(level = "debug", err)]
     pub async fn create_or_import(
         args: CreatePoolRequest,
     ) -> Result<Lvs, Error> {
-        if args.disks.len() != 1 {
+        Self::load(args, true).await
+    }
+
+    /// imports an existing pool from its disks, which are striped in the given
+    /// order if there are several. Unlike `create_or_import` a new pool is
+    /// never created, so the disks of an exported pool can be moved to another
+    /// node without the risk of overwriting them
+    #[instrument(level = "debug", err)]
+    pub async fn import_from_disks(
+        args: CreatePoolRequest,
+    ) -> Result<Lvs, Error> {
+        Self::load(args, false).await
+    }
+
+    /// attaches the disks of the pool and imports it, creating the pool when
+    /// none is found on the disks and `create` is set
+    async fn load(args: CreatePoolRequest, create: bool) -> Result<Lvs, Error> {
+        if args.disks.is_empty() || args.disks.len() > u8::MAX as usize {
             return Err(Error::Invalid {
                 source: Errno::EINVAL,
                 msg: format!(
@@ -299,6 +784,23 @@ impl Lvs {
                 ),
             });
         }
+        if args.cluster_size != 0
+            && (!args.cluster_size.is_power_of_two()
+                || args.cluster_size < SPDK_BS_PAGE_SIZE)
+        {
+            return Err(Error::Invalid {
+                source: Errno::EINVAL,
+                msg: format!("invalid cluster size {}", args.cluster_size),
+            });
+        }
+        if args.metadata_ratio > 100 {
+            return Err(Error::Invalid {
+                source: Errno::EINVAL,
+                msg: format!("invalid metadata ratio {}", args.metadata_ratio),
+            });
+        }
+        let clear_method = ClearMethod::of_request(&args)?;
+        let wipe_method = WipeMethod::of_request(args.wipe_method)?;
 
         // default to uring if kernel supports it
         let disks = args
@@ -321,13 +823,20 @@ impl Lvs {
             })
             .collect::<Vec<_>>();
 
-        let parsed = Uri::parse(&disks[0]).map_err(|e| Error::InvalidBdev {
-            source: e,
-            name: args.name.clone(),
-        })?;
+        let parsed = disks
+            .iter()
+            .map(|d| Uri::parse(d))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::InvalidBdev {
+                source: e,
+                name: args.name.clone(),
+            })?;
+        let names = parsed.iter().map(|p| p.get_name()).collect::<Vec<_>>();
 
         if let Some(pool) = Self::lookup(&args.name) {
-            return if pool.base_bdev().name() == parsed.get_name() {
+            return if pool.disk_names() == names {
+                pool.check_options(&args, clear_method)?;
+                pool.apply_settings(&args, wipe_method).await?;
                 Ok(pool)
             } else {
                 Err(Error::Create {
@@ -337,21 +846,44 @@ impl Lvs {
             };
         }
 
-        let bdev = match parsed.create().await {
-            Err(e) => match e {
-                NexusBdevError::BdevExists {
+        let mut created = Vec::new();
+        for (disk, uri) in parsed.into_iter().zip(args.disks.iter()) {
+            match disk.create().await {
+                Ok(_) => created.push(disk),
+                Err(NexusBdevError::BdevExists {
                     ..
-                } => Ok(parsed.get_name()),
-                _ => Err(Error::InvalidBdev {
-                    source: e,
-                    name: args.disks[0].clone(),
-                }),
-            },
-            Ok(name) => Ok(name),
-        }?;
+                }) => {}
+                Err(e) => {
+                    Self::destroy_created(created).await;
+                    return Err(Error::InvalidBdev {
+                        source: e,
+                        name: uri.clone(),
+                    });
+                }
+            }
+        }
 
-        match Self::import(&args.name, &bdev).await {
-            Ok(pool) => Ok(pool),
+        let (bdev, pool_data) =
+            match Self::stripe_disks(&args.name, &names, create).await {
+                Ok(stripe) => stripe,
+                Err(source) => {
+                    Self::destroy_created(created).await;
+                    return Err(Error::StripeDisks {
+                        source,
+                        name: args.name.clone(),
+                    });
+                }
+            };
+
+        let pool = match Self::import(&args.name, &bdev).await {
+            Ok(pool) => match pool.check_options(&args, clear_method) {
+                Ok(_) => Ok(pool),
+                Err(e) => {
+                    // leave the disks of the pool as they were
+                    let _ = pool.export().await;
+                    Err(e)
+                }
+            },
             Err(Error::Import {
                 source,
                 name,
@@ -363,18 +895,54 @@ impl Lvs {
                     name,
                 })
             }
+            // there is no pool on the disks to import
+            Err(Error::Import {
+                source,
+                name,
+            }) if source == Errno::EILSEQ && !create => {
+                Self::destroy_stripe(&bdev).await;
+                Self::destroy_created(created).await;
+                Err(Error::Import {
+                    source: Errno::ENOENT,
+                    name,
+                })
+            }
+            // the disks hold the data of a pool which failed to be imported,
+            // which must not be overwritten by a new pool
+            Err(Error::Import {
+                source,
+                name,
+            }) if source == Errno::EILSEQ && pool_data => {
+                error!(
+                    "the disks {:?} hold the data of a pool, not creating pool {} over them",
+                    names, name
+                );
+                Self::destroy_stripe(&bdev).await;
+                Self::destroy_created(created).await;
+                Err(Error::Create {
+                    source: Errno::EEXIST,
+                    name,
+                })
+            }
             // try to create the pool
             Err(Error::Import {
                 source, ..
             }) if source == Errno::EILSEQ => {
-                match Self::create(&args.name, &bdev).await {
+                match Self::create_ext(
+                    &args.name,
+                    &bdev,
+                    args.cluster_size,
+                    args.metadata_ratio,
+                    clear_method.unwrap_or(ClearMethod::None),
+                )
+                .await
+                {
                     Err(create) => {
-                        let _ = parsed.destroy().await.map_err(|_e| {
-                            // we failed to delete the base_bdev be loud about it
-                            // there is not much we can do about it here, likely
-                            // some desc is still holding on to it or something.
-                            error!("failed to delete base_bdev {} after failed pool creation", bdev);
-                        });
+                        Self::destroy_stripe(&bdev).await;
+                        if names.len() > 1 {
+                            let _ = Raid::clear_labels(&names).await;
+                        }
+                        Self::destroy_created(created).await;
                         Err(create)
                     }
                     Ok(pool) => Ok(pool),
@@ -382,7 +950,124 @@ impl Lvs {
             }
             // some other error, bubble it back up
             Err(e) => Err(e),
+        }?;
+
+        pool.apply_settings(&args, wipe_method).await?;
+        Ok(pool)
+    }
+
+    /// stripes the bdevs `disks` of the pool `pool`, if there are several,
+    /// in the order recorded by their member labels. Disks which hold no
+    /// pool are labelled in the given order when the pool is to be created.
+    /// Returns the name of the bdev to import the pool from, and whether the
+    /// disks held the data of a pool beforehand.
+    async fn stripe_disks(
+        pool: &str,
+        disks: &[String],
+        create: bool,
+    ) -> Result<(String, bool), Errno> {
+        let labels = Raid::read_labels(disks).await?;
+        let pool_data = Raid::hold_pool_data(disks, &labels).await?;
+        if disks.len() == 1 {
+            return Ok((disks[0].clone(), pool_data));
+        }
+
+        let (disks, labelled) = match Raid::order(pool, disks, &labels)? {
+            Some(ordered) => {
+                if ordered != disks {
+                    info!(
+                        "striping the disks of pool {} in the order of their labels {:?}",
+                        pool, ordered
+                    );
+                }
+                (ordered, false)
+            }
+            None if create && !pool_data => {
+                Raid::write_labels(pool, disks).await?;
+                (disks.to_vec(), true)
+            }
+            None => (disks.to_vec(), false),
+        };
+
+        let name = Raid::name_of(pool);
+        if let Err(e) = Raid::create(&name, &disks).await {
+            if labelled {
+                let _ = Raid::clear_labels(&disks).await;
+            }
+            return Err(e);
+        }
+        Ok((name, pool_data))
+    }
+
+    /// destroys the raid striping the disks of a pool which failed to be
+    /// created or imported, if it has several disks
+    async fn destroy_stripe(bdev: &str) {
+        if let Some(raid) = Raid::lookup(bdev) {
+            let _ = raid.destroy().await;
+        }
+    }
+
+    /// destroys the base bdevs created for a pool which failed to be created
+    async fn destroy_created(
+        created: Vec<Box<dyn BdevCreateDestroy<Error = NexusBdevError>>>,
+    ) {
+        for disk in created {
+            let name = disk.get_name();
+            let _ = disk.destroy().await.map_err(|_e| {
+                // we failed to delete the base_bdev be loud about it
+                // there is not much we can do about it here, likely
+                // some desc is still holding on to it or something.
+                error!(
+                    "failed to delete base_bdev {} after failed pool creation",
+                    name
+                );
+            });
+        }
+    }
+
+    /// destroys the base bdevs of an unloaded pool, the base bdev of which
+    /// was `base_bdev` and its disks striped by `raid` if it had several,
+    /// erasing their member labels if the pool has been destroyed
+    async fn destroy_disks(
+        base_bdev: Bdev,
+        raid: Option<Raid>,
+        pool: &str,
+        destroyed: bool,
+    ) -> Result<(), Error> {
+        let disks = match raid {
+            Some(raid) => {
+                let disks = raid
+                    .members()
+                    .iter()
+                    .filter_map(|name| Bdev::lookup_by_name(name))
+                    .collect::<Vec<_>>();
+                let members = raid.members();
+                raid.destroy().await.map_err(|e| Error::UnstripeDisks {
+                    source: e,
+                    name: pool.to_string(),
+                })?;
+                if destroyed {
+                    if let Err(e) = Raid::clear_labels(&members).await {
+                        warn!(
+                            "failed to clear the labels of the disks of pool {}: {}",
+                            pool, e
+                        );
+                    }
+                }
+                disks
+            }
+            None => vec![base_bdev],
+        };
+
+        for disk in disks {
+            bdev_destroy(&disk.bdev_uri().unwrap()).await.map_err(|e| {
+                Error::Destroy {
+                    source: e,
+                    name: disk.name(),
+                }
+            })?;
         }
+        Ok(())
     }
 
     /// export the given lvl
@@ -391,29 +1076,50 @@ impl Lvs {
     pub async fn export(self) -> Result<(), Error> {
         let pool = self.name().to_string();
         let base_bdev = self.base_bdev();
+        let raid = Raid::lookup(&base_bdev.name());
         let (s, r) = pair::<i32>();
 
+        Self::unmonitor(&pool);
         self.unshare_all().await;
 
         unsafe {
             vbdev_lvs_unload(self.0.as_ptr(), Some(Self::lvs_op_cb), cb_arg(s))
         };
 
-        r.await
+        let result = r
+            .await
             .expect("callback gone while exporting lvs")
             .to_result(|e| Error::Export {
                 source: Errno::from_i32(e),
                 name: pool.clone(),
-            })?;
+            });
+        Self::forget(&pool);
+        result?;
 
-        info!("pool {} exported successfully", pool);
-        bdev_destroy(&base_bdev.bdev_uri().unwrap())
+        // the settings are read from the disks again when it is imported
+        Self::forget_settings(&pool);
+
+        // the disks are moved to another node once exported, so make sure
+        // that all the data written to them is persisted before detaching
+        Self::flush_disks(&base_bdev)
             .await
-            .map_err(|e| Error::Destroy {
+            .map_err(|e| Error::Flush {
                 source: e,
-                name: base_bdev.name(),
+                name: pool.clone(),
             })?;
-        Ok(())
+
+        info!("pool {} exported successfully", pool);
+        Self::destroy_disks(base_bdev, raid, &pool, false).await
+    }
+
+    /// flushes the base bdev of an unloaded pool, which is no longer claimed
+    /// by the lvs so it can be opened for writing
+    async fn flush_disks(base_bdev: &Bdev) -> Result<(), CoreError> {
+        if !base_bdev.io_type_supported(IoType::Flush) {
+            return Ok(());
+        }
+        let hdl = BdevHandle::open_with_bdev(base_bdev, true)?;
+        hdl.flush().await
     }
 
     /// unshare all lvols prior to export or destroy
@@ -428,26 +1134,25 @@ impl Lvs {
         }
     }
 
-    /// share all lvols who have the shared property set, this is implicitly
-    /// shared over nvmf
+    /// share all lvols who have the shared property set, with the protocol
+    /// they were shared with
     async fn share_all(&self) {
         if let Some(lvols) = self.lvols() {
             for l in lvols {
-                if let Ok(prop) = l.get(PropName::Shared).await {
-                    match prop {
-                        PropValue::Shared(true) => {
-                            if let Err(e) = l.share_nvmf().await {
-                                error!(
-                                    "failed to share {} {}",
-                                    l.name(),
-                                    e.to_string()
-                                );
-                            }
-                        }
-                        PropValue::Shared(false) => {
-                            debug!("{} not shared on disk", l.name())
-                        }
+                let result = match l.get(PropName::Shared).await {
+                    Ok(PropValue::Shared(Protocol::Nvmf)) => {
+                        l.share_nvmf().await
+                    }
+                    Ok(PropValue::Shared(Protocol::Iscsi)) => {
+                        l.share_iscsi().await
+                    }
+                    _ => {
+                        debug!("{} not shared on disk", l.name());
+                        continue;
                     }
+                };
+                if let Err(e) = result {
+                    error!("failed to share {} {}", l.name(), e.to_string());
                 }
             }
         }
@@ -462,9 +1167,11 @@ impl Lvs {
         let (s, r) = pair::<i32>();
 
         // when destroying a pool unshare all volumes
+        Self::unmonitor(&pool);
         self.unshare_all().await;
 
         let base_bdev = self.base_bdev();
+        let raid = Raid::lookup(&base_bdev.name());
 
         unsafe {
             vbdev_lvs_destruct(
@@ -474,23 +1181,20 @@ impl Lvs {
             )
         };
 
-        r.await
+        let result = r
+            .await
             .expect("callback gone while destroying lvs")
             .to_result(|e| Error::Export {
                 source: Errno::from_i32(e),
                 name: pool.clone(),
-            })?;
+            });
+        Self::forget(&pool);
+        result?;
 
+        Self::forget_settings(&pool);
         info!("pool {} destroyed successfully", pool);
 
-        bdev_destroy(&base_bdev.bdev_uri().unwrap())
-            .await
-            .map_err(|e| Error::Destroy {
-                source: e,
-                name: base_bdev.name(),
-            })?;
-
-        Ok(())
+        Self::destroy_disks(base_bdev, raid, &pool, true).await
     }
 
     /// return an iterator that filters out all bdevs that patch the pool
@@ -535,6 +1239,12 @@ impl Lvs {
             });
         };
 
+        self.check_space(name, size, if thin { 0 } else { size })
+            .map_err(|source| Error::RepCreate {
+                source,
+                name: name.to_string(),
+            })?;
+
         let (s, r) = pair::<ErrnoResult<*mut spdk_lvol>>();
 
         let cname = name.into_cstring();
@@ -563,7 +1273,48 @@ impl Lvs {
             })
             .map(|lvol| Lvol(NonNull::new(lvol).unwrap()))?;
 
+        if let Err(e) = lvol.set_created().await {
+            warn!("failed to record the creation time of {}: {}", lvol, e);
+        }
+
         info!("created {}", lvol);
         Ok(lvol)
     }
+
+    /// checks that `size` bytes can be committed to the lvol `name` without
+    /// exceeding the overcommit limit, and that `allocated` bytes can be
+    /// allocated to it without using space reserved for thin provisioned
+    /// lvols. Fails with ENOSPC otherwise.
+    pub(super) fn check_space(
+        &self,
+        name: &str,
+        size: u64,
+        allocated: u64,
+    ) -> Result<(), Errno> {
+        let limit = self.overcommit_limit() as u64;
+        let committed = self.committed() + size;
+        if limit > 0 && committed * 100 > self.capacity() * limit {
+            error!(
+                "{}: {} would commit {} bytes, exceeding the limit of {}% of {} bytes",
+                self.name(),
+                name,
+                committed,
+                limit,
+                self.capacity()
+            );
+            return Err(Errno::ENOSPC);
+        }
+
+        if allocated > self.unreserved() {
+            error!(
+                "{}: {} bytes needed for {}, {} unreserved",
+                self.name(),
+                allocated,
+                name,
+                self.unreserved()
+            );
+            return Err(Errno::ENOSPC);
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,436 @@
+//! Striping of the disks of a pool which has more than one disk. The disks
+//! are striped by a raid0 bdev on which the lvol store is created. The raid
+//! bdev leaves out the last strip of each disk, which holds a label recording
+//! the position of the disk in the stripe, so that a pool is imported by
+//! striping its disks in the same order whichever order they are given in.
+
+use std::{ffi::CStr, io::Cursor, os::raw::c_void, ptr::NonNull};
+
+use bincode::{deserialize_from, serialize_into};
+use crc::crc32;
+use futures::channel::oneshot;
+use nix::errno::Errno;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use spdk_sys::{
+    raid_bdev_add_base_devices,
+    raid_bdev_config,
+    raid_bdev_config_add,
+    raid_bdev_config_add_base_bdev,
+    raid_bdev_config_cleanup,
+    raid_bdev_config_find_by_name,
+    raid_bdev_create,
+    raid_bdev_remove_base_devices,
+    spdk_bdev_notify_blockcnt_change,
+    RAID0,
+    RAID_BDEV_STATE_ONLINE,
+};
+
+use crate::{
+    core::{Bdev, BdevHandle, Share},
+    ffihelper::{cb_arg, pair, IntoCString},
+};
+
+/// size of a strip of the striped disks in KiB
+const STRIP_SIZE_KB: u32 = 64;
+
+/// signature at the start of the block 0 of a blobstore
+const BLOBSTORE_SIGNATURE: &[u8; 8] = b"SPDKBLOB";
+
+/// Label in the last strip of each disk of a striped pool
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub(crate) struct MemberLabel {
+    /// signature identifying this as a member label
+    signature: [u8; 8],
+    /// CRC-32 checksum of the label with the checksum set to 0
+    checksum: u32,
+    /// uuid of the stripe, generated when the pool is created
+    uuid: String,
+    /// name of the pool
+    pool: String,
+    /// position of the disk in the stripe
+    index: u8,
+    /// number of disks in the stripe
+    count: u8,
+}
+
+impl MemberLabel {
+    const SIGNATURE: [u8; 8] = *b"MayaRaid";
+
+    fn checksum(&self) -> u32 {
+        let mut copy = self.clone();
+        copy.checksum = 0;
+        crc32::checksum_ieee(&bincode::serialize(&copy).unwrap_or_default())
+    }
+}
+
+/// State of a disk of a pool
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiskState {
+    /// the disk is in use by the pool
+    Online,
+    /// the disk has been hot-removed and is being removed from the pool
+    Removing,
+    /// the disk is gone
+    Missing,
+}
+
+/// A disk of a pool and its state
+#[derive(Debug, Clone)]
+pub struct PoolDisk {
+    /// URI the disk was created with, or the name of its bdev
+    pub uri: String,
+    /// current state of the disk
+    pub state: DiskState,
+}
+
+impl PoolDisk {
+    /// returns the disk of the bdev `name`
+    pub(crate) fn of_bdev(name: &str, state: DiskState) -> Self {
+        let uri = Bdev::lookup_by_name(name)
+            .and_then(|b| b.bdev_uri())
+            .unwrap_or_else(|| name.to_string());
+        Self {
+            uri,
+            state,
+        }
+    }
+}
+
+/// raid0 bdev striping the disks of a pool
+pub(crate) struct Raid(NonNull<raid_bdev_config>);
+
+impl Raid {
+    /// callback when operation has been performed on the raid bdev
+    extern "C" fn raid_op_cb(sender: *mut c_void, errno: i32) {
+        let sender =
+            unsafe { Box::from_raw(sender as *mut oneshot::Sender<i32>) };
+        sender.send(errno).unwrap();
+    }
+
+    /// returns the name of the bdev striping the disks of the pool `pool`
+    pub(crate) fn name_of(pool: &str) -> String {
+        format!("{}-disks", pool)
+    }
+
+    /// lookup a raid bdev by its name
+    pub(crate) fn lookup(name: &str) -> Option<Self> {
+        let name = name.into_cstring();
+        NonNull::new(unsafe { raid_bdev_config_find_by_name(name.as_ptr()) })
+            .map(Self)
+    }
+
+    /// stripes the bdevs `disks`, in the given order, into the raid bdev
+    /// `name`
+    pub(crate) async fn create(
+        name: &str,
+        disks: &[String],
+    ) -> Result<Self, Errno> {
+        if disks.len() > u8::MAX as usize {
+            return Err(Errno::E2BIG);
+        }
+
+        let cname = name.into_cstring();
+        let mut config: *mut raid_bdev_config = std::ptr::null_mut();
+        let rc = unsafe {
+            raid_bdev_config_add(
+                cname.as_ptr(),
+                STRIP_SIZE_KB,
+                disks.len() as u8,
+                RAID0,
+                &mut config,
+            )
+        };
+        if rc != 0 {
+            return Err(Errno::from_i32(rc.abs()));
+        }
+
+        let rc = disks
+            .iter()
+            .enumerate()
+            .map(|(slot, disk)| {
+                let disk = disk.as_str().into_cstring();
+                unsafe {
+                    raid_bdev_config_add_base_bdev(
+                        config,
+                        disk.as_ptr(),
+                        slot as u8,
+                    )
+                }
+            })
+            .find(|rc| *rc != 0)
+            .unwrap_or_else(|| unsafe { raid_bdev_create(config) });
+        if rc != 0 {
+            unsafe { raid_bdev_config_cleanup(config) };
+            return Err(Errno::from_i32(rc.abs()));
+        }
+
+        let raid = Self(NonNull::new(config).unwrap());
+        let rc = unsafe { raid_bdev_add_base_devices(config) };
+        if rc != 0 || !raid.online() {
+            error!("failed to stripe the disks {:?} into {}", disks, name);
+            let _ = raid.destroy().await;
+            return Err(if rc != 0 {
+                Errno::from_i32(rc.abs())
+            } else {
+                Errno::ENODEV
+            });
+        }
+
+        // leave the last strip of each disk, which holds its label, out of
+        // the raid bdev
+        let rc = unsafe {
+            let raid_bdev = (*config).raid_bdev;
+            let labels =
+                u64::from((*raid_bdev).strip_size) * disks.len() as u64;
+            spdk_bdev_notify_blockcnt_change(
+                &mut (*raid_bdev).bdev,
+                (*raid_bdev).bdev.blockcnt - labels,
+            )
+        };
+        if rc != 0 {
+            error!("failed to reserve the labels of the disks of {}", name);
+            let _ = raid.destroy().await;
+            return Err(Errno::from_i32(rc.abs()));
+        }
+
+        info!("striped the disks {:?} into {}", disks, name);
+        Ok(raid)
+    }
+
+    /// returns the byte offset of the label of each of the bdevs `disks`
+    /// when striped together, which is in the last strip of the disks as far
+    /// as the smallest of them goes
+    fn label_offset(disks: &[Bdev]) -> Option<u64> {
+        let block_len = u64::from(disks.first()?.block_len());
+        let strip_blocks = u64::from(STRIP_SIZE_KB) * 1024 / block_len;
+        let num_blocks = disks.iter().map(|d| d.num_blocks()).min()?;
+        let strips = num_blocks / strip_blocks;
+        if strips < 2 {
+            return None;
+        }
+        Some((strips - 1) * strip_blocks * block_len)
+    }
+
+    /// looks up the bdevs `disks` and opens a handle on each of them
+    fn open_disks(
+        disks: &[String],
+        write: bool,
+    ) -> Result<Vec<BdevHandle>, Errno> {
+        disks
+            .iter()
+            .map(|name| {
+                Bdev::open_by_name(name, write)
+                    .and_then(|d| d.into_handle())
+                    .map_err(|e| {
+                        error!("failed to open disk {}: {}", name, e);
+                        Errno::ENODEV
+                    })
+            })
+            .collect()
+    }
+
+    /// reads the member labels of the bdevs `disks`, None for those which
+    /// hold no valid label
+    pub(crate) async fn read_labels(
+        disks: &[String],
+    ) -> Result<Vec<Option<MemberLabel>>, Errno> {
+        let handles = Self::open_disks(disks, false)?;
+        let bdevs = handles.iter().map(|h| h.get_bdev()).collect::<Vec<_>>();
+        let offset = match Self::label_offset(&bdevs) {
+            Some(offset) => offset,
+            None => return Ok(vec![None; disks.len()]),
+        };
+
+        let mut labels = Vec::new();
+        for hdl in &handles {
+            let mut buf = hdl
+                .dma_malloc(u64::from(hdl.get_bdev().block_len()))
+                .map_err(|_| Errno::ENOMEM)?;
+            hdl.read_at(offset, &mut buf)
+                .await
+                .map_err(|_| Errno::EIO)?;
+
+            let label: Option<MemberLabel> =
+                deserialize_from(&mut Cursor::new(buf.as_slice())).ok();
+            labels.push(label.filter(|l| {
+                l.signature == MemberLabel::SIGNATURE
+                    && l.checksum == l.checksum()
+            }));
+        }
+        Ok(labels)
+    }
+
+    /// writes the member labels of a new stripe of the pool `pool` on the
+    /// bdevs `disks`, in the order they are to be striped in
+    pub(crate) async fn write_labels(
+        pool: &str,
+        disks: &[String],
+    ) -> Result<(), Errno> {
+        let handles = Self::open_disks(disks, true)?;
+        let bdevs = handles.iter().map(|h| h.get_bdev()).collect::<Vec<_>>();
+        let offset = Self::label_offset(&bdevs).ok_or(Errno::ENOSPC)?;
+        let uuid = Uuid::new_v4().to_string();
+
+        for (index, hdl) in handles.iter().enumerate() {
+            let mut label = MemberLabel {
+                signature: MemberLabel::SIGNATURE,
+                checksum: 0,
+                uuid: uuid.clone(),
+                pool: pool.to_string(),
+                index: index as u8,
+                count: disks.len() as u8,
+            };
+            label.checksum = label.checksum();
+
+            let mut buf = hdl
+                .dma_malloc(u64::from(hdl.get_bdev().block_len()))
+                .map_err(|_| Errno::ENOMEM)?;
+            buf.fill(0);
+            serialize_into(&mut Cursor::new(buf.as_mut_slice()), &label)
+                .map_err(|_| Errno::EINVAL)?;
+            hdl.write_at(offset, &buf).await.map_err(|_| Errno::EIO)?;
+        }
+        Ok(())
+    }
+
+    /// erases the member labels of the bdevs `disks`, which are no longer
+    /// striped into a pool
+    pub(crate) async fn clear_labels(disks: &[String]) -> Result<(), Errno> {
+        let handles = Self::open_disks(disks, true)?;
+        let bdevs = handles.iter().map(|h| h.get_bdev()).collect::<Vec<_>>();
+        let offset = match Self::label_offset(&bdevs) {
+            Some(offset) => offset,
+            None => return Ok(()),
+        };
+
+        for hdl in &handles {
+            let mut buf = hdl
+                .dma_malloc(u64::from(hdl.get_bdev().block_len()))
+                .map_err(|_| Errno::ENOMEM)?;
+            buf.fill(0);
+            hdl.write_at(offset, &buf).await.map_err(|_| Errno::EIO)?;
+        }
+        Ok(())
+    }
+
+    /// returns the bdevs `disks` in the order their labels record for the
+    /// pool `pool`, or None if none of them holds a label. Fails if they do
+    /// not make up a complete stripe of the pool.
+    pub(crate) fn order(
+        pool: &str,
+        disks: &[String],
+        labels: &[Option<MemberLabel>],
+    ) -> Result<Option<Vec<String>>, Errno> {
+        if labels.iter().all(Option::is_none) {
+            return Ok(None);
+        }
+
+        let first = labels[0].as_ref().ok_or(Errno::ENXIO)?;
+        let mut ordered = vec![None; disks.len()];
+        for (disk, label) in disks.iter().zip(labels) {
+            let label = label.as_ref().ok_or(Errno::ENXIO)?;
+            if label.uuid != first.uuid
+                || label.pool != pool
+                || label.count as usize != disks.len()
+            {
+                error!(
+                    "disk {} is member {} of {} of pool {}, not of {}",
+                    disk, label.index, label.count, label.pool, pool
+                );
+                return Err(Errno::ENXIO);
+            }
+            match ordered.get_mut(label.index as usize) {
+                Some(slot @ None) => *slot = Some(disk.clone()),
+                _ => return Err(Errno::ENXIO),
+            }
+        }
+        Ok(Some(ordered.into_iter().flatten().collect()))
+    }
+
+    /// returns true if any of the bdevs `disks` holds the data of a pool,
+    /// ie a member label or a blobstore, which must not be overwritten by
+    /// a new pool
+    pub(crate) async fn hold_pool_data(
+        disks: &[String],
+        labels: &[Option<MemberLabel>],
+    ) -> Result<bool, Errno> {
+        if labels.iter().any(Option::is_some) {
+            return Ok(true);
+        }
+
+        for hdl in Self::open_disks(disks, false)? {
+            let mut buf = hdl
+                .dma_malloc(u64::from(hdl.get_bdev().block_len()))
+                .map_err(|_| Errno::ENOMEM)?;
+            hdl.read_at(0, &mut buf).await.map_err(|_| Errno::EIO)?;
+            if buf.as_slice().starts_with(BLOBSTORE_SIGNATURE) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// removes the raid bdev, releasing its disks
+    pub(crate) async fn destroy(self) -> Result<(), Errno> {
+        let (s, r) = pair::<i32>();
+        unsafe {
+            raid_bdev_remove_base_devices(
+                self.0.as_ptr(),
+                Some(Self::raid_op_cb),
+                cb_arg(s),
+            )
+        };
+
+        let rc = r.await.expect("callback gone while destroying raid bdev");
+        if rc != 0 {
+            return Err(Errno::from_i32(rc.abs()));
+        }
+
+        unsafe { raid_bdev_config_cleanup(self.0.as_ptr()) };
+        Ok(())
+    }
+
+    /// returns true if all disks are striped and the raid bdev is usable
+    fn online(&self) -> bool {
+        let raid = unsafe { self.0.as_ref().raid_bdev };
+        !raid.is_null() && unsafe { (*raid).state } == RAID_BDEV_STATE_ONLINE
+    }
+
+    /// returns the names of the bdevs of the striped disks, in order
+    pub(crate) fn members(&self) -> Vec<String> {
+        let config = unsafe { self.0.as_ref() };
+        (0 .. config.num_base_bdevs as usize)
+            .map(|slot| unsafe {
+                CStr::from_ptr((*config.base_bdev.add(slot)).name)
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect()
+    }
+
+    /// returns the striped disks and their state, in order
+    pub(crate) fn disks(&self) -> Vec<PoolDisk> {
+        let raid = unsafe { self.0.as_ref().raid_bdev };
+        self.members()
+            .iter()
+            .enumerate()
+            .map(|(slot, name)| {
+                let state = if raid.is_null() {
+                    DiskState::Missing
+                } else {
+                    let info = unsafe { &*(*raid).base_bdev_info.add(slot) };
+                    if info.bdev.is_null() {
+                        DiskState::Missing
+                    } else if info.remove_scheduled {
+                        DiskState::Removing
+                    } else {
+                        DiskState::Online
+                    }
+                };
+                PoolDisk::of_bdev(name, state)
+            })
+            .collect()
+    }
+}
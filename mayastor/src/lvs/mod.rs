@@ -1,7 +1,16 @@
 pub use error::Error;
-pub use lvol::{Lvol, PropName, PropValue};
-pub use lvs_pool::Lvs;
+pub use lvol::{Lvol, LvolProperties, PropName, PropValue, RevertState};
+pub use lvol_wipe::{WipeMethod, WipeProgress};
+pub use lvs_check::{Issue, IssueKind, LvsCheck};
+pub use lvs_monitor::PoolHealth;
+pub use lvs_pool::{ClearMethod, Lvs, LvsState};
+pub use lvs_raid::{DiskState, PoolDisk};
 
 mod error;
 mod lvol;
+mod lvol_migrate;
+mod lvol_wipe;
+mod lvs_check;
+mod lvs_monitor;
 mod lvs_pool;
+mod lvs_raid;
@@ -128,6 +128,14 @@ impl From<Pool> for rpc::Pool {
             state: rpc::PoolState::PoolOnline as i32,
             capacity: pool.get_capacity(),
             used: pool.get_capacity() - pool.get_free(),
+            disk_states: Vec::new(),
+            committed: 0,
+            reserved: 0,
+            overcommit_limit: 0,
+            cluster_size: 0,
+            metadata_ratio: 0,
+            clear_method: 0,
+            wipe_method: 0,
         }
     }
 }
@@ -1,8 +1,16 @@
 /// Rebuild api module
 mod rebuild_api;
+/// Persistent rebuild progress module
+mod rebuild_checkpoint;
 /// Rebuild implementation module
 pub mod rebuild_impl;
+/// Rebuild rate limiting module
+mod rebuild_throttle;
+/// Rebuild progress streaming module
+mod rebuild_watch;
 
 pub use rebuild_api::*;
+pub use rebuild_checkpoint::RebuildCheckpoint;
+pub use rebuild_watch::RebuildUpdate;
 // for the tests only
 pub use rebuild_impl::SEGMENT_SIZE;
@@ -1,7 +1,8 @@
 #![warn(missing_docs)]
 
-use std::fmt;
+use std::{fmt, time::Instant};
 
+use chrono::{DateTime, Utc};
 use crossbeam::channel::{Receiver, Sender};
 use futures::channel::oneshot;
 use snafu::Snafu;
@@ -12,7 +13,12 @@ use crate::{
     nexus_uri::NexusBdevError,
 };
 
-use super::rebuild_impl::*;
+use super::{
+    rebuild_checkpoint::*,
+    rebuild_impl::*,
+    rebuild_throttle::*,
+    rebuild_watch::*,
+};
 
 #[derive(Debug, Snafu, Clone)]
 #[snafu(visibility = "pub(crate)")]
@@ -72,6 +78,22 @@ pub enum RebuildError {
     },
     #[snafu(display("Failed to get bdev name from URI {}", uri))]
     BdevInvalidURI { source: NexusBdevError, uri: String },
+    #[snafu(display("No healthy source left to rebuild {} from", job))]
+    NoHealthySource { job: String },
+    #[snafu(display(
+        "Failed to serialize the rebuild checkpoint: {}",
+        reason
+    ))]
+    CheckpointSerialize { reason: String },
+    #[snafu(display(
+        "Verification of {} found {} segments which differ from the source",
+        destination,
+        mismatches
+    ))]
+    VerifyMismatch {
+        destination: String,
+        mismatches: u64,
+    },
 }
 
 #[derive(Debug, PartialEq, Copy, Clone)]
@@ -106,6 +128,72 @@ impl fmt::Display for RebuildState {
     }
 }
 
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+/// priority classes of a rebuild job
+pub enum RebuildPriority {
+    /// Low runs with fewer concurrent copy tasks, leaving more room for
+    /// frontend IO
+    Low,
+    /// Normal is the default priority
+    Normal,
+    /// Urgent is exempt from the node wide limits, eg when only a single
+    /// healthy child of the nexus remains
+    Urgent,
+}
+
+impl Default for RebuildPriority {
+    fn default() -> Self {
+        RebuildPriority::Normal
+    }
+}
+
+impl fmt::Display for RebuildPriority {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RebuildPriority::Low => write!(f, "low"),
+            RebuildPriority::Normal => write!(f, "normal"),
+            RebuildPriority::Urgent => write!(f, "urgent"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+/// how a rebuild job copies the segments
+pub enum RebuildMode {
+    /// Full copies every segment from the source
+    Full,
+    /// Checksum compares the checksums of each segment of the source and
+    /// the destinations, which are computed where the data resides, and
+    /// only copies the segments which differ. This is faster for a child
+    /// which has missed few writes, eg after it was briefly offline.
+    Checksum,
+}
+
+impl Default for RebuildMode {
+    fn default() -> Self {
+        RebuildMode::Full
+    }
+}
+
+impl fmt::Display for RebuildMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RebuildMode::Full => write!(f, "full"),
+            RebuildMode::Checksum => write!(f, "checksum"),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Copy, Clone)]
+/// limits on the rate at which data is copied by a rebuild job or by all
+/// rebuild jobs of the node, zero means unlimited
+pub struct RebuildLimits {
+    /// bandwidth in bytes per second
+    pub bandwidth: u64,
+    /// number of segments copied per second
+    pub segments: u64,
+}
+
 /// A rebuild job is responsible for managing a rebuild (copy) which reads
 /// from source_hdl and writes into destination_hdl from specified start to end
 #[derive(Debug)]
@@ -114,8 +202,12 @@ pub struct RebuildJob {
     pub nexus: String,
     /// descriptor for the nexus
     pub(super) nexus_descriptor: Descriptor,
-    /// source URI of the healthy child to rebuild from
+    /// source URI of the healthy child currently rebuilt from
     pub source: String,
+    /// healthy children which may be rebuilt from
+    pub(super) sources: RebuildSources,
+    /// all children rebuilt by the job, the destination first
+    pub(super) destinations: Vec<RebuildDestination>,
     /// target URI of the out of sync child in need of a rebuild
     pub destination: String,
     pub(super) block_size: u64,
@@ -123,9 +215,32 @@ pub struct RebuildJob {
     pub(super) next: u64,
     pub(super) segment_size_blks: u64,
     pub(super) task_pool: RebuildTasks,
+    /// how the segments are copied
+    pub(super) mode: RebuildMode,
+    /// verify the destinations once all segments have been copied
+    pub(super) verify: bool,
+    /// the segments are being verified rather than copied
+    pub(super) verifying: bool,
+    /// priority class of the job
+    pub(super) priority: RebuildPriority,
+    /// rate limits of the job
+    pub(super) throttle: RebuildThrottle,
+    /// block of the destination where the progress is checkpointed, if any
+    pub(super) checkpoint_lba: Option<u64>,
+    /// write generation of the nexus recorded in the checkpoints
+    pub(super) generation: u64,
+    /// time of the last checkpoint
+    pub(super) last_checkpoint: Instant,
+    /// time the job was started
+    pub(super) start_time: Option<DateTime<Utc>>,
+    /// time the job was started and the number of segments done at the
+    /// time, eg when resumed from a checkpoint
+    pub(super) started: Option<(Instant, u64)>,
     pub(super) notify_fn: fn(String, String) -> (),
     /// channel used to signal rebuild update
     pub notify_chan: (Sender<RebuildState>, Receiver<RebuildState>),
+    /// clients watching the rebuild
+    pub(super) watchers: Vec<RebuildWatcher>,
     /// current state of the rebuild job
     pub(super) states: RebuildStates,
     /// channel list which allows the await of the rebuild
@@ -135,6 +250,7 @@ pub struct RebuildJob {
 }
 
 /// rebuild statistics
+#[derive(Debug, Clone)]
 pub struct RebuildStats {
     /// total number of blocks to recover
     pub blocks_total: u64,
@@ -150,6 +266,47 @@ pub struct RebuildStats {
     pub tasks_total: u64,
     /// number of current active tasks
     pub tasks_active: u64,
+    /// priority class of the job
+    pub priority: RebuildPriority,
+    /// rate limits of the job
+    pub limits: RebuildLimits,
+    /// source URI of the healthy child currently rebuilt from
+    pub source: String,
+    /// number of times the job switched to another source after a read error
+    pub source_switches: u64,
+    /// URIs of all children rebuilt by the job
+    pub destinations: Vec<String>,
+    /// error which ended the rebuild of this child, either on its own while
+    /// the job carried on with its other destinations, or with the job
+    pub error: Option<String>,
+    /// how the segments are copied
+    pub mode: RebuildMode,
+    /// number of segments which were not copied as their checksums matched
+    pub segments_skipped: u64,
+    /// the destinations are verified once all segments have been copied
+    pub verify: bool,
+    /// number of segments verified so far
+    pub segments_verified: u64,
+    /// number of verified segments of this child which differ from the
+    /// source
+    pub verify_mismatches: u64,
+    /// time the job was started, if it has been
+    pub start_time: Option<DateTime<Utc>>,
+    /// average number of bytes rebuilt per second since the job was started
+    pub throughput: u64,
+}
+
+/// Rebuild of a child as listed by `RebuildJob::list`
+#[derive(Debug, Clone)]
+pub struct RebuildInfo {
+    /// name of the nexus the child belongs to
+    pub nexus: String,
+    /// URI of the child
+    pub destination: String,
+    /// state of the rebuild of the child
+    pub state: RebuildState,
+    /// statistics of the rebuild of the child
+    pub stats: RebuildStats,
 }
 
 /// Public facing operations on a Rebuild Job
@@ -169,6 +326,11 @@ pub trait ClientOperations {
     /// this could be used to mitigate excess load on the source bdev, eg
     /// too much contention with frontend IO
     fn resume(&mut self) -> Result<(), RebuildError>;
+    /// Sets the rate limits of the job, which also apply when it is
+    /// already running
+    fn set_limits(&mut self, limits: RebuildLimits);
+    /// Sets the priority class of the job
+    fn set_priority(&mut self, priority: RebuildPriority);
 
     /// Forcefully terminates the job, overriding any pending client operation
     /// returns an async channel which can be used to await for termination
@@ -176,31 +338,58 @@ pub trait ClientOperations {
 }
 
 impl RebuildJob {
-    /// Creates a new RebuildJob which rebuilds from source URI to target URI
-    /// from start to end (of the data partition); notify_fn callback is called
-    /// when the rebuild state is updated - with the nexus and destination
-    /// URI as arguments
+    /// Creates a new RebuildJob which rebuilds from the source URIs to target
+    /// URI from start to end (of the data partition); notify_fn callback is
+    /// called when the rebuild state is updated - with the nexus and
+    /// destination URI as arguments.
+    /// The first source is read from, switching over to the next source
+    /// should a read fail.
     pub fn create<'a>(
         nexus: &str,
-        source: &str,
+        sources: &[String],
         destination: &'a str,
         range: std::ops::Range<u64>,
         notify_fn: fn(String, String) -> (),
     ) -> Result<&'a mut Self, RebuildError> {
-        Self::new(nexus, source, destination, range, notify_fn)?.store()?;
+        Self::new(nexus, sources, destination, range, notify_fn)?.store()?;
 
         Ok(Self::lookup(destination)?)
     }
 
-    /// Lookup a rebuild job by its destination uri and return it
+    /// Lookup a rebuild job by any of its destination uris and return it
     pub fn lookup(name: &str) -> Result<&mut Self, RebuildError> {
-        if let Some(job) = Self::get_instances().get_mut(name) {
-            Ok(job)
-        } else {
-            Err(RebuildError::JobNotFound {
+        let instances = Self::get_instances();
+        if instances.contains_key(name) {
+            return Ok(instances.get_mut(name).unwrap());
+        }
+
+        instances
+            .values_mut()
+            .find(|j| j.destinations.iter().any(|d| d.uri == name))
+            .map(|j| j.as_mut())
+            .ok_or_else(|| RebuildError::JobNotFound {
                 job: name.to_owned(),
             })
-        }
+    }
+
+    /// Lists the rebuilds of all children rebuilt by the jobs of this node,
+    /// a job rebuilding several children is listed once for each of them
+    pub fn list() -> Vec<RebuildInfo> {
+        let mut list = Self::get_instances()
+            .values()
+            .flat_map(|job| {
+                job.destinations.iter().map(move |d| RebuildInfo {
+                    nexus: job.nexus.clone(),
+                    destination: d.uri.clone(),
+                    state: job.destination_state(&d.uri),
+                    stats: job.stats_of(d),
+                })
+            })
+            .collect::<Vec<_>>();
+        list.sort_by(|a, b| {
+            (&a.nexus, &a.destination).cmp(&(&b.nexus, &b.destination))
+        });
+        list
     }
 
     /// Lookup all rebuilds jobs with name as its source
@@ -222,6 +411,247 @@ impl RebuildJob {
         }
     }
 
+    /// Rate limits shared by all rebuild jobs of the node
+    pub fn node_limits() -> RebuildLimits {
+        node_throttle().limits()
+    }
+
+    /// Sets the rate limits shared by all rebuild jobs of the node, which
+    /// also apply to the jobs already running
+    pub fn set_node_limits(limits: RebuildLimits) {
+        info!("Setting the node rebuild limits to {:?}", limits);
+        node_throttle().set_limits(limits);
+    }
+
+    /// Enables checkpoints of the progress of the job, which are written to
+    /// block `lba` of each destination along with the write `generation` of
+    /// the nexus. If `resume` is set and all destinations hold a checkpoint
+    /// of an earlier job rebuilding the same range, taken during this or the
+    /// previous generation, the job continues from where that job left off;
+    /// any other checkpoint is cleared. Returns the block from which the job
+    /// resumes, if any.
+    ///
+    /// Resuming is only safe if the destinations have not missed any write
+    /// since the checkpoints were written, ie the caller must ensure that the
+    /// sources still hold `generation` and that no writes have been issued
+    /// since the nexus was opened.
+    pub async fn use_checkpoints(
+        &mut self,
+        lba: u64,
+        generation: u64,
+        resume: bool,
+    ) -> Option<u64> {
+        self.checkpoint_lba = Some(lba);
+        self.generation = generation;
+
+        let mut handles = Vec::new();
+        let mut next = Some(self.range.end);
+        for uri in self.destinations() {
+            let hdl = match Self::open_handle(&uri, true, false) {
+                Ok(hdl) => hdl,
+                Err(e) => {
+                    warn!(
+                        "Rebuild job {}: failed to open destination {} for checkpoints: {}",
+                        self.destination,
+                        uri,
+                        e.verbose()
+                    );
+                    next = None;
+                    continue;
+                }
+            };
+
+            match RebuildCheckpoint::read(&hdl, lba).await {
+                Ok(Some(checkpoint)) => {
+                    next = match next {
+                        Some(next)
+                            if resume && checkpoint.matches(self, &uri) =>
+                        {
+                            Some(std::cmp::min(next, checkpoint.next))
+                        }
+                        _ => None,
+                    };
+                    handles.push(hdl);
+                }
+                Ok(None) => next = None,
+                Err(e) => {
+                    warn!(
+                        "Rebuild job {}: failed to read the checkpoint of {}: {}",
+                        self.destination,
+                        uri,
+                        e.verbose()
+                    );
+                    next = None;
+                    handles.push(hdl);
+                }
+            }
+        }
+
+        if let Some(next) = next {
+            self.resume_from(next);
+            info!(
+                "Rebuild job {}: resuming from checkpoint at block {}",
+                self.destination, next
+            );
+            return Some(next);
+        }
+
+        for hdl in handles {
+            if let Err(e) = RebuildCheckpoint::clear(&hdl, lba).await {
+                warn!(
+                    "Rebuild job {}: failed to clear stale checkpoint: {}",
+                    self.destination,
+                    e.verbose()
+                );
+            }
+        }
+        None
+    }
+
+    /// Adds another child to be rebuilt by the job, which shares the reads
+    /// from the source with the existing destinations. Only allowed before
+    /// the job has been started.
+    pub fn add_destination(
+        &mut self,
+        destination: &str,
+    ) -> Result<(), RebuildError> {
+        if self.state() != RebuildState::Init {
+            return Err(RebuildError::OpError {
+                operation: "AddDestination".to_string(),
+                state: self.state().to_string(),
+            });
+        }
+        if Self::lookup(destination).is_ok() {
+            return Err(RebuildError::JobAlreadyExists {
+                job: destination.to_string(),
+            });
+        }
+
+        let source_hdl = Self::open_handle(&self.source, false, false)?;
+        let destination_hdl = Self::open_handle(destination, true, false)?;
+        if !Self::validate(
+            &source_hdl.get_bdev(),
+            &destination_hdl.get_bdev(),
+            &self.range,
+            self.block_size,
+        ) {
+            return Err(RebuildError::InvalidParameters {});
+        }
+
+        self.destinations.push(RebuildDestination::new(destination));
+        Ok(())
+    }
+
+    /// Stops rebuilding one of the additional destinations of the job, eg
+    /// because the child is being removed. The destination the job was
+    /// created for cannot be removed, as the job would have to be stopped
+    /// instead. Returns false if the child is not an additional destination.
+    pub fn remove_destination(&mut self, uri: &str) -> bool {
+        if uri == self.destination {
+            return false;
+        }
+
+        match self.destinations.iter().position(|d| d.uri == uri) {
+            Some(index) => {
+                info!(
+                    "Rebuild job {}: removing destination {}",
+                    self.destination, uri
+                );
+                self.destinations.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// URIs of all children rebuilt by the job
+    pub fn destinations(&self) -> Vec<String> {
+        self.destinations.iter().map(|d| d.uri.clone()).collect()
+    }
+
+    /// The error which ended the rebuild of the given destination while the
+    /// job carried on with its other destinations, if any
+    pub fn destination_error(&self, uri: &str) -> Option<&RebuildError> {
+        self.destinations
+            .iter()
+            .find(|d| d.uri == uri)
+            .and_then(|d| d.error.as_ref())
+    }
+
+    /// State of the rebuild of one of the destinations, which failed if the
+    /// job carried on without it
+    pub fn destination_state(&self, uri: &str) -> RebuildState {
+        match self.destination_error(uri) {
+            Some(_) => RebuildState::Failed,
+            None => self.state(),
+        }
+    }
+
+    /// Collects statistics of the rebuild of one of the destinations,
+    /// falling back to those of the job for an unknown destination
+    pub fn destination_stats(&self, uri: &str) -> RebuildStats {
+        match self.destinations.iter().find(|d| d.uri == uri) {
+            Some(destination) => self.stats_of(destination),
+            None => self.stats(),
+        }
+    }
+
+    /// Spread the reads of the segments across all sources rather than
+    /// reading from one source at a time
+    pub fn set_spread_reads(&mut self, spread: bool) {
+        self.sources.set_spread(spread);
+    }
+
+    /// Stops reading from the given source, eg because the child is being
+    /// removed. Returns false if the job was not reading from it.
+    pub fn remove_source(&mut self, uri: &str) -> bool {
+        if !self.sources.remove(uri) {
+            return false;
+        }
+        if let Some(source) = self.sources.current() {
+            self.source = source.to_string();
+        }
+        true
+    }
+
+    /// Sets how the job copies the segments, only allowed before the job
+    /// has been started
+    pub fn set_mode(&mut self, mode: RebuildMode) -> Result<(), RebuildError> {
+        if self.state() != RebuildState::Init {
+            return Err(RebuildError::OpError {
+                operation: "SetMode".to_string(),
+                state: self.state().to_string(),
+            });
+        }
+        self.mode = mode;
+        Ok(())
+    }
+
+    /// How the job copies the segments
+    pub fn mode(&self) -> RebuildMode {
+        self.mode
+    }
+
+    /// Enables a verification pass once all segments have been copied,
+    /// which reads each segment back from the source and the destinations
+    /// and compares them. A destination which differs fails with
+    /// `VerifyMismatch`. Only allowed before the job has been started.
+    pub fn set_verify(&mut self, verify: bool) -> Result<(), RebuildError> {
+        if self.state() != RebuildState::Init {
+            return Err(RebuildError::OpError {
+                operation: "SetVerify".to_string(),
+                state: self.state().to_string(),
+            });
+        }
+        self.verify = verify;
+        Ok(())
+    }
+
+    /// Priority class of the rebuild job
+    pub fn priority(&self) -> RebuildPriority {
+        self.priority
+    }
+
     /// Number of rebuild job instances
     pub fn count() -> usize {
         Self::get_instances().len()
@@ -0,0 +1,160 @@
+#![warn(missing_docs)]
+
+use std::io::Cursor;
+
+use bincode::{deserialize_from, serialize_into};
+use crc::crc32;
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+
+use crate::core::BdevHandle;
+
+use super::rebuild_api::*;
+
+/// Progress of a rebuild job persisted on each destination, which allows a
+/// rebuild to be resumed after a restart rather than starting from scratch.
+///
+/// The checkpoint occupies a single block of the destination, the first block
+/// of the "MayaMeta" partition which is not used otherwise. A checkpoint is
+/// only written while the destination receives all frontend writes, so any
+/// block below `next` is in sync as long as no write has been missed since.
+/// Writes which might have been missed are detected by the write generation
+/// of the nexus, which is bumped on the sources each time it is opened.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub struct RebuildCheckpoint {
+    /// signature identifying this as a rebuild checkpoint
+    signature: [u8; 8],
+    /// CRC-32 checksum of the checkpoint with the checksum set to 0
+    checksum: u32,
+    /// name of the nexus the rebuild belongs to
+    pub nexus: String,
+    /// write generation of the nexus when the checkpoint was written
+    pub generation: u64,
+    /// source URI of the rebuild
+    pub source: String,
+    /// destination URI of the rebuild
+    pub destination: String,
+    /// size in bytes of each block
+    pub block_size: u64,
+    /// first block of the rebuild range
+    pub start: u64,
+    /// end of the rebuild range
+    pub end: u64,
+    /// all blocks of the range before this one have been rebuilt
+    pub next: u64,
+}
+
+impl RebuildCheckpoint {
+    const SIGNATURE: [u8; 8] = *b"MayaRbld";
+
+    /// new checkpoint for the given destination of the job, which has
+    /// rebuilt all blocks before `next`
+    pub fn new(job: &RebuildJob, destination: &str, next: u64) -> Self {
+        Self {
+            signature: Self::SIGNATURE,
+            checksum: 0,
+            nexus: job.nexus.clone(),
+            generation: job.generation,
+            source: job.source.clone(),
+            destination: destination.to_string(),
+            block_size: job.block_size,
+            start: job.range.start,
+            end: job.range.end,
+            next,
+        }
+    }
+
+    /// true if the checkpoint was written by a job which rebuilt the same
+    /// range of the same destination for the same nexus, either since it
+    /// was opened or before it was last opened. Any other nexus opening the
+    /// sources in between would have bumped their generation once more.
+    pub fn matches(&self, job: &RebuildJob, destination: &str) -> bool {
+        self.nexus == job.nexus
+            && (self.generation == job.generation
+                || self.generation + 1 == job.generation)
+            && self.destination == destination
+            && self.block_size == job.block_size
+            && self.start == job.range.start
+            && self.end == job.range.end
+            && (self.start ..= self.end).contains(&self.next)
+    }
+
+    fn checksum(&self) -> u32 {
+        let mut copy = self.clone();
+        copy.checksum = 0;
+        crc32::checksum_ieee(&bincode::serialize(&copy).unwrap_or_default())
+    }
+
+    /// Read the checkpoint stored in block `lba` of the destination.
+    /// Returns None if the block does not hold a valid checkpoint.
+    pub async fn read(
+        hdl: &BdevHandle,
+        lba: u64,
+    ) -> Result<Option<Self>, RebuildError> {
+        let bdev = hdl.get_bdev();
+        let block_size = bdev.block_len() as u64;
+
+        let mut buf = hdl.dma_malloc(block_size).context(NoCopyBuffer {})?;
+        hdl.read_at(lba * block_size, &mut buf)
+            .await
+            .context(ReadIoError {
+                bdev: bdev.name(),
+            })?;
+
+        let checkpoint: Self =
+            match deserialize_from(&mut Cursor::new(buf.as_slice())) {
+                Ok(checkpoint) => checkpoint,
+                Err(_) => return Ok(None),
+            };
+
+        if checkpoint.signature != Self::SIGNATURE
+            || checkpoint.checksum != checkpoint.checksum()
+        {
+            return Ok(None);
+        }
+
+        Ok(Some(checkpoint))
+    }
+
+    /// Write the checkpoint to block `lba` of the destination
+    pub async fn write(
+        &self,
+        hdl: &BdevHandle,
+        lba: u64,
+    ) -> Result<(), RebuildError> {
+        let bdev = hdl.get_bdev();
+        let block_size = bdev.block_len() as u64;
+
+        let mut checkpoint = self.clone();
+        checkpoint.checksum = checkpoint.checksum();
+
+        let mut buf = hdl.dma_malloc(block_size).context(NoCopyBuffer {})?;
+        buf.fill(0);
+        serialize_into(&mut Cursor::new(buf.as_mut_slice()), &checkpoint)
+            .map_err(|e| RebuildError::CheckpointSerialize {
+                reason: e.to_string(),
+            })?;
+
+        hdl.write_at(lba * block_size, &buf)
+            .await
+            .context(WriteIoError {
+                bdev: bdev.name(),
+            })?;
+        Ok(())
+    }
+
+    /// Invalidate any checkpoint stored in block `lba` of the destination
+    pub async fn clear(hdl: &BdevHandle, lba: u64) -> Result<(), RebuildError> {
+        let bdev = hdl.get_bdev();
+        let block_size = bdev.block_len() as u64;
+
+        let mut buf = hdl.dma_malloc(block_size).context(NoCopyBuffer {})?;
+        buf.fill(0);
+        hdl.write_at(lba * block_size, &buf)
+            .await
+            .context(WriteIoError {
+                bdev: bdev.name(),
+            })?;
+        Ok(())
+    }
+}
@@ -1,11 +1,17 @@
 #![warn(missing_docs)]
 #![allow(clippy::unknown_clippy_lints)]
 
-use std::{cell::UnsafeCell, collections::HashMap};
+use std::{
+    cell::UnsafeCell,
+    collections::HashMap,
+    time::{Duration, Instant},
+};
 
+use chrono::Utc;
 use crossbeam::channel::unbounded;
 use futures::{
     channel::{mpsc, oneshot},
+    future::join_all,
     StreamExt,
 };
 use once_cell::sync::OnceCell;
@@ -15,11 +21,19 @@ use spdk_sys::{spdk_get_thread, SPDK_BDEV_LARGE_BUF_MAX_SIZE};
 
 use crate::{
     bdev::VerboseError,
-    core::{Bdev, BdevHandle, DmaBuf, RangeContext, Reactors},
+    core::{
+        poller::sleep,
+        Bdev,
+        BdevHandle,
+        CoreError,
+        DmaBuf,
+        RangeContext,
+        Reactors,
+    },
     nexus_uri::bdev_get_name,
 };
 
-use super::rebuild_api::*;
+use super::{rebuild_api::*, rebuild_checkpoint::*, rebuild_throttle::*};
 
 /// Global list of rebuild jobs using a static OnceCell
 pub(super) struct RebuildInstances {
@@ -46,6 +60,11 @@ struct TaskResult {
 const SEGMENT_TASKS: usize = 16;
 /// Size of each segment used by the copy task
 pub const SEGMENT_SIZE: u64 = SPDK_BDEV_LARGE_BUF_MAX_SIZE as u64;
+/// Interval at which the progress of a running job is checkpointed
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(10);
+/// Longest a throttled task sleeps before it checks the limits and the state
+/// of the job again
+const THROTTLE_SLICE: Duration = Duration::from_millis(100);
 
 /// Each rebuild task needs a unique buffer to read/write from source to target
 /// A mpsc channel is used to communicate with the management task
@@ -54,6 +73,86 @@ struct RebuildTask {
     buffer: DmaBuf,
     sender: mpsc::Sender<TaskResult>,
     error: Option<TaskResult>,
+    /// the task is copying a segment
+    busy: bool,
+    /// block the task is copying, if busy
+    blk: u64,
+}
+
+/// Healthy children a rebuild job may read from
+#[derive(Debug)]
+pub(super) struct RebuildSources {
+    /// URIs of the sources, the current source first
+    uris: Vec<String>,
+    /// spread the reads of the segments across all sources
+    spread: bool,
+    /// number of sources dropped because of a read error
+    switches: u64,
+}
+
+impl RebuildSources {
+    fn new(uris: Vec<String>) -> Self {
+        Self {
+            uris,
+            spread: false,
+            switches: 0,
+        }
+    }
+
+    /// the source preferred for reads
+    pub(super) fn current(&self) -> Option<&str> {
+        self.uris.first().map(String::as_str)
+    }
+
+    /// the source to read the segment with the given index from
+    fn pick(&self, segment: u64) -> Option<&str> {
+        if self.spread && !self.uris.is_empty() {
+            let index = segment % self.uris.len() as u64;
+            Some(&self.uris[index as usize])
+        } else {
+            self.current()
+        }
+    }
+
+    pub(super) fn set_spread(&mut self, spread: bool) {
+        self.spread = spread;
+    }
+
+    /// returns false if the uri was not a source (anymore)
+    pub(super) fn remove(&mut self, uri: &str) -> bool {
+        match self.uris.iter().position(|s| s == uri) {
+            Some(index) => {
+                self.uris.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// A child rebuilt by a rebuild job
+#[derive(Debug)]
+pub(super) struct RebuildDestination {
+    /// URI of the child
+    pub(super) uri: String,
+    /// error which ended the rebuild of the child while the job carried on
+    /// with its other destinations
+    pub(super) error: Option<RebuildError>,
+    /// number of segments rebuilt before the error
+    pub(super) segments_done: u64,
+    /// number of verified segments which differ from the source
+    pub(super) mismatches: u64,
+}
+
+impl RebuildDestination {
+    pub(super) fn new(uri: &str) -> Self {
+        Self {
+            uri: uri.to_string(),
+            error: None,
+            segments_done: 0,
+            mismatches: 0,
+        }
+    }
 }
 
 /// Pool of rebuild tasks and progress tracking
@@ -68,6 +167,10 @@ pub(super) struct RebuildTasks {
     total: usize,
 
     segments_done: u64,
+    /// segments which did not need to be copied
+    segments_skipped: u64,
+    /// segments which have been verified
+    segments_verified: u64,
 }
 
 /// Checks whether a range is contained within another range
@@ -91,7 +194,9 @@ impl RebuildJob {
     pub(super) fn store(self) -> Result<(), RebuildError> {
         let rebuild_list = Self::get_instances();
 
-        if rebuild_list.contains_key(&self.destination) {
+        if rebuild_list.contains_key(&self.destination)
+            || Self::lookup(&self.destination).is_ok()
+        {
             Err(RebuildError::JobAlreadyExists {
                 job: self.destination,
             })
@@ -106,25 +211,31 @@ impl RebuildJob {
     #[allow(clippy::same_item_push)]
     pub(super) fn new(
         nexus: &str,
-        source: &str,
+        sources: &[String],
         destination: &str,
         range: std::ops::Range<u64>,
         notify_fn: fn(String, String) -> (),
     ) -> Result<Self, RebuildError> {
-        let source_hdl = RebuildJob::open_handle(source, false, false)?;
-        let destination_hdl =
-            RebuildJob::open_handle(destination, true, false)?;
-
-        if !Self::validate(
-            &source_hdl.get_bdev(),
-            &destination_hdl.get_bdev(),
-            &range,
-        ) {
+        if sources.is_empty() {
             return Err(RebuildError::InvalidParameters {});
-        };
+        }
 
-        // validation passed, block size is the same for both
+        let destination_hdl =
+            RebuildJob::open_handle(destination, true, false)?;
+        // the range is in units of the block size of the destination
         let block_size = destination_hdl.get_bdev().block_len() as u64;
+
+        for source in sources {
+            let source_hdl = RebuildJob::open_handle(source, false, false)?;
+            if !Self::validate(
+                &source_hdl.get_bdev(),
+                &destination_hdl.get_bdev(),
+                &range,
+                block_size,
+            ) {
+                return Err(RebuildError::InvalidParameters {});
+            };
+        }
         let segment_size_blks = (SEGMENT_SIZE / block_size) as u64;
 
         let mut tasks = RebuildTasks {
@@ -135,6 +246,8 @@ impl RebuildJob {
             active: 0,
             total: SEGMENT_TASKS,
             segments_done: 0,
+            segments_skipped: 0,
+            segments_verified: 0,
         };
 
         for _ in 0 .. tasks.total {
@@ -145,11 +258,13 @@ impl RebuildJob {
                 buffer: copy_buffer,
                 sender: tasks.channel.0.clone(),
                 error: None,
+                busy: false,
+                blk: 0,
             });
         }
 
         let (source, destination, nexus) = (
-            source.to_string(),
+            sources[0].clone(),
             destination.to_string(),
             nexus.to_string(),
         );
@@ -163,14 +278,27 @@ impl RebuildJob {
             nexus,
             nexus_descriptor,
             source,
+            sources: RebuildSources::new(sources.to_vec()),
+            destinations: vec![RebuildDestination::new(&destination)],
             destination,
             next: range.start,
             range,
             block_size,
             segment_size_blks,
             task_pool: tasks,
+            mode: RebuildMode::default(),
+            verify: false,
+            verifying: false,
+            priority: RebuildPriority::default(),
+            throttle: RebuildThrottle::new(RebuildLimits::default()),
+            checkpoint_lba: None,
+            generation: 0,
+            last_checkpoint: Instant::now(),
+            start_time: None,
+            started: None,
             notify_fn,
             notify_chan: unbounded::<RebuildState>(),
+            watchers: Vec::new(),
             states: Default::default(),
             complete_chan: Vec::new(),
             error: None,
@@ -188,12 +316,23 @@ impl RebuildJob {
                     None => {
                         match self.states.pending {
                             None | Some(RebuildState::Running) => {
-                                self.start_task_by_id(r.id);
+                                self.start_idle_tasks();
+                                if self.last_checkpoint.elapsed()
+                                    >= CHECKPOINT_INTERVAL
+                                {
+                                    self.checkpoint().await;
+                                }
                             }
                             _ => {
                                 // await all active tasks as we might still have
                                 // ongoing IO. do we need a timeout?
                                 self.await_all_tasks().await;
+                                if self
+                                    .states
+                                    .pending_equals(RebuildState::Paused)
+                                {
+                                    self.checkpoint().await;
+                                }
                                 break;
                             }
                         }
@@ -217,6 +356,64 @@ impl RebuildJob {
         self.reconcile();
     }
 
+    /// All blocks of the range before the returned block have been rebuilt
+    fn low_water_mark(&self) -> u64 {
+        if self.verifying {
+            return self.range.end;
+        }
+        self.task_pool
+            .tasks
+            .iter()
+            .filter(|t| t.busy)
+            .map(|t| t.blk)
+            .min()
+            .unwrap_or(self.next)
+    }
+
+    /// Persists the progress of the job on the destinations, if checkpoints
+    /// are enabled. A failure to do so is not fatal as it only means that a
+    /// restarted rebuild has more to copy.
+    async fn checkpoint(&mut self) {
+        let lba = match self.checkpoint_lba {
+            Some(lba) => lba,
+            None => return,
+        };
+        self.last_checkpoint = Instant::now();
+
+        let next = self.low_water_mark();
+        for uri in self.active_destinations() {
+            let checkpoint = RebuildCheckpoint::new(self, &uri, next);
+            let result = match Self::open_handle(&uri, true, false) {
+                Ok(hdl) => checkpoint.write(&hdl, lba).await,
+                Err(e) => Err(e),
+            };
+
+            match result {
+                Ok(_) => trace!(
+                    "Rebuild job {}: checkpoint of {} at block {}",
+                    self.destination,
+                    uri,
+                    next
+                ),
+                Err(e) => warn!(
+                    "Rebuild job {}: failed to write the checkpoint of {}: {}",
+                    self.destination,
+                    uri,
+                    e.verbose()
+                ),
+            }
+        }
+    }
+
+    /// Continue the rebuild from block `next` onwards, accounting for the
+    /// blocks before it as recovered
+    pub(super) fn resume_from(&mut self, next: u64) {
+        self.next = next;
+        self.task_pool.segments_done =
+            (next - self.range.start + self.segment_size_blks - 1)
+                / self.segment_size_blks;
+    }
+
     /// Return the size of the segment to be copied.
     fn get_segment_size_blks(&self, blk: u64) -> u64 {
         // Adjust the segments size for the last segment
@@ -226,9 +423,43 @@ impl RebuildJob {
         self.segment_size_blks
     }
 
-    /// Copies one segment worth of data from source into destination. During
-    /// this time the LBA range being copied is locked so that there cannot be
-    /// front end I/O to the same LBA range.
+    /// Waits until the limits of the job and, unless it is urgent, the limits
+    /// of the node allow for the copy of a segment of the given size. The
+    /// wait is done in slices, so that changes to the limits apply to the
+    /// waiting tasks and a job which is being stopped or paused stops waiting
+    /// and only has to finish the segments it is copying.
+    async fn throttle(&mut self, bytes: u64) {
+        loop {
+            let pending = self.states.pending;
+            if matches!(pending, Some(s) if s != RebuildState::Running) {
+                return;
+            }
+
+            let wait = {
+                let mut node = node_throttle();
+                let urgent = self.priority == RebuildPriority::Urgent;
+
+                let node_wait = if urgent { None } else { node.wait_time() };
+                match self.throttle.wait_time().max(node_wait) {
+                    None => {
+                        self.throttle.take(bytes);
+                        if !urgent {
+                            node.take(bytes);
+                        }
+                        return;
+                    }
+                    Some(wait) => wait,
+                }
+            };
+
+            sleep(wait.min(THROTTLE_SLICE)).await;
+        }
+    }
+
+    /// Copies one segment worth of data from source into destination, or
+    /// verifies it once all segments have been copied. During this time the
+    /// LBA range being copied is locked so that there cannot be front end I/O
+    /// to the same LBA range.
     ///
     /// # Safety
     ///
@@ -239,7 +470,7 @@ impl RebuildJob {
     ///
     /// The use of RangeContext here is safe because it is stored on the stack
     /// for the duration of the calls to lock and unlock.
-    async fn locked_copy_one(
+    async fn locked_process_one(
         &mut self,
         id: usize,
         blk: u64,
@@ -248,8 +479,17 @@ impl RebuildJob {
         // The nexus children have metadata and data partitions, whereas the
         // nexus has a data partition only. Because we are locking the range on
         // the nexus, we need to calculate the offset from the start of the data
-        // partition.
-        let mut ctx = RangeContext::new(blk - self.range.start, len);
+        // partition. The nexus block size may also differ from that of the
+        // children, so convert the range to nexus blocks covering the segment.
+        let nexus_blk_size =
+            self.nexus_descriptor.get_bdev().block_len() as u64;
+        let start = (blk - self.range.start) * self.block_size;
+        let end = start + len * self.block_size;
+        let mut ctx = RangeContext::new(
+            start / nexus_blk_size,
+            (end + nexus_blk_size - 1) / nexus_blk_size
+                - start / nexus_blk_size,
+        );
         let ch = self
             .nexus_descriptor
             .get_channel()
@@ -266,8 +506,12 @@ impl RebuildJob {
                 len,
             })?;
 
-        // Perform the copy
-        let result = self.copy_one(id, blk).await;
+        // Perform the copy or the verification
+        let result = if self.verifying {
+            self.verify_one(blk).await
+        } else {
+            self.copy_one(id, blk).await
+        };
 
         // Wait for the LBA range to be unlocked.
         // This allows others I/Os to be issued to this LBA range once again.
@@ -282,21 +526,70 @@ impl RebuildJob {
         result
     }
 
-    /// Copies one segment worth of data from source into destination.
+    /// Destinations whose segment differs from that of the source, based on
+    /// checksums which are computed where the data resides. A destination
+    /// which fails to compute its checksum is considered to differ, so that
+    /// the write reports the error. Fails if the source fails.
+    async fn differing_destinations(
+        &self,
+        source_hdl: &BdevHandle,
+        destinations: &[String],
+        blk: u64,
+    ) -> Result<Vec<String>, RebuildError> {
+        let len = self.get_segment_size_blks(blk);
+        let source = self
+            .segment_checksum(source_hdl, blk, len)
+            .await
+            .context(ReadIoError {
+                bdev: source_hdl.get_bdev().name(),
+            })?;
+
+        let checksums = join_all(destinations.iter().map(|uri| async move {
+            let hdl = RebuildJob::open_handle(uri, false, false)?;
+            self.segment_checksum(&hdl, blk, len)
+                .await
+                .context(ReadIoError {
+                    bdev: uri,
+                })
+        }))
+        .await;
+
+        Ok(destinations
+            .iter()
+            .zip(checksums)
+            .filter(|(_, checksum)| match checksum {
+                Ok(checksum) => *checksum != source,
+                Err(_) => true,
+            })
+            .map(|(uri, _)| uri.clone())
+            .collect())
+    }
+
+    /// Copies one segment worth of data from source into all destinations.
+    /// Should the read fail, the segment is read from another source.
+    /// In checksum mode only the destinations whose segment differs from
+    /// that of the source are written to.
     async fn copy_one(
         &mut self,
         id: usize,
         blk: u64,
     ) -> Result<(), RebuildError> {
-        let mut copy_buffer: DmaBuf;
-        let source_hdl = RebuildJob::open_handle(&self.source, false, false)?;
-        let destination_hdl =
-            RebuildJob::open_handle(&self.destination, true, false)?;
+        let mut destinations = self.active_destinations();
+        let destination_hdl = match destinations.first() {
+            Some(destination) => {
+                RebuildJob::open_handle(destination, true, false)?
+            }
+            None => {
+                return Err(RebuildError::MissingDestination {
+                    job: self.destination.clone(),
+                })
+            }
+        };
 
-        let copy_buffer = if self.get_segment_size_blks(blk)
+        let mut last_buffer = if self.get_segment_size_blks(blk)
             == self.segment_size_blks
         {
-            &mut self.task_pool.tasks[id].buffer
+            None
         } else {
             let segment_size_blks = self.range.end - blk;
 
@@ -305,30 +598,344 @@ impl RebuildJob {
                     self.segment_size_blks, segment_size_blks, blk, self.range,
                 );
 
-            copy_buffer = destination_hdl
-                .dma_malloc(segment_size_blks * self.block_size)
-                .context(NoCopyBuffer {})?;
+            Some(
+                destination_hdl
+                    .dma_malloc(segment_size_blks * self.block_size)
+                    .context(NoCopyBuffer {})?,
+            )
+        };
+
+        let segment = (blk - self.range.start) / self.segment_size_blks;
+        loop {
+            let source = match self.sources.pick(segment) {
+                Some(source) => source.to_string(),
+                None => {
+                    return Err(RebuildError::NoHealthySource {
+                        job: self.destination.clone(),
+                    })
+                }
+            };
+
+            let source_hdl =
+                match RebuildJob::open_handle(&source, false, false) {
+                    Ok(source_hdl) => source_hdl,
+                    Err(e) => {
+                        self.fail_source(&source, e)?;
+                        continue;
+                    }
+                };
+
+            let differing = match self.mode {
+                RebuildMode::Full => Ok(destinations.clone()),
+                RebuildMode::Checksum => {
+                    self.differing_destinations(&source_hdl, &destinations, blk)
+                        .await
+                }
+            };
+
+            let result = match differing {
+                Ok(differing) if differing.is_empty() => Ok(differing),
+                Ok(differing) => {
+                    let copy_buffer = match last_buffer.as_mut() {
+                        Some(buffer) => buffer,
+                        None => &mut self.task_pool.tasks[id].buffer,
+                    };
+                    source_hdl
+                        .read_at(blk * self.block_size, copy_buffer)
+                        .await
+                        .context(ReadIoError {
+                            bdev: &source,
+                        })
+                        .map(|_| differing)
+                }
+                Err(e) => Err(e),
+            };
+
+            match result {
+                Ok(differing) => {
+                    destinations = differing;
+                    break;
+                }
+                Err(e) => self.fail_source(&source, e)?,
+            }
+        }
+
+        if destinations.is_empty() {
+            self.task_pool.segments_skipped += 1;
+            return Ok(());
+        }
 
-            &mut copy_buffer
+        // the segment is read once and written to all destinations
+        let copy_buffer = match last_buffer.as_ref() {
+            Some(buffer) => buffer,
+            None => &self.task_pool.tasks[id].buffer,
         };
+        let offset = blk * self.block_size;
+        let results = join_all(destinations.iter().map(|uri| async move {
+            let hdl = RebuildJob::open_handle(uri, true, false)?;
+            hdl.write_at(offset, copy_buffer)
+                .await
+                .context(WriteIoError {
+                    bdev: uri,
+                })?;
+            Ok::<(), RebuildError>(())
+        }))
+        .await;
+
+        for (uri, result) in destinations.iter().zip(results) {
+            if let Err(e) = result {
+                self.fail_destination(uri, e)?;
+            }
+        }
 
-        source_hdl
-            .read_at(blk * self.block_size, copy_buffer)
-            .await
-            .context(ReadIoError {
-                bdev: &self.source,
-            })?;
+        Ok(())
+    }
 
-        destination_hdl
-            .write_at(blk * self.block_size, copy_buffer)
-            .await
-            .context(WriteIoError {
-                bdev: &self.destination,
+    /// Reads one segment from the source and from all destinations and
+    /// counts the destinations which differ from the source. Should the read
+    /// of the source fail, the segment is read from another source.
+    async fn verify_one(&mut self, blk: u64) -> Result<(), RebuildError> {
+        let destinations = self.active_destinations();
+        let len = self.get_segment_size_blks(blk) * self.block_size;
+        let offset = blk * self.block_size;
+
+        let segment = (blk - self.range.start) / self.segment_size_blks;
+        let expected = loop {
+            let source = match self.sources.pick(segment) {
+                Some(source) => source.to_string(),
+                None => {
+                    return Err(RebuildError::NoHealthySource {
+                        job: self.destination.clone(),
+                    })
+                }
+            };
+
+            let result = async {
+                let hdl = RebuildJob::open_handle(&source, false, false)?;
+                let mut buf = hdl.dma_malloc(len).context(NoCopyBuffer {})?;
+                hdl.read_at(offset, &mut buf).await.context(ReadIoError {
+                    bdev: &source,
+                })?;
+                Ok::<DmaBuf, RebuildError>(buf)
+            }
+            .await;
+
+            match result {
+                Ok(buf) => break buf,
+                Err(e) => self.fail_source(&source, e)?,
+            }
+        };
+
+        let expected = expected.as_slice();
+        let results = join_all(destinations.iter().map(|uri| async move {
+            let hdl = RebuildJob::open_handle(uri, false, false)?;
+            let mut buf = hdl.dma_malloc(len).context(NoCopyBuffer {})?;
+            hdl.read_at(offset, &mut buf).await.context(ReadIoError {
+                bdev: uri,
             })?;
+            Ok::<bool, RebuildError>(buf.as_slice() == expected)
+        }))
+        .await;
+
+        for (uri, result) in destinations.iter().zip(results) {
+            match result {
+                Ok(true) => {}
+                Ok(false) => {
+                    warn!(
+                        "Rebuild job {}: segment at block {} of {} differs from the source",
+                        self.destination, blk, uri
+                    );
+                    if let Some(destination) =
+                        self.destinations.iter_mut().find(|d| &d.uri == uri)
+                    {
+                        destination.mismatches += 1;
+                    }
+                }
+                Err(e) => self.fail_destination(uri, e)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fails the destinations with segments which differ from the source,
+    /// the job fails if none of the destinations passed the verification
+    fn verify_done(&mut self) -> Result<(), RebuildError> {
+        let failed = self
+            .destinations
+            .iter()
+            .filter(|d| d.error.is_none() && d.mismatches > 0)
+            .map(|d| (d.uri.clone(), d.mismatches))
+            .collect::<Vec<_>>();
+
+        for (uri, mismatches) in failed {
+            self.fail_destination(
+                &uri,
+                RebuildError::VerifyMismatch {
+                    destination: uri.clone(),
+                    mismatches,
+                },
+            )?;
+        }
+
+        info!(
+            "Rebuild job {}: verified {} segments",
+            self.destination, self.task_pool.segments_verified
+        );
+        Ok(())
+    }
+
+    /// Statistics of the job as seen by one of its destinations, which
+    /// stopped making progress if it failed
+    pub(super) fn stats_of(
+        &self,
+        destination: &RebuildDestination,
+    ) -> RebuildStats {
+        let blocks_total = self.range.end - self.range.start;
+        let segments_done = if destination.error.is_some() {
+            destination.segments_done
+        } else {
+            self.task_pool.segments_done
+        };
+
+        // segment size may not be aligned to the total size
+        let blocks_recovered =
+            std::cmp::min(segments_done * self.segment_size_blks, blocks_total);
+
+        let progress = (blocks_recovered * 100) / blocks_total;
+
+        let throughput = match self.started {
+            Some((started, segments)) => {
+                let elapsed = started.elapsed().as_micros();
+                let bytes = segments_done.saturating_sub(segments)
+                    * self.segment_size_blks
+                    * self.block_size;
+                if elapsed == 0 {
+                    0
+                } else {
+                    (bytes as u128 * 1_000_000 / elapsed) as u64
+                }
+            }
+            None => 0,
+        };
+
+        trace!(
+            "State: {}, Src: {}, Dst: {}, range: {:?}, next: {}, \
+             block_size: {}, segment_sz: {}, recovered_blks: {}, progress: {}%",
+            self.state(),
+            self.source,
+            destination.uri,
+            self.range,
+            self.next,
+            self.block_size,
+            self.segment_size_blks,
+            blocks_recovered,
+            progress,
+        );
+
+        RebuildStats {
+            blocks_total,
+            blocks_recovered,
+            progress,
+            segment_size_blks: self.segment_size_blks,
+            block_size: self.block_size,
+            tasks_total: self.task_pool.total as u64,
+            tasks_active: self.task_pool.active as u64,
+            priority: self.priority,
+            limits: self.throttle.limits(),
+            source: self.source.clone(),
+            source_switches: self.sources.switches,
+            destinations: self
+                .destinations
+                .iter()
+                .map(|d| d.uri.clone())
+                .collect(),
+            error: destination
+                .error
+                .as_ref()
+                .or_else(|| self.error.as_ref())
+                .map(|e| e.verbose()),
+            mode: self.mode,
+            segments_skipped: self.task_pool.segments_skipped,
+            verify: self.verify,
+            segments_verified: self.task_pool.segments_verified,
+            verify_mismatches: destination.mismatches,
+            start_time: self.start_time,
+            throughput,
+        }
+    }
+
+    /// URIs of the destinations which have not failed
+    fn active_destinations(&self) -> Vec<String> {
+        self.destinations
+            .iter()
+            .filter(|d| d.error.is_none())
+            .map(|d| d.uri.clone())
+            .collect()
+    }
+
+    /// Stops writing to a destination which failed a write, letting the
+    /// job carry on with the other destinations. Returns the error if no
+    /// other destination remains, which fails the job.
+    fn fail_destination(
+        &mut self,
+        uri: &str,
+        error: RebuildError,
+    ) -> Result<(), RebuildError> {
+        if self.active_destinations().len() <= 1 {
+            return Err(error);
+        }
 
+        let segments_done = self.task_pool.segments_done;
+        if let Some(destination) = self
+            .destinations
+            .iter_mut()
+            .find(|d| d.uri == uri && d.error.is_none())
+        {
+            error!(
+                "Rebuild job {}: destination {} failed, continuing with the other destinations: {}",
+                self.destination,
+                uri,
+                error.verbose()
+            );
+            destination.error = Some(error);
+            destination.segments_done = segments_done;
+            (self.notify_fn)(self.nexus.clone(), uri.to_string());
+            self.notify_watchers();
+        }
         Ok(())
     }
 
+    /// Stops reading from a source which failed a read and switches over to
+    /// another source, returning the error if no other source remains
+    fn fail_source(
+        &mut self,
+        source: &str,
+        error: RebuildError,
+    ) -> Result<(), RebuildError> {
+        // concurrent tasks may have dropped the same source already
+        if self.remove_source(source) {
+            self.sources.switches += 1;
+            warn!(
+                "Rebuild job {}: dropping source {} after error: {}",
+                self.destination,
+                source,
+                error.verbose()
+            );
+        }
+
+        match self.sources.current() {
+            Some(current) => {
+                info!(
+                    "Rebuild job {}: continuing with source {}",
+                    self.destination, current
+                );
+                Ok(())
+            }
+            None => Err(error),
+        }
+    }
+
     fn notify(&mut self) {
         self.stats();
         self.send_notify();
@@ -338,23 +945,50 @@ impl RebuildJob {
     fn send_notify(&mut self) {
         // should this return a status before we notify the sender channel?
         (self.notify_fn)(self.nexus.clone(), self.destination.clone());
+        self.notify_watchers();
         if let Err(e) = self.notify_chan.0.send(self.state()) {
             error!("Rebuild Job {} of nexus {} failed to send complete via the unbound channel with err {}", self.destination, self.nexus, e);
         }
     }
 
     /// Check if the source and destination block devices are compatible for
-    /// rebuild
-    fn validate(
+    /// rebuild. The range is in units of `block_size`, the block size of the
+    /// destinations. A source with another block size is accessed at the
+    /// same byte offsets, so the range must start and end on its blocks.
+    pub(super) fn validate(
         source: &Bdev,
         destination: &Bdev,
         range: &std::ops::Range<u64>,
+        block_size: u64,
     ) -> bool {
         // todo: make sure we don't overwrite the labels
         let data_partition_start = 0;
-        range.within(data_partition_start .. source.num_blocks())
-            && range.within(data_partition_start .. destination.num_blocks())
-            && source.block_len() == destination.block_len()
+        let bytes = range.start * block_size .. range.end * block_size;
+        let fits = |bdev: &Bdev| {
+            let block_len = bdev.block_len() as u64;
+            bytes.start % block_len == 0
+                && bytes.end % block_len == 0
+                && bytes.within(data_partition_start .. bdev.size_in_bytes())
+        };
+        destination.block_len() as u64 == block_size
+            && fits(source)
+            && fits(destination)
+    }
+
+    /// Checksum of the segment of `len` blocks at `blk`, which are in units
+    /// of the block size of the job, on a bdev whose block size may differ
+    async fn segment_checksum(
+        &self,
+        hdl: &BdevHandle,
+        blk: u64,
+        len: u64,
+    ) -> Result<u64, CoreError> {
+        let block_len = hdl.get_bdev().block_len() as u64;
+        hdl.checksum(
+            blk * self.block_size / block_len,
+            len * self.block_size / block_len,
+        )
+        .await
     }
 
     /// reconcile the pending state to the current and clear the pending
@@ -423,7 +1057,7 @@ impl RebuildJob {
     }
 
     /// Open a bdev handle for the given uri
-    fn open_handle(
+    pub(super) fn open_handle(
         uri: &str,
         read_write: bool,
         claim: bool,
@@ -470,39 +1104,7 @@ impl std::fmt::Display for RebuildOperation {
 
 impl ClientOperations for RebuildJob {
     fn stats(&self) -> RebuildStats {
-        let blocks_total = self.range.end - self.range.start;
-
-        // segment size may not be aligned to the total size
-        let blocks_recovered = std::cmp::min(
-            self.task_pool.segments_done * self.segment_size_blks,
-            blocks_total,
-        );
-
-        let progress = (blocks_recovered * 100) / blocks_total;
-
-        info!(
-            "State: {}, Src: {}, Dst: {}, range: {:?}, next: {}, \
-             block_size: {}, segment_sz: {}, recovered_blks: {}, progress: {}%",
-            self.state(),
-            self.source,
-            self.destination,
-            self.range,
-            self.next,
-            self.block_size,
-            self.segment_size_blks,
-            blocks_recovered,
-            progress,
-        );
-
-        RebuildStats {
-            blocks_total,
-            blocks_recovered,
-            progress,
-            segment_size_blks: self.segment_size_blks,
-            block_size: self.block_size,
-            tasks_total: self.task_pool.total as u64,
-            tasks_active: self.task_pool.active as u64,
-        }
+        self.stats_of(&self.destinations[0])
     }
 
     fn start(
@@ -526,6 +1128,24 @@ impl ClientOperations for RebuildJob {
         self.exec_client_op(RebuildOperation::Resume)
     }
 
+    fn set_limits(&mut self, limits: RebuildLimits) {
+        info!(
+            "Rebuild job {}: setting limits to {:?}",
+            self.destination, limits
+        );
+        self.throttle.set_limits(limits);
+    }
+
+    fn set_priority(&mut self, priority: RebuildPriority) {
+        if self.priority != priority {
+            info!(
+                "Rebuild job {}: changing priority from {} to {}",
+                self.destination, self.priority, priority
+            );
+            self.priority = priority;
+        }
+    }
+
     fn terminate(&mut self) -> oneshot::Receiver<RebuildState> {
         self.exec_internal_op(RebuildOperation::Stop).ok();
         let end_channel = oneshot::channel();
@@ -560,39 +1180,75 @@ impl RebuildJob {
             self.task_pool.active
         );
 
-        for n in 0 .. self.task_pool.total {
-            self.next = match self.send_segment_task(n) {
-                Some(next) => {
-                    self.task_pool.active += 1;
-                    next
-                }
-                None => break, /* we've already got enough tasks to rebuild
-                                * the bdev */
-            };
+        self.start_idle_tasks();
+    }
+
+    /// Number of concurrent copy tasks allowed by the priority of the job
+    fn max_active_tasks(&self) -> usize {
+        match self.priority {
+            RebuildPriority::Low => std::cmp::max(self.task_pool.total / 4, 1),
+            RebuildPriority::Normal | RebuildPriority::Urgent => {
+                self.task_pool.total
+            }
         }
     }
 
-    fn start_task_by_id(&mut self, id: usize) {
-        match self.send_segment_task(id) {
-            Some(next) => {
-                self.task_pool.active += 1;
-                self.next = next;
+    /// Starts idle tasks until the number of active tasks reaches the
+    /// maximum allowed by the priority of the job, completing the job when
+    /// there is nothing left to copy
+    fn start_idle_tasks(&mut self) {
+        for id in 0 .. self.task_pool.total {
+            if self.task_pool.active >= self.max_active_tasks() {
+                break;
             }
-            None => {
-                if self.task_pool.active == 0 {
-                    self.complete();
+            if self.task_pool.tasks[id].busy {
+                continue;
+            }
+            let blk = self.next;
+            match self.send_segment_task(id) {
+                Some(next) => {
+                    self.task_pool.tasks[id].busy = true;
+                    self.task_pool.tasks[id].blk = blk;
+                    self.task_pool.active += 1;
+                    self.next = next;
                 }
+                None => break,
             }
-        };
+        }
+
+        if self.task_pool.active == 0 {
+            if self.verify && !self.verifying {
+                info!(
+                    "Rebuild job {}: all segments copied, verifying",
+                    self.destination
+                );
+                self.verifying = true;
+                self.next = self.range.start;
+                self.start_idle_tasks();
+            } else if let Err(e) = self.verify_done() {
+                error!(
+                    "Rebuild job {}: verification failed: {}",
+                    self.destination,
+                    e.verbose()
+                );
+                self.error = Some(e);
+                self.fail();
+            } else {
+                self.complete();
+            }
+        }
     }
 
     async fn await_one_task(&mut self) -> Option<TaskResult> {
         self.task_pool.channel.1.next().await.map(|f| {
             self.task_pool.active -= 1;
-            if f.error.is_none() {
-                self.task_pool.segments_done += 1;
-            } else {
+            self.task_pool.tasks[f.id].busy = false;
+            if f.error.is_some() {
                 self.task_pool.tasks[f.id].error = Some(f.clone());
+            } else if self.verifying {
+                self.task_pool.segments_verified += 1;
+            } else {
+                self.task_pool.segments_done += 1;
             }
             f
         })
@@ -631,11 +1287,13 @@ impl RebuildJob {
 
             Reactors::current().send_future(async move {
                 let job = Self::lookup(&name).unwrap();
+                job.throttle(job.get_segment_size_blks(blk) * job.block_size)
+                    .await;
 
                 let r = TaskResult {
                     blk,
                     id,
-                    error: job.locked_copy_one(id, blk).await.err(),
+                    error: job.locked_process_one(id, blk).await.err(),
                 };
 
                 let task = &mut job.task_pool.tasks[id];
@@ -753,6 +1411,11 @@ impl RebuildJob {
                     S::Running => Ok(()),
                     S::Init => {
                         self.states.set_pending(S::Running, false)?;
+                        self.start_time = Some(Utc::now());
+                        self.started = Some((
+                            Instant::now(),
+                            self.task_pool.segments_done,
+                        ));
                         self.schedule();
                         Ok(())
                     }
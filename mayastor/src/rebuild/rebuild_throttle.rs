@@ -0,0 +1,140 @@
+#![warn(missing_docs)]
+
+use std::{
+    cmp::min,
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::OnceCell;
+
+use super::rebuild_api::RebuildLimits;
+
+/// Token bucket which holds at most one second worth of tokens. Consumers may
+/// take more tokens than available, in which case the debt has to be paid off
+/// before more tokens can be taken.
+#[derive(Debug)]
+struct TokenBucket {
+    /// tokens added per second, zero means unlimited
+    rate: u64,
+    /// tokens currently available, negative when in debt
+    available: i64,
+    /// time of the last refill
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u64) -> Self {
+        Self {
+            rate,
+            available: rate as i64,
+            last: Instant::now(),
+        }
+    }
+
+    /// changing the rate keeps the current debt, if any
+    fn set_rate(&mut self, rate: u64) {
+        self.refill();
+        self.rate = rate;
+        self.available = min(self.available, rate as i64);
+    }
+
+    fn refill(&mut self) {
+        if self.rate == 0 {
+            return;
+        }
+
+        let elapsed = self.last.elapsed().as_micros();
+        let tokens = elapsed * self.rate as u128 / 1_000_000;
+        if tokens == 0 {
+            return;
+        }
+
+        if self.available as i128 + tokens as i128 >= self.rate as i128 {
+            self.available = self.rate as i64;
+            self.last = Instant::now();
+        } else {
+            self.available += tokens as i64;
+            // only account for the time it took to add whole tokens
+            self.last += Duration::from_micros(
+                (tokens * 1_000_000 / self.rate as u128) as u64,
+            );
+        }
+    }
+
+    /// time to wait until tokens can be taken, None if they can be taken now
+    fn wait_time(&mut self) -> Option<Duration> {
+        if self.rate == 0 {
+            return None;
+        }
+
+        self.refill();
+        if self.available >= 0 {
+            None
+        } else {
+            let debt = -self.available as u128;
+            Some(Duration::from_micros(
+                (debt * 1_000_000 / self.rate as u128) as u64 + 1,
+            ))
+        }
+    }
+
+    fn take(&mut self, tokens: u64) {
+        if self.rate != 0 {
+            self.available -= tokens as i64;
+        }
+    }
+}
+
+/// Rate limiter of a rebuild job or of all rebuild jobs of the node
+#[derive(Debug)]
+pub(super) struct RebuildThrottle {
+    limits: RebuildLimits,
+    bandwidth: TokenBucket,
+    segments: TokenBucket,
+}
+
+impl RebuildThrottle {
+    /// new rate limiter with the given limits
+    pub(super) fn new(limits: RebuildLimits) -> Self {
+        Self {
+            limits,
+            bandwidth: TokenBucket::new(limits.bandwidth),
+            segments: TokenBucket::new(limits.segments),
+        }
+    }
+
+    /// current limits
+    pub(super) fn limits(&self) -> RebuildLimits {
+        self.limits
+    }
+
+    /// change the limits, which applies to the next segment copy
+    pub(super) fn set_limits(&mut self, limits: RebuildLimits) {
+        self.limits = limits;
+        self.bandwidth.set_rate(limits.bandwidth);
+        self.segments.set_rate(limits.segments);
+    }
+
+    /// time to wait before the next segment may be copied, None if it may be
+    /// copied now
+    pub(super) fn wait_time(&mut self) -> Option<Duration> {
+        self.bandwidth.wait_time().max(self.segments.wait_time())
+    }
+
+    /// account for the copy of a segment of the given size in bytes
+    pub(super) fn take(&mut self, bytes: u64) {
+        self.bandwidth.take(bytes);
+        self.segments.take(1);
+    }
+}
+
+/// Rate limiter shared by all rebuild jobs of the node
+pub(super) fn node_throttle() -> MutexGuard<'static, RebuildThrottle> {
+    static NODE_THROTTLE: OnceCell<Mutex<RebuildThrottle>> = OnceCell::new();
+
+    NODE_THROTTLE
+        .get_or_init(|| Mutex::new(RebuildThrottle::new(Default::default())))
+        .lock()
+        .expect("rebuild node throttle poisoned")
+}
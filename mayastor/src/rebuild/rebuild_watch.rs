@@ -0,0 +1,176 @@
+#![warn(missing_docs)]
+
+use std::time::{Duration, Instant};
+
+use futures::{
+    channel::mpsc,
+    future::{select, Either},
+    StreamExt,
+};
+
+use crate::core::{poller::sleep, Reactors};
+
+use super::rebuild_api::*;
+
+/// Update sent to the watchers of a rebuild, on every state change and
+/// periodically while the rebuild makes progress
+#[derive(Debug)]
+pub struct RebuildUpdate {
+    /// state of the rebuild of the watched child
+    pub state: RebuildState,
+    /// statistics of the rebuild of the watched child
+    pub stats: RebuildStats,
+    /// bytes rebuilt per second since the previous update
+    pub throughput: u64,
+    /// estimated time until all blocks are rebuilt, based on the average
+    /// rate since the watch started, None if no progress has been made yet
+    pub eta: Option<Duration>,
+}
+
+/// A client watching the rebuild of one of the destinations of a job
+#[derive(Debug)]
+pub(super) struct RebuildWatcher {
+    /// URI of the watched destination
+    uri: String,
+    /// channel to the watch task, which is sent every state change
+    sender: mpsc::UnboundedSender<(RebuildState, RebuildStats)>,
+}
+
+/// Rate at which a watched rebuild makes progress
+struct ProgressRate {
+    /// time the watch started and the blocks recovered at the time
+    start: (Instant, u64),
+    /// time of the previous update and the blocks recovered at the time
+    last: (Instant, u64),
+}
+
+impl ProgressRate {
+    fn new(stats: &RebuildStats) -> Self {
+        let now = Instant::now();
+        Self {
+            start: (now, stats.blocks_recovered),
+            last: (now, stats.blocks_recovered),
+        }
+    }
+
+    fn update(
+        &mut self,
+        state: RebuildState,
+        stats: RebuildStats,
+    ) -> RebuildUpdate {
+        let now = Instant::now();
+
+        let elapsed = now.duration_since(self.last.0).as_micros();
+        let recovered = stats.blocks_recovered.saturating_sub(self.last.1);
+        let throughput = if elapsed == 0 {
+            0
+        } else {
+            (recovered as u128 * stats.block_size as u128 * 1_000_000 / elapsed)
+                as u64
+        };
+        self.last = (now, stats.blocks_recovered);
+
+        let elapsed = now.duration_since(self.start.0).as_micros();
+        let recovered = stats.blocks_recovered.saturating_sub(self.start.1);
+        let remaining =
+            stats.blocks_total.saturating_sub(stats.blocks_recovered);
+        let eta = if remaining == 0 {
+            Some(Duration::default())
+        } else if recovered == 0 {
+            None
+        } else {
+            Some(Duration::from_micros(
+                (elapsed * remaining as u128 / recovered as u128) as u64,
+            ))
+        };
+
+        RebuildUpdate {
+            state,
+            stats,
+            throughput,
+            eta,
+        }
+    }
+}
+
+impl RebuildJob {
+    /// Watches the rebuild of the child `uri`. The returned channel receives
+    /// an update on every state change of the rebuild and every `interval`
+    /// in between. The channel is closed once the rebuild has reached its
+    /// final state or the job is gone, and dropping it ends the watch.
+    /// Must be called on the reactor of the job.
+    pub fn watch(
+        uri: &str,
+        interval: Duration,
+    ) -> Result<mpsc::UnboundedReceiver<RebuildUpdate>, RebuildError> {
+        let job = Self::lookup(uri)?;
+        let (sender, mut events) = mpsc::unbounded();
+        job.watchers.push(RebuildWatcher {
+            uri: uri.to_string(),
+            sender,
+        });
+
+        let uri = uri.to_string();
+        let first = (job.destination_state(&uri), job.destination_stats(&uri));
+        let (updates, receiver) = mpsc::unbounded();
+
+        Reactors::current().send_future(async move {
+            let mut rate = ProgressRate::new(&first.1);
+            let mut event = Some(first);
+            loop {
+                let (state, stats) = match event.take() {
+                    Some(event) => event,
+                    None => {
+                        match select(events.next(), Box::pin(sleep(interval)))
+                            .await
+                        {
+                            Either::Left((Some(event), _)) => event,
+                            // the job is gone
+                            Either::Left((None, _)) => break,
+                            Either::Right(_) => match Self::lookup(&uri) {
+                                Ok(job) => (
+                                    job.destination_state(&uri),
+                                    job.destination_stats(&uri),
+                                ),
+                                Err(_) => break,
+                            },
+                        }
+                    }
+                };
+
+                // the client is gone
+                if updates.unbounded_send(rate.update(state, stats)).is_err() {
+                    break;
+                }
+                if state.done() {
+                    break;
+                }
+            }
+            debug!("Rebuild of {}: watch ended", uri);
+        });
+
+        Ok(receiver)
+    }
+
+    /// Sends the current state of their destination to all watchers,
+    /// dropping those which are no longer watching
+    pub(super) fn notify_watchers(&mut self) {
+        let events = self
+            .watchers
+            .iter()
+            .map(|w| {
+                (
+                    self.destination_state(&w.uri),
+                    self.destination_stats(&w.uri),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let mut events = events.into_iter();
+        self.watchers.retain(|w| {
+            events
+                .next()
+                .map_or(false, |e| w.sender.unbounded_send(e).is_ok())
+        });
+    }
+}
@@ -281,6 +281,9 @@ impl From<Replica> for rpc::Replica {
                 None => rpc::ShareProtocolReplica::ReplicaNone,
             } as i32,
             uri: r.get_share_uri(),
+            properties: None,
+            allocated: 0,
+            reservation: 0,
         }
     }
 }
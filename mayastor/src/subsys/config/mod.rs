@@ -18,6 +18,7 @@ use std::{
 use byte_unit::Byte;
 use futures::FutureExt;
 use once_cell::sync::OnceCell;
+use rpc::mayastor::{CreatePoolRequest, PoolClearMethod, ReplicaWipeMethod};
 use serde::{Deserialize, Serialize};
 use snafu::Snafu;
 use spdk_sys::{
@@ -32,17 +33,16 @@ use crate::{
     bdev::{
         nexus::{
             instances,
-            nexus_child::{ChildState, NexusChild, Reason},
+            nexus_child::{ChildState, Reason},
             nexus_child_status_config::ChildStatusConfig,
         },
         nexus_create,
         VerboseError,
     },
-    core::{Bdev, Cores, Reactor, Share},
+    core::{Bdev, Cores, Reactor},
     jsonrpc::{jsonrpc_register, Code, RpcErrorCode},
-    lvs::Lvs,
+    lvs::{ClearMethod, Lvs, WipeMethod},
     nexus_uri::bdev_create,
-    pool::PoolsIter,
     replica::{ReplicaIter, ShareType},
     subsys::{
         config::opts::{
@@ -296,19 +296,21 @@ impl Config {
         current.nexus_bdevs = Some(nexus_bdevs);
 
         // collect any pools that are on the system, and insert them
-        let pools = PoolsIter::new()
-            .map(|p| {
-                let base = p.get_base_bdev();
-                Pool {
-                    name: p.get_name().into(),
-                    disks: vec![base.bdev_uri().unwrap_or_else(|| base.name())],
-                    replicas: ReplicaIter::new()
-                        .map(|p| Replica {
-                            name: p.get_uuid().to_string(),
-                            share: p.get_share_type(),
-                        })
-                        .collect::<Vec<_>>(),
-                }
+        let pools = Lvs::iter()
+            .map(|p| Pool {
+                name: p.name().into(),
+                disks: p.disks().into_iter().map(|d| d.uri).collect(),
+                overcommit_limit: p.overcommit_limit(),
+                cluster_size: p.cluster_size() as u32,
+                metadata_ratio: p.metadata_ratio(),
+                clear_method: Some(p.clear_method()),
+                wipe_method: p.wipe_method(),
+                replicas: ReplicaIter::new()
+                    .map(|p| Replica {
+                        name: p.get_uuid().to_string(),
+                        share: p.get_share_type(),
+                    })
+                    .collect::<Vec<_>>(),
             })
             .collect::<Vec<_>>();
 
@@ -423,13 +425,14 @@ impl Config {
                 if let Some(nexus_instance) =
                     instances().iter().find(|n| n.name == nexus.name)
                 {
-                    let degraded_children: Vec<&NexusChild> = nexus_instance
+                    let degraded_children: Vec<String> = nexus_instance
                         .children
                         .iter()
                         .filter(|child| {
                             child.state()
                                 == ChildState::Faulted(Reason::OutOfSync)
                         })
+                        .map(|child| child.name.clone())
                         .collect::<Vec<_>>();
 
                     // Get a mutable reference to the nexus instance. We can't
@@ -442,16 +445,12 @@ impl Config {
                         .find(|n| n.name == nexus.name)
                         .expect("Failed to find nexus");
 
-                    for child in degraded_children {
-                        dbg!("Start rebuilding child {}", &child.name);
-                        if nexus_instance
-                            .start_rebuild(&child.name)
-                            .await
-                            .is_err()
-                        {
-                            error!("Failed to start rebuild for {}", child);
-                        }
-                    }
+                    // degraded children are rebuilt by a single job
+                    debug!(
+                        "Start rebuilding children {:?}",
+                        &degraded_children
+                    );
+                    nexus_instance.start_rebuild_jobs(degraded_children).await;
                 }
             }
         }
@@ -582,23 +581,50 @@ pub struct BaseBdev {
 }
 
 #[derive(Debug, Default, PartialEq, Serialize, Deserialize, Clone)]
-/// Pools that we create. Future work will include the ability to create RAID0
-/// or RAID5.
+/// Pools that we create. A pool with several disks is created on the disks
+/// striped (RAID0) in the given order.
 pub struct Pool {
     /// name of the pool to be created or imported
     pub name: String,
     /// bdevs to create outside of the nexus control
     pub disks: Vec<String>,
+    /// max size of the replicas in percent of the capacity, 0 for no limit
+    #[serde(default)]
+    pub overcommit_limit: u32,
+    /// size of the clusters in bytes, 0 for the default
+    #[serde(default)]
+    pub cluster_size: u32,
+    /// metadata pages per cluster in percent, 0 for the default
+    #[serde(default)]
+    pub metadata_ratio: u32,
+    /// method to clear the disks with when the pool is created
+    #[serde(default)]
+    pub clear_method: Option<ClearMethod>,
+    /// method to wipe the data of the replicas with when they are destroyed
+    #[serde(default)]
+    pub wipe_method: WipeMethod,
     /// list of replicas (not required, informational only)
     pub replicas: Vec<Replica>,
 }
 
 /// Convert Pool into a gRPC request payload
-impl From<&Pool> for rpc::mayastor::CreatePoolRequest {
+impl From<&Pool> for CreatePoolRequest {
     fn from(o: &Pool) -> Self {
         Self {
             name: o.name.clone(),
             disks: o.disks.clone(),
+            // no limit in the config keeps the limit stored on the disks
+            overcommit_limit: Some(o.overcommit_limit).filter(|l| *l != 0),
+            cluster_size: o.cluster_size,
+            metadata_ratio: o.metadata_ratio,
+            clear_method: o
+                .clear_method
+                .map_or(
+                    PoolClearMethod::PoolClearDefault,
+                    PoolClearMethod::from,
+                )
+                .into(),
+            wipe_method: ReplicaWipeMethod::from(o.wipe_method).into(),
         }
     }
 }
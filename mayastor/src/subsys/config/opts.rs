@@ -53,6 +53,19 @@ pub struct NexusOpts {
     pub iscsi_nexus_port: u16,
     /// Port for replica target portal
     pub iscsi_replica_port: u16,
+    /// number of events kept in the journal of each nexus
+    pub journal_size: usize,
+    /// reject writes to a nexus once it can no longer guarantee write
+    /// redundancy, i.e. only one of its children is healthy
+    pub degraded_read_only: bool,
+    /// spread the reads of a rebuild across all healthy children rather
+    /// than reading from a single child
+    pub rebuild_spread_reads: bool,
+    /// only copy the segments of a child which differ from those of the
+    /// source, comparing checksums, rather than copying all of them
+    pub rebuild_checksum: bool,
+    /// verify a child once it has been rebuilt by comparing it to the source
+    pub rebuild_verify: bool,
 }
 
 /// Default nvmf port used for replicas.
@@ -75,6 +88,11 @@ impl Default for NexusOpts {
             iscsi_enable: true,
             iscsi_nexus_port: ISCSI_PORT_NEXUS,
             iscsi_replica_port: ISCSI_PORT_REPLICA,
+            journal_size: 128,
+            degraded_read_only: false,
+            rebuild_spread_reads: false,
+            rebuild_checksum: false,
+            rebuild_verify: false,
         }
     }
 }
@@ -6,6 +6,9 @@
 //! The registration messages are currently sent on an `HB_INTERVAL` by default
 //! but can be overridden by the `MAYASTOR_HB_INTERVAL` environment variable.
 //! containing the node name and the grpc endpoint.
+//!
+//! Events of the node, like changes of the state of its pools, are queued to
+//! the registration handler which publishes them.
 
 use futures::{select, FutureExt, StreamExt};
 use mbus_api::{v0::*, *};
@@ -41,6 +44,8 @@ pub enum Error {
     QueueRegister { cause: std::io::Error },
     #[snafu(display("Failed to queue deregister request: {:?}", cause))]
     QueueDeregister { cause: std::io::Error },
+    #[snafu(display("Failed to queue pool event: {:?}", cause))]
+    QueuePoolEvent { cause: std::io::Error },
 }
 
 #[derive(Clone)]
@@ -58,9 +63,9 @@ pub struct Registration {
     /// Configuration of the registration
     config: Configuration,
     /// Receive channel for messages and termination
-    rcv_chan: smol::channel::Receiver<()>,
-    /// Termination channel
-    fini_chan: smol::channel::Sender<()>,
+    rcv_chan: smol::channel::Receiver<PoolEvent>,
+    /// Channel to send messages, closed on termination
+    fini_chan: smol::channel::Sender<PoolEvent>,
 }
 
 static MESSAGE_BUS_REG: OnceCell<Registration> = OnceCell::new();
@@ -88,8 +93,25 @@ impl Registration {
         Ok(())
     }
 
+    /// queue the change of the state of a pool to be published, if mayastor
+    /// is registered with the control plane
+    pub fn publish_pool_event(pool: &str, state: PoolState, reason: &str) {
+        if let Some(registration) = MESSAGE_BUS_REG.get() {
+            let event = PoolEvent {
+                node: registration.config.node.clone(),
+                pool: pool.to_string(),
+                state,
+                reason: reason.to_string(),
+            };
+            if registration.fini_chan.try_send(event).is_err() {
+                warn!("Failed to queue the event of pool {}", pool);
+            }
+        }
+    }
+
     fn new(node: &str, grpc_endpoint: &str) -> Registration {
-        let (msg_sender, msg_receiver) = smol::channel::unbounded::<()>();
+        let (msg_sender, msg_receiver) =
+            smol::channel::unbounded::<PoolEvent>();
         let config = Configuration {
             node: node.to_owned(),
             grpc_endpoint: grpc_endpoint.to_owned(),
@@ -124,7 +146,11 @@ impl Registration {
                 _ = tokio::time::delay_for(self.config.hb_interval).fuse() => continue,
                 msg = self.rcv_chan.next().fuse() => {
                     match msg {
-                        Some(_) => log::info!("Messages have not been implemented yet"),
+                        Some(event) => {
+                            if let Err(err) = Self::publish(event).await {
+                                error!("Publishing event failed: {:?}", err);
+                            }
+                        }
                         _ => {
                             log::info!("Terminating the registration handler");
                             break;
@@ -163,6 +189,22 @@ impl Registration {
         Ok(())
     }
 
+    /// Publish an event on the MessageBus.
+    async fn publish(event: PoolEvent) -> Result<(), Error> {
+        event
+            .publish()
+            .await
+            .map_err(|cause| Error::QueuePoolEvent {
+                cause,
+            })?;
+        debug!(
+            "Published event of pool '{}': {}",
+            event.pool,
+            event.state.to_string()
+        );
+        Ok(())
+    }
+
     /// Send a deregister message to the MessageBus.
     async fn deregister(&self) -> Result<(), Error> {
         let payload = Deregister {
@@ -11,6 +11,8 @@ pub use config::{
 };
 pub use nvmf::{
     create_snapshot,
+    encode_snapshot_time,
+    nexus_host_nqn,
     set_snapshot_time,
     Error as NvmfError,
     NvmeCpl,
@@ -4,6 +4,7 @@ use std::{
     convert::TryFrom,
     ffi::c_void,
     ptr::NonNull,
+    sync::atomic::Ordering,
     time::{SystemTime, UNIX_EPOCH},
 };
 
@@ -19,8 +20,12 @@ use spdk_sys::{
 };
 
 use crate::{
-    bdev::nexus::{nexus_io::nvme_admin_opc, nexus_module},
-    core::{Bdev, Reactors},
+    bdev::nexus::{
+        nexus_bdev::nexus_lookup,
+        nexus_io::nvme_admin_opc,
+        nexus_module,
+    },
+    core::{Bdev, Reactors, MAX_CHECKSUM_SIZE},
     lvs::Lvol,
 };
 
@@ -49,6 +54,26 @@ impl NvmfReq {
     }
 }
 
+impl NvmfReq {
+    /// Completes the request with a generic success status if `ok`, or with
+    /// an internal device error otherwise
+    pub(crate) fn complete(&self, ok: bool) {
+        let mut rsp = self.response();
+        let nvme_status = rsp.status();
+        nvme_status.set_sct(0); // SPDK_NVME_SCT_GENERIC
+        nvme_status.set_sc(if ok {
+            0
+        } else {
+            0x06 // SPDK_NVME_SC_INTERNAL_DEVICE_ERROR
+        });
+
+        // From nvmf_bdev_ctrlr_complete_cmd
+        unsafe {
+            spdk_sys::spdk_nvmf_request_complete(self.0.as_ptr());
+        }
+    }
+}
+
 impl From<*mut c_void> for NvmfReq {
     fn from(ptr: *mut c_void) -> Self {
         NvmfReq(NonNull::new(ptr as *mut spdk_nvmf_request).unwrap())
@@ -58,16 +83,46 @@ impl From<*mut c_void> for NvmfReq {
 /// Set the snapshot time in an spdk_nvme_cmd struct to the current time
 /// Returns seconds since Unix epoch
 pub fn set_snapshot_time(cmd: &mut spdk_nvme_cmd) -> u64 {
-    // encode snapshot time in cdw10/11
     let now = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap()
         .as_secs();
+    encode_snapshot_time(cmd, now);
+    now as u64
+}
+
+/// Encode the time of a snapshot in cdw10/11 of an spdk_nvme_cmd struct
+pub fn encode_snapshot_time(cmd: &mut spdk_nvme_cmd, time: u64) {
     unsafe {
-        *spdk_sys::nvme_cmd_cdw10_get(&mut *cmd) = now as u32;
-        *spdk_sys::nvme_cmd_cdw11_get(&mut *cmd) = (now >> 32) as u32;
+        *spdk_sys::nvme_cmd_cdw10_get(&mut *cmd) = time as u32;
+        *spdk_sys::nvme_cmd_cdw11_get(&mut *cmd) = (time >> 32) as u32;
+    }
+}
+
+/// Decode the time of a snapshot from cdw10/11 of an spdk_nvme_cmd struct
+fn decode_snapshot_time(cmd: *const spdk_nvme_cmd) -> u64 {
+    unsafe {
+        spdk_sys::nvme_cmd_cdw10_get_val(cmd) as u64
+            | (spdk_sys::nvme_cmd_cdw11_get_val(cmd) as u64) << 32
     }
-    now as u64
+}
+
+/// Returns the lvol of the shared replica a request has been received on
+fn request_lvol(req: *mut spdk_nvmf_request) -> Option<Lvol> {
+    let mut bdev: *mut spdk_bdev = std::ptr::null_mut();
+    let mut desc: *mut spdk_bdev_desc = std::ptr::null_mut();
+    let mut ch: *mut spdk_io_channel = std::ptr::null_mut();
+    let rc = unsafe {
+        spdk_sys::spdk_nvmf_request_get_bdev(
+            1, req, &mut bdev, &mut desc, &mut ch,
+        )
+    };
+    if rc != 0 {
+        debug!("no bdev found");
+        return None;
+    }
+
+    Lvol::try_from(Bdev::from(bdev)).ok()
 }
 
 /// NVMf custom command handler for opcode c0h
@@ -149,6 +204,266 @@ pub fn create_snapshot(
     });
 }
 
+/// NVMf custom command handler for opcode c2h
+/// Computes the CRC-64 checksum of the blocks starting at cdw10/11 with the
+/// number of blocks in cdw12, spanning at most MAX_CHECKSUM_SIZE bytes, and
+/// returns it in the first 8 bytes of the data buffer, which saves a rebuild
+/// from transferring the blocks to compare them
+/// Return: <0 for any error, caller handles it as unsupported opcode
+extern "C" fn nvmf_segment_checksum_hdlr(req: *mut spdk_nvmf_request) -> i32 {
+    debug!("nvmf_segment_checksum_hdlr {:?}", req);
+
+    let mut bdev: *mut spdk_bdev = std::ptr::null_mut();
+    let mut desc: *mut spdk_bdev_desc = std::ptr::null_mut();
+    let mut ch: *mut spdk_io_channel = std::ptr::null_mut();
+    let rc = unsafe {
+        spdk_sys::spdk_nvmf_request_get_bdev(
+            1, req, &mut bdev, &mut desc, &mut ch,
+        )
+    };
+    if rc != 0 {
+        debug!("no bdev found");
+        return -1;
+    }
+
+    let mut data: *mut c_void = std::ptr::null_mut();
+    let mut length: u32 = 0;
+    unsafe {
+        spdk_sys::spdk_nvmf_request_get_data(req, &mut data, &mut length)
+    };
+    if data.is_null() || length < 8 {
+        debug!("no buffer for the checksum");
+        return -1;
+    }
+
+    let cmd = unsafe { spdk_sys::spdk_nvmf_request_get_cmd(req) };
+    let (offset, num_blocks) = unsafe {
+        (
+            spdk_sys::nvme_cmd_cdw10_get_val(cmd) as u64
+                | (spdk_sys::nvme_cmd_cdw11_get_val(cmd) as u64) << 32,
+            spdk_sys::nvme_cmd_cdw12_get_val(cmd) as u64,
+        )
+    };
+
+    let bd = Bdev::from(bdev);
+    if num_blocks * bd.block_len() as u64 > MAX_CHECKSUM_SIZE
+        || offset + num_blocks > bd.num_blocks()
+    {
+        debug!("invalid checksum range {}+{}", offset, num_blocks);
+        return -1;
+    }
+
+    let nvmf_req = NvmfReq(NonNull::new(req).unwrap());
+    // the request must be completed on the thread it was received on
+    Reactors::current().send_future(async move {
+        let result = match bd.open(false).and_then(|d| d.into_handle()) {
+            Ok(hdl) => hdl.checksum(offset, num_blocks).await,
+            Err(e) => Err(e),
+        };
+
+        let mut rsp = nvmf_req.response();
+        let nvme_status = rsp.status();
+        nvme_status.set_sct(0); // SPDK_NVME_SCT_GENERIC
+        match result {
+            Ok(checksum) => {
+                let buf = unsafe {
+                    std::slice::from_raw_parts_mut(data as *mut u8, 8)
+                };
+                buf.copy_from_slice(&checksum.to_le_bytes());
+                nvme_status.set_sc(0);
+            }
+            Err(e) => {
+                error!("checksum of {} failed: {}", bd.name(), e);
+                nvme_status.set_sc(0x06); // SPDK_NVME_SC_INTERNAL_DEVICE_ERROR
+            }
+        }
+
+        // From nvmf_bdev_ctrlr_complete_cmd
+        unsafe {
+            spdk_sys::spdk_nvmf_request_complete(nvmf_req.0.as_ptr());
+        }
+    });
+    1 // SPDK_NVMF_REQUEST_EXEC_STATUS_ASYNCHRONOUS
+}
+
+/// NVMf custom command handler for opcode c6h
+/// Returns the number of snapshots of the replica in the first 8 bytes of the
+/// data buffer, followed by their creation times, oldest first
+/// Return: <0 for any error, caller handles it as unsupported opcode
+extern "C" fn nvmf_list_snapshots_hdlr(req: *mut spdk_nvmf_request) -> i32 {
+    debug!("nvmf_list_snapshots_hdlr {:?}", req);
+
+    let lvol = match request_lvol(req) {
+        Some(lvol) => lvol,
+        None => return -1,
+    };
+
+    let mut data: *mut c_void = std::ptr::null_mut();
+    let mut length: u32 = 0;
+    unsafe {
+        spdk_sys::spdk_nvmf_request_get_data(req, &mut data, &mut length)
+    };
+    if data.is_null() || length < 8 {
+        debug!("no buffer for the snapshots");
+        return -1;
+    }
+
+    let nvmf_req = NvmfReq(NonNull::new(req).unwrap());
+    // Blobfs operations must be on md_thread
+    Reactors::master().send_future(async move {
+        let times = lvol
+            .snapshots()
+            .into_iter()
+            .map(|(t, _)| t)
+            .take(length as usize / 8 - 1)
+            .collect::<Vec<_>>();
+        let buf = unsafe {
+            std::slice::from_raw_parts_mut(
+                data as *mut u8,
+                (times.len() + 1) * 8,
+            )
+        };
+        buf[.. 8].copy_from_slice(&(times.len() as u64).to_le_bytes());
+        for (chunk, t) in buf[8 ..].chunks_exact_mut(8).zip(times.iter()) {
+            chunk.copy_from_slice(&t.to_le_bytes());
+        }
+        nvmf_req.complete(true);
+    });
+    1 // SPDK_NVMF_REQUEST_EXEC_STATUS_ASYNCHRONOUS
+}
+
+/// NVMf custom command handler for opcode c4h
+/// Destroys the snapshot of the replica created at the time in cdw10/11
+/// Return: <0 for any error, caller handles it as unsupported opcode
+extern "C" fn nvmf_destroy_snapshot_hdlr(req: *mut spdk_nvmf_request) -> i32 {
+    debug!("nvmf_destroy_snapshot_hdlr {:?}", req);
+
+    let lvol = match request_lvol(req) {
+        Some(lvol) => lvol,
+        None => return -1,
+    };
+    let time = decode_snapshot_time(unsafe {
+        spdk_sys::spdk_nvmf_request_get_cmd(req)
+    });
+
+    let nvmf_req = NvmfReq(NonNull::new(req).unwrap());
+    // Blobfs operations must be on md_thread
+    Reactors::master().send_future(async move {
+        let result = lvol.destroy_snapshot(time).await;
+        if let Err(e) = &result {
+            error!("failed to destroy snapshot of {}: {}", lvol, e);
+        }
+        nvmf_req.complete(result.is_ok());
+    });
+    1 // SPDK_NVMF_REQUEST_EXEC_STATUS_ASYNCHRONOUS
+}
+
+/// NVMf custom command handler for opcode c8h
+/// Starts reverting the replica to its snapshot created at the time in
+/// cdw10/11 in the background, the state of which is polled with opcode cah
+/// Return: <0 for any error, caller handles it as unsupported opcode
+extern "C" fn nvmf_revert_snapshot_hdlr(req: *mut spdk_nvmf_request) -> i32 {
+    debug!("nvmf_revert_snapshot_hdlr {:?}", req);
+
+    let lvol = match request_lvol(req) {
+        Some(lvol) => lvol,
+        None => return -1,
+    };
+    let time = decode_snapshot_time(unsafe {
+        spdk_sys::spdk_nvmf_request_get_cmd(req)
+    });
+
+    let nvmf_req = NvmfReq(NonNull::new(req).unwrap());
+    // Blobfs operations must be on md_thread
+    Reactors::master().send_future(async move {
+        let result = lvol.start_revert(time);
+        if let Err(e) = &result {
+            error!("failed to revert {} to its snapshot: {}", lvol, e);
+        }
+        nvmf_req.complete(result.is_ok());
+    });
+    1 // SPDK_NVMF_REQUEST_EXEC_STATUS_ASYNCHRONOUS
+}
+
+/// NVMf custom command handler for opcode cah
+/// Returns the state of the last revert of the replica to one of its
+/// snapshots in the first byte of the data buffer
+/// Return: <0 for any error, caller handles it as unsupported opcode
+extern "C" fn nvmf_revert_snapshot_state_hdlr(
+    req: *mut spdk_nvmf_request,
+) -> i32 {
+    debug!("nvmf_revert_snapshot_state_hdlr {:?}", req);
+
+    let lvol = match request_lvol(req) {
+        Some(lvol) => lvol,
+        None => return -1,
+    };
+
+    let mut data: *mut c_void = std::ptr::null_mut();
+    let mut length: u32 = 0;
+    unsafe {
+        spdk_sys::spdk_nvmf_request_get_data(req, &mut data, &mut length)
+    };
+    if data.is_null() || length < 1 {
+        debug!("no buffer for the revert state");
+        return -1;
+    }
+
+    let nvmf_req = NvmfReq(NonNull::new(req).unwrap());
+    Reactors::master().send_future(async move {
+        unsafe { *(data as *mut u8) = lvol.revert_state().into() };
+        nvmf_req.complete(true);
+    });
+    1 // SPDK_NVMF_REQUEST_EXEC_STATUS_ASYNCHRONOUS
+}
+
+/// NVMf custom command handler for the identify command (06h)
+/// Marks the namespace of a nexus published read-only as write protected, so
+/// that the hosts do not attempt to write to it
+/// Return: <0 to let the target handle the command as usual
+extern "C" fn nvmf_identify_hdlr(req: *mut spdk_nvmf_request) -> i32 {
+    let cmd = unsafe { spdk_sys::spdk_nvmf_request_get_cmd(req) };
+    // CNS 00h identifies a namespace
+    let (cns, nsid) = unsafe {
+        (
+            spdk_sys::nvme_cmd_cdw10_get_val(cmd) & 0xff,
+            spdk_sys::nvme_cmd_nsid_get_val(cmd),
+        )
+    };
+    if cns != 0 {
+        return -1;
+    }
+
+    let mut bdev: *mut spdk_bdev = std::ptr::null_mut();
+    let mut desc: *mut spdk_bdev_desc = std::ptr::null_mut();
+    let mut ch: *mut spdk_io_channel = std::ptr::null_mut();
+    let rc = unsafe {
+        spdk_sys::spdk_nvmf_request_get_bdev(
+            nsid, req, &mut bdev, &mut desc, &mut ch,
+        )
+    };
+    if rc != 0 {
+        return -1;
+    }
+
+    match nexus_lookup(&Bdev::from(bdev).name()) {
+        Some(nexus) if nexus.share_read_only.load(Ordering::Relaxed) => unsafe {
+            spdk_sys::nvmf_identify_ns_write_protected(req)
+        },
+        _ => -1,
+    }
+}
+
+/// Register custom NVMe admin command handler for the identify command
+pub fn setup_identify_hdlr() {
+    unsafe {
+        spdk_sys::spdk_nvmf_set_custom_admin_cmd_hdlr(
+            0x06, // SPDK_NVME_OPC_IDENTIFY
+            Some(nvmf_identify_hdlr),
+        );
+    }
+}
+
 /// Register custom NVMe admin command handler
 pub fn setup_create_snapshot_hdlr() {
     unsafe {
@@ -158,3 +473,36 @@ pub fn setup_create_snapshot_hdlr() {
         );
     }
 }
+
+/// Register custom NVMe admin command handler for segment checksums
+pub fn setup_segment_checksum_hdlr() {
+    unsafe {
+        spdk_sys::spdk_nvmf_set_custom_admin_cmd_hdlr(
+            nvme_admin_opc::SEGMENT_CHECKSUM,
+            Some(nvmf_segment_checksum_hdlr),
+        );
+    }
+}
+
+/// Register custom NVMe admin command handlers for listing, destroying and
+/// reverting to snapshots
+pub fn setup_snapshot_hdlrs() {
+    unsafe {
+        spdk_sys::spdk_nvmf_set_custom_admin_cmd_hdlr(
+            nvme_admin_opc::LIST_SNAPSHOTS,
+            Some(nvmf_list_snapshots_hdlr),
+        );
+        spdk_sys::spdk_nvmf_set_custom_admin_cmd_hdlr(
+            nvme_admin_opc::DESTROY_SNAPSHOT,
+            Some(nvmf_destroy_snapshot_hdlr),
+        );
+        spdk_sys::spdk_nvmf_set_custom_admin_cmd_hdlr(
+            nvme_admin_opc::REVERT_SNAPSHOT,
+            Some(nvmf_revert_snapshot_hdlr),
+        );
+        spdk_sys::spdk_nvmf_set_custom_admin_cmd_hdlr(
+            nvme_admin_opc::REVERT_SNAPSHOT_STATE,
+            Some(nvmf_revert_snapshot_state_hdlr),
+        );
+    }
+}
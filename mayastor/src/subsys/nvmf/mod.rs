@@ -13,14 +13,20 @@ use std::cell::RefCell;
 use nix::errno::Errno;
 use snafu::Snafu;
 
-pub use admin_cmd::{create_snapshot, set_snapshot_time, NvmeCpl, NvmfReq};
+pub use admin_cmd::{
+    create_snapshot,
+    encode_snapshot_time,
+    set_snapshot_time,
+    NvmeCpl,
+    NvmfReq,
+};
 use poll_groups::PollGroup;
 use spdk_sys::{
     spdk_subsystem,
     spdk_subsystem_fini_next,
     spdk_subsystem_init_next,
 };
-pub use subsystem::{NvmfSubsystem, SubType};
+pub use subsystem::{nexus_host_nqn, NvmfSubsystem, SubType};
 pub use target::Target;
 
 use crate::{
@@ -87,8 +93,11 @@ impl Nvmf {
 
         // this code only ever gets run on the first core
 
-        // set up custom NVMe Admin command handler
+        // set up custom NVMe Admin command handlers
         admin_cmd::setup_create_snapshot_hdlr();
+        admin_cmd::setup_segment_checksum_hdlr();
+        admin_cmd::setup_snapshot_hdlrs();
+        admin_cmd::setup_identify_hdlr();
 
         if Config::get().nexus_opts.nvmf_enable {
             NVMF_TGT.with(|tgt| {
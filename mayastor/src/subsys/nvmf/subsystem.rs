@@ -14,8 +14,11 @@ use serde::export::{Formatter, TryFrom};
 use spdk_sys::{
     spdk_bdev_nvme_opts,
     spdk_nvmf_ns_get_bdev,
+    spdk_nvmf_ns_get_id,
+    spdk_nvmf_ns_get_opts,
     spdk_nvmf_ns_opts,
     spdk_nvmf_subsystem,
+    spdk_nvmf_subsystem_add_host,
     spdk_nvmf_subsystem_add_listener,
     spdk_nvmf_subsystem_add_ns,
     spdk_nvmf_subsystem_create,
@@ -28,6 +31,7 @@ use spdk_sys::{
     spdk_nvmf_subsystem_get_nqn,
     spdk_nvmf_subsystem_listener_get_trid,
     spdk_nvmf_subsystem_pause,
+    spdk_nvmf_subsystem_remove_ns,
     spdk_nvmf_subsystem_resume,
     spdk_nvmf_subsystem_set_allow_any_host,
     spdk_nvmf_subsystem_set_ana_reporting,
@@ -217,6 +221,71 @@ impl NvmfSubsystem {
         }
     }
 
+    /// replace the bdev of the namespace by the given bdev, keeping the ID,
+    /// the NGUID and the UUID of the namespace so that the hosts see the
+    /// same namespace. The subsystem must be paused. Should the given bdev
+    /// fail to be added, the namespace is restored with its original bdev.
+    pub fn replace_namespace(&self, bdev: &Bdev) -> Result<(), Error> {
+        let ns = unsafe { spdk_nvmf_subsystem_get_first_ns(self.0.as_ptr()) };
+        if ns.is_null() {
+            return Err(Error::Namespace {
+                bdev: bdev.name(),
+                msg: "no namespace to replace".to_string(),
+            });
+        }
+
+        let mut opts = spdk_nvmf_ns_opts::default();
+        let (ns_id, original) = unsafe {
+            spdk_nvmf_ns_get_opts(
+                ns,
+                &mut opts as *mut _,
+                size_of::<spdk_nvmf_ns_opts>() as u64,
+            );
+            (spdk_nvmf_ns_get_id(ns), spdk_nvmf_ns_get_bdev(ns))
+        };
+
+        let rc =
+            unsafe { spdk_nvmf_subsystem_remove_ns(self.0.as_ptr(), ns_id) };
+        if rc != 0 {
+            return Err(Error::Namespace {
+                bdev: bdev.name(),
+                msg: format!("failed to remove NS ID {}: {}", ns_id, rc),
+            });
+        }
+
+        let add_ns = |ns_bdev| unsafe {
+            spdk_nvmf_subsystem_add_ns(
+                self.0.as_ptr(),
+                ns_bdev,
+                &opts as *const _,
+                size_of::<spdk_nvmf_ns_opts>() as u64,
+                ptr::null_mut(),
+            )
+        };
+
+        let new_id = add_ns(bdev.as_ptr());
+        if new_id == ns_id {
+            info!("replaced the bdev of NS ID {} by {}", ns_id, bdev.name());
+            return Ok(());
+        }
+
+        // 0 is returned on failure, but a namespace with another ID can not
+        // be kept either
+        if new_id != 0 {
+            unsafe { spdk_nvmf_subsystem_remove_ns(self.0.as_ptr(), new_id) };
+        }
+        let msg = if add_ns(original) == ns_id {
+            format!("failed to add NS ID {}, the original bdev is kept", ns_id)
+        } else {
+            error!("failed to restore the original bdev of NS ID {}", ns_id);
+            format!("failed to add NS ID {}, the namespace is lost", ns_id)
+        };
+        Err(Error::Namespace {
+            bdev: bdev.name(),
+            msg,
+        })
+    }
+
     /// destroy the subsystem
     pub fn destroy(&self) {
         unsafe { spdk_nvmf_subsystem_destroy(self.0.as_ptr()) }
@@ -238,6 +307,25 @@ impl NvmfSubsystem {
         };
     }
 
+    /// only allow the hosts with the given NQNs to connect to the subsystem,
+    /// or any host if there are none. This must be done before the subsystem
+    /// is started
+    pub fn allow_hosts(&self, hosts: &[String]) -> Result<(), Error> {
+        self.allow_any(hosts.is_empty());
+        for host in hosts {
+            let hostnqn = host.as_str().into_cstring();
+            unsafe {
+                spdk_nvmf_subsystem_add_host(self.0.as_ptr(), hostnqn.as_ptr())
+            }
+            .to_result(|e| Error::Subsystem {
+                source: Errno::from_i32(-e),
+                nqn: self.get_nqn(),
+                msg: format!("failed to allow host {}", host),
+            })?;
+        }
+        Ok(())
+    }
+
     /// enable Asymmetric Namespace Access (ANA) reporting
     pub fn set_ana_reporting(&self, enable: bool) -> Result<(), Error> {
         unsafe {
@@ -584,3 +672,10 @@ impl NvmfSubsystem {
 fn gen_nqn(id: &str) -> String {
     format!("nqn.2019-05.io.openebs:{}", id)
 }
+
+/// returns the host NQN a nexus connects to its nvmf children with, which
+/// includes the generation of its ownership of the children so that a nexus
+/// with an outdated ownership can not connect
+pub fn nexus_host_nqn(nexus_uuid: &str, generation: u64) -> String {
+    gen_nqn(&format!("nexus-host-{}-{}", nexus_uuid, generation))
+}
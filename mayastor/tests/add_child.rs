@@ -75,7 +75,7 @@ async fn add_child() {
     ms.spawn(async {
         let nexus = nexus_lookup(NEXUS_NAME).unwrap();
         nexus
-            .share(rpc::mayastor::ShareProtocolNexus::NexusIscsi, None)
+            .share(rpc::mayastor::ShareProtocolNexus::NexusIscsi, None, false)
             .await
             .expect("Failed to share nexus");
     })
@@ -130,6 +130,8 @@ async fn create_nexus(container: &str, mut kiddos: Vec<String>) -> String {
             uuid: NEXUS_UUID.to_string(),
             size: 60 * 1024 * 1024,
             children: kiddos,
+            block_size: 0,
+            owner_generation: 0,
         })
         .await
         .unwrap();
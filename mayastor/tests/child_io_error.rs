@@ -98,6 +98,8 @@ async fn nexus_create_and_publish(
             uuid: uuid.clone(),
             size,
             children,
+            block_size: 0,
+            owner_generation: 0,
         })
         .await
         .unwrap();
@@ -106,6 +108,7 @@ async fn nexus_create_and_publish(
             uuid: uuid.clone(),
             key: "".into(),
             share: ShareProtocolNexus::NexusNvmf as i32,
+            read_only: false,
         })
         .await
         .unwrap()
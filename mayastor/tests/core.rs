@@ -232,7 +232,7 @@ async fn core_5() {
                 let nexus = nexus_lookup(nexus_name).unwrap();
                 let device = common::device_path_from_uri(
                     nexus
-                        .share(ShareProtocolNexus::NexusNbd, None)
+                        .share(ShareProtocolNexus::NexusNbd, None, false)
                         .await
                         .unwrap(),
                 );
@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+
+use common::MayastorTest;
+use mayastor::{
+    core::MayastorCliArgs,
+    lvs::{LvolProperties, Lvs, PropName, PropValue},
+};
+use rpc::mayastor::CreatePoolRequest;
+
+pub mod common;
+
+static DISKNAME: &str = "/tmp/lvol_properties.img";
+static VOLUME_UUID: &str = "b6a2a4a5-d1ae-4a5c-8e2a-e5c0d6a9e6e4";
+static NEXUS_UUID: &str = "2a5c3cbd-7ab1-4b5d-9bd7-54eb27a1b47a";
+
+fn pool_args() -> CreatePoolRequest {
+    CreatePoolRequest {
+        name: "ppool".into(),
+        disks: vec![format!("aio://{}", DISKNAME)],
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn lvol_properties() {
+    common::delete_file(&[DISKNAME.to_string()]);
+    common::truncate_file(DISKNAME, 64 * 1024);
+
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    let props = LvolProperties {
+        nexus_uuid: NEXUS_UUID.into(),
+        owner_generation: 1,
+        volume_uuid: VOLUME_UUID.into(),
+        labels: vec![
+            ("app".into(), "db".into()),
+            ("tier".into(), "gold".into()),
+        ]
+        .into_iter()
+        .collect(),
+        created: 0,
+        allowed_hosts: vec!["nqn.2019-05.io.openebs:node1".into()],
+    };
+
+    let expected = props.clone();
+    ms.spawn(async move {
+        let pool = Lvs::create_or_import(pool_args()).await.unwrap();
+        let lvol = pool
+            .create_lvol("pvol", 4 * 1024 * 1024, true)
+            .await
+            .unwrap();
+
+        // the creation time is recorded, the other properties are empty
+        let created = lvol.properties().created;
+        assert!(created > 0);
+        assert_eq!(
+            lvol.properties(),
+            LvolProperties {
+                created,
+                ..Default::default()
+            }
+        );
+
+        // setting the properties keeps the creation time and the owner
+        lvol.set_properties(&props).await.unwrap();
+        assert_eq!(
+            lvol.properties(),
+            LvolProperties {
+                created,
+                nexus_uuid: String::new(),
+                owner_generation: 0,
+                ..props.clone()
+            }
+        );
+
+        // the owner is changed by transferring the ownership to a newer
+        // generation only
+        assert!(lvol.transfer_ownership(NEXUS_UUID, 0).await.is_err());
+        lvol.transfer_ownership(NEXUS_UUID, 1).await.unwrap();
+        lvol.transfer_ownership(NEXUS_UUID, 1).await.unwrap();
+        assert!(lvol.transfer_ownership("other", 1).await.is_err());
+        assert_eq!(
+            lvol.properties(),
+            LvolProperties {
+                created,
+                ..props.clone()
+            }
+        );
+
+        // properties can be set one by one
+        let mut labels = BTreeMap::new();
+        labels.insert("app".to_string(), "db".to_string());
+        labels.insert("tier".to_string(), "gold".to_string());
+        assert_eq!(
+            lvol.get(PropName::Labels).await.unwrap(),
+            PropValue::Labels(labels)
+        );
+        lvol.set(PropValue::VolumeUuid(VOLUME_UUID.into()))
+            .await
+            .unwrap();
+
+        // properties too large to be stored are refused and leave the stored
+        // ones alone
+        let mut large = props.clone();
+        large.labels.insert("large".into(), "x".repeat(64 * 1024));
+        assert!(large.validate("pvol").is_err());
+        assert!(lvol.set_properties(&large).await.is_err());
+        assert!(lvol.set(PropValue::Labels(large.labels)).await.is_err());
+        assert_eq!(
+            lvol.properties(),
+            LvolProperties {
+                created,
+                ..props
+            }
+        );
+
+        pool.export().await.unwrap();
+    })
+    .await;
+
+    // the properties are stored on disk
+    ms.spawn(async move {
+        let pool = Lvs::create_or_import(pool_args()).await.unwrap();
+        let lvol = pool.lvols().unwrap().find(|l| l.name() == "pvol").unwrap();
+        let props = lvol.properties();
+        assert!(props.created > 0);
+        assert_eq!(
+            props,
+            LvolProperties {
+                created: props.created,
+                ..expected
+            }
+        );
+        pool.destroy().await.unwrap();
+    })
+    .await;
+
+    common::delete_file(&[DISKNAME.to_string()]);
+}
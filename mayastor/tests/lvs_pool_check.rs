@@ -0,0 +1,125 @@
+use std::{convert::TryFrom, ffi::CString, os::raw::c_void};
+
+use futures::channel::oneshot;
+
+use common::{bdev_io, MayastorTest};
+use mayastor::{
+    core::{Bdev, MayastorCliArgs, Share},
+    ffihelper::cb_arg,
+    lvs::{IssueKind, Lvol, Lvs},
+};
+use rpc::mayastor::CreatePoolRequest;
+use spdk_sys::{spdk_bs_create_blob, vbdev_get_lvol_store_by_name};
+
+pub mod common;
+
+static DISKNAME: &str = "/tmp/lvs_pool_check.img";
+
+fn pool_args() -> CreatePoolRequest {
+    CreatePoolRequest {
+        name: "cpool".into(),
+        disks: vec![format!("aio://{}", DISKNAME)],
+        ..Default::default()
+    }
+}
+
+/// creates a blob which does not belong to any lvol, as left behind by an
+/// interrupted lvol creation
+async fn create_orphaned_blob(pool: &str) {
+    extern "C" fn done_cb(sender: *mut c_void, _id: u64, errno: i32) {
+        let sender =
+            unsafe { Box::from_raw(sender as *mut oneshot::Sender<i32>) };
+        sender.send(errno).unwrap();
+    }
+
+    let name = CString::new(pool).unwrap();
+    let (s, r) = oneshot::channel::<i32>();
+    unsafe {
+        let lvs = vbdev_get_lvol_store_by_name(name.as_ptr());
+        spdk_bs_create_blob((*lvs).blobstore, Some(done_cb), cb_arg(s));
+    }
+    assert_eq!(r.await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn lvs_pool_check() {
+    common::delete_file(&[DISKNAME.to_string()]);
+    common::truncate_file(DISKNAME, 64 * 1024);
+
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    ms.spawn(async {
+        let pool = Lvs::create_or_import(pool_args()).await.unwrap();
+        pool.create_lvol("thick", 8 * 1024 * 1024, false)
+            .await
+            .unwrap();
+        let thin = pool
+            .create_lvol("thin", 8 * 1024 * 1024, true)
+            .await
+            .unwrap();
+        bdev_io::write_some(&thin.name(), 0, 0xaa).await.unwrap();
+
+        // the super blob and the two lvols are consistent
+        let check = pool.check(false).await.unwrap();
+        assert_eq!(check.blobs, 3);
+        assert!(check.clusters > 0);
+        assert!(check.issues.is_empty());
+
+        // an orphaned blob is reported, but a pool which has been used since
+        // it was imported can not be repaired
+        create_orphaned_blob("cpool").await;
+        let check = pool.check(false).await.unwrap();
+        assert_eq!(check.blobs, 4);
+        assert_eq!(check.issues.len(), 1);
+        assert_eq!(check.issues[0].kind, IssueKind::OrphanedBlob);
+        assert!(!check.issues[0].repaired);
+        assert!(pool.check(true).await.is_err());
+
+        pool.export().await.unwrap();
+    })
+    .await;
+
+    // the orphaned blob is deleted when repairing the freshly imported pool
+    ms.spawn(async {
+        let pool = Lvs::import_from_disks(pool_args()).await.unwrap();
+        let check = pool.check(true).await.unwrap();
+        assert_eq!(check.issues.len(), 1);
+        assert!(check.issues[0].repaired);
+
+        let check = pool.check(false).await.unwrap();
+        assert_eq!(check.blobs, 3);
+        assert!(check.issues.is_empty());
+
+        // a pool with a shared lvol can not be repaired
+        let thin =
+            Lvol::try_from(Bdev::lookup_by_name("thin").unwrap()).unwrap();
+        thin.share_nvmf().await.unwrap();
+        assert!(pool.check(true).await.is_err());
+        thin.unshare().await.unwrap();
+        assert!(pool.check(true).await.is_err());
+
+        pool.export().await.unwrap();
+    })
+    .await;
+
+    // the repair is persisted
+    ms.spawn(async {
+        let pool = Lvs::import_from_disks(pool_args()).await.unwrap();
+        let check = pool.check(false).await.unwrap();
+        assert_eq!(check.blobs, 3);
+        assert!(check.issues.is_empty());
+
+        // nor can a pool whose metadata has been changed since it was
+        // imported, whatever changed it
+        assert!(pool.check(true).await.is_ok());
+        let thin =
+            Lvol::try_from(Bdev::lookup_by_name("thin").unwrap()).unwrap();
+        thin.resize(16 * 1024 * 1024).await.unwrap();
+        assert!(pool.check(true).await.is_err());
+
+        pool.destroy().await.unwrap();
+    })
+    .await;
+
+    common::delete_file(&[DISKNAME.to_string()]);
+}
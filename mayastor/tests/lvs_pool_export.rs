@@ -0,0 +1,152 @@
+use common::{bdev_io, compose::Builder, MayastorTest};
+use mayastor::{
+    core::{MayastorCliArgs, Share},
+    lvs::Lvs,
+};
+use rpc::mayastor::{
+    CreatePoolRequest,
+    CreateReplicaRequest,
+    ExportPoolRequest,
+    ImportPoolRequest,
+    Null,
+};
+use tonic::Code;
+
+pub mod common;
+
+static DISKNAME: &str = "/tmp/lvs_pool_export.img";
+static UUID: &str = "9a7e3b2c-1f4d-4c6e-8a5b-3d2f1e0c9b81";
+static UUID2: &str = "9a7e3b2c-1f4d-4c6e-8a5b-3d2f1e0c9b82";
+
+fn pool_args() -> CreatePoolRequest {
+    CreatePoolRequest {
+        name: "epool".into(),
+        disks: vec![format!("aio://{}", DISKNAME)],
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn lvs_pool_export() {
+    common::delete_file(&[DISKNAME.to_string()]);
+    common::truncate_file(DISKNAME, 64 * 1024);
+
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    let (uri, uri2) = ms
+        .spawn(async {
+            // there is no pool on the disk to import
+            assert!(Lvs::import_from_disks(pool_args()).await.is_err());
+            assert!(Lvs::lookup("epool").is_none());
+
+            let pool = Lvs::create_or_import(pool_args()).await.unwrap();
+            let lvol = pool
+                .create_lvol(UUID, 4 * 1024 * 1024, false)
+                .await
+                .unwrap();
+            let uri = lvol.share_nvmf().await.unwrap();
+            bdev_io::write_some(&lvol.name(), 0, 0xaa).await.unwrap();
+            let lvol2 = pool
+                .create_lvol(UUID2, 4 * 1024 * 1024, false)
+                .await
+                .unwrap();
+            lvol2.share_iscsi().await.unwrap();
+            let uri2 = lvol2.share_uri().unwrap();
+            assert!(uri2.starts_with("iscsi://"));
+
+            pool.export().await.unwrap();
+            assert!(Lvs::lookup("epool").is_none());
+            (uri, uri2)
+        })
+        .await;
+
+    ms.spawn(async move {
+        // the replicas are shared again with the same protocol and their
+        // data is kept
+        let pool = Lvs::import_from_disks(pool_args()).await.unwrap();
+        let lvol = pool.lvols().unwrap().find(|l| l.name() == UUID).unwrap();
+        assert_eq!(lvol.share_uri().unwrap(), uri);
+        bdev_io::read_some(&lvol.name(), 0, 0xaa).await.unwrap();
+        let lvol2 = pool.lvols().unwrap().find(|l| l.name() == UUID2).unwrap();
+        assert_eq!(lvol2.share_uri().unwrap(), uri2);
+
+        // importing the imported pool again is OK
+        Lvs::import_from_disks(pool_args()).await.unwrap();
+        pool.destroy().await.unwrap();
+    })
+    .await;
+
+    common::delete_file(&[DISKNAME.to_string()]);
+}
+
+#[tokio::test]
+async fn lvs_pool_export_rpc() {
+    let test = Builder::new()
+        .name("lvs-pool-export")
+        .network("10.1.0.0/16")
+        .add_container("ms1")
+        .with_clean(true)
+        .with_prune(true)
+        .build()
+        .await
+        .unwrap();
+    let ms1 = &mut test.grpc_handle("ms1").await.unwrap();
+
+    ms1.mayastor
+        .create_pool(CreatePoolRequest {
+            name: "tpool".into(),
+            disks: vec!["malloc:///disk0?size_mb=64".into()],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    ms1.mayastor
+        .create_replica(CreateReplicaRequest {
+            uuid: UUID.into(),
+            pool: "tpool".into(),
+            size: 4 * 1024 * 1024,
+            thin: false,
+            share: 1,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    // the replica is unshared and the pool is gone, exporting it again is OK
+    for _ in 0 .. 2 {
+        ms1.mayastor
+            .export_pool(ExportPoolRequest {
+                name: "tpool".into(),
+            })
+            .await
+            .unwrap();
+    }
+    let pools = ms1.mayastor.list_pools(Null {}).await.unwrap().into_inner();
+    assert!(pools.pools.is_empty());
+    let replicas = ms1
+        .mayastor
+        .list_replicas(Null {})
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(replicas.replicas.is_empty());
+
+    // the malloc disk lost its data when it was detached, so there is no pool
+    // to import and importing must not create a new one
+    let err = ms1
+        .mayastor
+        .import_pool(ImportPoolRequest {
+            name: "tpool".into(),
+            disks: vec!["malloc:///disk0?size_mb=64".into()],
+            overcommit_limit: None,
+            cluster_size: 0,
+            metadata_ratio: 0,
+            clear_method: 0,
+            wipe_method: 0,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::NotFound);
+    let pools = ms1.mayastor.list_pools(Null {}).await.unwrap().into_inner();
+    assert!(pools.pools.is_empty());
+}
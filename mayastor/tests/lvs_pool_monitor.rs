@@ -0,0 +1,159 @@
+use std::time::Duration;
+
+use common::{
+    error_bdev::{
+        create_error_bdev,
+        inject_error,
+        SPDK_BDEV_IO_TYPE_WRITE,
+        VBDEV_IO_FAILURE,
+    },
+    MayastorTest,
+};
+use mayastor::{
+    core::{Bdev, MayastorCliArgs},
+    lvs::{DiskState, Lvs, LvsState},
+    nexus_uri::bdev_destroy,
+};
+use rpc::mayastor::CreatePoolRequest;
+
+pub mod common;
+
+static DISK: &str = "malloc:///mdisk0?size_mb=64";
+
+static ERROR_DISK: &str = "/tmp/monitor_disk.img";
+static ERROR_DEVICE: &str = "monitor_error_device";
+// The prefix is added by the vbdev_error module
+static EE_ERROR_DEVICE: &str = "EE_monitor_error_device";
+
+/// waits for the monitor to find the state of the pool `name` to be `state`
+async fn wait_for_state(ms: &MayastorTest<'_>, name: &str, state: LvsState) {
+    for _ in 0 .. 30 {
+        let name = name.to_string();
+        let current = ms
+            .spawn(async move {
+                Lvs::lookup(&name)
+                    .map(|l| l.health())
+                    .or_else(|| {
+                        Lvs::gone().into_iter().find(|h| h.name == name)
+                    })
+                    .map(|h| h.state)
+            })
+            .await;
+        if current == Some(state) {
+            return;
+        }
+        tokio::time::delay_for(Duration::from_millis(500)).await;
+    }
+    panic!("pool {} did not become {:?}", name, state);
+}
+
+#[tokio::test]
+async fn lvs_pool_monitor() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    ms.spawn(async {
+        let pool = Lvs::create_or_import(CreatePoolRequest {
+            name: "mpool".into(),
+            disks: vec![DISK.into()],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        assert_eq!(pool.state(), LvsState::Online);
+        assert_eq!(pool.disks()[0].state, DiskState::Online);
+
+        // use all space of the pool
+        pool.create_lvol("mvol", pool.available(), false)
+            .await
+            .unwrap();
+    })
+    .await;
+
+    // the pool is degraded while it is running out of space
+    wait_for_state(&ms, "mpool", LvsState::Degraded).await;
+    ms.spawn(async {
+        let health = Lvs::lookup("mpool").unwrap().health();
+        assert!(health.reason.contains("free space"));
+
+        let lvol = Lvs::lookup("mpool").unwrap().lvols().unwrap().next();
+        lvol.unwrap().destroy().await.unwrap();
+    })
+    .await;
+    wait_for_state(&ms, "mpool", LvsState::Online).await;
+
+    // removing the disk unloads the pool, which is then faulted
+    ms.spawn(async {
+        bdev_destroy(DISK).await.unwrap();
+    })
+    .await;
+    wait_for_state(&ms, "mpool", LvsState::Faulted).await;
+    ms.spawn(async {
+        assert!(Lvs::lookup("mpool").is_none());
+        let gone = Lvs::gone();
+        assert_eq!(gone.len(), 1);
+        assert_eq!(gone[0].disks.len(), 1);
+        assert_eq!(gone[0].disks[0].state, DiskState::Missing);
+
+        // forgetting the pool removes it for good
+        Lvs::forget("mpool");
+        assert!(Lvs::gone().is_empty());
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn lvs_pool_monitor_io_errors() {
+    common::delete_file(&[ERROR_DISK.into()]);
+    common::truncate_file(ERROR_DISK, 64 * 1024);
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    ms.spawn(async {
+        create_error_bdev(ERROR_DEVICE, ERROR_DISK);
+        let pool = Lvs::create_or_import(CreatePoolRequest {
+            name: "epool".into(),
+            disks: vec![format!("bdev:///{}", EE_ERROR_DEVICE)],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        pool.create_lvol("evol", 8 * 1024 * 1024, false)
+            .await
+            .unwrap();
+    })
+    .await;
+
+    // a write of the pool which fails on its disk degrades the pool
+    ms.spawn(async {
+        inject_error(
+            EE_ERROR_DEVICE,
+            SPDK_BDEV_IO_TYPE_WRITE,
+            VBDEV_IO_FAILURE,
+            1,
+        );
+        let lvol = Lvs::lookup("epool").unwrap().lvols().unwrap().next();
+        let hdl = Bdev::from(lvol.unwrap())
+            .open(true)
+            .unwrap()
+            .into_handle()
+            .unwrap();
+        let mut buf = hdl.dma_malloc(4096).unwrap();
+        buf.fill(0xff);
+        assert!(hdl.write_at(0, &buf).await.is_err());
+    })
+    .await;
+    wait_for_state(&ms, "epool", LvsState::Degraded).await;
+    ms.spawn(async {
+        let health = Lvs::lookup("epool").unwrap().health();
+        assert!(health.reason.contains("I/O errors"));
+    })
+    .await;
+
+    // and it is online again once its I/O succeeds
+    wait_for_state(&ms, "epool", LvsState::Online).await;
+    ms.spawn(async {
+        Lvs::lookup("epool").unwrap().export().await.unwrap();
+    })
+    .await;
+
+    common::delete_file(&[ERROR_DISK.into()]);
+}
@@ -0,0 +1,130 @@
+use common::MayastorTest;
+use mayastor::{
+    core::MayastorCliArgs,
+    lvs::{ClearMethod, Lvs, WipeMethod},
+};
+use rpc::mayastor::{CreatePoolRequest, PoolClearMethod, ReplicaWipeMethod};
+
+pub mod common;
+
+static DISKNAME: &str = "/tmp/lvs_pool_options.img";
+const MB: u32 = 1024 * 1024;
+
+fn pool_args(
+    cluster_size: u32,
+    metadata_ratio: u32,
+    clear_method: PoolClearMethod,
+) -> CreatePoolRequest {
+    CreatePoolRequest {
+        name: "opool".into(),
+        disks: vec![format!("aio://{}", DISKNAME)],
+        cluster_size,
+        metadata_ratio,
+        clear_method: clear_method as i32,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn lvs_pool_options() {
+    common::delete_file(&[DISKNAME.to_string()]);
+    common::truncate_file(DISKNAME, 64 * 1024);
+
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    ms.spawn(async {
+        // invalid options are rejected
+        let default = PoolClearMethod::PoolClearDefault;
+        assert!(Lvs::create_or_import(pool_args(3 * MB, 0, default))
+            .await
+            .is_err());
+        assert!(Lvs::create_or_import(pool_args(0, 101, default))
+            .await
+            .is_err());
+        assert!(Lvs::create_or_import(CreatePoolRequest {
+            clear_method: 42,
+            ..pool_args(0, 0, default)
+        })
+        .await
+        .is_err());
+
+        let args = CreatePoolRequest {
+            overcommit_limit: Some(150),
+            wipe_method: ReplicaWipeMethod::WipeWriteZeroes as i32,
+            ..pool_args(MB, 10, PoolClearMethod::PoolClearWriteZeroes)
+        };
+        let pool = Lvs::create_or_import(args.clone()).await.unwrap();
+        assert_eq!(pool.cluster_size(), u64::from(MB));
+        assert_eq!(pool.metadata_ratio(), 10);
+        assert_eq!(pool.clear_method(), ClearMethod::WriteZeroes);
+        assert_eq!(pool.overcommit_limit(), 150);
+        assert_eq!(pool.wipe_method(), WipeMethod::WriteZeroes);
+
+        // the options of a loaded pool can not be changed
+        assert!(Lvs::create_or_import(pool_args(2 * MB, 0, default))
+            .await
+            .is_err());
+        Lvs::create_or_import(args).await.unwrap();
+
+        // the settings of a pool are kept unless they are given
+        Lvs::create_or_import(pool_args(0, 0, default))
+            .await
+            .unwrap();
+        assert_eq!(pool.overcommit_limit(), 150);
+        assert_eq!(pool.wipe_method(), WipeMethod::WriteZeroes);
+        Lvs::create_or_import(CreatePoolRequest {
+            overcommit_limit: Some(0),
+            ..pool_args(0, 0, default)
+        })
+        .await
+        .unwrap();
+        assert_eq!(pool.overcommit_limit(), 0);
+        Lvs::create_or_import(CreatePoolRequest {
+            overcommit_limit: Some(200),
+            ..pool_args(0, 0, default)
+        })
+        .await
+        .unwrap();
+        assert_eq!(pool.overcommit_limit(), 200);
+
+        pool.export().await.unwrap();
+    })
+    .await;
+
+    ms.spawn(async {
+        // an import which contradicts the options on disk fails and leaves
+        // the pool exported
+        let default = PoolClearMethod::PoolClearDefault;
+        for args in vec![
+            pool_args(4 * MB, 0, default),
+            pool_args(0, 100, default),
+            pool_args(0, 0, PoolClearMethod::PoolClearUnmap),
+        ] {
+            assert!(Lvs::import_from_disks(args).await.is_err());
+            assert!(Lvs::lookup("opool").is_none());
+        }
+
+        // the options are read from the disk
+        let pool = Lvs::import_from_disks(pool_args(0, 0, default))
+            .await
+            .unwrap();
+        assert_eq!(pool.cluster_size(), u64::from(MB));
+        assert_eq!(pool.metadata_ratio(), 10);
+        assert_eq!(pool.clear_method(), ClearMethod::WriteZeroes);
+        assert_eq!(pool.overcommit_limit(), 200);
+        assert_eq!(pool.wipe_method(), WipeMethod::WriteZeroes);
+        pool.export().await.unwrap();
+
+        let pool = Lvs::import_from_disks(pool_args(
+            MB,
+            10,
+            PoolClearMethod::PoolClearWriteZeroes,
+        ))
+        .await
+        .unwrap();
+        pool.destroy().await.unwrap();
+    })
+    .await;
+
+    common::delete_file(&[DISKNAME.to_string()]);
+}
@@ -30,6 +30,7 @@ async fn lvs_pool_rpc() {
         .create_pool(CreatePoolRequest {
             name: "tpool".to_string(),
             disks: vec!["malloc:///disk0?size_mb=64".into()],
+            ..Default::default()
         })
         .await
         .unwrap();
@@ -38,6 +39,7 @@ async fn lvs_pool_rpc() {
         .create_pool(CreatePoolRequest {
             name: "tpool".to_string(),
             disks: vec!["malloc:///disk0?size_mb=64".into()],
+            ..Default::default()
         })
         .await
         .unwrap();
@@ -54,6 +56,7 @@ async fn lvs_pool_rpc() {
             size: 4 * 1024,
             thin: false,
             share: 0,
+            ..Default::default()
         })
         .await
         .unwrap();
@@ -66,6 +69,7 @@ async fn lvs_pool_rpc() {
             size: 4 * 1024,
             thin: false,
             share: 0,
+            ..Default::default()
         })
         .await
         .unwrap();
@@ -127,6 +131,7 @@ async fn lvs_pool_rpc() {
     gdl.mayastor
         .destroy_replica(DestroyReplicaRequest {
             uuid: "cdc2a7db-3ac3-403a-af80-7fadc1581c47".to_string(),
+            wipe: 0,
         })
         .await
         .unwrap();
@@ -0,0 +1,147 @@
+use common::MayastorTest;
+use mayastor::{
+    core::{Bdev, MayastorCliArgs},
+    lvs::{DiskState, Lvs, LvsState},
+};
+use rpc::mayastor::CreatePoolRequest;
+
+pub mod common;
+
+static DISKS: [&str; 3] = [
+    "/tmp/striped1.img",
+    "/tmp/striped2.img",
+    "/tmp/striped3.img",
+];
+
+fn disk_uris() -> Vec<String> {
+    DISKS.iter().map(|d| format!("aio://{}", d)).collect()
+}
+
+fn pool_args(disks: Vec<String>) -> CreatePoolRequest {
+    CreatePoolRequest {
+        name: "spool".into(),
+        disks,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn lvs_pool_striped() {
+    let disks = DISKS.iter().map(|d| d.to_string()).collect::<Vec<_>>();
+    common::delete_file(&disks);
+    for disk in DISKS.iter() {
+        common::truncate_file(disk, 64 * 1024);
+    }
+
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    // create a pool striped over all disks
+    ms.spawn(async {
+        let pool = Lvs::create_or_import(pool_args(disk_uris())).await.unwrap();
+
+        // the pool spans the disks, minus the space for its metadata
+        assert!(pool.capacity() > 2 * 64 * 1024 * 1024);
+        assert_eq!(pool.state(), LvsState::Online);
+
+        let disks = pool.disks();
+        assert_eq!(disks.len(), 3);
+        for (disk, name) in disks.iter().zip(DISKS.iter()) {
+            assert!(disk.uri.contains(name));
+            assert_eq!(disk.state, DiskState::Online);
+        }
+
+        for i in 0 .. 4 {
+            pool.create_lvol(&format!("svol-{}", i), 8 * 1024 * 1024, false)
+                .await
+                .unwrap();
+        }
+    })
+    .await;
+
+    // creating the pool again with the same disks is OK, with the disks in
+    // another order it is not
+    ms.spawn(async {
+        assert!(Lvs::create_or_import(pool_args(disk_uris())).await.is_ok());
+
+        let mut disks = disk_uris();
+        disks.reverse();
+        assert!(Lvs::create_or_import(pool_args(disks)).await.is_err());
+    })
+    .await;
+
+    // export the pool, which releases all disks, and import it again
+    ms.spawn(async {
+        let pool = Lvs::lookup("spool").unwrap();
+        let uuid = pool.uuid();
+        pool.export().await.unwrap();
+
+        assert_eq!(Lvs::iter().count(), 0);
+        for disk in DISKS.iter() {
+            assert!(Bdev::lookup_by_name(disk).is_none());
+        }
+
+        let pool = Lvs::create_or_import(pool_args(disk_uris())).await.unwrap();
+        assert_eq!(pool.uuid(), uuid);
+        assert_eq!(pool.lvols().unwrap().count(), 4);
+    })
+    .await;
+
+    // the disks are labelled with their position in the stripe, so the pool
+    // is imported whichever order they are given in
+    ms.spawn(async {
+        let pool = Lvs::lookup("spool").unwrap();
+        let uuid = pool.uuid();
+        pool.export().await.unwrap();
+
+        let mut disks = disk_uris();
+        disks.reverse();
+        let pool = Lvs::create_or_import(pool_args(disks)).await.unwrap();
+        assert_eq!(pool.uuid(), uuid);
+        assert_eq!(pool.lvols().unwrap().count(), 4);
+        for (disk, name) in pool.disks().iter().zip(DISKS.iter()) {
+            assert!(disk.uri.contains(name));
+        }
+    })
+    .await;
+
+    // a pool is never created over disks which hold one, be it some of the
+    // disks of a striped pool or a single one of them
+    ms.spawn(async {
+        Lvs::lookup("spool").unwrap().export().await.unwrap();
+
+        let disks = disk_uris();
+        assert!(Lvs::create_or_import(pool_args(disks[.. 2].to_vec()))
+            .await
+            .is_err());
+        assert!(Lvs::create_or_import(pool_args(disks[1 .. 2].to_vec()))
+            .await
+            .is_err());
+        assert_eq!(Lvs::iter().count(), 0);
+        for disk in DISKS.iter() {
+            assert!(Bdev::lookup_by_name(disk).is_none());
+        }
+
+        let pool = Lvs::create_or_import(pool_args(disk_uris())).await.unwrap();
+        assert_eq!(pool.lvols().unwrap().count(), 4);
+    })
+    .await;
+
+    // destroying the pool releases all disks and erases their labels, so a
+    // new pool can be created on them in another order
+    ms.spawn(async {
+        Lvs::lookup("spool").unwrap().destroy().await.unwrap();
+        assert_eq!(Lvs::iter().count(), 0);
+        for disk in DISKS.iter() {
+            assert!(Bdev::lookup_by_name(disk).is_none());
+        }
+
+        let mut disks = disk_uris();
+        disks.reverse();
+        let pool = Lvs::create_or_import(pool_args(disks)).await.unwrap();
+        assert_eq!(pool.lvols().unwrap().count(), 0);
+        pool.destroy().await.unwrap();
+    })
+    .await;
+
+    common::delete_file(&disks);
+}
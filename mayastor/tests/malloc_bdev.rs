@@ -1,6 +1,6 @@
 use common::MayastorTest;
 use mayastor::{
-    core::{Bdev, DmaBuf, MayastorCliArgs},
+    core::{Bdev, DmaBuf, MayastorCliArgs, MAX_CHECKSUM_SIZE},
     nexus_uri::{bdev_create, bdev_destroy},
 };
 
@@ -62,6 +62,20 @@ async fn malloc_bdev() {
             assert_eq!(s0[i], 3);
             assert_eq!(s0[i], s1[i])
         }
+
+        // equal blocks have equal checksums, which are limited in size and to
+        // the blocks of the bdev
+        assert_eq!(
+            h0.checksum(0, 8).await.unwrap(),
+            h1.checksum(0, 8).await.unwrap()
+        );
+        assert_ne!(
+            h0.checksum(0, 8).await.unwrap(),
+            h0.checksum(8, 8).await.unwrap()
+        );
+        assert!(h0.checksum(0, (MAX_CHECKSUM_SIZE / 512) + 1).await.is_err());
+        let num_blocks = h0.get_bdev().num_blocks();
+        assert!(h0.checksum(num_blocks - 1, 2).await.is_err());
     })
     .await;
 
@@ -43,7 +43,7 @@ async fn create_connected_nvmf_nexus(
             create_nexus().await;
             let nexus = nexus_lookup("nexus").unwrap();
             nexus
-                .share(ShareProtocolNexus::NexusNvmf, None)
+                .share(ShareProtocolNexus::NexusNvmf, None, false)
                 .await
                 .unwrap()
         })
@@ -91,7 +91,7 @@ async fn mount_test(ms: &'static MayastorTest<'static>, fstype: &str) {
             .spawn(async move {
                 let nexus = nexus_lookup(n).unwrap();
                 nexus
-                    .share(ShareProtocolNexus::NexusNvmf, None)
+                    .share(ShareProtocolNexus::NexusNvmf, None, false)
                     .await
                     .unwrap()
             })
@@ -0,0 +1,167 @@
+use mayastor::{
+    bdev::{nexus_create_with_block_size, nexus_lookup},
+    core::{Bdev, MayastorCliArgs},
+};
+
+pub mod common;
+
+static NEXUS_NAME: &str = "BlockSizeNexus";
+static NEXUS_SIZE: u64 = 10 * 1024 * 1024;
+static CHILD_1: &str = "malloc:///malloc0?blk_size=4096&size_mb=12";
+static CHILD_2: &str = "malloc:///malloc1?blk_size=4096&size_mb=12";
+static CHILD_3: &str = "malloc:///malloc2?blk_size=512&size_mb=12";
+
+#[tokio::test]
+async fn nexus_block_size() {
+    let ms = common::MayastorTest::new(MayastorCliArgs::default());
+    ms.spawn(async {
+        // only power of two block sizes between 512 and 4096 are allowed
+        for blk_size in &[0, 520, 8192] {
+            assert!(nexus_create_with_block_size(
+                NEXUS_NAME,
+                NEXUS_SIZE,
+                None,
+                Some(*blk_size),
+                &[CHILD_1.to_string()],
+            )
+            .await
+            .is_err());
+        }
+
+        nexus_create_with_block_size(
+            NEXUS_NAME,
+            NEXUS_SIZE,
+            None,
+            Some(512),
+            &[CHILD_1.to_string(), CHILD_2.to_string()],
+        )
+        .await
+        .unwrap();
+
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        assert!(nexus.is_emulated());
+
+        let d = Bdev::lookup_by_name(NEXUS_NAME)
+            .unwrap()
+            .open(true)
+            .unwrap()
+            .into_handle()
+            .unwrap();
+        assert_eq!(d.get_bdev().block_len(), 512);
+        assert_eq!(d.get_bdev().size_in_bytes() % 512, 0);
+
+        // write a single 512 byte block in the middle of a child block
+        let mut buf = d.dma_malloc(512).unwrap();
+        buf.fill(0xaa);
+        d.write_at(1024, &buf).await.unwrap();
+
+        // which must leave the rest of the child block untouched
+        let mut buf = d.dma_malloc(4096).unwrap();
+        d.read_at(0, &mut buf).await.unwrap();
+        for (i, b) in buf.as_slice().iter().enumerate() {
+            let expected = if (1024 .. 1536).contains(&i) { 0xaa } else { 0 };
+            assert_eq!(*b, expected, "mismatch at offset {}", i);
+        }
+
+        // an unaligned read across child blocks
+        let mut buf = d.dma_malloc(1024).unwrap();
+        d.read_at(3584, &mut buf).await.unwrap();
+        assert!(buf.as_slice().iter().all(|b| *b == 0));
+        d.read_at(512, &mut buf).await.unwrap();
+        assert!(buf.as_slice()[.. 512].iter().all(|b| *b == 0));
+        assert!(buf.as_slice()[512 ..].iter().all(|b| *b == 0xaa));
+
+        nexus.destroy().await.unwrap();
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn nexus_mixed_block_size() {
+    let ms = common::MayastorTest::new(MayastorCliArgs::default());
+    ms.spawn(async {
+        // children with 512 and 4096 byte blocks share a layout in units of
+        // the largest block size, which the nexus inherits
+        nexus_create_with_block_size(
+            NEXUS_NAME,
+            NEXUS_SIZE,
+            None,
+            None,
+            &[CHILD_1.to_string(), CHILD_3.to_string()],
+        )
+        .await
+        .unwrap();
+
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        assert!(!nexus.is_emulated());
+        let data_offset = nexus.data_ent_offset * 4096;
+
+        let d = Bdev::lookup_by_name(NEXUS_NAME)
+            .unwrap()
+            .open(true)
+            .unwrap()
+            .into_handle()
+            .unwrap();
+        assert_eq!(d.get_bdev().block_len(), 4096);
+
+        let mut buf = d.dma_malloc(8192).unwrap();
+        buf.fill(0x55);
+        d.write_at(4096, &buf).await.unwrap();
+
+        // the data lands at the same byte offsets on both children
+        for child in &["malloc0", "malloc2"] {
+            let h = Bdev::lookup_by_name(child)
+                .unwrap()
+                .open(false)
+                .unwrap()
+                .into_handle()
+                .unwrap();
+            let mut buf = h.dma_malloc(16384).unwrap();
+            h.read_at(data_offset, &mut buf).await.unwrap();
+            for (i, b) in buf.as_slice().iter().enumerate() {
+                let expected = if (4096 .. 12288).contains(&i) {
+                    0x55
+                } else {
+                    0
+                };
+                assert_eq!(*b, expected, "{}: mismatch at offset {}", child, i);
+            }
+        }
+
+        nexus.destroy().await.unwrap();
+
+        // and 512 byte blocks are emulated on top of the 4096 byte child
+        nexus_create_with_block_size(
+            NEXUS_NAME,
+            NEXUS_SIZE,
+            None,
+            Some(512),
+            &[CHILD_1.to_string(), CHILD_3.to_string()],
+        )
+        .await
+        .unwrap();
+
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        assert!(nexus.is_emulated());
+
+        let d = Bdev::lookup_by_name(NEXUS_NAME)
+            .unwrap()
+            .open(true)
+            .unwrap()
+            .into_handle()
+            .unwrap();
+        let mut buf = d.dma_malloc(512).unwrap();
+        buf.fill(0xaa);
+        d.write_at(4608, &buf).await.unwrap();
+
+        let mut buf = d.dma_malloc(4096).unwrap();
+        d.read_at(4096, &mut buf).await.unwrap();
+        for (i, b) in buf.as_slice().iter().enumerate() {
+            let expected = if (512 .. 1024).contains(&i) { 0xaa } else { 0 };
+            assert_eq!(*b, expected, "mismatch at offset {}", i);
+        }
+
+        nexus.destroy().await.unwrap();
+    })
+    .await;
+}
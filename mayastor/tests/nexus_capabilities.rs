@@ -0,0 +1,51 @@
+use mayastor::{
+    bdev::{nexus_create, nexus_lookup},
+    core::MayastorCliArgs,
+};
+
+pub mod common;
+
+static NEXUS_NAME: &str = "CapabilitiesNexus";
+static NEXUS_SIZE: u64 = 10 * 1024 * 1024;
+
+static MALLOC_CHILD: &str = "malloc:///malloc0?blk_size=512&size_mb=12";
+
+static DISKNAME: &str = "/tmp/capabilities.img";
+static AIO_CHILD: &str = "aio:///tmp/capabilities.img?blk_size=512";
+
+#[tokio::test]
+async fn nexus_capabilities() {
+    common::delete_file(&[DISKNAME.into()]);
+    common::truncate_file(DISKNAME, 64 * 1024);
+
+    let ms = common::MayastorTest::new(MayastorCliArgs::default());
+    ms.spawn(async {
+        nexus_create(NEXUS_NAME, NEXUS_SIZE, None, &[MALLOC_CHILD.to_string()])
+            .await
+            .unwrap();
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+
+        let caps = nexus.to_grpc().capabilities.unwrap();
+        assert!(caps.unmap);
+        assert!(caps.write_zeroes);
+        assert!(!caps.write_zeroes_emulated);
+
+        // aio lacks support for unmap and write zeroes, the child takes part
+        // in the IO path as soon as it is being rebuilt
+        nexus.add_child(AIO_CHILD, false).await.unwrap();
+        let caps = nexus.to_grpc().capabilities.unwrap();
+        assert!(!caps.unmap);
+        assert!(caps.write_zeroes);
+        assert!(caps.write_zeroes_emulated);
+
+        nexus.remove_child(AIO_CHILD).await.unwrap();
+        let caps = nexus.to_grpc().capabilities.unwrap();
+        assert!(caps.unmap);
+        assert!(!caps.write_zeroes_emulated);
+
+        nexus.destroy().await.unwrap();
+    })
+    .await;
+
+    common::delete_file(&[DISKNAME.into()]);
+}
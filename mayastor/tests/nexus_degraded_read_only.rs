@@ -0,0 +1,60 @@
+use mayastor::{
+    bdev::{nexus_create, nexus_lookup, Reason},
+    core::MayastorCliArgs,
+    subsys::{Config, NexusOpts},
+};
+
+pub mod common;
+
+static NEXUS_NAME: &str = "DegradedNexus";
+static NEXUS_SIZE: u64 = 10 * 1024 * 1024;
+static CHILD_1: &str = "malloc:///malloc0?blk_size=512&size_mb=10";
+static CHILD_2: &str = "malloc:///malloc1?blk_size=512&size_mb=10";
+static CHILD_3: &str = "malloc:///malloc2?blk_size=512&size_mb=10";
+
+#[tokio::test]
+async fn nexus_degraded_read_only() {
+    Config::get_or_init(|| Config {
+        nexus_opts: NexusOpts {
+            degraded_read_only: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+    .apply();
+    let ms = common::MayastorTest::new(MayastorCliArgs::default());
+    ms.spawn(async {
+        // a nexus which never had write redundancy stays writable when a
+        // child is added, and while that child is out of sync
+        nexus_create(NEXUS_NAME, NEXUS_SIZE, None, &[CHILD_1.to_string()])
+            .await
+            .unwrap();
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        nexus.add_child(CHILD_2, true).await.unwrap();
+        assert!(!nexus.is_read_only());
+        nexus.destroy().await.unwrap();
+
+        // losing one of two healthy children makes it read-only
+        nexus_create(
+            NEXUS_NAME,
+            NEXUS_SIZE,
+            None,
+            &[CHILD_1.to_string(), CHILD_2.to_string()],
+        )
+        .await
+        .unwrap();
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        assert!(!nexus.is_read_only());
+        nexus.fault_child(CHILD_2, Reason::Rpc).await.unwrap();
+        assert!(nexus.is_read_only());
+
+        // until the lost child is removed on purpose
+        nexus.remove_child(CHILD_2).await.unwrap();
+        assert!(!nexus.is_read_only());
+        nexus.add_child(CHILD_3, true).await.unwrap();
+        assert!(!nexus.is_read_only());
+
+        nexus.destroy().await.unwrap();
+    })
+    .await;
+}
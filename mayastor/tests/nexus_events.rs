@@ -0,0 +1,63 @@
+use mayastor::{
+    bdev::{nexus_create, nexus_lookup, EventOrigin, NexusEventType, Reason},
+    core::MayastorCliArgs,
+};
+
+pub mod common;
+
+static NEXUS_NAME: &str = "NexusEventsNexus";
+static NEXUS_SIZE: u64 = 10 * 1024 * 1024;
+static CHILD_1: &str = "malloc:///malloc0?blk_size=512&size_mb=10";
+static CHILD_2: &str = "malloc:///malloc1?blk_size=512&size_mb=10";
+
+#[tokio::test]
+async fn nexus_events() {
+    let ms = common::MayastorTest::new(MayastorCliArgs::default());
+    ms.spawn(async {
+        nexus_create(NEXUS_NAME, NEXUS_SIZE, None, &[CHILD_1.to_string()])
+            .await
+            .unwrap();
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        nexus.add_child(CHILD_2, true).await.unwrap();
+        let request = nexus.record_request("FaultNexusChild", Some(CHILD_2));
+        nexus.fault_child(CHILD_2, Reason::Rpc).await.unwrap();
+        let id = request.id();
+        drop(request);
+
+        let events = nexus.events();
+        assert_eq!(events[0].event, NexusEventType::Created);
+
+        let added = events
+            .iter()
+            .find(|e| e.event == NexusEventType::ChildAdded)
+            .unwrap();
+        assert_eq!(added.child.as_deref(), Some(CHILD_2));
+
+        // the request must precede the fault it has caused, which is
+        // attributed to it
+        let origin = EventOrigin::Rpc("FaultNexusChild".to_string());
+        let request = events
+            .iter()
+            .position(|e| e.event == NexusEventType::Request)
+            .unwrap();
+        assert_eq!(events[request].origin, origin);
+        assert_eq!(events[request].request, Some(id));
+        let faulted = events
+            .iter()
+            .position(|e| e.event == NexusEventType::ChildFaulted)
+            .unwrap();
+        assert!(request < faulted);
+        assert_eq!(events[faulted].child.as_deref(), Some(CHILD_2));
+        assert_eq!(events[faulted].origin, origin);
+        assert_eq!(events[faulted].request, Some(id));
+
+        // the events of the nexus are no longer attributed to the request
+        // once it has completed
+        nexus.remove_child(CHILD_2).await.unwrap();
+        let events = nexus.events();
+        assert!(events[faulted + 1 ..].iter().all(|e| e.request.is_none()));
+
+        nexus.destroy().await.unwrap();
+    })
+    .await;
+}
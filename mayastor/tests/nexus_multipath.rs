@@ -41,6 +41,7 @@ async fn nexus_multipath() {
         .create_pool(CreatePoolRequest {
             name: POOL_NAME.to_string(),
             disks: vec!["malloc:///disk0?size_mb=64".into()],
+            ..Default::default()
         })
         .await
         .unwrap();
@@ -54,6 +55,7 @@ async fn nexus_multipath() {
             size: 32 * 1024 * 1024,
             thin: false,
             share: 0,
+            ..Default::default()
         })
         .await
         .unwrap();
@@ -65,6 +67,8 @@ async fn nexus_multipath() {
             uuid: UUID.to_string(),
             size: 32 * 1024 * 1024,
             children: [format!("loopback:///{}", UUID)].to_vec(),
+            block_size: 0,
+            owner_generation: 0,
         })
         .await
         .unwrap();
@@ -96,7 +100,7 @@ async fn nexus_multipath() {
             // publish nexus on local node over nvmf
             nexus_lookup(&nexus_name)
                 .unwrap()
-                .share(ShareProtocolNexus::NexusNvmf, None)
+                .share(ShareProtocolNexus::NexusNvmf, None, false)
                 .await
                 .unwrap();
         })
@@ -109,6 +113,7 @@ async fn nexus_multipath() {
             uuid: UUID.to_string(),
             key: "".to_string(),
             share: ShareProtocolNexus::NexusNvmf as i32,
+            read_only: false,
         })
         .await
         .unwrap();
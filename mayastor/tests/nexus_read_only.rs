@@ -0,0 +1,66 @@
+use mayastor::{
+    bdev::{nexus_create, nexus_lookup},
+    core::{Bdev, MayastorCliArgs},
+};
+use rpc::mayastor::ShareProtocolNexus;
+
+pub mod common;
+
+static NEXUS_NAME: &str = "ReadOnlyNexus";
+static NEXUS_SIZE: u64 = 10 * 1024 * 1024;
+static CHILD_1: &str = "malloc:///malloc0?blk_size=512&size_mb=10";
+
+async fn write_nexus() -> bool {
+    let d = Bdev::lookup_by_name(NEXUS_NAME)
+        .expect("failed to lookup nexus")
+        .open(true)
+        .expect("failed open bdev")
+        .into_handle()
+        .unwrap();
+    let buf = d.dma_malloc(512).expect("failed to allocate buffer");
+    d.write_at(0, &buf).await.is_ok()
+}
+
+#[tokio::test]
+async fn nexus_read_only() {
+    let ms = common::MayastorTest::new(MayastorCliArgs::default());
+    ms.spawn(async {
+        nexus_create(NEXUS_NAME, NEXUS_SIZE, None, &[CHILD_1.to_string()])
+            .await
+            .unwrap();
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        assert!(!nexus.is_read_only());
+        assert!(write_nexus().await);
+
+        nexus
+            .share(ShareProtocolNexus::NexusNvmf, None, true)
+            .await
+            .unwrap();
+        assert!(nexus.is_read_only());
+        assert!(!write_nexus().await);
+
+        // sharing again with a different access mode is not allowed
+        assert!(nexus
+            .share(ShareProtocolNexus::NexusNvmf, None, false)
+            .await
+            .is_err());
+
+        nexus.unshare_nexus().await.unwrap();
+        assert!(!nexus.is_read_only());
+        assert!(write_nexus().await);
+
+        nexus
+            .share(ShareProtocolNexus::NexusIscsi, None, true)
+            .await
+            .unwrap();
+        assert!(nexus.is_read_only());
+        assert!(!write_nexus().await);
+
+        nexus.unshare_nexus().await.unwrap();
+        assert!(!nexus.is_read_only());
+        assert!(write_nexus().await);
+
+        nexus.destroy().await.unwrap();
+    })
+    .await;
+}
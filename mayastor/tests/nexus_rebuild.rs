@@ -5,12 +5,26 @@ use once_cell::sync::Lazy;
 use tracing::error;
 
 use mayastor::{
-    bdev::nexus_lookup,
+    bdev::{nexus_lookup, ChildState},
     core::{MayastorCliArgs, MayastorEnvironment, Mthread, Reactor},
-    rebuild::RebuildJob,
+    rebuild::{
+        ClientOperations,
+        RebuildCheckpoint,
+        RebuildJob,
+        RebuildLimits,
+        RebuildPriority,
+        RebuildState,
+    },
 };
 use rpc::mayastor::ShareProtocolNexus;
 
+use common::error_bdev::{
+    create_error_bdev,
+    inject_error,
+    SPDK_BDEV_IO_TYPE_READ,
+    VBDEV_IO_FAILURE,
+};
+
 pub mod common;
 
 // each test `should` use a different nexus name to prevent clashing with
@@ -99,7 +113,7 @@ async fn nexus_share() -> String {
     let nexus = nexus_lookup(nexus_name()).unwrap();
     let device = common::device_path_from_uri(
         nexus
-            .share(ShareProtocolNexus::NexusNbd, None)
+            .share(ShareProtocolNexus::NexusNbd, None, false)
             .await
             .unwrap(),
     );
@@ -176,3 +190,314 @@ fn rebuild_lookup() {
 
     test_fini();
 }
+
+#[test]
+fn rebuild_throttle() {
+    test_ini("rebuild_throttle");
+
+    Reactor::block_on(async move {
+        let children = 2;
+        nexus_create(NEXUS_SIZE, children, false).await;
+        let nexus = nexus_lookup(nexus_name()).unwrap();
+        nexus.add_child(&get_dev(children), true).await.unwrap();
+
+        let node_limits = RebuildLimits {
+            bandwidth: 1024 * 1024,
+            segments: 0,
+        };
+        RebuildJob::set_node_limits(node_limits);
+        assert_eq!(RebuildJob::node_limits(), node_limits);
+
+        let complete = nexus.start_rebuild(&get_dev(children)).await.unwrap();
+        let limits = RebuildLimits {
+            bandwidth: 0,
+            segments: 1,
+        };
+        nexus
+            .set_rebuild_limits(&get_dev(children), limits)
+            .await
+            .unwrap();
+        nexus
+            .set_rebuild_priority(&get_dev(children), RebuildPriority::Low)
+            .await
+            .unwrap();
+
+        let stats = RebuildJob::lookup(&get_dev(children))
+            .unwrap()
+            .as_client()
+            .stats();
+        assert_eq!(stats.limits, limits);
+        assert_eq!(stats.priority, RebuildPriority::Low);
+        assert!(stats.progress < 100);
+
+        // lifting the limits lets the running rebuild complete
+        RebuildJob::set_node_limits(RebuildLimits::default());
+        nexus
+            .set_rebuild_limits(&get_dev(children), RebuildLimits::default())
+            .await
+            .unwrap();
+        assert_eq!(complete.await.unwrap(), RebuildState::Completed);
+
+        nexus_lookup(nexus_name()).unwrap().destroy().await.unwrap();
+    });
+
+    test_fini();
+}
+
+#[test]
+fn rebuild_urgent() {
+    test_ini("rebuild_urgent");
+
+    Reactor::block_on(async move {
+        nexus_create(NEXUS_SIZE, 1, false).await;
+        let nexus = nexus_lookup(nexus_name()).unwrap();
+        nexus.add_child(&get_dev(1), true).await.unwrap();
+
+        // the only healthy child is the rebuild source
+        let _ = nexus.start_rebuild(&get_dev(1)).await.unwrap();
+        assert_eq!(
+            RebuildJob::lookup(&get_dev(1)).unwrap().priority(),
+            RebuildPriority::Urgent
+        );
+
+        nexus.remove_child(&get_dev(1)).await.unwrap();
+        nexus_lookup(nexus_name()).unwrap().destroy().await.unwrap();
+    });
+
+    test_fini();
+}
+
+#[test]
+fn rebuild_checkpoint() {
+    test_ini("rebuild_checkpoint");
+
+    Reactor::block_on(async move {
+        nexus_create(NEXUS_SIZE, 1, false).await;
+        let nexus = nexus_lookup(nexus_name()).unwrap();
+        nexus.add_child(&get_dev(1), true).await.unwrap();
+
+        let _ = nexus.start_rebuild(&get_dev(1)).await.unwrap();
+        nexus
+            .set_rebuild_priority(&get_dev(1), RebuildPriority::Low)
+            .await
+            .unwrap();
+        nexus
+            .set_rebuild_limits(
+                &get_dev(1),
+                RebuildLimits {
+                    bandwidth: 0,
+                    segments: 8,
+                },
+            )
+            .await
+            .unwrap();
+
+        while RebuildJob::lookup(&get_dev(1))
+            .unwrap()
+            .stats()
+            .blocks_recovered
+            == 0
+        {
+            reactor_poll!(100);
+        }
+
+        // pausing the rebuild checkpoints its progress
+        nexus.pause_rebuild(&get_dev(1)).await.unwrap();
+        while RebuildJob::lookup(&get_dev(1)).unwrap().state()
+            != RebuildState::Paused
+        {
+            reactor_poll!(100);
+        }
+
+        let hdl = nexus
+            .children
+            .iter()
+            .find(|c| c.name == get_dev(1))
+            .unwrap()
+            .handle()
+            .unwrap();
+        let lba = nexus.meta_ent_offset;
+        let checkpoint = RebuildCheckpoint::read(&hdl, lba)
+            .await
+            .unwrap()
+            .expect("the checkpoint should have been written");
+        assert_eq!(checkpoint.nexus, nexus_name());
+        assert_eq!(checkpoint.destination, get_dev(1));
+        assert!(checkpoint.next > checkpoint.start);
+
+        // the checkpoint is cleared once the job is done
+        nexus.stop_rebuild(&get_dev(1)).await.unwrap();
+        while RebuildJob::lookup(&get_dev(1)).is_ok() {
+            reactor_poll!(100);
+        }
+        assert_eq!(RebuildCheckpoint::read(&hdl, lba).await.unwrap(), None);
+
+        // a checkpoint written at another write generation of the nexus is
+        // not resumed from, as the source might have been written to by
+        // another nexus in between
+        let mut stale = checkpoint.clone();
+        stale.generation += 2;
+        stale.write(&hdl, lba).await.unwrap();
+        let _ = nexus.start_rebuild(&get_dev(1)).await.unwrap();
+        let stats = RebuildJob::lookup(&get_dev(1)).unwrap().stats();
+        assert!(stats.blocks_recovered < checkpoint.next - checkpoint.start);
+        nexus.stop_rebuild(&get_dev(1)).await.unwrap();
+        while RebuildJob::lookup(&get_dev(1)).is_ok() {
+            reactor_poll!(100);
+        }
+
+        // a new job resumes from the checkpoint as long as the nexus has not
+        // been written to, as would be the case after a restart
+        checkpoint.write(&hdl, lba).await.unwrap();
+        let complete = nexus.start_rebuild(&get_dev(1)).await.unwrap();
+        let stats = RebuildJob::lookup(&get_dev(1)).unwrap().stats();
+        assert!(stats.blocks_recovered >= checkpoint.next - checkpoint.start);
+        assert_eq!(complete.await.unwrap(), RebuildState::Completed);
+
+        while RebuildJob::lookup(&get_dev(1)).is_ok() {
+            reactor_poll!(100);
+        }
+        assert_eq!(RebuildCheckpoint::read(&hdl, lba).await.unwrap(), None);
+
+        nexus_lookup(nexus_name()).unwrap().destroy().await.unwrap();
+    });
+
+    test_fini();
+}
+
+#[test]
+fn rebuild_source_failover() {
+    test_ini("rebuild_source_failover");
+
+    Reactor::block_on(async move {
+        // the first child, which is the preferred rebuild source, fails reads
+        let backing_disk = get_disk(0);
+        get_err_bdev().push(0);
+        create_error_bdev(&get_disk(0), &backing_disk);
+
+        nexus_create(NEXUS_SIZE, 2, false).await;
+        let nexus = nexus_lookup(nexus_name()).unwrap();
+        nexus.add_child(&get_dev(2), true).await.unwrap();
+
+        inject_error(
+            &format!("EE_{}", get_disk(0)),
+            SPDK_BDEV_IO_TYPE_READ,
+            VBDEV_IO_FAILURE,
+            100,
+        );
+
+        let complete = nexus.start_rebuild(&get_dev(2)).await.unwrap();
+        nexus
+            .set_rebuild_limits(
+                &get_dev(2),
+                RebuildLimits {
+                    bandwidth: 0,
+                    segments: 8,
+                },
+            )
+            .await
+            .unwrap();
+
+        while RebuildJob::lookup(&get_dev(2))
+            .unwrap()
+            .stats()
+            .source_switches
+            == 0
+        {
+            reactor_poll!(100);
+        }
+        let stats = RebuildJob::lookup(&get_dev(2)).unwrap().stats();
+        assert_eq!(stats.source, get_dev(1));
+        assert_eq!(stats.source_switches, 1);
+
+        // the rebuild completes from the remaining source
+        nexus
+            .set_rebuild_limits(&get_dev(2), RebuildLimits::default())
+            .await
+            .unwrap();
+        assert_eq!(complete.await.unwrap(), RebuildState::Completed);
+
+        nexus_lookup(nexus_name()).unwrap().destroy().await.unwrap();
+    });
+
+    test_fini();
+}
+
+#[test]
+fn rebuild_source_removed() {
+    test_ini("rebuild_source_removed");
+
+    Reactor::block_on(async move {
+        nexus_create(NEXUS_SIZE, 2, false).await;
+        let nexus = nexus_lookup(nexus_name()).unwrap();
+        nexus.add_child(&get_dev(2), true).await.unwrap();
+
+        let complete = nexus.start_rebuild(&get_dev(2)).await.unwrap();
+        nexus
+            .set_rebuild_limits(
+                &get_dev(2),
+                RebuildLimits {
+                    bandwidth: 0,
+                    segments: 8,
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            RebuildJob::lookup(&get_dev(2)).unwrap().stats().source,
+            get_dev(0)
+        );
+
+        // removing the source of the job switches it over to the other
+        // healthy child rather than restarting it
+        nexus.remove_child(&get_dev(0)).await.unwrap();
+        let stats = RebuildJob::lookup(&get_dev(2)).unwrap().stats();
+        assert_eq!(stats.source, get_dev(1));
+
+        nexus
+            .set_rebuild_limits(&get_dev(2), RebuildLimits::default())
+            .await
+            .unwrap();
+        assert_eq!(complete.await.unwrap(), RebuildState::Completed);
+
+        nexus_lookup(nexus_name()).unwrap().destroy().await.unwrap();
+    });
+
+    test_fini();
+}
+
+#[test]
+fn rebuild_shared() {
+    test_ini("rebuild_shared");
+
+    Reactor::block_on(async move {
+        nexus_create(NEXUS_SIZE, 1, false).await;
+        let nexus = nexus_lookup(nexus_name()).unwrap();
+        nexus.add_child(&get_dev(1), true).await.unwrap();
+        nexus.add_child(&get_dev(2), true).await.unwrap();
+
+        // both children are rebuilt by a single job
+        let complete = nexus
+            .start_shared_rebuild(&[get_dev(1), get_dev(2)])
+            .await
+            .unwrap();
+        assert_eq!(RebuildJob::count(), 1);
+        let job = RebuildJob::lookup(&get_dev(2)).unwrap();
+        assert_eq!(job.destination, get_dev(1));
+        assert_eq!(job.destinations(), vec![get_dev(1), get_dev(2)]);
+        assert_eq!(job.stats().destinations, vec![get_dev(1), get_dev(2)]);
+
+        assert_eq!(complete.await.unwrap(), RebuildState::Completed);
+        while RebuildJob::count() > 0 {
+            reactor_poll!(100);
+        }
+
+        for child in nexus.children.iter() {
+            assert_eq!(child.state(), ChildState::Open);
+        }
+
+        nexus_lookup(nexus_name()).unwrap().destroy().await.unwrap();
+    });
+
+    test_fini();
+}
@@ -14,13 +14,18 @@ use rpc::mayastor::{
     Null,
     PauseRebuildRequest,
     PublishNexusRequest,
+    RebuildLimits,
+    RebuildMode,
     RebuildProgressRequest,
     RebuildStateRequest,
+    RebuildStatsReply,
+    RebuildStatsRequest,
     RemoveChildNexusRequest,
     ResumeRebuildRequest,
     ShareProtocolNexus,
     StartRebuildRequest,
     StopRebuildRequest,
+    WatchRebuildRequest,
 };
 
 use std::time::Duration;
@@ -418,6 +423,8 @@ async fn rebuild_segment_sizes() {
                 uuid: NEXUS_UUID.into(),
                 size: nexus_size,
                 children: vec![child1],
+                block_size: 0,
+                owner_generation: 0,
             })
             .await
             .unwrap();
@@ -607,6 +614,175 @@ async fn rebuild_multiple() {
     }
 }
 
+/// Test that a checksum rebuild skips the segments which are in sync.
+#[tokio::test]
+async fn rebuild_checksum() {
+    let test = start_infrastructure("rebuild_checksum").await;
+    let (mut ms1, _, ms3) = setup_test(&test, 1).await;
+    let nexus_hdl = &mut ms1;
+    let child = &get_share_uri(&ms3);
+
+    // Both children are zeroed malloc bdevs, so none of the segments differ.
+    add_child(nexus_hdl, child, false).await;
+
+    // Slow the rebuild down to be able to look at it while it is running.
+    set_node_rebuild_limits(nexus_hdl, 100).await;
+    nexus_hdl
+        .mayastor
+        .start_rebuild(StartRebuildRequest {
+            uuid: NEXUS_UUID.into(),
+            uri: child.clone(),
+            shared_uris: Vec::new(),
+            mode: RebuildMode::RebuildChecksum as i32,
+            verify: false,
+        })
+        .await
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(500));
+
+    let stats = get_rebuild_stats(nexus_hdl, child).await;
+    assert_eq!(stats.mode, RebuildMode::RebuildChecksum as i32);
+    assert!(stats.segments_skipped > 0);
+
+    set_node_rebuild_limits(nexus_hdl, 0).await;
+    assert!(
+        wait_for_rebuild_completion(nexus_hdl, child, Duration::from_secs(20))
+            .await
+    );
+    check_nexus_state(nexus_hdl, NexusState::NexusOnline).await;
+}
+
+/// Test that a rebuilt child is verified before it comes online.
+#[tokio::test]
+async fn rebuild_verify() {
+    let test = start_infrastructure("rebuild_verify").await;
+    let (mut ms1, _, ms3) = setup_test(&test, 1).await;
+    let nexus_hdl = &mut ms1;
+    let child = &get_share_uri(&ms3);
+
+    // Slow the rebuild down to be able to look at the verification while it
+    // is running.
+    add_child(nexus_hdl, child, false).await;
+    set_node_rebuild_limits(nexus_hdl, 400).await;
+    nexus_hdl
+        .mayastor
+        .start_rebuild(StartRebuildRequest {
+            uuid: NEXUS_UUID.into(),
+            uri: child.clone(),
+            shared_uris: Vec::new(),
+            mode: RebuildMode::RebuildFull as i32,
+            verify: true,
+        })
+        .await
+        .unwrap();
+
+    // Wait for all segments to be copied.
+    let time = std::time::Instant::now();
+    loop {
+        let stats = get_rebuild_stats(nexus_hdl, child).await;
+        assert!(stats.verify);
+        if stats.progress == 100 {
+            break;
+        }
+        assert!(time.elapsed() < Duration::from_secs(20));
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    std::thread::sleep(Duration::from_millis(500));
+
+    let stats = get_rebuild_stats(nexus_hdl, child).await;
+    assert!(stats.segments_verified > 0);
+    assert_eq!(stats.verify_mismatches, 0);
+
+    set_node_rebuild_limits(nexus_hdl, 0).await;
+    assert!(
+        wait_for_rebuild_completion(nexus_hdl, child, Duration::from_secs(20))
+            .await
+    );
+    check_nexus_state(nexus_hdl, NexusState::NexusOnline).await;
+}
+
+/// Test that the progress of a rebuild can be watched until it completes.
+#[tokio::test]
+async fn rebuild_watch() {
+    let test = start_infrastructure("rebuild_watch").await;
+    let (mut ms1, _, ms3) = setup_test(&test, 1).await;
+    let nexus_hdl = &mut ms1;
+    let child = &get_share_uri(&ms3);
+
+    // Slow the rebuild down to receive several progress updates.
+    set_node_rebuild_limits(nexus_hdl, 400).await;
+    add_child(nexus_hdl, child, true).await;
+
+    let mut stream = nexus_hdl
+        .mayastor
+        .watch_rebuild(WatchRebuildRequest {
+            uuid: NEXUS_UUID.into(),
+            uri: child.clone(),
+            interval_ms: 100,
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    let mut updates = Vec::new();
+    while let Some(update) = stream.message().await.unwrap() {
+        updates.push(update);
+    }
+
+    // The stream ends with the final state of the rebuild.
+    assert!(updates.len() > 2);
+    let last = updates.last().unwrap();
+    assert_eq!(last.state, "completed");
+    assert_eq!(last.stats.as_ref().unwrap().progress, 100);
+    assert_eq!(last.eta_secs, 0);
+    assert!(updates.iter().any(|u| u.throughput > 0));
+    assert!(updates.windows(2).all(|w| {
+        w[0].stats.as_ref().unwrap().progress
+            <= w[1].stats.as_ref().unwrap().progress
+    }));
+
+    check_nexus_state(nexus_hdl, NexusState::NexusOnline).await;
+}
+
+/// Test that all rebuild jobs of the node are listed with their details.
+#[tokio::test]
+async fn rebuild_list() {
+    let test = start_infrastructure("rebuild_list").await;
+    let (mut ms1, ms2, ms3) = setup_test(&test, 1).await;
+    let nexus_hdl = &mut ms1;
+    let child = &get_share_uri(&ms3);
+
+    let list = nexus_hdl.mayastor.list_rebuilds(Null {}).await.unwrap();
+    assert!(list.into_inner().rebuilds.is_empty());
+
+    // Slow the rebuild down so that it is still running when listed.
+    set_node_rebuild_limits(nexus_hdl, 100).await;
+    add_child(nexus_hdl, child, true).await;
+
+    let rebuilds = nexus_hdl
+        .mayastor
+        .list_rebuilds(Null {})
+        .await
+        .unwrap()
+        .into_inner()
+        .rebuilds;
+    assert_eq!(rebuilds.len(), 1);
+    let rebuild = &rebuilds[0];
+    assert_eq!(rebuild.uuid, NEXUS_UUID);
+    assert_eq!(&rebuild.uri, child);
+    assert_eq!(rebuild.state, "running");
+    let stats = rebuild.stats.as_ref().unwrap();
+    assert_eq!(stats.source, get_share_uri(&ms2));
+    assert!(!stats.start_time.is_empty());
+    assert!(stats.error.is_empty());
+
+    set_node_rebuild_limits(nexus_hdl, 0).await;
+    assert!(
+        wait_for_rebuild_completion(nexus_hdl, child, Duration::from_secs(20))
+            .await
+    );
+    check_nexus_state(nexus_hdl, NexusState::NexusOnline).await;
+}
+
 /// Test rebuild while running front-end I/O.
 /// Note: This test can take some time to complete because it is running fio and
 /// then comparing the contents of the children to make sure they are in-sync.
@@ -714,6 +890,7 @@ async fn publish_nexus(hdl: &mut RpcHandle) -> String {
             uuid: NEXUS_UUID.into(),
             key: "".to_string(),
             share: ShareProtocolNexus::NexusNvmf as i32,
+            read_only: false,
         })
         .await
         .unwrap()
@@ -801,6 +978,8 @@ async fn create_nexus(hdl: &mut RpcHandle, children: Vec<String>) {
             uuid: NEXUS_UUID.into(),
             size: NEXUS_SIZE,
             children,
+            block_size: 0,
+            owner_generation: 0,
         })
         .await
         .unwrap();
@@ -846,6 +1025,9 @@ async fn start_rebuild(hdl: &mut RpcHandle, child: &str) -> Result<(), ()> {
         .start_rebuild(StartRebuildRequest {
             uuid: NEXUS_UUID.into(),
             uri: child.into(),
+            shared_uris: Vec::new(),
+            mode: RebuildMode::RebuildDefault as i32,
+            verify: false,
         })
         .await
     {
@@ -910,6 +1092,33 @@ async fn get_rebuild_progress(hdl: &mut RpcHandle, child: &str) -> u32 {
     reply.into_inner().progress
 }
 
+/// Get the rebuild stats for the given child.
+async fn get_rebuild_stats(
+    hdl: &mut RpcHandle,
+    child: &str,
+) -> RebuildStatsReply {
+    hdl.mayastor
+        .get_rebuild_stats(RebuildStatsRequest {
+            uuid: NEXUS_UUID.into(),
+            uri: child.into(),
+        })
+        .await
+        .expect("Failed to get rebuild stats")
+        .into_inner()
+}
+
+/// Limit the number of segments copied per second by all rebuilds of the
+/// node, 0 is unlimited.
+async fn set_node_rebuild_limits(hdl: &mut RpcHandle, segments: u64) {
+    hdl.mayastor
+        .set_node_rebuild_limits(RebuildLimits {
+            bandwidth: 0,
+            segments,
+        })
+        .await
+        .expect("Failed to set the node rebuild limits");
+}
+
 /// Waits on the given rebuild state or times out.
 /// Returns false if a timeout occurs.
 async fn wait_for_rebuild_state(
@@ -0,0 +1,181 @@
+use composer::{Builder, RpcHandle};
+use rpc::mayastor::{
+    CreateCloneRequest,
+    CreateNexusRequest,
+    CreatePoolRequest,
+    CreateReplicaRequest,
+    CreateSnapshotRequest,
+    DestroyReplicaRequest,
+    InflateReplicaRequest,
+    Null,
+    Replica,
+    ShareProtocolReplica,
+};
+use tonic::Code;
+
+pub mod common;
+
+const UUID: &str = "cdc2a7db-3ac3-403a-af80-7fadc1581c48";
+const CLONE_UUID: &str = "cdc2a7db-3ac3-403a-af80-7fadc1581c49";
+const NEXUS_UUID: &str = "cdc2a7db-3ac3-403a-af80-7fadc1581c4a";
+const MB: u64 = 1024 * 1024;
+
+async fn create_clone(
+    hdl: &mut RpcHandle,
+    snapshot: &str,
+) -> Result<Replica, Code> {
+    hdl.mayastor
+        .create_clone(CreateCloneRequest {
+            snapshot: snapshot.into(),
+            uuid: CLONE_UUID.into(),
+            share: ShareProtocolReplica::ReplicaNvmf as i32,
+        })
+        .await
+        .map(|r| r.into_inner())
+        .map_err(|e| e.code())
+}
+
+async fn destroy_replica(hdl: &mut RpcHandle, uuid: &str) -> Result<(), Code> {
+    hdl.mayastor
+        .destroy_replica(DestroyReplicaRequest {
+            uuid: uuid.into(),
+            wipe: 0,
+        })
+        .await
+        .map(|_| ())
+        .map_err(|e| e.code())
+}
+
+#[tokio::test]
+async fn replica_clone() {
+    let test = Builder::new()
+        .name("replica_clone")
+        .network("10.1.0.0/16")
+        .add_container("ms1")
+        .with_clean(true)
+        .with_prune(true)
+        .build()
+        .await
+        .unwrap();
+    let ms1 = &mut test.grpc_handle("ms1").await.unwrap();
+
+    ms1.mayastor
+        .create_pool(CreatePoolRequest {
+            name: "tpool".into(),
+            disks: vec!["malloc:///disk0?size_mb=64".into()],
+            overcommit_limit: Some(20),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    ms1.mayastor
+        .create_replica(CreateReplicaRequest {
+            uuid: UUID.into(),
+            pool: "tpool".into(),
+            size: 8 * MB,
+            thin: false,
+            share: 0,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    // snapshot the replica through a nexus
+    ms1.mayastor
+        .create_nexus(CreateNexusRequest {
+            uuid: NEXUS_UUID.into(),
+            size: 8 * MB,
+            children: vec![format!("bdev:///{}", UUID)],
+            block_size: 0,
+            owner_generation: 0,
+        })
+        .await
+        .unwrap();
+    let name = ms1
+        .mayastor
+        .create_snapshot(CreateSnapshotRequest {
+            uuid: NEXUS_UUID.into(),
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .name;
+    let time = name.rsplit("-snap-").next().unwrap();
+    let snapshot = format!("{}-snap-{}", UUID, time);
+
+    // only snapshots can be cloned
+    assert_eq!(
+        create_clone(ms1, UUID).await.unwrap_err(),
+        Code::InvalidArgument
+    );
+    assert_eq!(
+        create_clone(ms1, "no-such-snapshot").await.unwrap_err(),
+        Code::NotFound
+    );
+
+    // the clone commits as much space as the snapshot
+    assert_eq!(
+        create_clone(ms1, &snapshot).await.unwrap_err(),
+        Code::ResourceExhausted
+    );
+    ms1.mayastor
+        .create_pool(CreatePoolRequest {
+            name: "tpool".into(),
+            disks: vec!["malloc:///disk0?size_mb=64".into()],
+            overcommit_limit: Some(200),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let clone = create_clone(ms1, &snapshot).await.unwrap();
+    assert_eq!(clone.uuid, CLONE_UUID);
+    assert_eq!(clone.pool, "tpool");
+    assert_eq!(clone.size, 8 * MB);
+    assert!(clone.thin);
+    assert!(clone.uri.starts_with("nvmf://"));
+
+    // creating the clone again is OK
+    let again = create_clone(ms1, &snapshot).await.unwrap();
+    assert_eq!(again.uri, clone.uri);
+
+    // a bdev of that name which is not a clone of the snapshot is refused
+    assert_eq!(
+        ms1.mayastor
+            .create_clone(CreateCloneRequest {
+                snapshot: snapshot.clone(),
+                uuid: snapshot.clone(),
+                share: ShareProtocolReplica::ReplicaNone as i32,
+            })
+            .await
+            .unwrap_err()
+            .code(),
+        Code::AlreadyExists
+    );
+
+    // the snapshot has two clones, the replica and the clone, and can not be
+    // destroyed until the clone is independent of it
+    assert!(destroy_replica(ms1, &snapshot).await.is_err());
+    let inflated = ms1
+        .mayastor
+        .inflate_replica(InflateReplicaRequest {
+            uuid: CLONE_UUID.into(),
+            decouple: true,
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(inflated.uri, clone.uri);
+    destroy_replica(ms1, &snapshot).await.unwrap();
+
+    let replicas = ms1
+        .mayastor
+        .list_replicas(Null {})
+        .await
+        .unwrap()
+        .into_inner()
+        .replicas;
+    assert_eq!(replicas.len(), 2);
+    assert!(replicas.iter().any(|r| r.uuid == UUID));
+    assert!(replicas.iter().any(|r| r.uuid == CLONE_UUID));
+}
@@ -0,0 +1,118 @@
+use std::{cell::Cell, convert::TryFrom, task::Poll};
+
+use futures::future;
+
+use common::{bdev_io, MayastorTest};
+use mayastor::{
+    core::{Bdev, MayastorCliArgs, Protocol, Share},
+    lvs::{Lvol, Lvs, PropValue},
+    rebuild::RebuildJob,
+};
+use rpc::mayastor::CreatePoolRequest;
+
+pub mod common;
+
+static DISKNAME1: &str = "/tmp/replica_migrate1.img";
+static DISKNAME2: &str = "/tmp/replica_migrate2.img";
+const SIZE: u64 = 8 * 1024 * 1024;
+
+fn pool_args(name: &str, disk: &str) -> CreatePoolRequest {
+    CreatePoolRequest {
+        name: name.into(),
+        disks: vec![format!("aio://{}", disk)],
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn replica_migrate() {
+    let disks = [DISKNAME1.to_string(), DISKNAME2.to_string()];
+    common::delete_file(&disks);
+    common::truncate_file(DISKNAME1, 64 * 1024);
+    common::truncate_file(DISKNAME2, 64 * 1024);
+
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    ms.spawn(async {
+        let pool1 = Lvs::create_or_import(pool_args("mpool1", DISKNAME1))
+            .await
+            .unwrap();
+        let pool2 = Lvs::create_or_import(pool_args("mpool2", DISKNAME2))
+            .await
+            .unwrap();
+
+        let lvol = pool1.create_lvol("migrated", SIZE, false).await.unwrap();
+        lvol.set(PropValue::VolumeUuid("volume".into()))
+            .await
+            .unwrap();
+        bdev_io::write_some("migrated", 0, 0xaa).await.unwrap();
+        let uri = lvol.share_nvmf().await.unwrap();
+
+        // a lvol can not be migrated to its own pool
+        assert!(lvol.migrate(&pool1).await.is_err());
+
+        // the data, the properties and the share are kept
+        let lvol =
+            Lvol::try_from(Bdev::lookup_by_name("migrated").unwrap()).unwrap();
+        let lvol = lvol.migrate(&pool2).await.unwrap();
+        assert_eq!(lvol.name(), "migrated");
+        assert_eq!(lvol.pool(), "mpool2");
+        assert_eq!(lvol.shared(), Some(Protocol::Nvmf));
+        assert_eq!(lvol.share_uri(), Some(uri.clone()));
+        assert_eq!(lvol.properties().volume_uuid, "volume");
+        assert!(Bdev::lookup_by_name("migrated-migrate").is_none());
+        assert_eq!(pool1.lvols().unwrap().count(), 0);
+        bdev_io::read_some("migrated", 0, 0xaa).await.unwrap();
+
+        // the writes made while the lvol is being copied are migrated, as
+        // well as the writes made once it has been migrated
+        let done = Cell::new(false);
+        let migrating = async {
+            let lvol =
+                Lvol::try_from(Bdev::lookup_by_name("migrated").unwrap())
+                    .unwrap();
+            let result = lvol.migrate(&pool1).await;
+            done.set(true);
+            result
+        };
+        let writer = async {
+            future::poll_fn(|cx| {
+                if RebuildJob::lookup("migrated").is_ok() || done.get() {
+                    Poll::Ready(())
+                } else {
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                }
+            })
+            .await;
+            bdev_io::write_some("migrated", SIZE / 2, 0xbb)
+                .await
+                .unwrap();
+        };
+        let (lvol, _) = future::join(migrating, writer).await;
+        let lvol = lvol.unwrap();
+        assert_eq!(lvol.pool(), "mpool1");
+        assert_eq!(lvol.share_uri(), Some(uri));
+        assert!(Bdev::lookup_by_name("migrated-migrated").is_none());
+        bdev_io::read_some("migrated", 0, 0xaa).await.unwrap();
+        bdev_io::read_some("migrated", SIZE / 2, 0xbb)
+            .await
+            .unwrap();
+
+        // the unallocated clusters of a thin lvol stay unallocated
+        let thin = pool1.create_lvol("thin", SIZE, true).await.unwrap();
+        let thin = thin.migrate(&pool2).await.unwrap();
+        assert_eq!(thin.pool(), "mpool2");
+        assert!(thin.is_thin());
+        assert_eq!(thin.allocated(), 0);
+        bdev_io::read_some("thin", 0, 0).await.unwrap();
+
+        thin.destroy().await.unwrap();
+        lvol.destroy().await.unwrap();
+        pool1.destroy().await.unwrap();
+        pool2.destroy().await.unwrap();
+    })
+    .await;
+
+    common::delete_file(&disks);
+}
@@ -0,0 +1,136 @@
+use composer::{Builder, RpcHandle};
+use rpc::mayastor::{
+    BdevUri,
+    CreateNexusRequest,
+    CreatePoolRequest,
+    CreateReplicaRequest,
+    Nexus,
+    Replica,
+    ReplicaProperties,
+    TransferReplicaOwnershipRequest,
+};
+use tonic::Code;
+
+pub mod common;
+
+const UUID: &str = "cdc2a7db-3ac3-403a-af80-7fadc1581c50";
+const NEXUS1_UUID: &str = "cdc2a7db-3ac3-403a-af80-7fadc1581c51";
+const NEXUS2_UUID: &str = "cdc2a7db-3ac3-403a-af80-7fadc1581c52";
+const MB: u64 = 1024 * 1024;
+
+async fn create_nexus(
+    hdl: &mut RpcHandle,
+    uuid: &str,
+    generation: u64,
+    replica: &Replica,
+) -> Result<Nexus, Code> {
+    hdl.mayastor
+        .create_nexus(CreateNexusRequest {
+            uuid: uuid.into(),
+            size: 8 * MB,
+            children: vec![replica.uri.clone()],
+            block_size: 0,
+            owner_generation: generation,
+        })
+        .await
+        .map(|r| r.into_inner())
+        .map_err(|e| e.code())
+}
+
+async fn transfer(
+    hdl: &mut RpcHandle,
+    nexus_uuid: &str,
+    generation: u64,
+) -> Result<Replica, Code> {
+    hdl.mayastor
+        .transfer_replica_ownership(TransferReplicaOwnershipRequest {
+            uuid: UUID.into(),
+            nexus_uuid: nexus_uuid.into(),
+            generation,
+        })
+        .await
+        .map(|r| r.into_inner())
+        .map_err(|e| e.code())
+}
+
+#[tokio::test]
+async fn replica_ownership() {
+    let test = Builder::new()
+        .name("replica_ownership")
+        .network("10.1.0.0/16")
+        .add_container("ms1")
+        .add_container("ms2")
+        .add_container("ms3")
+        .with_clean(true)
+        .with_prune(true)
+        .build()
+        .await
+        .unwrap();
+    let ms1 = &mut test.grpc_handle("ms1").await.unwrap();
+    let ms2 = &mut test.grpc_handle("ms2").await.unwrap();
+    let ms3 = &mut test.grpc_handle("ms3").await.unwrap();
+
+    ms1.mayastor
+        .create_pool(CreatePoolRequest {
+            name: "tpool".into(),
+            disks: vec!["malloc:///disk0?size_mb=64".into()],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    // the replica is created owned by the first nexus
+    let replica = ms1
+        .mayastor
+        .create_replica(CreateReplicaRequest {
+            uuid: UUID.into(),
+            pool: "tpool".into(),
+            size: 8 * MB,
+            thin: false,
+            share: 1,
+            properties: Some(ReplicaProperties {
+                nexus_uuid: NEXUS1_UUID.into(),
+                owner_generation: 1,
+                ..Default::default()
+            }),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    let props = replica.properties.clone().unwrap();
+    assert_eq!(props.nexus_uuid, NEXUS1_UUID);
+    assert_eq!(props.owner_generation, 1);
+
+    // only the owner can connect to the replica
+    create_nexus(ms2, NEXUS1_UUID, 1, &replica).await.unwrap();
+    assert!(create_nexus(ms3, NEXUS2_UUID, 1, &replica).await.is_err());
+    assert!(ms3
+        .bdev
+        .create(BdevUri {
+            uri: replica.uri.clone(),
+        })
+        .await
+        .is_err());
+
+    // fail over to the second nexus, which requires a newer generation
+    assert_eq!(
+        transfer(ms1, NEXUS2_UUID, 1).await.unwrap_err(),
+        Code::FailedPrecondition
+    );
+    let transferred = transfer(ms1, NEXUS2_UUID, 2).await.unwrap();
+    let props = transferred.properties.unwrap();
+    assert_eq!(props.nexus_uuid, NEXUS2_UUID);
+    assert_eq!(props.owner_generation, 2);
+    assert_eq!(transferred.uri, replica.uri);
+
+    // the owner can not connect with an outdated generation of the
+    // ownership, as a nexus of the same uuid left behind on another node
+    assert!(create_nexus(ms2, NEXUS2_UUID, 1, &replica).await.is_err());
+    create_nexus(ms3, NEXUS2_UUID, 2, &replica).await.unwrap();
+
+    // an outdated transfer can not take the replica back
+    assert_eq!(
+        transfer(ms1, NEXUS1_UUID, 1).await.unwrap_err(),
+        Code::FailedPrecondition
+    );
+}
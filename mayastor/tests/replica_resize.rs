@@ -0,0 +1,121 @@
+use std::time::Duration;
+
+use composer::{Builder, RpcHandle};
+use rpc::mayastor::{
+    BdevUri,
+    CreatePoolRequest,
+    CreateReplicaRequest,
+    Null,
+    Replica,
+    ResizeReplicaRequest,
+};
+use tonic::Code;
+
+pub mod common;
+
+const UUID: &str = "cdc2a7db-3ac3-403a-af80-7fadc1581c47";
+const MB: u64 = 1024 * 1024;
+
+async fn resize(hdl: &mut RpcHandle, size: u64) -> Result<Replica, Code> {
+    hdl.mayastor
+        .resize_replica(ResizeReplicaRequest {
+            uuid: UUID.into(),
+            size,
+        })
+        .await
+        .map(|r| r.into_inner())
+        .map_err(|e| e.code())
+}
+
+/// returns the size in bytes of the bdev `name` as seen by `hdl`
+async fn bdev_size(hdl: &mut RpcHandle, name: &str) -> u64 {
+    let bdevs = hdl.bdev.list(Null {}).await.unwrap().into_inner().bdevs;
+    let bdev = bdevs.iter().find(|b| b.name == name).unwrap();
+    bdev.num_blocks * bdev.blk_size as u64
+}
+
+#[tokio::test]
+async fn replica_resize() {
+    let test = Builder::new()
+        .name("replica_resize")
+        .network("10.1.0.0/16")
+        .add_container("ms1")
+        .add_container("ms2")
+        .with_clean(true)
+        .with_prune(true)
+        .build()
+        .await
+        .unwrap();
+    let ms1 = &mut test.grpc_handle("ms1").await.unwrap();
+    let ms2 = &mut test.grpc_handle("ms2").await.unwrap();
+
+    ms1.mayastor
+        .create_pool(CreatePoolRequest {
+            name: "tpool".into(),
+            disks: vec!["malloc:///disk0?size_mb=64".into()],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let replica = ms1
+        .mayastor
+        .create_replica(CreateReplicaRequest {
+            uuid: UUID.into(),
+            pool: "tpool".into(),
+            size: 8 * MB,
+            thin: false,
+            share: 1,
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(replica.size, 8 * MB);
+
+    // connect to the shared replica from the other node
+    let name = ms2
+        .bdev
+        .create(BdevUri {
+            uri: replica.uri.clone(),
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .name;
+    assert_eq!(bdev_size(ms2, &name).await, 8 * MB);
+
+    // grow the replica while it is shared, resizing to the same size is OK
+    assert_eq!(resize(ms1, 16 * MB).await.unwrap().size, 16 * MB);
+    assert_eq!(resize(ms1, 16 * MB).await.unwrap().size, 16 * MB);
+
+    // shrinking and growing beyond the capacity of the pool fail
+    assert_eq!(
+        resize(ms1, 4 * MB).await.unwrap_err(),
+        Code::InvalidArgument
+    );
+    assert_eq!(
+        resize(ms1, 128 * MB).await.unwrap_err(),
+        Code::ResourceExhausted
+    );
+
+    let replicas = ms1
+        .mayastor
+        .list_replicas(Null {})
+        .await
+        .unwrap()
+        .into_inner()
+        .replicas;
+    assert_eq!(replicas[0].size, 16 * MB);
+
+    // the other node picks up the new size
+    let mut size = 0;
+    for _ in 0 .. 20 {
+        size = bdev_size(ms2, &name).await;
+        if size == 16 * MB {
+            break;
+        }
+        tokio::time::delay_for(Duration::from_millis(250)).await;
+    }
+    assert_eq!(size, 16 * MB);
+}
@@ -49,6 +49,7 @@ async fn replica_snapshot() {
         .create_pool(CreatePoolRequest {
             name: POOL2_NAME.to_string(),
             disks: vec!["malloc:///disk0?size_mb=96".into()],
+            ..Default::default()
         })
         .await
         .unwrap();
@@ -62,6 +63,7 @@ async fn replica_snapshot() {
             size: 64 * 1024 * 1024,
             thin: false,
             share: ShareProtocolReplica::ReplicaNvmf as i32,
+            ..Default::default()
         })
         .await
         .unwrap();
@@ -74,6 +76,7 @@ async fn replica_snapshot() {
             Lvs::create_or_import(CreatePoolRequest {
                 name: POOL1_NAME.to_string(),
                 disks: vec![format!("aio://{}", DISKNAME1)],
+                ..Default::default()
             })
             .await
             .unwrap();
@@ -0,0 +1,148 @@
+use composer::{Builder, RpcHandle};
+use rpc::mayastor::{
+    CreatePoolRequest,
+    CreateReplicaRequest,
+    Null,
+    Pool,
+    Replica,
+    ResizeReplicaRequest,
+};
+use tonic::Code;
+
+pub mod common;
+
+const UUID1: &str = "f2a1c7c1-27f0-4c43-9e9b-4d4a1e3c5f01";
+const UUID2: &str = "f2a1c7c1-27f0-4c43-9e9b-4d4a1e3c5f02";
+const UUID3: &str = "f2a1c7c1-27f0-4c43-9e9b-4d4a1e3c5f03";
+const UUID4: &str = "f2a1c7c1-27f0-4c43-9e9b-4d4a1e3c5f04";
+
+async fn create_replica(
+    hdl: &mut RpcHandle,
+    uuid: &str,
+    size: u64,
+    thin: bool,
+    reservation: u64,
+) -> Result<Replica, Code> {
+    hdl.mayastor
+        .create_replica(CreateReplicaRequest {
+            uuid: uuid.into(),
+            pool: "tpool".into(),
+            size,
+            thin,
+            share: 0,
+            reservation,
+            ..Default::default()
+        })
+        .await
+        .map(|r| r.into_inner())
+        .map_err(|e| e.code())
+}
+
+async fn pool(hdl: &mut RpcHandle) -> Pool {
+    let pools = hdl.mayastor.list_pools(Null {}).await.unwrap().into_inner();
+    pools.pools.into_iter().find(|p| p.name == "tpool").unwrap()
+}
+
+#[tokio::test]
+async fn replica_thin() {
+    let test = Builder::new()
+        .name("replica_thin")
+        .network("10.1.0.0/16")
+        .add_container("ms1")
+        .with_clean(true)
+        .with_prune(true)
+        .build()
+        .await
+        .unwrap();
+    let ms1 = &mut test.grpc_handle("ms1").await.unwrap();
+
+    ms1.mayastor
+        .create_pool(CreatePoolRequest {
+            name: "tpool".into(),
+            disks: vec!["malloc:///disk0?size_mb=64".into()],
+            overcommit_limit: Some(200),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let capacity = pool(ms1).await.capacity;
+
+    // a thin replica as large as the pool with half of it reserved, which is
+    // allocated up front
+    let thin = create_replica(ms1, UUID1, capacity, true, capacity / 2)
+        .await
+        .unwrap();
+    assert!(thin.thin);
+    assert!(thin.allocated >= capacity / 2);
+    assert!(thin.allocated < capacity);
+    assert_eq!(thin.reservation, capacity / 2);
+
+    // thick replicas can not use the reserved space
+    assert_eq!(
+        create_replica(ms1, UUID2, capacity * 3 / 4, false, 0)
+            .await
+            .unwrap_err(),
+        Code::ResourceExhausted
+    );
+    let thick = create_replica(ms1, UUID2, capacity / 4, false, 0)
+        .await
+        .unwrap();
+    assert!(thick.allocated >= capacity / 4);
+
+    // thin replicas can overcommit the pool up to its limit
+    create_replica(ms1, UUID3, capacity / 2, true, 0)
+        .await
+        .unwrap();
+    assert_eq!(
+        create_replica(ms1, UUID4, capacity / 2, true, 0)
+            .await
+            .unwrap_err(),
+        Code::ResourceExhausted
+    );
+
+    let tpool = pool(ms1).await;
+    assert_eq!(tpool.overcommit_limit, 200);
+    assert_eq!(tpool.committed, capacity + capacity / 4 + capacity / 2);
+    assert_eq!(tpool.reserved, 0);
+    assert_eq!(tpool.used, thin.allocated + thick.allocated);
+
+    // the limit is kept unless it is given
+    ms1.mayastor
+        .create_pool(CreatePoolRequest {
+            name: "tpool".into(),
+            disks: vec!["malloc:///disk0?size_mb=64".into()],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(pool(ms1).await.overcommit_limit, 200);
+
+    // raising the limit allows more thin replicas
+    ms1.mayastor
+        .create_pool(CreatePoolRequest {
+            name: "tpool".into(),
+            disks: vec!["malloc:///disk0?size_mb=64".into()],
+            overcommit_limit: Some(300),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    create_replica(ms1, UUID4, capacity / 2, true, 0)
+        .await
+        .unwrap();
+
+    // growing a thin replica commits more of the pool too
+    let resize = |size| ResizeReplicaRequest {
+        uuid: UUID4.into(),
+        size,
+    };
+    assert_eq!(
+        ms1.mayastor
+            .resize_replica(resize(capacity + capacity / 2))
+            .await
+            .unwrap_err()
+            .code(),
+        Code::ResourceExhausted
+    );
+    ms1.mayastor.resize_replica(resize(capacity)).await.unwrap();
+}
@@ -72,7 +72,7 @@ async fn replica_stop_cont() {
             .unwrap();
             nexus_lookup(&NXNAME)
                 .unwrap()
-                .share(ShareProtocolNexus::NexusNvmf, None)
+                .share(ShareProtocolNexus::NexusNvmf, None, false)
                 .await
                 .expect("should publish nexus over nvmf");
         })
@@ -0,0 +1,218 @@
+use std::{
+    cell::Cell,
+    convert::TryFrom,
+    ffi::CString,
+    os::raw::c_void,
+    task::Poll,
+};
+
+use futures::{channel::oneshot, future};
+
+use common::{bdev_io, MayastorTest};
+use mayastor::{
+    core::{Bdev, BdevHandle, MayastorCliArgs},
+    ffihelper::cb_arg,
+    lvs::{Lvol, Lvs, WipeMethod, WipeProgress},
+};
+use rpc::mayastor::{CreatePoolRequest, ReplicaWipeMethod};
+use spdk_sys::{
+    lvs_blob_cluster,
+    spdk_lvol,
+    vbdev_lvol_create_snapshot,
+    vbdev_lvol_get_from_bdev,
+};
+
+pub mod common;
+
+static DISKNAME: &str = "/tmp/replica_wipe.img";
+const SIZE: u64 = 8 * 1024 * 1024;
+
+fn pool_args(wipe_method: ReplicaWipeMethod) -> CreatePoolRequest {
+    CreatePoolRequest {
+        name: "wpool".into(),
+        disks: vec![format!("aio://{}", DISKNAME)],
+        wipe_method: wipe_method as i32,
+        ..Default::default()
+    }
+}
+
+/// returns the offset on the disks of the pool of the first cluster of the
+/// lvol, which must be allocated
+fn first_cluster_offset(pool: &Lvs, lvol: &str) -> u64 {
+    let bdev = Bdev::lookup_by_name(lvol).unwrap();
+    let cluster = unsafe {
+        let lvol = vbdev_lvol_get_from_bdev(bdev.as_ptr());
+        lvs_blob_cluster((*lvol).blob, 0)
+    };
+    assert_ne!(cluster, 0);
+    cluster * pool.cluster_size()
+}
+
+/// reads a block of the disks of the pool at the given offset
+async fn read_disks(pool: &Lvs, offset: u64) -> Vec<u8> {
+    let h = BdevHandle::open(&pool.base_bdev().name(), false, false).unwrap();
+    let mut buf = h.dma_malloc(512).unwrap();
+    h.read_at(offset, &mut buf).await.unwrap();
+    buf.as_slice().to_vec()
+}
+
+/// snapshots the lvol, which becomes a clone of the snapshot
+async fn create_snapshot(lvol: &str, snapshot: &str) -> Lvol {
+    extern "C" fn done_cb(
+        sender: *mut c_void,
+        _lvol: *mut spdk_lvol,
+        errno: i32,
+    ) {
+        let sender =
+            unsafe { Box::from_raw(sender as *mut oneshot::Sender<i32>) };
+        sender.send(errno).unwrap();
+    }
+
+    let bdev = Bdev::lookup_by_name(lvol).unwrap();
+    let name = CString::new(snapshot).unwrap();
+    let (s, r) = oneshot::channel::<i32>();
+    unsafe {
+        vbdev_lvol_create_snapshot(
+            vbdev_lvol_get_from_bdev(bdev.as_ptr()),
+            name.as_ptr(),
+            Some(done_cb),
+            cb_arg(s),
+        );
+    }
+    assert_eq!(r.await.unwrap(), 0);
+    Lvol::try_from(Bdev::lookup_by_name(snapshot).unwrap()).unwrap()
+}
+
+/// writes to the first cluster of a lvol and wipes it, returning the block
+/// of the disks of the pool it was written to
+async fn wipe_written(pool: &Lvs, method: WipeMethod) -> Vec<u8> {
+    let lvol = pool.create_lvol("written", SIZE, false).await.unwrap();
+    bdev_io::write_some("written", 0, 0xaa).await.unwrap();
+    let offset = first_cluster_offset(pool, "written");
+    assert!(read_disks(pool, offset).await.iter().all(|b| *b == 0xaa));
+
+    lvol.destroy_wiped(Some(method)).await.unwrap();
+    assert!(Bdev::lookup_by_name("written").is_none());
+    assert!(WipeProgress::list().is_empty());
+    read_disks(pool, offset).await
+}
+
+#[tokio::test]
+async fn replica_wipe() {
+    common::delete_file(&[DISKNAME.to_string()]);
+    common::truncate_file(DISKNAME, 64 * 1024);
+
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    ms.spawn(async {
+        assert!(Lvs::create_or_import(CreatePoolRequest {
+            wipe_method: 42,
+            ..pool_args(ReplicaWipeMethod::WipeDefault)
+        })
+        .await
+        .is_err());
+
+        let pool =
+            Lvs::create_or_import(pool_args(ReplicaWipeMethod::WipeDefault))
+                .await
+                .unwrap();
+        assert_eq!(pool.wipe_method(), WipeMethod::None);
+
+        // the data of a wiped lvol is overwritten on the disks
+        let block = wipe_written(&pool, WipeMethod::WriteZeroes).await;
+        assert!(block.iter().all(|b| *b == 0));
+        let block = wipe_written(&pool, WipeMethod::Random).await;
+        assert!(!block.iter().all(|b| *b == 0xaa));
+        assert!(!block.iter().all(|b| *b == 0));
+
+        // the progress of a wipe is reported until the lvol is destroyed
+        let lvol = pool.create_lvol("wiped", SIZE, false).await.unwrap();
+        let uuid = lvol.uuid();
+        let allocated = lvol.allocated();
+        let done = Cell::new(false);
+        let wipe = async {
+            let result =
+                lvol.destroy_wiped(Some(WipeMethod::WriteZeroes)).await;
+            done.set(true);
+            result
+        };
+        let progress = future::poll_fn(|cx| {
+            if let Some(p) = WipeProgress::list().pop() {
+                Poll::Ready(Some(p))
+            } else if done.get() {
+                // the wipe completed without being observed
+                Poll::Ready(None)
+            } else {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        });
+        let (result, progress) = future::join(wipe, progress).await;
+        result.unwrap();
+        let progress = progress.unwrap();
+        assert_eq!(progress.uuid, uuid);
+        assert_eq!(progress.pool, "wpool");
+        assert_eq!(progress.method, WipeMethod::WriteZeroes);
+        assert_eq!(progress.total, allocated);
+        assert!(progress.wiped <= progress.total);
+        assert!(WipeProgress::list().is_empty());
+
+        // the clusters of a wiped lvol read back as zeroes when reused
+        let lvol = pool.create_lvol("reused", SIZE, false).await.unwrap();
+        bdev_io::read_some("reused", 0, 0).await.unwrap();
+        bdev_io::write_some("reused", 0, 0xaa).await.unwrap();
+
+        // the default of the pool is updated when creating it again
+        let pool =
+            Lvs::create_or_import(pool_args(ReplicaWipeMethod::WipeRandom))
+                .await
+                .unwrap();
+        assert_eq!(pool.wipe_method(), WipeMethod::Random);
+        lvol.destroy_wiped(None).await.unwrap();
+        assert!(Bdev::lookup_by_name("reused").is_none());
+        assert!(WipeProgress::list().is_empty());
+
+        // a thin lvol has nothing to wipe until it is written to
+        let lvol = pool.create_lvol("thin", SIZE, true).await.unwrap();
+        lvol.destroy_wiped(None).await.unwrap();
+
+        // a snapshot is not wiped while its clone depends on its data, and
+        // its clusters are wiped on the disks once its clone is destroyed
+        let lvol = pool.create_lvol("origin", SIZE, false).await.unwrap();
+        bdev_io::write_some("origin", 0, 0xaa).await.unwrap();
+        let snapshot = create_snapshot("origin", "origin-snap").await;
+        let offset = first_cluster_offset(&pool, "origin-snap");
+        assert!(read_disks(&pool, offset).await.iter().all(|b| *b == 0xaa));
+        assert!(snapshot.destroy_wiped(None).await.is_err());
+        let snapshot =
+            Lvol::try_from(Bdev::lookup_by_name("origin-snap").unwrap())
+                .unwrap();
+        lvol.destroy_wiped(None).await.unwrap();
+        snapshot.destroy_wiped(None).await.unwrap();
+        assert!(Bdev::lookup_by_name("origin-snap").is_none());
+        assert!(!read_disks(&pool, offset).await.iter().all(|b| *b == 0xaa));
+        assert!(WipeProgress::list().is_empty());
+
+        let pool =
+            Lvs::create_or_import(pool_args(ReplicaWipeMethod::WipeDefault))
+                .await
+                .unwrap();
+        assert_eq!(pool.wipe_method(), WipeMethod::None);
+        pool.destroy().await.unwrap();
+
+        // the disks of a malloc pool read back zeroes once unmapped
+        let pool = Lvs::create_or_import(CreatePoolRequest {
+            name: "upool".into(),
+            disks: vec!["malloc:///wdisk?size_mb=64".into()],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let block = wipe_written(&pool, WipeMethod::Unmap).await;
+        assert!(block.iter().all(|b| *b == 0));
+        pool.destroy().await.unwrap();
+    })
+    .await;
+
+    common::delete_file(&[DISKNAME.to_string()]);
+}
@@ -0,0 +1,186 @@
+use std::time::Duration;
+
+use common::bdev_io;
+use mayastor::{
+    bdev::{nexus_create, nexus_lookup},
+    core::MayastorCliArgs,
+    lvs::{Lvol, Lvs},
+};
+use rpc::mayastor::{
+    CreatePoolRequest,
+    CreateReplicaRequest,
+    Null,
+    ShareProtocolReplica,
+};
+
+pub mod common;
+use common::{compose::Builder, MayastorTest};
+
+static DISKNAME1: &str = "/tmp/snapshot_lifecycle.img";
+static POOL1_NAME: &str = "pool1";
+static POOL2_NAME: &str = "pool2";
+
+static DISKSIZE_KB: u64 = 96 * 1024;
+
+static UUID1: &str = "00000000-76b6-4fcf-864d-1027d4038757";
+
+static NXNAME: &str = "snapshot_lifecycle_test";
+
+#[tokio::test]
+async fn snapshot_lifecycle() {
+    common::delete_file(&[DISKNAME1.to_string()]);
+    common::truncate_file(DISKNAME1, DISKSIZE_KB);
+
+    let test = Builder::new()
+        .name("snapshot_lifecycle_test")
+        .network("10.1.0.0/16")
+        .add_container("ms1")
+        .with_clean(true)
+        .build()
+        .await
+        .unwrap();
+
+    let mut hdls = test.grpc_handles().await.unwrap();
+
+    // create a replica on the remote node, shared over nvmf
+    hdls[0]
+        .mayastor
+        .create_pool(CreatePoolRequest {
+            name: POOL2_NAME.to_string(),
+            disks: vec!["malloc:///disk0?size_mb=96".into()],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    hdls[0]
+        .mayastor
+        .create_replica(CreateReplicaRequest {
+            uuid: UUID1.to_string(),
+            pool: POOL2_NAME.to_string(),
+            size: 64 * 1024 * 1024,
+            thin: false,
+            share: ShareProtocolReplica::ReplicaNvmf as i32,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let mayastor = MayastorTest::new(MayastorCliArgs::default());
+    let ip0 = hdls[0].endpoint.ip();
+
+    let (snap1, snap2) = mayastor
+        .spawn(async move {
+            Lvs::create_or_import(CreatePoolRequest {
+                name: POOL1_NAME.to_string(),
+                disks: vec![format!("aio://{}", DISKNAME1)],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+            let pool = Lvs::lookup(POOL1_NAME).unwrap();
+            pool.create_lvol(UUID1, 64 * 1024 * 1024, false)
+                .await
+                .unwrap();
+
+            let children = vec![
+                format!("loopback:///{}", UUID1),
+                format!("nvmf://{}:8420/nqn.2019-05.io.openebs:{}", ip0, UUID1),
+            ];
+            nexus_create(NXNAME, 64 * 1024 * 1024, None, &children)
+                .await
+                .unwrap();
+            let nexus = nexus_lookup(NXNAME).unwrap();
+
+            bdev_io::write_some(NXNAME, 0, 0xff).await.unwrap();
+            let snap1 = nexus.create_snapshot().await.unwrap().name;
+
+            // snapshots are named after their creation time in seconds
+            tokio::time::delay_for(Duration::from_millis(1100)).await;
+            bdev_io::write_some(NXNAME, 0, 0xaa).await.unwrap();
+            let snap2 = nexus.create_snapshot().await.unwrap().name;
+            bdev_io::write_some(NXNAME, 0, 0x55).await.unwrap();
+
+            // both replicas have both snapshots
+            let snapshots = nexus.list_snapshots().await.unwrap().snapshots;
+            assert_eq!(snapshots.len(), 2);
+            assert_eq!(snapshots[0].name, snap1);
+            assert_eq!(snapshots[1].name, snap2);
+            assert!(snapshots.iter().all(|s| s.complete));
+
+            // revert to the oldest snapshot
+            nexus.revert_to_snapshot(&snap1).await.unwrap();
+            bdev_io::read_some(NXNAME, 0, 0xff).await.unwrap();
+
+            // the names must be of snapshots of this nexus
+            assert!(nexus.revert_to_snapshot("foo-snap-1").await.is_err());
+            assert!(nexus.destroy_snapshot(NXNAME).await.is_err());
+            let unknown = format!("{}-snap-1", NXNAME);
+            assert!(nexus.destroy_snapshot(&unknown).await.is_err());
+
+            // destroy the oldest snapshot and revert to the remaining one
+            nexus.destroy_snapshot(&snap1).await.unwrap();
+            assert!(nexus.destroy_snapshot(&snap1).await.is_err());
+            let snapshots = nexus.list_snapshots().await.unwrap().snapshots;
+            assert_eq!(snapshots.len(), 1);
+            assert_eq!(snapshots[0].name, snap2);
+
+            nexus.revert_to_snapshot(&snap2).await.unwrap();
+            bdev_io::read_some(NXNAME, 0, 0xaa).await.unwrap();
+
+            // IO to the nexus still works after reverting
+            bdev_io::write_some(NXNAME, 0, 0x11).await.unwrap();
+            bdev_io::read_some(NXNAME, 0, 0x11).await.unwrap();
+            (snap1, snap2)
+        })
+        .await;
+
+    // the remote replica only has the remaining snapshot
+    let replicas = hdls[0]
+        .mayastor
+        .list_replicas(Null {})
+        .await
+        .unwrap()
+        .into_inner()
+        .replicas;
+    let suffix = |name: &str| name[NXNAME.len() ..].to_string();
+    assert!(replicas.iter().any(|r| r.uuid.ends_with(&suffix(&snap2))));
+    assert!(!replicas.iter().any(|r| r.uuid.ends_with(&suffix(&snap1))));
+
+    mayastor
+        .spawn(async move {
+            let nexus = nexus_lookup(NXNAME).unwrap();
+            nexus.destroy_snapshot(&snap2).await.unwrap();
+            assert!(nexus.list_snapshots().await.unwrap().snapshots.is_empty());
+        })
+        .await;
+
+    // a replica which has grown since its snapshot was created is zeroed
+    // beyond the end of the snapshot when reverted to it
+    mayastor
+        .spawn(async move {
+            let nexus = nexus_lookup(NXNAME).unwrap();
+            let snap3 = nexus.create_snapshot().await.unwrap().name;
+            let time = Lvol::snapshot_time(NXNAME, &snap3).unwrap();
+            nexus.destroy().await.unwrap();
+
+            let lvol = Lvs::lookup(POOL1_NAME)
+                .unwrap()
+                .lvols()
+                .unwrap()
+                .find(|l| l.name() == UUID1)
+                .unwrap();
+            lvol.resize(68 * 1024 * 1024).await.unwrap();
+            let alias = format!("{}/{}", POOL1_NAME, UUID1);
+            bdev_io::write_some(&alias, 64 * 1024 * 1024, 0xbb)
+                .await
+                .unwrap();
+
+            lvol.revert_to_snapshot(time).await.unwrap();
+            bdev_io::read_some(&alias, 64 * 1024 * 1024, 0)
+                .await
+                .unwrap();
+        })
+        .await;
+
+    common::delete_file(&[DISKNAME1.to_string()]);
+}
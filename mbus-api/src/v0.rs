@@ -15,6 +15,8 @@ pub enum ChannelVs {
     Node,
     /// Keep it In Sync Service
     Kiiss,
+    /// Events of the pools of mayastor instances
+    Pool,
 }
 impl Default for ChannelVs {
     fn default() -> Self {
@@ -47,6 +49,9 @@ pub enum MessageIdVs {
     /// Node Service
     /// Get all node information
     GetNodes,
+    /// Pool Service
+    /// State of a pool changed
+    PoolEvent,
 }
 
 // Only V0 should export this macro
@@ -183,3 +188,47 @@ pub struct Node {
 #[derive(Serialize, Deserialize, Default, Debug, Clone)]
 pub struct Nodes(pub Vec<Node>);
 bus_impl_message_all!(GetNodes, GetNodes, Nodes, Node);
+
+/// Pool Service
+
+/// State of a Pool
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    EnumString,
+    strum_macros::ToString,
+    Eq,
+    PartialEq,
+)]
+#[strum(serialize_all = "camelCase")]
+pub enum PoolState {
+    /// the pool is in normal working order
+    Online,
+    /// the pool has experienced a failure but can still function
+    Degraded,
+    /// the pool is completely inaccessible
+    Faulted,
+}
+
+impl Default for PoolState {
+    fn default() -> Self {
+        Self::Online
+    }
+}
+
+/// Pool event payload, published when the state of a pool changes
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolEvent {
+    /// id of the mayastor instance
+    pub node: String,
+    /// name of the pool
+    pub pool: String,
+    /// new state of the pool
+    pub state: PoolState,
+    /// human readable reason of the change
+    pub reason: String,
+}
+bus_impl_message_all!(PoolEvent, PoolEvent, (), Pool);
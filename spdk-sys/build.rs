@@ -36,6 +36,11 @@ fn build_wrapper() {
         .include(".")
         .file("nvme_helper.c")
         .compile("nvme_helper");
+    cc::Build::new()
+        .include("spdk/include")
+        .include(".")
+        .file("lvol_helper.c")
+        .compile("lvol_helper");
 }
 
 fn main() {
@@ -84,7 +89,10 @@ fn main() {
         .whitelist_function("^vbdev_.*")
         .whitelist_function("^nvme_cmd_.*")
         .whitelist_function("^nvme_status_.*")
+        .whitelist_function("^lvol_allocated_clusters")
         .whitelist_function("^nvmf_tgt_accept")
+        .whitelist_function("^nvmf_identify_ns_write_protected")
+        .whitelist_function("^raid_bdev.*")
         .blacklist_type("^longfunc")
         .whitelist_var("^NVMF.*")
         .whitelist_var("^SPDK.*")
@@ -126,4 +134,5 @@ fn main() {
     println!("cargo:rerun-if-changed=wrapper.h");
     println!("cargo:rerun-if-changed=logwrapper.c");
     println!("cargo:rerun-if-changed=nvme_helper.c");
+    println!("cargo:rerun-if-changed=lvol_helper.c");
 }
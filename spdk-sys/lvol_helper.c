@@ -0,0 +1,664 @@
+#include "lvol_helper.h"
+
+#include <bdev/lvol/vbdev_lvol.h>
+#include <spdk/bit_array.h>
+#include <spdk/lib/blob/blobstore.h>
+#include <spdk/string.h>
+#include <spdk_internal/lvolstore.h>
+
+#define LVS_CLEAR_METHOD_XATTR "clear_method"
+
+uint64_t
+lvol_allocated_clusters(const struct spdk_lvol *lvol) {
+       const struct spdk_blob *blob = lvol->blob;
+       uint64_t allocated = 0;
+       uint64_t i;
+
+       for (i = 0; i < blob->active.num_clusters; i++) {
+               if (blob->active.clusters[i] != 0) {
+                       allocated++;
+               }
+       }
+
+       return allocated;
+}
+
+struct lvs_create_ctx {
+       enum lvs_clear_method clear_method;
+       struct spdk_lvol_store *lvs;
+       spdk_lvs_op_with_handle_complete cb_fn;
+       void *cb_arg;
+};
+
+static void
+lvs_create_recorded(void *cb_arg, int lvserrno) {
+       struct lvs_create_ctx *ctx = cb_arg;
+
+       ctx->cb_fn(ctx->cb_arg, ctx->lvs, lvserrno);
+       free(ctx);
+}
+
+static void
+lvs_create_done(void *cb_arg, struct spdk_lvol_store *lvs, int lvserrno) {
+       struct lvs_create_ctx *ctx = cb_arg;
+
+       if (lvserrno != 0) {
+               ctx->cb_fn(ctx->cb_arg, NULL, lvserrno);
+               free(ctx);
+               return;
+       }
+
+       ctx->lvs = lvs;
+       lvs_set_xattr(lvs, LVS_CLEAR_METHOD_XATTR, ctx->clear_method,
+           lvs_create_recorded, ctx);
+}
+
+/*
+ * Creates a lvs on the bdev with vbdev_lvs_create() using the cluster size,
+ * the metadata ratio and the clear method of opts, and records the clear
+ * method in the super blob so that it is known when the lvs is imported. The
+ * lvs is passed to cb_fn even if the clear method fails to be recorded.
+ */
+int
+lvs_create_ext(const char *bdev_name, const struct spdk_lvs_opts *opts,
+    spdk_lvs_op_with_handle_complete cb_fn, void *cb_arg) {
+       struct lvs_create_ctx *ctx;
+       int rc;
+
+       if (strnlen(opts->name, SPDK_LVS_NAME_MAX) == SPDK_LVS_NAME_MAX) {
+               return -EINVAL;
+       }
+
+       ctx = calloc(1, sizeof(*ctx));
+       if (ctx == NULL) {
+               return -ENOMEM;
+       }
+       ctx->clear_method = opts->clear_method;
+       ctx->cb_fn = cb_fn;
+       ctx->cb_arg = cb_arg;
+
+       rc = vbdev_lvs_create(bdev_name, opts->name, opts->cluster_sz,
+           opts->clear_method, opts->num_md_pages_per_cluster_ratio,
+           lvs_create_done, ctx);
+       if (rc != 0) {
+               free(ctx);
+       }
+       return rc;
+}
+
+uint64_t
+lvs_md_pages(const struct spdk_lvol_store *lvs) {
+       return lvs->blobstore->md_len;
+}
+
+struct lvs_xattr_ctx {
+       char name[SPDK_LVS_NAME_MAX];
+       uint32_t value;
+       struct spdk_blob *blob;
+       int rc;
+       lvs_xattr_complete get_fn;
+       lvs_op_complete set_fn;
+       void *cb_arg;
+};
+
+static void
+lvs_xattr_done(struct lvs_xattr_ctx *ctx, int rc) {
+       if (ctx->get_fn != NULL) {
+               ctx->get_fn(ctx->cb_arg, ctx->value, rc);
+       } else {
+               ctx->set_fn(ctx->cb_arg, rc);
+       }
+       free(ctx);
+}
+
+static void
+lvs_xattr_closed(void *cb_arg, int bserrno) {
+       struct lvs_xattr_ctx *ctx = cb_arg;
+
+       lvs_xattr_done(ctx, ctx->rc != 0 ? ctx->rc : bserrno);
+}
+
+static void
+lvs_xattr_synced(void *cb_arg, int bserrno) {
+       struct lvs_xattr_ctx *ctx = cb_arg;
+
+       ctx->rc = bserrno;
+       spdk_blob_close(ctx->blob, lvs_xattr_closed, ctx);
+}
+
+static void
+lvs_xattr_opened(void *cb_arg, struct spdk_blob *blob, int bserrno) {
+       struct lvs_xattr_ctx *ctx = cb_arg;
+       const void *value;
+       size_t len;
+
+       if (bserrno != 0) {
+               lvs_xattr_done(ctx, bserrno);
+               return;
+       }
+
+       ctx->blob = blob;
+       if (ctx->get_fn != NULL) {
+               if (spdk_blob_get_xattr_value(blob, ctx->name, &value,
+                   &len) == 0 && len == sizeof(uint32_t)) {
+                       ctx->value = *(const uint32_t *)value;
+               }
+               spdk_blob_close(blob, lvs_xattr_closed, ctx);
+               return;
+       }
+
+       ctx->rc = spdk_blob_set_xattr(blob, ctx->name, &ctx->value,
+           sizeof(ctx->value));
+       if (ctx->rc != 0) {
+               spdk_blob_close(blob, lvs_xattr_closed, ctx);
+               return;
+       }
+       spdk_blob_sync_md(blob, lvs_xattr_synced, ctx);
+}
+
+static int
+lvs_xattr_ctx(const char *name, uint32_t value, void *cb_arg,
+    struct lvs_xattr_ctx **ctx) {
+       if (strnlen(name, SPDK_LVS_NAME_MAX) == SPDK_LVS_NAME_MAX) {
+               return -EINVAL;
+       }
+
+       *ctx = calloc(1, sizeof(**ctx));
+       if (*ctx == NULL) {
+               return -ENOMEM;
+       }
+       snprintf((*ctx)->name, sizeof((*ctx)->name), "%s", name);
+       (*ctx)->value = value;
+       (*ctx)->cb_arg = cb_arg;
+       return 0;
+}
+
+/*
+ * Reads the setting of the lvs stored as the xattr name of its super blob,
+ * calling back with value if it has never been set.
+ */
+void
+lvs_get_xattr(struct spdk_lvol_store *lvs, const char *name,
+    uint32_t value, lvs_xattr_complete cb_fn, void *cb_arg) {
+       struct lvs_xattr_ctx *ctx;
+       int rc = lvs_xattr_ctx(name, value, cb_arg, &ctx);
+
+       if (rc != 0) {
+               cb_fn(cb_arg, value, rc);
+               return;
+       }
+       ctx->get_fn = cb_fn;
+
+       spdk_bs_open_blob(lvs->blobstore, lvs->super_blob_id,
+           lvs_xattr_opened, ctx);
+}
+
+/*
+ * Stores a setting of the lvs as the xattr name of its super blob, which is
+ * opened and synced the same way spdk_lvs_rename() stores the name of the lvs.
+ */
+void
+lvs_set_xattr(struct spdk_lvol_store *lvs, const char *name,
+    uint32_t value, lvs_op_complete cb_fn, void *cb_arg) {
+       struct lvs_xattr_ctx *ctx;
+       int rc = lvs_xattr_ctx(name, value, cb_arg, &ctx);
+
+       if (rc != 0) {
+               cb_fn(cb_arg, rc);
+               return;
+       }
+       ctx->set_fn = cb_fn;
+
+       spdk_bs_open_blob(lvs->blobstore, lvs->super_blob_id,
+           lvs_xattr_opened, ctx);
+}
+
+/*
+ * Returns the id of the next blob of the lvs after prev, or the first one if
+ * prev is SPDK_BLOBID_INVALID. Unlike spdk_bs_iter_next() this also returns
+ * the blobs which can not be opened.
+ */
+spdk_blob_id
+lvs_next_blob_id(const struct spdk_lvol_store *lvs, spdk_blob_id prev) {
+       struct spdk_blob_store *bs = lvs->blobstore;
+       uint32_t page = 0;
+
+       if (prev != SPDK_BLOBID_INVALID) {
+               page = bs_blobid_to_page(prev) + 1;
+       }
+
+       page = spdk_bit_array_find_first_set(bs->used_blobids, page);
+       if (page == UINT32_MAX) {
+               return SPDK_BLOBID_INVALID;
+       }
+
+       return bs_page_to_blobid(page);
+}
+
+/*
+ * Returns the cluster of the blobstore holding the cluster i of the blob, 0 if
+ * it is not allocated. Cluster 0 always holds the metadata of the blobstore.
+ */
+uint64_t
+lvs_blob_cluster(const struct spdk_blob *blob, uint64_t i) {
+       if (i >= blob->active.num_clusters || blob->active.clusters[i] == 0) {
+               return 0;
+       }
+
+       return bs_lba_to_cluster(blob->bs, blob->active.clusters[i]);
+}
+
+bool
+lvs_cluster_used(const struct spdk_lvol_store *lvs, uint64_t cluster) {
+       return spdk_bit_array_get(lvs->blobstore->used_clusters, cluster);
+}
+
+struct lvs_io_watch {
+       struct spdk_bs_dev dev;
+       struct spdk_bs_dev *base;
+       char name[SPDK_LVS_NAME_MAX];
+       lvs_io_error_cb cb_fn;
+       /* number of the users of the chunks written, see lvs_track_writes() */
+       uint32_t trackers;
+       /* number of writes submitted, see lvs_write_count() */
+       uint64_t writes;
+       uint64_t chunk_size;
+       uint64_t words;
+       uint64_t *written;
+};
+
+struct lvs_io_watch_ctx {
+       struct lvs_io_watch *watch;
+       struct spdk_bs_dev_cb_args *cb_args;
+       spdk_bs_dev_cpl cb_fn;
+       void *cb_arg;
+};
+
+static void
+lvs_io_watch_done(struct spdk_io_channel *channel, void *cb_arg,
+    int bserrno) {
+       struct lvs_io_watch_ctx *ctx = cb_arg;
+       struct spdk_bs_dev_cb_args *cb_args = ctx->cb_args;
+
+       cb_args->cb_fn = ctx->cb_fn;
+       cb_args->cb_arg = ctx->cb_arg;
+       if (bserrno != 0) {
+               ctx->watch->cb_fn(ctx->watch->name, bserrno);
+       }
+       free(ctx);
+
+       cb_args->cb_fn(channel, cb_args->cb_arg, bserrno);
+}
+
+/*
+ * Counts a write to the device and marks the chunks holding the given blocks
+ * as written, while the writes are tracked. This is called as the write is
+ * submitted, and from any thread, so the counter and the bits are updated
+ * atomically.
+ */
+static void
+lvs_io_watch_mark(struct lvs_io_watch *watch, uint64_t lba,
+    uint64_t lba_count) {
+       uint64_t chunk, last;
+
+       __atomic_fetch_add(&watch->writes, 1, __ATOMIC_ACQ_REL);
+       if (lba_count == 0 ||
+           __atomic_load_n(&watch->trackers, __ATOMIC_ACQUIRE) == 0) {
+               return;
+       }
+
+       chunk = lba * watch->dev.blocklen / watch->chunk_size;
+       last = ((lba + lba_count) * watch->dev.blocklen - 1) /
+           watch->chunk_size;
+       for (; chunk <= last; chunk++) {
+               __atomic_fetch_or(&watch->written[chunk / 64],
+                   1ULL << (chunk % 64), __ATOMIC_RELEASE);
+       }
+}
+
+/*
+ * Routes the completion of an I/O through lvs_io_watch_done(). Should there
+ * be no memory for it, the I/O completes as usual without being watched.
+ */
+static struct spdk_bs_dev_cb_args *
+lvs_io_watch_args(struct lvs_io_watch *watch,
+    struct spdk_bs_dev_cb_args *cb_args) {
+       struct lvs_io_watch_ctx *ctx = malloc(sizeof(*ctx));
+
+       if (ctx != NULL) {
+               ctx->watch = watch;
+               ctx->cb_args = cb_args;
+               ctx->cb_fn = cb_args->cb_fn;
+               ctx->cb_arg = cb_args->cb_arg;
+               cb_args->cb_fn = lvs_io_watch_done;
+               cb_args->cb_arg = ctx;
+       }
+       return cb_args;
+}
+
+static struct spdk_io_channel *
+lvs_io_watch_create_channel(struct spdk_bs_dev *dev) {
+       struct lvs_io_watch *watch = SPDK_CONTAINEROF(dev, struct lvs_io_watch,
+           dev);
+
+       return watch->base->create_channel(watch->base);
+}
+
+static void
+lvs_io_watch_destroy_channel(struct spdk_bs_dev *dev,
+    struct spdk_io_channel *channel) {
+       struct lvs_io_watch *watch = SPDK_CONTAINEROF(dev, struct lvs_io_watch,
+           dev);
+
+       watch->base->destroy_channel(watch->base, channel);
+}
+
+static void
+lvs_io_watch_destroy(struct spdk_bs_dev *dev) {
+       struct lvs_io_watch *watch = SPDK_CONTAINEROF(dev, struct lvs_io_watch,
+           dev);
+
+       watch->base->destroy(watch->base);
+       free(watch->written);
+       free(watch);
+}
+
+static void
+lvs_io_watch_read(struct spdk_bs_dev *dev, struct spdk_io_channel *channel,
+    void *payload, uint64_t lba, uint32_t lba_count,
+    struct spdk_bs_dev_cb_args *cb_args) {
+       struct lvs_io_watch *watch = SPDK_CONTAINEROF(dev, struct lvs_io_watch,
+           dev);
+
+       watch->base->read(watch->base, channel, payload, lba, lba_count,
+           lvs_io_watch_args(watch, cb_args));
+}
+
+static void
+lvs_io_watch_write(struct spdk_bs_dev *dev, struct spdk_io_channel *channel,
+    void *payload, uint64_t lba, uint32_t lba_count,
+    struct spdk_bs_dev_cb_args *cb_args) {
+       struct lvs_io_watch *watch = SPDK_CONTAINEROF(dev, struct lvs_io_watch,
+           dev);
+
+       lvs_io_watch_mark(watch, lba, lba_count);
+       watch->base->write(watch->base, channel, payload, lba, lba_count,
+           lvs_io_watch_args(watch, cb_args));
+}
+
+static void
+lvs_io_watch_readv(struct spdk_bs_dev *dev, struct spdk_io_channel *channel,
+    struct iovec *iov, int iovcnt, uint64_t lba, uint32_t lba_count,
+    struct spdk_bs_dev_cb_args *cb_args) {
+       struct lvs_io_watch *watch = SPDK_CONTAINEROF(dev, struct lvs_io_watch,
+           dev);
+
+       watch->base->readv(watch->base, channel, iov, iovcnt, lba, lba_count,
+           lvs_io_watch_args(watch, cb_args));
+}
+
+static void
+lvs_io_watch_writev(struct spdk_bs_dev *dev, struct spdk_io_channel *channel,
+    struct iovec *iov, int iovcnt, uint64_t lba, uint32_t lba_count,
+    struct spdk_bs_dev_cb_args *cb_args) {
+       struct lvs_io_watch *watch = SPDK_CONTAINEROF(dev, struct lvs_io_watch,
+           dev);
+
+       lvs_io_watch_mark(watch, lba, lba_count);
+       watch->base->writev(watch->base, channel, iov, iovcnt, lba, lba_count,
+           lvs_io_watch_args(watch, cb_args));
+}
+
+static void
+lvs_io_watch_write_zeroes(struct spdk_bs_dev *dev,
+    struct spdk_io_channel *channel, uint64_t lba, uint64_t lba_count,
+    struct spdk_bs_dev_cb_args *cb_args) {
+       struct lvs_io_watch *watch = SPDK_CONTAINEROF(dev, struct lvs_io_watch,
+           dev);
+
+       lvs_io_watch_mark(watch, lba, lba_count);
+       watch->base->write_zeroes(watch->base, channel, lba, lba_count,
+           lvs_io_watch_args(watch, cb_args));
+}
+
+static void
+lvs_io_watch_unmap(struct spdk_bs_dev *dev, struct spdk_io_channel *channel,
+    uint64_t lba, uint64_t lba_count, struct spdk_bs_dev_cb_args *cb_args) {
+       struct lvs_io_watch *watch = SPDK_CONTAINEROF(dev, struct lvs_io_watch,
+           dev);
+
+       lvs_io_watch_mark(watch, lba, lba_count);
+       watch->base->unmap(watch->base, channel, lba, lba_count,
+           lvs_io_watch_args(watch, cb_args));
+}
+
+static struct spdk_bdev *
+lvs_io_watch_get_base_bdev(struct spdk_bs_dev *dev) {
+       struct lvs_io_watch *watch = SPDK_CONTAINEROF(dev, struct lvs_io_watch,
+           dev);
+
+       return watch->base->get_base_bdev(watch->base);
+}
+
+/*
+ * Interposes on the device of the blobstore of the lvs, so that cb_fn is
+ * called with the name of the lvs whenever an I/O of the blobstore to its
+ * base bdev fails, and that the writes to the device can be tracked. The
+ * device is freed when the blobstore is unloaded.
+ */
+int
+lvs_watch_io_errors(struct spdk_lvol_store *lvs, lvs_io_error_cb cb_fn) {
+       struct spdk_blob_store *bs = lvs->blobstore;
+       struct lvs_io_watch *watch;
+
+       if (bs->dev->destroy == lvs_io_watch_destroy) {
+               return -EALREADY;
+       }
+
+       watch = calloc(1, sizeof(*watch));
+       if (watch == NULL) {
+               return -ENOMEM;
+       }
+
+       watch->base = bs->dev;
+       snprintf(watch->name, sizeof(watch->name), "%s", lvs->name);
+       watch->cb_fn = cb_fn;
+
+       watch->dev = *bs->dev;
+       watch->dev.create_channel = lvs_io_watch_create_channel;
+       watch->dev.destroy_channel = lvs_io_watch_destroy_channel;
+       watch->dev.destroy = lvs_io_watch_destroy;
+       watch->dev.read = lvs_io_watch_read;
+       watch->dev.write = lvs_io_watch_write;
+       watch->dev.readv = lvs_io_watch_readv;
+       watch->dev.writev = lvs_io_watch_writev;
+       watch->dev.write_zeroes = lvs_io_watch_write_zeroes;
+       watch->dev.unmap = lvs_io_watch_unmap;
+       watch->dev.get_base_bdev = lvs_io_watch_get_base_bdev;
+
+       bs->dev = &watch->dev;
+       lvs->bs_dev = &watch->dev;
+       return 0;
+}
+
+static struct lvs_io_watch *
+lvs_io_watch_of(const struct spdk_lvol_store *lvs) {
+       struct spdk_bs_dev *dev = lvs->blobstore->dev;
+
+       if (dev->destroy != lvs_io_watch_destroy) {
+               return NULL;
+       }
+       return SPDK_CONTAINEROF(dev, struct lvs_io_watch, dev);
+}
+
+/*
+ * Starts tracking the chunks of chunk_size bytes of the device of the lvs
+ * which are written to, until lvs_untrack_writes() is called. The chunks
+ * are tracked for all the users at once, so a chunk may have been written
+ * to before a user started tracking, but none is missed.
+ */
+int
+lvs_track_writes(struct spdk_lvol_store *lvs, uint64_t chunk_size) {
+       struct lvs_io_watch *watch = lvs_io_watch_of(lvs);
+       uint64_t chunks;
+
+       if (watch == NULL) {
+               return -ENOTSUP;
+       }
+       if (chunk_size == 0 || chunk_size % watch->dev.blocklen != 0) {
+               return -EINVAL;
+       }
+
+       if (watch->written == NULL) {
+               chunks = (watch->dev.blockcnt * watch->dev.blocklen +
+                   chunk_size - 1) / chunk_size;
+               watch->words = (chunks + 63) / 64;
+               watch->written = calloc(watch->words, sizeof(uint64_t));
+               if (watch->written == NULL) {
+                       return -ENOMEM;
+               }
+               watch->chunk_size = chunk_size;
+       } else if (watch->chunk_size != chunk_size) {
+               return -EINVAL;
+       }
+
+       __atomic_fetch_add(&watch->trackers, 1, __ATOMIC_ACQ_REL);
+       return 0;
+}
+
+/*
+ * Stops tracking the writes for one user of lvs_track_writes(). The chunks
+ * written are forgotten once there is no user left.
+ */
+void
+lvs_untrack_writes(struct spdk_lvol_store *lvs) {
+       struct lvs_io_watch *watch = lvs_io_watch_of(lvs);
+
+       if (watch == NULL || watch->trackers == 0) {
+               return;
+       }
+       if (__atomic_sub_fetch(&watch->trackers, 1, __ATOMIC_ACQ_REL) == 0) {
+               memset(watch->written, 0, watch->words * sizeof(uint64_t));
+       }
+}
+
+/*
+ * Returns whether the chunk of the device of the lvs holding the byte at
+ * offset has been written to since the writes are tracked.
+ */
+bool
+lvs_written(const struct spdk_lvol_store *lvs, uint64_t offset) {
+       struct lvs_io_watch *watch = lvs_io_watch_of(lvs);
+       uint64_t chunk;
+
+       if (watch == NULL || watch->trackers == 0) {
+               return false;
+       }
+
+       chunk = offset / watch->chunk_size;
+       if (chunk / 64 >= watch->words) {
+               return false;
+       }
+       return (__atomic_load_n(&watch->written[chunk / 64],
+           __ATOMIC_ACQUIRE) & (1ULL << (chunk % 64))) != 0;
+}
+
+/*
+ * Returns the number of writes submitted to the device of the lvs since its
+ * I/O is watched or since lvs_reset_write_count() was called, UINT64_MAX if
+ * it is not watched, so that the lvs is never known to be unmodified.
+ */
+uint64_t
+lvs_write_count(const struct spdk_lvol_store *lvs) {
+       struct lvs_io_watch *watch = lvs_io_watch_of(lvs);
+
+       if (watch == NULL) {
+               return UINT64_MAX;
+       }
+       return __atomic_load_n(&watch->writes, __ATOMIC_ACQUIRE);
+}
+
+/*
+ * Resets the number of writes submitted to the device of the lvs, provided
+ * that it still is count, so that no write counted since is lost. Returns
+ * whether it has been reset.
+ */
+bool
+lvs_reset_write_count(struct spdk_lvol_store *lvs, uint64_t count) {
+       struct lvs_io_watch *watch = lvs_io_watch_of(lvs);
+       uint64_t expected = count;
+
+       if (watch == NULL) {
+               return false;
+       }
+       return __atomic_compare_exchange_n(&watch->writes, &expected, 0, false,
+           __ATOMIC_ACQ_REL, __ATOMIC_ACQUIRE);
+}
+
+struct lvs_dev_io_ctx {
+       struct spdk_bs_dev *dev;
+       struct spdk_bs_dev_cb_args cb_args;
+       lvs_op_complete cb_fn;
+       void *cb_arg;
+};
+
+static void
+lvs_dev_io_done(struct spdk_io_channel *channel, void *cb_arg, int bserrno) {
+       struct lvs_dev_io_ctx *ctx = cb_arg;
+
+       ctx->dev->destroy_channel(ctx->dev, channel);
+       ctx->cb_fn(ctx->cb_arg, bserrno);
+       free(ctx);
+}
+
+/*
+ * Overwrites the length bytes of the device of the lvs at offset, which are
+ * multiples of its block size, with the payload, or with zeroes if there is
+ * none, or unmaps them if unmap is set. The I/O is submitted to the device
+ * the way the blobstore submits its own, as it holds the claim on the base
+ * bdev, so that the clusters of the blobs which can not be written to, such
+ * as the snapshots, can be wiped.
+ */
+void
+lvs_dev_wipe(struct spdk_lvol_store *lvs, uint64_t offset, uint64_t length,
+    bool unmap, void *payload, lvs_op_complete cb_fn, void *cb_arg) {
+       struct spdk_bs_dev *dev = lvs->blobstore->dev;
+       struct spdk_io_channel *channel;
+       struct lvs_dev_io_ctx *ctx;
+       uint64_t lba = offset / dev->blocklen;
+       uint64_t lba_count = length / dev->blocklen;
+
+       if (offset % dev->blocklen != 0 || length % dev->blocklen != 0 ||
+           lba_count > UINT32_MAX || lba + lba_count > dev->blockcnt) {
+               cb_fn(cb_arg, -EINVAL);
+               return;
+       }
+
+       ctx = calloc(1, sizeof(*ctx));
+       if (ctx == NULL) {
+               cb_fn(cb_arg, -ENOMEM);
+               return;
+       }
+       channel = dev->create_channel(dev);
+       if (channel == NULL) {
+               free(ctx);
+               cb_fn(cb_arg, -ENOMEM);
+               return;
+       }
+
+       ctx->dev = dev;
+       ctx->cb_fn = cb_fn;
+       ctx->cb_arg = cb_arg;
+       ctx->cb_args.cb_fn = lvs_dev_io_done;
+       ctx->cb_args.channel = channel;
+       ctx->cb_args.cb_arg = ctx;
+
+       if (unmap) {
+               dev->unmap(dev, channel, lba, lba_count, &ctx->cb_args);
+       } else if (payload == NULL) {
+               dev->write_zeroes(dev, channel, lba, lba_count, &ctx->cb_args);
+       } else {
+               dev->write(dev, channel, payload, lba, lba_count,
+                   &ctx->cb_args);
+       }
+}
@@ -0,0 +1,50 @@
+#include <stdint.h>
+
+#include <stdbool.h>
+
+#include <spdk/blob.h>
+#include <spdk/lvol.h>
+
+struct spdk_lvol;
+struct spdk_lvol_store;
+
+typedef void (*lvs_op_complete)(void *cb_arg, int lvserrno);
+typedef void (*lvs_xattr_complete)(void *cb_arg, uint32_t value,
+    int lvserrno);
+typedef void (*lvs_io_error_cb)(const char *lvs_name, int bserrno);
+
+uint64_t lvol_allocated_clusters(const struct spdk_lvol *lvol);
+
+int lvs_create_ext(const char *bdev_name, const struct spdk_lvs_opts *opts,
+    spdk_lvs_op_with_handle_complete cb_fn, void *cb_arg);
+
+uint64_t lvs_md_pages(const struct spdk_lvol_store *lvs);
+
+void lvs_get_xattr(struct spdk_lvol_store *lvs, const char *name,
+    uint32_t value, lvs_xattr_complete cb_fn, void *cb_arg);
+
+void lvs_set_xattr(struct spdk_lvol_store *lvs, const char *name,
+    uint32_t value, lvs_op_complete cb_fn, void *cb_arg);
+
+spdk_blob_id lvs_next_blob_id(const struct spdk_lvol_store *lvs,
+    spdk_blob_id prev);
+
+uint64_t lvs_blob_cluster(const struct spdk_blob *blob, uint64_t i);
+
+bool lvs_cluster_used(const struct spdk_lvol_store *lvs, uint64_t cluster);
+
+int lvs_watch_io_errors(struct spdk_lvol_store *lvs, lvs_io_error_cb cb_fn);
+
+int lvs_track_writes(struct spdk_lvol_store *lvs, uint64_t chunk_size);
+
+void lvs_untrack_writes(struct spdk_lvol_store *lvs);
+
+bool lvs_written(const struct spdk_lvol_store *lvs, uint64_t offset);
+
+uint64_t lvs_write_count(const struct spdk_lvol_store *lvs);
+
+bool lvs_reset_write_count(struct spdk_lvol_store *lvs, uint64_t count);
+
+void lvs_dev_wipe(struct spdk_lvol_store *lvs, uint64_t offset,
+    uint64_t length, bool unmap, void *payload, lvs_op_complete cb_fn,
+    void *cb_arg);
@@ -3,6 +3,8 @@
 #include <spdk/bdev_module.h>
 #include <spdk/lib/bdev/bdev_internal.h>
 #include <spdk/nvme_spec.h>
+#include <spdk/nvmf_cmd.h>
+#include <spdk/nvmf_transport.h>
 #include <spdk/thread.h>
 
 uint32_t *
@@ -15,6 +17,11 @@ nvme_cmd_cdw11_get(struct spdk_nvme_cmd *cmd) {
        return &cmd->cdw11;
 }
 
+uint32_t *
+nvme_cmd_cdw12_get(struct spdk_nvme_cmd *cmd) {
+       return &cmd->cdw12;
+}
+
 uint32_t
 nvme_cmd_cdw10_get_val(const struct spdk_nvme_cmd *cmd) {
        return cmd->cdw10;
@@ -25,6 +32,16 @@ nvme_cmd_cdw11_get_val(const struct spdk_nvme_cmd *cmd) {
        return cmd->cdw11;
 }
 
+uint32_t
+nvme_cmd_cdw12_get_val(const struct spdk_nvme_cmd *cmd) {
+       return cmd->cdw12;
+}
+
+uint32_t
+nvme_cmd_nsid_get_val(const struct spdk_nvme_cmd *cmd) {
+       return cmd->nsid;
+}
+
 struct spdk_nvme_status *
 nvme_status_get(struct spdk_nvme_cpl *cpl) {
 	return &cpl->status;
@@ -68,3 +85,34 @@ spdk_bdev_nvme_admin_passthru_ro(struct spdk_bdev_desc *desc, struct spdk_io_cha
 	bdev_io_submit(bdev_io);
 	return 0;
 }
+
+/* Identifies the namespace of the request like the target does and marks it
+ * as write protected, so that the hosts do not attempt to write to it.
+ * Returns the spdk_nvmf_request_exec_status of the request, or -1 to let the
+ * target handle the request if it has no room for the namespace data.
+ */
+int
+nvmf_identify_ns_write_protected(struct spdk_nvmf_request *req)
+{
+	struct spdk_nvme_cmd *cmd = spdk_nvmf_request_get_cmd(req);
+	struct spdk_nvme_cpl *rsp = spdk_nvmf_request_get_response(req);
+	struct spdk_nvme_ns_data *nsdata;
+	uint32_t length = 0;
+	void *data = NULL;
+	int rc;
+
+	spdk_nvmf_request_get_data(req, &data, &length);
+	if (data == NULL || length < sizeof(*nsdata)) {
+		return -1;
+	}
+
+	nsdata = data;
+	memset(nsdata, 0, sizeof(*nsdata));
+	rc = spdk_nvmf_ctrlr_identify_ns(req->qpair->ctrlr, cmd, rsp, nsdata);
+	if (rc == SPDK_NVMF_REQUEST_EXEC_STATUS_COMPLETE &&
+	    rsp->status.sct == SPDK_NVME_SCT_GENERIC &&
+	    rsp->status.sc == SPDK_NVME_SC_SUCCESS) {
+		nsdata->nsattr.write_protected = 1;
+	}
+	return rc;
+}
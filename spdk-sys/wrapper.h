@@ -6,6 +6,7 @@
 #include <bdev/nvme/bdev_nvme.h>
 #include <bdev/malloc/bdev_malloc.h>
 #include <bdev/null/bdev_null.h>
+#include <bdev/raid/bdev_raid.h>
 #include <bdev/uring/bdev_uring.h>
 #include <iscsi/init_grp.h>
 #include <iscsi/iscsi.h>
@@ -37,3 +38,4 @@
 
 #include "logwrapper.h"
 #include "nvme_helper.h"
+#include "lvol_helper.h"